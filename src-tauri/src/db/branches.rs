@@ -0,0 +1,361 @@
+//! Conversation trees.
+//!
+//! Messages link to the message they follow through `parent_message_id`, so an edited user
+//! message or a regenerated answer is a sibling branch rather than a replacement. Each thread
+//! remembers the leaf of the branch it shows (`active_message_id`); the path from the root to
+//! that leaf is the linear history sent to the model.
+//!
+//! Links are read through the `chat_message_parents` view, where messages saved without a parent
+//! follow the message before them, so threads written before branches read as one line. Writers
+//! set parents and the active leaf explicitly; nothing is re-parented after the fact.
+
+use super::messages::{self, Message};
+use super::rows::column_text;
+use super::{DbError, DbResult};
+use serde::Serialize;
+use serde_json::{json, Value};
+use sqlx::sqlite::SqliteExecutor;
+use sqlx::SqlitePool;
+
+/// Longest path followed, so a corrupt parent cycle cannot loop forever
+const MAX_DEPTH: i64 = 100_000;
+
+/// The alternatives at one point of a conversation
+#[derive(Debug, Clone, Serialize)]
+pub struct Branches {
+    /// Messages sharing a parent, oldest first
+    pub siblings: Vec<Message>,
+    /// Position of the sibling on the active branch, if one is
+    pub active_index: Option<usize>,
+}
+
+/// Messages from the root of the thread down to `message_id`
+pub async fn path_to(pool: &SqlitePool, message_id: &str) -> DbResult<Vec<Message>> {
+    let rows = sqlx::query(
+        r#"
+        WITH RECURSIVE path(id, depth) AS (
+            SELECT id, 0 FROM chat_messages WHERE id = ?1
+            UNION ALL
+            SELECT p.parent_id, path.depth + 1
+            FROM path
+            JOIN chat_message_parents p ON p.id = path.id
+            WHERE p.parent_id IS NOT NULL AND path.depth < ?2
+        )
+        SELECT m.* FROM path JOIN chat_messages m ON m.id = path.id
+        ORDER BY path.depth DESC
+        "#,
+    )
+    .bind(message_id)
+    .bind(MAX_DEPTH)
+    .fetch_all(pool)
+    .await?;
+    if rows.is_empty() {
+        return Err(DbError::not_found("Message", message_id));
+    }
+    rows.iter().map(Message::from_row).collect()
+}
+
+/// Leaf of the thread's active branch: the one it remembers, else its latest message
+pub async fn active_leaf<'e>(executor: impl SqliteExecutor<'e>, thread_id: &str) -> DbResult<Option<String>> {
+    let leaf: Option<Option<String>> = sqlx::query_scalar(
+        r#"
+        SELECT COALESCE(
+            m.id,
+            (SELECT id FROM chat_messages WHERE thread_id = t.id ORDER BY created_at DESC, rowid DESC LIMIT 1)
+        )
+        FROM chat_threads t
+        LEFT JOIN chat_messages m ON m.id = t.active_message_id AND m.thread_id = t.id
+        WHERE t.id = ?
+        "#,
+    )
+    .bind(thread_id)
+    .fetch_optional(executor)
+    .await?;
+    leaf.ok_or_else(|| DbError::not_found("Thread", thread_id))
+}
+
+/// Make `message_id` the leaf of its thread's active branch
+pub async fn set_active_leaf<'e>(executor: impl SqliteExecutor<'e>, thread_id: &str, message_id: &str) -> DbResult<()> {
+    sqlx::query("UPDATE chat_threads SET active_message_id = ? WHERE id = ?")
+        .bind(message_id)
+        .bind(thread_id)
+        .execute(executor)
+        .await?;
+    Ok(())
+}
+
+/// Parent `message_id` follows, as read by `path_to`
+async fn parent_of(pool: &SqlitePool, message_id: &str) -> DbResult<Option<String>> {
+    let parent: Option<Option<String>> = sqlx::query_scalar("SELECT parent_id FROM chat_message_parents WHERE id = ?")
+        .bind(message_id)
+        .fetch_optional(pool)
+        .await?;
+    parent.ok_or_else(|| DbError::not_found("Message", message_id))
+}
+
+/// The thread's active branch, root first; empty for a thread without messages
+pub async fn active_path(pool: &SqlitePool, thread_id: &str) -> DbResult<Vec<Message>> {
+    match active_leaf(pool, thread_id).await? {
+        Some(leaf) => path_to(pool, &leaf).await,
+        None => Ok(Vec::new()),
+    }
+}
+
+/// `message_id` and its siblings, marking the one on the active branch
+pub async fn branches(pool: &SqlitePool, message_id: &str) -> DbResult<Branches> {
+    let siblings: Vec<Message> = sqlx::query(
+        r#"
+        SELECT s.* FROM chat_message_parents m
+        JOIN chat_message_parents sp ON sp.thread_id = m.thread_id AND sp.parent_id IS m.parent_id
+        JOIN chat_messages s ON s.id = sp.id
+        WHERE m.id = ?
+        ORDER BY s.created_at, s.rowid
+        "#,
+    )
+    .bind(message_id)
+    .fetch_all(pool)
+    .await?
+    .iter()
+    .map(Message::from_row)
+    .collect::<DbResult<_>>()?;
+    if siblings.is_empty() {
+        return Err(DbError::not_found("Message", message_id));
+    }
+
+    let active = active_path(pool, &thread_of(pool, message_id).await?).await?;
+    let active_index = siblings
+        .iter()
+        .position(|sibling| active.iter().any(|message| message.id == sibling.id));
+    Ok(Branches { siblings, active_index })
+}
+
+async fn thread_of(pool: &SqlitePool, message_id: &str) -> DbResult<String> {
+    sqlx::query_scalar("SELECT thread_id FROM chat_messages WHERE id = ?")
+        .bind(message_id)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| DbError::not_found("Message", message_id))
+}
+
+/// Make the branch through `message_id` the active one, following the latest reply at each
+/// step below it; returns the new active path
+pub async fn switch_branch(pool: &SqlitePool, message_id: &str) -> DbResult<Vec<Message>> {
+    let thread_id = thread_of(pool, message_id).await?;
+    let leaf: String = sqlx::query_scalar(
+        r#"
+        WITH RECURSIVE down(id, depth) AS (
+            SELECT ?1, 0
+            UNION ALL
+            SELECT (
+                SELECT c.id FROM chat_message_parents c
+                WHERE c.thread_id = ?3 AND c.parent_id = down.id
+                ORDER BY c.created_at DESC, c.position DESC
+                LIMIT 1
+            ), down.depth + 1
+            FROM down
+            WHERE down.depth < ?2
+              AND EXISTS (SELECT 1 FROM chat_message_parents c WHERE c.thread_id = ?3 AND c.parent_id = down.id)
+        )
+        SELECT id FROM down ORDER BY depth DESC LIMIT 1
+        "#,
+    )
+    .bind(message_id)
+    .bind(MAX_DEPTH)
+    .bind(&thread_id)
+    .fetch_one(pool)
+    .await?;
+
+    set_active_leaf(pool, &thread_id, &leaf).await?;
+    path_to(pool, &leaf).await
+}
+
+/// Edit a user message by adding a sibling with the new content, which becomes the active
+/// branch; the original and everything after it stay as they were
+pub async fn edit_message(pool: &SqlitePool, message_id: &str, content: &str) -> DbResult<Message> {
+    let original = messages::get_message(&mut *pool.acquire().await?, message_id).await?;
+    if original.role != "user" {
+        return Err(DbError::invalid_input(format!(
+            "Only user messages can be edited, not {} messages",
+            original.role
+        )));
+    }
+
+    let mut history = match original.edit_history {
+        Value::Array(versions) => versions,
+        _ => Vec::new(),
+    };
+    history.push(json!({
+        "message_id": original.id,
+        "content": original.content,
+        "edited_at": original.updated_at,
+    }));
+
+    // A sibling of the original as it is read, so an edit of a message saved without a parent
+    // still follows the message before the original
+    let parent = parent_of(pool, message_id).await?;
+    let id = uuid::Uuid::new_v4().to_string();
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        r#"
+        INSERT INTO chat_messages (id, thread_id, parent_message_id, role, content, attachments, metadata,
+                                   is_edited, edit_history)
+        VALUES (?, ?, ?, ?, ?, ?, '{}', TRUE, ?)
+        "#,
+    )
+    .bind(&id)
+    .bind(&original.thread_id)
+    .bind(&parent)
+    .bind(&original.role)
+    .bind(content)
+    .bind(column_text(&original.attachments, "[]"))
+    .bind(Value::Array(history).to_string())
+    .execute(&mut *tx)
+    .await?;
+    set_active_leaf(&mut *tx, &original.thread_id, &id).await?;
+    let edited = messages::get_message(&mut tx, &id).await?;
+    tx.commit().await?;
+    Ok(edited)
+}
+
+/// Start a new thread from the path up to `message_id`; returns the new thread's id.
+///
+/// The thread keeps the original's settings and links back to it; messages are copied without
+/// their usage, which stays accounted to the original.
+pub async fn fork_thread(pool: &SqlitePool, message_id: &str, title: Option<&str>) -> DbResult<String> {
+    let path = path_to(pool, message_id).await?;
+    let thread_id = thread_of(pool, message_id).await?;
+    let id = uuid::Uuid::new_v4().to_string();
+
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        r#"
+        INSERT INTO chat_threads (id, project_id, title, description, model_provider, model_name, system_prompt,
+                                  tool_presets, settings, tags, thread_type, priority, parent_thread_id)
+        SELECT ?1, project_id, COALESCE(?2, title || ' (fork)'), description, model_provider, model_name,
+               system_prompt, tool_presets, settings, tags, thread_type, priority, id
+        FROM chat_threads WHERE id = ?3
+        "#,
+    )
+    .bind(&id)
+    .bind(title.filter(|title| !title.trim().is_empty()))
+    .bind(&thread_id)
+    .execute(&mut *tx)
+    .await?;
+
+    let mut parent: Option<String> = None;
+    for message in &path {
+        let message_id = uuid::Uuid::new_v4().to_string();
+        sqlx::query(
+            r#"
+            INSERT INTO chat_messages (id, thread_id, parent_message_id, role, content, attachments, thinking,
+                                       tool_calls, metadata, response_time, model_used, provider_used,
+                                       is_edited, edit_history, created_at, updated_at)
+            SELECT ?1, ?2, ?3, role, content, attachments, thinking, tool_calls, metadata, response_time,
+                   model_used, provider_used, is_edited, edit_history, created_at, updated_at
+            FROM chat_messages WHERE id = ?4
+            "#,
+        )
+        .bind(&message_id)
+        .bind(&id)
+        .bind(&parent)
+        .bind(&message.id)
+        .execute(&mut *tx)
+        .await?;
+        parent = Some(message_id);
+    }
+    if let Some(leaf) = &parent {
+        set_active_leaf(&mut *tx, &id, leaf).await?;
+    }
+    tx.commit().await?;
+    Ok(id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+
+    fn contents(messages: &[Message]) -> Vec<&str> {
+        messages.iter().map(|message| message.content.as_str()).collect()
+    }
+
+    #[tokio::test]
+    async fn test_edit_regenerate_switch_and_fork() {
+        let db = Database::in_memory().await;
+        // Saved before branches, without parents: read as one line
+        sqlx::raw_sql(
+            r#"
+            INSERT INTO chat_threads (id, project_id, title) VALUES ('t1', 'default_project', 'Trip');
+            INSERT INTO chat_messages (id, thread_id, role, content, created_at) VALUES
+                ('u1', 't1', 'user', 'Plan a trip', '2025-03-01 10:00:00');
+            INSERT INTO chat_messages (id, thread_id, role, content, created_at) VALUES
+                ('a1', 't1', 'assistant', 'Where to?', '2025-03-01 10:00:01');
+            INSERT INTO chat_messages (id, thread_id, role, content, created_at) VALUES
+                ('u2', 't1', 'user', 'Rome', '2025-03-01 10:00:02');
+            INSERT INTO chat_messages (id, thread_id, role, content, created_at) VALUES
+                ('a2', 't1', 'assistant', 'Rome it is', '2025-03-01 10:00:03');
+            "#,
+        )
+        .execute(db.pool())
+        .await
+        .unwrap();
+        assert_eq!(
+            contents(&active_path(db.pool(), "t1").await.unwrap()),
+            ["Plan a trip", "Where to?", "Rome", "Rome it is"]
+        );
+
+        // Editing branches off beside the original
+        let edited = edit_message(db.pool(), "u2", "Paris").await.unwrap();
+        assert!(edited.is_edited);
+        assert_eq!(edited.parent_message_id.as_deref(), Some("a1"));
+        assert_eq!(edited.edit_history[0]["content"], "Rome");
+        assert_eq!(contents(&active_path(db.pool(), "t1").await.unwrap()), ["Plan a trip", "Where to?", "Paris"]);
+
+        // Two answers to the edit: the regenerated one is a sibling and becomes active
+        let mut conn = db.pool().acquire().await.unwrap();
+        for (id, content, parent) in [("a3", "Paris it is", None), ("a4", "Paris, great", Some(edited.id.clone()))] {
+            let answer = messages::NewMessage {
+                id: Some(id.to_string()),
+                thread_id: "t1".to_string(),
+                parent_message_id: parent,
+                role: "assistant".to_string(),
+                content: content.to_string(),
+                ..messages::NewMessage::default()
+            };
+            let created = messages::create_message(&mut conn, &answer).await.unwrap();
+            assert_eq!(created.parent_message_id.as_ref(), Some(&edited.id));
+        }
+        drop(conn);
+        let answers = branches(db.pool(), "a3").await.unwrap();
+        assert_eq!(contents(&answers.siblings), ["Paris it is", "Paris, great"]);
+        assert_eq!(answers.active_index, Some(1));
+        let questions = branches(db.pool(), "u2").await.unwrap();
+        assert_eq!(contents(&questions.siblings), ["Rome", "Paris"]);
+        assert_eq!(questions.active_index, Some(1));
+
+        // Back to the original question and the answer below it
+        let path = switch_branch(db.pool(), "u2").await.unwrap();
+        assert_eq!(contents(&path), ["Plan a trip", "Where to?", "Rome", "Rome it is"]);
+        assert_eq!(active_leaf(db.pool(), "t1").await.unwrap().as_deref(), Some("a2"));
+
+        // A fork copies the path up to the message into a new thread
+        let fork = fork_thread(db.pool(), "a1", None).await.unwrap();
+        let path = active_path(db.pool(), &fork).await.unwrap();
+        assert_eq!(contents(&path), ["Plan a trip", "Where to?"]);
+        assert_eq!(path[1].parent_message_id.as_ref(), Some(&path[0].id));
+        let (title, parent): (String, Option<String>) =
+            sqlx::query_as("SELECT title, parent_thread_id FROM chat_threads WHERE id = ?")
+                .bind(&fork)
+                .fetch_one(db.pool())
+                .await
+                .unwrap();
+        assert_eq!(title, "Trip (fork)");
+        assert_eq!(parent.as_deref(), Some("t1"));
+
+        assert!(matches!(
+            edit_message(db.pool(), "a1", "Anywhere").await,
+            Err(DbError::InvalidInput { .. })
+        ));
+        assert!(matches!(branches(db.pool(), "missing").await, Err(DbError::NotFound { .. })));
+    }
+}
@@ -0,0 +1,373 @@
+//! Message embeddings and semantic search over chat history.
+//!
+//! Vectors are stored as BLOBs and compared in memory; a local history is small enough that a
+//! full scan per query beats maintaining an index.
+
+use super::DbResult;
+use serde::Serialize;
+use sqlx::SqlitePool;
+
+/// Attempts at embedding a message before it is skipped; editing the message tries again
+pub const MAX_ATTEMPTS: i64 = 3;
+
+/// A message that has no embedding for a model yet, or was edited since it was embedded
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct PendingMessage {
+    pub id: String,
+    pub content: String,
+    pub updated_at: String,
+}
+
+/// A message shown in search results
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct SearchMessage {
+    pub id: String,
+    pub role: String,
+    pub content: String,
+    pub created_at: String,
+}
+
+/// A matching message with the thread it belongs to and its neighbours there
+#[derive(Debug, Clone, Serialize)]
+pub struct SemanticHit {
+    /// Cosine similarity to the query, higher is closer
+    pub score: f32,
+    pub message: SearchMessage,
+    pub thread_id: String,
+    pub thread_title: String,
+    pub project_id: Option<String>,
+    /// Messages right before the match, oldest first
+    pub before: Vec<SearchMessage>,
+    /// Messages right after the match
+    pub after: Vec<SearchMessage>,
+}
+
+/// User and assistant messages to embed with `model`, newest first, with those that failed
+/// before last.
+///
+/// Empty and still-streaming messages are skipped, as are messages that failed `MAX_ATTEMPTS`
+/// times since they last changed.
+pub async fn pending_messages(pool: &SqlitePool, model: &str, limit: usize) -> DbResult<Vec<PendingMessage>> {
+    Ok(sqlx::query_as(
+        r#"
+        SELECT m.id, m.content, m.updated_at
+        FROM chat_messages m
+        LEFT JOIN message_embeddings e ON e.message_id = m.id AND e.model = ?1
+        LEFT JOIN message_embedding_failures f
+            ON f.message_id = m.id AND f.model = ?1 AND f.message_updated_at = m.updated_at
+        WHERE m.role IN ('user', 'assistant')
+          AND trim(m.content) != ''
+          AND COALESCE(CASE WHEN json_valid(m.metadata) THEN json_extract(m.metadata, '$.status') END, '') != 'streaming'
+          AND (e.message_id IS NULL OR e.message_updated_at != m.updated_at)
+          AND COALESCE(f.attempts, 0) < ?3
+        ORDER BY COALESCE(f.attempts, 0), m.created_at DESC, m.rowid DESC
+        LIMIT ?2
+        "#,
+    )
+    .bind(model)
+    .bind(limit as i64)
+    .bind(MAX_ATTEMPTS)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Count a failed attempt at embedding a message; attempts restart when the message changed
+pub async fn record_failure(
+    pool: &SqlitePool,
+    message_id: &str,
+    model: &str,
+    updated_at: &str,
+    error: &str,
+) -> DbResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO message_embedding_failures (message_id, model, message_updated_at, error)
+        SELECT ?1, ?2, ?3, ?4
+        WHERE EXISTS (SELECT 1 FROM chat_messages WHERE id = ?1)
+        ON CONFLICT (message_id, model) DO UPDATE SET
+            attempts = CASE WHEN message_updated_at = excluded.message_updated_at THEN attempts + 1 ELSE 1 END,
+            message_updated_at = excluded.message_updated_at,
+            error = excluded.error,
+            failed_at = CURRENT_TIMESTAMP
+        "#,
+    )
+    .bind(message_id)
+    .bind(model)
+    .bind(updated_at)
+    .bind(error)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Store a message's embedding, replacing the one it had for `model`.
+///
+/// `updated_at` is the message's as read with it; messages deleted meanwhile are skipped.
+pub async fn store_embedding(
+    pool: &SqlitePool,
+    message_id: &str,
+    model: &str,
+    updated_at: &str,
+    vector: &[f32],
+) -> DbResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO message_embeddings (message_id, model, dimensions, vector, message_updated_at)
+        SELECT ?1, ?2, ?3, ?4, ?5
+        WHERE EXISTS (SELECT 1 FROM chat_messages WHERE id = ?1)
+        ON CONFLICT (message_id, model) DO UPDATE SET
+            dimensions = excluded.dimensions,
+            vector = excluded.vector,
+            message_updated_at = excluded.message_updated_at,
+            created_at = CURRENT_TIMESTAMP
+        "#,
+    )
+    .bind(message_id)
+    .bind(model)
+    .bind(vector.len() as i64)
+    .bind(encode(vector))
+    .bind(updated_at)
+    .execute(pool)
+    .await?;
+    sqlx::query("DELETE FROM message_embedding_failures WHERE message_id = ? AND model = ?")
+        .bind(message_id)
+        .bind(model)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Messages closest to `query` among those embedded with `model`, each with `context`
+/// messages on either side
+pub async fn semantic_search(
+    pool: &SqlitePool,
+    model: &str,
+    query: &[f32],
+    project_id: Option<&str>,
+    limit: usize,
+    context: usize,
+) -> DbResult<Vec<SemanticHit>> {
+    let candidates: Vec<(String, Vec<u8>)> = sqlx::query_as(
+        r#"
+        SELECT e.message_id, e.vector
+        FROM message_embeddings e
+        JOIN chat_messages m ON m.id = e.message_id
+        JOIN chat_threads t ON t.id = m.thread_id
+        WHERE e.model = ?1 AND e.dimensions = ?2 AND (?3 IS NULL OR t.project_id = ?3)
+        "#,
+    )
+    .bind(model)
+    .bind(query.len() as i64)
+    .bind(project_id)
+    .fetch_all(pool)
+    .await?;
+
+    let mut scored: Vec<(f32, String)> = candidates
+        .into_iter()
+        .map(|(id, vector)| (cosine(query, &decode(&vector)), id))
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.truncate(limit);
+
+    let mut hits = Vec::with_capacity(scored.len());
+    for (score, id) in scored {
+        hits.extend(hit(pool, &id, score, context).await?);
+    }
+    Ok(hits)
+}
+
+#[derive(sqlx::FromRow)]
+struct HitRow {
+    id: String,
+    role: String,
+    content: String,
+    created_at: String,
+    rowid: i64,
+    thread_id: String,
+    thread_title: String,
+    project_id: Option<String>,
+}
+
+/// A message with its thread and neighbours; `None` if it was deleted since the vectors were read
+async fn hit(pool: &SqlitePool, id: &str, score: f32, context: usize) -> DbResult<Option<SemanticHit>> {
+    let row: Option<HitRow> = sqlx::query_as(
+        r#"
+        SELECT m.id, m.role, m.content, m.created_at, m.rowid AS rowid,
+               t.id AS thread_id, t.title AS thread_title, t.project_id
+        FROM chat_messages m
+        JOIN chat_threads t ON t.id = m.thread_id
+        WHERE m.id = ?
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let mut before: Vec<SearchMessage> = sqlx::query_as(
+        r#"
+        SELECT id, role, content, created_at FROM chat_messages
+        WHERE thread_id = ?1 AND (created_at < ?2 OR (created_at = ?2 AND rowid < ?3))
+        ORDER BY created_at DESC, rowid DESC
+        LIMIT ?4
+        "#,
+    )
+    .bind(&row.thread_id)
+    .bind(&row.created_at)
+    .bind(row.rowid)
+    .bind(context as i64)
+    .fetch_all(pool)
+    .await?;
+    before.reverse();
+    let after: Vec<SearchMessage> = sqlx::query_as(
+        r#"
+        SELECT id, role, content, created_at FROM chat_messages
+        WHERE thread_id = ?1 AND (created_at > ?2 OR (created_at = ?2 AND rowid > ?3))
+        ORDER BY created_at ASC, rowid ASC
+        LIMIT ?4
+        "#,
+    )
+    .bind(&row.thread_id)
+    .bind(&row.created_at)
+    .bind(row.rowid)
+    .bind(context as i64)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Some(SemanticHit {
+        score,
+        message: SearchMessage {
+            id: row.id,
+            role: row.role,
+            content: row.content,
+            created_at: row.created_at,
+        },
+        thread_id: row.thread_id,
+        thread_title: row.thread_title,
+        project_id: row.project_id,
+        before,
+        after,
+    }))
+}
+
+fn encode(vector: &[f32]) -> Vec<u8> {
+    vector.iter().flat_map(|value| value.to_le_bytes()).collect()
+}
+
+fn decode(bytes: &[u8]) -> Vec<f32> {
+    bytes
+        .chunks_exact(4)
+        .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+        .collect()
+}
+
+/// Cosine similarity; 0 when either vector is all zeros
+fn cosine(a: &[f32], b: &[f32]) -> f32 {
+    let (mut dot, mut norm_a, mut norm_b) = (0.0f32, 0.0f32, 0.0f32);
+    for (x, y) in a.iter().zip(b) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+
+    #[tokio::test]
+    async fn test_index_and_search_with_context() {
+        let db = Database::in_memory().await;
+        sqlx::raw_sql(
+            r#"
+            INSERT INTO projects (id, name) VALUES ('p1', 'Backend');
+            INSERT INTO chat_threads (id, project_id, title) VALUES ('t1', 'p1', 'Debugging the pool');
+            INSERT INTO chat_threads (id, project_id, title) VALUES ('t2', 'default_project', 'Recipes');
+            INSERT INTO chat_messages (id, thread_id, role, content) VALUES
+                ('m1', 't1', 'user', 'The connection pool deadlocks'),
+                ('m2', 't1', 'assistant', 'Check the max_connections setting'),
+                ('m3', 't1', 'user', 'That fixed it'),
+                ('m4', 't2', 'user', 'How long to boil an egg?'),
+                ('m5', 't2', 'assistant', '');
+            "#,
+        )
+        .execute(db.pool())
+        .await
+        .unwrap();
+
+        let pending = pending_messages(db.pool(), "ollama::nomic", 10).await.unwrap();
+        assert_eq!(pending.len(), 4, "empty messages are skipped");
+        for message in &pending {
+            let vector = match message.id.as_str() {
+                "m1" => [1.0, 0.0],
+                "m2" => [0.9, 0.1],
+                "m3" => [0.1, 0.9],
+                _ => [0.0, 1.0],
+            };
+            store_embedding(db.pool(), &message.id, "ollama::nomic", &message.updated_at, &vector)
+                .await
+                .unwrap();
+        }
+        assert!(pending_messages(db.pool(), "ollama::nomic", 10).await.unwrap().is_empty());
+        assert_eq!(pending_messages(db.pool(), "openai::text-embedding-3-small", 10).await.unwrap().len(), 4);
+
+        let hits = semantic_search(db.pool(), "ollama::nomic", &[1.0, 0.0], None, 2, 1).await.unwrap();
+        assert_eq!(hits[0].message.id, "m1");
+        assert!((hits[0].score - 1.0).abs() < 1e-6);
+        assert_eq!(hits[0].thread_title, "Debugging the pool");
+        assert!(hits[0].before.is_empty());
+        assert_eq!(hits[0].after[0].id, "m2");
+        assert_eq!(hits[1].message.id, "m2");
+        assert_eq!(hits[1].before[0].id, "m1");
+        assert_eq!(hits[1].after[0].id, "m3");
+
+        let scoped = semantic_search(db.pool(), "ollama::nomic", &[0.0, 1.0], Some("default_project"), 5, 0)
+            .await
+            .unwrap();
+        assert_eq!(scoped.len(), 1);
+        assert_eq!(scoped[0].message.id, "m4");
+        assert!(hit(db.pool(), "deleted", 1.0, 1).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_failing_message_is_retried_then_skipped() {
+        let db = Database::in_memory().await;
+        sqlx::raw_sql(
+            r#"
+            INSERT INTO chat_threads (id, project_id, title) VALUES ('t1', 'default_project', 'Logs');
+            INSERT INTO chat_messages (id, thread_id, role, content, created_at, updated_at) VALUES
+                ('m1', 't1', 'user', 'Huge paste', '2025-03-01 10:00:01', '2025-03-01 10:00:01'),
+                ('m2', 't1', 'user', 'Short question', '2025-03-01 10:00:00', '2025-03-01 10:00:00');
+            "#,
+        )
+        .execute(db.pool())
+        .await
+        .unwrap();
+        let ids = |pending: Vec<PendingMessage>| pending.into_iter().map(|m| m.id).collect::<Vec<_>>();
+
+        let updated_at = pending_messages(db.pool(), "ollama::nomic", 10).await.unwrap()[0].updated_at.clone();
+        record_failure(db.pool(), "m1", "ollama::nomic", &updated_at, "input too long").await.unwrap();
+        assert_eq!(
+            ids(pending_messages(db.pool(), "ollama::nomic", 1).await.unwrap()),
+            ["m2"],
+            "failed messages come last"
+        );
+        for _ in 1..MAX_ATTEMPTS {
+            record_failure(db.pool(), "m1", "ollama::nomic", &updated_at, "input too long").await.unwrap();
+        }
+        assert_eq!(ids(pending_messages(db.pool(), "ollama::nomic", 10).await.unwrap()), ["m2"]);
+
+        sqlx::query("UPDATE chat_messages SET content = 'Trimmed paste' WHERE id = 'm1'")
+            .execute(db.pool())
+            .await
+            .unwrap();
+        assert_eq!(ids(pending_messages(db.pool(), "ollama::nomic", 10).await.unwrap()), ["m1", "m2"]);
+    }
+}
@@ -0,0 +1,54 @@
+//! Error types for backend database access
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+/// Database error
+#[derive(Error, Debug, Serialize, Deserialize)]
+pub enum DbError {
+    /// Query or connection failure
+    #[error("Database error: {message}")]
+    Query { message: String },
+
+    /// Row not found
+    #[error("{entity} not found: {id}")]
+    NotFound { entity: String, id: String },
+
+    /// Invalid input
+    #[error("Invalid input: {details}")]
+    InvalidInput { details: String },
+}
+
+/// Result type alias for database operations
+pub type DbResult<T> = Result<T, DbError>;
+
+impl DbError {
+    pub fn not_found(entity: impl Into<String>, id: impl Into<String>) -> Self {
+        Self::NotFound {
+            entity: entity.into(),
+            id: id.into(),
+        }
+    }
+
+    pub fn invalid_input(details: impl Into<String>) -> Self {
+        Self::InvalidInput {
+            details: details.into(),
+        }
+    }
+}
+
+impl From<sqlx::Error> for DbError {
+    fn from(err: sqlx::Error) -> Self {
+        Self::Query {
+            message: err.to_string(),
+        }
+    }
+}
+
+impl From<serde_json::Error> for DbError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::InvalidInput {
+            details: err.to_string(),
+        }
+    }
+}
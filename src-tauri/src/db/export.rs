@@ -0,0 +1,426 @@
+//! Export of threads and projects as Markdown, lossless JSON or a self-contained HTML page.
+//!
+//! The JSON archive holds every stored field of the exported rows, with JSON columns (attachments,
+//! thinking, tool calls, settings, ...) embedded as JSON; it is what `import` reads back. Counters
+//! derived from messages are exported for reference but recomputed on import.
+
+use super::messages::Message;
+use super::projects::{self, Project};
+use super::threads::{self, Thread};
+use super::DbResult;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::SqlitePool;
+use std::fmt::Write;
+
+/// `format` of our JSON archives
+pub const ARCHIVE_FORMAT: &str = "aye-mcp-export";
+
+/// Current archive version; older versions stay importable
+pub const ARCHIVE_VERSION: u32 = 1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+    Html,
+}
+
+/// Exported threads, with their project when a whole project was exported
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExportArchive {
+    pub format: String,
+    pub version: u32,
+    pub exported_at: String,
+    #[serde(default)]
+    pub project: Option<Project>,
+    pub threads: Vec<ExportedThread>,
+}
+
+/// A thread with all its messages
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ExportedThread {
+    #[serde(flatten)]
+    pub thread: Thread,
+    /// Oldest first; branches are kept through `parent_message_id`
+    #[serde(default)]
+    pub messages: Vec<Message>,
+}
+
+/// Reasoning steps of a message's thinking column, in order
+fn thinking_steps(message: &Message) -> Vec<&str> {
+    message.thinking["steps"]
+        .as_array()
+        .map(|steps| {
+            steps
+                .iter()
+                .filter_map(|step| step["content"].as_str())
+                .filter(|content| !content.trim().is_empty())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn json_list(value: &Value) -> &[Value] {
+    value.as_array().map_or(&[], Vec::as_slice)
+}
+
+async fn messages(pool: &SqlitePool, thread_id: &str) -> DbResult<Vec<Message>> {
+    sqlx::query("SELECT * FROM chat_messages WHERE thread_id = ? ORDER BY created_at, rowid")
+        .bind(thread_id)
+        .fetch_all(pool)
+        .await?
+        .iter()
+        .map(Message::from_row)
+        .collect()
+}
+
+/// A thread with all its messages
+pub async fn load_thread(pool: &SqlitePool, thread_id: &str) -> DbResult<ExportedThread> {
+    let thread = threads::get_thread(&mut *pool.acquire().await?, thread_id).await?;
+    Ok(ExportedThread {
+        thread,
+        messages: messages(pool, thread_id).await?,
+    })
+}
+
+/// Archive of one thread
+pub async fn thread_archive(pool: &SqlitePool, thread_id: &str) -> DbResult<ExportArchive> {
+    Ok(archive(None, vec![load_thread(pool, thread_id).await?]))
+}
+
+/// Archive of a project and all its threads, oldest thread first
+pub async fn project_archive(pool: &SqlitePool, project_id: &str) -> DbResult<ExportArchive> {
+    let project = projects::get_project(&mut *pool.acquire().await?, project_id).await?;
+
+    let rows = sqlx::query("SELECT * FROM chat_threads WHERE project_id = ? ORDER BY created_at, rowid")
+        .bind(project_id)
+        .fetch_all(pool)
+        .await?;
+    let mut threads = Vec::with_capacity(rows.len());
+    for row in &rows {
+        let thread = Thread::from_row(row)?;
+        let messages = messages(pool, &thread.id).await?;
+        threads.push(ExportedThread { thread, messages });
+    }
+    Ok(archive(Some(project), threads))
+}
+
+fn archive(project: Option<Project>, threads: Vec<ExportedThread>) -> ExportArchive {
+    ExportArchive {
+        format: ARCHIVE_FORMAT.to_string(),
+        version: ARCHIVE_VERSION,
+        exported_at: Utc::now().to_rfc3339(),
+        project,
+        threads,
+    }
+}
+
+/// An archive in the given format
+pub fn render(archive: &ExportArchive, format: ExportFormat) -> DbResult<String> {
+    match format {
+        ExportFormat::Json => Ok(serde_json::to_string_pretty(archive)?),
+        ExportFormat::Markdown => Ok(markdown(archive)),
+        ExportFormat::Html => Ok(html(archive)),
+    }
+}
+
+fn role_label(role: &str) -> &str {
+    match role {
+        "user" => "User",
+        "assistant" => "Assistant",
+        "system" => "System",
+        "tool" => "Tool",
+        other => other,
+    }
+}
+
+/// Pretty JSON of a tool call's arguments
+fn tool_arguments(call: &Value) -> String {
+    serde_json::to_string_pretty(&call["parameters"]).unwrap_or_default()
+}
+
+/// A Markdown code fence longer than any backtick run in `text`
+fn fence(text: &str) -> String {
+    let longest = text
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+fn markdown(archive: &ExportArchive) -> String {
+    let mut out = String::new();
+    let level = match &archive.project {
+        Some(project) => {
+            let _ = writeln!(out, "# {}\n", project.name);
+            if let Some(description) = project.description.as_deref().filter(|d| !d.trim().is_empty()) {
+                let _ = writeln!(out, "{}\n", description.trim());
+            }
+            2
+        }
+        None => 1,
+    };
+    for (i, thread) in archive.threads.iter().enumerate() {
+        if i > 0 {
+            out.push_str("---\n\n");
+        }
+        markdown_thread(&mut out, thread, level);
+    }
+    out
+}
+
+fn markdown_thread(out: &mut String, exported: &ExportedThread, level: usize) {
+    let thread = &exported.thread;
+    let heading = "#".repeat(level);
+    let _ = writeln!(out, "{} {}\n", heading, thread.title);
+    if let Some(description) = thread.description.as_deref().filter(|d| !d.trim().is_empty()) {
+        let _ = writeln!(out, "> {}\n", description.trim().replace('\n', "\n> "));
+    }
+    if let Some(model) = thread.model() {
+        let _ = writeln!(out, "- Model: `{}`", model);
+    }
+    let _ = writeln!(out, "- Created: {}\n", thread.created_at);
+
+    for message in &exported.messages {
+        let _ = write!(out, "{}# {}", heading, role_label(&message.role));
+        if let Some(model) = message.model() {
+            let _ = write!(out, " ({})", model);
+        }
+        let _ = writeln!(out, " · {}\n", message.created_at);
+
+        let steps = thinking_steps(message);
+        if !steps.is_empty() {
+            let _ = writeln!(out, "<details>\n<summary>Thinking</summary>\n\n{}\n\n</details>\n", steps.join("\n\n"));
+        }
+        if !message.content.trim().is_empty() {
+            let _ = writeln!(out, "{}\n", message.content.trim_end());
+        }
+        for call in json_list(&message.tool_calls) {
+            let arguments = tool_arguments(call);
+            let ticks = fence(&arguments);
+            let _ = writeln!(
+                out,
+                "**Tool call** `{}`\n\n{}json\n{}\n{}\n",
+                call["name"].as_str().unwrap_or("tool"),
+                ticks,
+                arguments,
+                ticks
+            );
+            if let Some(result) = call.get("result").filter(|result| !result.is_null()) {
+                let result = match result {
+                    Value::String(text) => text.clone(),
+                    other => serde_json::to_string_pretty(other).unwrap_or_default(),
+                };
+                let ticks = fence(&result);
+                let _ = writeln!(out, "Result:\n\n{}\n{}\n{}\n", ticks, result, ticks);
+            }
+        }
+        let names: Vec<&str> = json_list(&message.attachments)
+            .iter()
+            .filter_map(|attachment| attachment["name"].as_str())
+            .collect();
+        if !names.is_empty() {
+            let _ = writeln!(out, "*Attachments: {}*\n", names.join(", "));
+        }
+    }
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+const HTML_STYLE: &str = r#"
+body { font: 15px/1.55 system-ui, -apple-system, sans-serif; max-width: 52rem; margin: 2rem auto; padding: 0 1rem; color: #1f2328; background: #fff; }
+header, article { margin-bottom: 3rem; }
+.meta { color: #656d76; font-size: 13px; }
+.message { border: 1px solid #d0d7de; border-radius: 8px; padding: .75rem 1rem; margin: 1rem 0; }
+.message.user { background: #f6f8fa; }
+.message.system, .message.tool { background: #fff8c5; }
+.role { font-weight: 600; margin-bottom: .5rem; }
+.role time, .role .model { font-weight: 400; color: #656d76; font-size: 13px; margin-left: .5rem; }
+.content { white-space: pre-wrap; word-wrap: break-word; }
+pre { background: #f6f8fa; border-radius: 6px; padding: .5rem .75rem; overflow-x: auto; white-space: pre-wrap; }
+details.thinking { color: #656d76; margin-bottom: .5rem; }
+.attachments img { max-width: 100%; border-radius: 6px; }
+@media (prefers-color-scheme: dark) {
+  body { color: #e6edf3; background: #0d1117; }
+  .message { border-color: #30363d; }
+  .message.user, pre { background: #161b22; }
+  .message.system, .message.tool { background: #272115; }
+}
+"#;
+
+fn html(archive: &ExportArchive) -> String {
+    let title = match (&archive.project, archive.threads.first()) {
+        (Some(project), _) => project.name.clone(),
+        (None, Some(exported)) => exported.thread.title.clone(),
+        (None, None) => "Export".to_string(),
+    };
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+         <meta name=\"viewport\" content=\"width=device-width, initial-scale=1\">\n\
+         <title>{}</title>\n<style>{}</style>\n</head>\n<body>\n",
+        escape(&title),
+        HTML_STYLE
+    );
+    if let Some(project) = &archive.project {
+        let _ = writeln!(out, "<header>\n<h1>{}</h1>", escape(&project.name));
+        if let Some(description) = project.description.as_deref().filter(|d| !d.trim().is_empty()) {
+            let _ = writeln!(out, "<p>{}</p>", escape(description.trim()));
+        }
+        let _ = writeln!(out, "</header>");
+    }
+    for thread in &archive.threads {
+        html_thread(&mut out, thread, archive.project.is_some());
+    }
+    let _ = writeln!(
+        out,
+        "<footer class=\"meta\">Exported {}</footer>\n</body>\n</html>",
+        escape(&archive.exported_at)
+    );
+    out
+}
+
+fn html_thread(out: &mut String, exported: &ExportedThread, nested: bool) {
+    let thread = &exported.thread;
+    let heading = if nested { "h2" } else { "h1" };
+    let _ = writeln!(out, "<article class=\"thread\">\n<{0}>{1}</{0}>", heading, escape(&thread.title));
+    let mut meta = vec![format!("Created {}", escape(&thread.created_at))];
+    if let Some(model) = thread.model() {
+        meta.push(escape(&model));
+    }
+    let _ = writeln!(out, "<p class=\"meta\">{}</p>", meta.join(" · "));
+    if let Some(description) = thread.description.as_deref().filter(|d| !d.trim().is_empty()) {
+        let _ = writeln!(out, "<p>{}</p>", escape(description.trim()));
+    }
+
+    for message in &exported.messages {
+        let _ = write!(
+            out,
+            "<section class=\"message {}\">\n<div class=\"role\">{}",
+            escape(&message.role),
+            escape(role_label(&message.role))
+        );
+        if let Some(model) = message.model() {
+            let _ = write!(out, "<span class=\"model\">{}</span>", escape(&model));
+        }
+        let _ = writeln!(out, "<time>{}</time></div>", escape(&message.created_at));
+
+        let steps = thinking_steps(message);
+        if !steps.is_empty() {
+            let _ = writeln!(
+                out,
+                "<details class=\"thinking\"><summary>Thinking</summary><div class=\"content\">{}</div></details>",
+                escape(&steps.join("\n\n"))
+            );
+        }
+        if !message.content.trim().is_empty() {
+            let _ = writeln!(out, "<div class=\"content\">{}</div>", escape(message.content.trim_end()));
+        }
+        for call in json_list(&message.tool_calls) {
+            let _ = writeln!(
+                out,
+                "<div class=\"tool-call\">Tool call <code>{}</code><pre>{}</pre></div>",
+                escape(call["name"].as_str().unwrap_or("tool")),
+                escape(&tool_arguments(call))
+            );
+        }
+        let attachments = json_list(&message.attachments);
+        if !attachments.is_empty() {
+            let _ = writeln!(out, "<ul class=\"attachments\">");
+            for attachment in attachments {
+                let name = escape(attachment["name"].as_str().unwrap_or("attachment"));
+                // Inline images are already data URLs, so they keep the page self-contained
+                let image = ["url", "preview"]
+                    .iter()
+                    .filter_map(|key| attachment[*key].as_str())
+                    .find(|url| url.starts_with("data:image/"));
+                match image {
+                    Some(url) => {
+                        let _ = writeln!(out, "<li><img src=\"{}\" alt=\"{}\"></li>", escape(url), name);
+                    }
+                    None => {
+                        let _ = writeln!(out, "<li>{}</li>", name);
+                    }
+                }
+            }
+            let _ = writeln!(out, "</ul>");
+        }
+        let _ = writeln!(out, "</section>");
+    }
+    let _ = writeln!(out, "</article>");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::{Database, DbError};
+
+    #[tokio::test]
+    async fn test_export_formats() {
+        let db = Database::in_memory().await;
+        sqlx::raw_sql(
+            r#"
+            INSERT INTO projects (id, name, description) VALUES ('p1', 'Weather', 'Forecast bot');
+            INSERT INTO chat_threads (id, project_id, title, model_provider, model_name)
+            VALUES ('t1', 'p1', 'Paris <today>', 'openai', 'gpt-4o');
+            INSERT INTO chat_messages (id, thread_id, role, content, attachments, created_at) VALUES
+                ('m1', 't1', 'user', 'Weather in Paris?', '[{"id":"a1","type":"file","name":"notes.txt","size":3}]', '2025-03-01 10:00:00');
+            INSERT INTO chat_messages (id, thread_id, role, content, thinking, tool_calls, parent_message_id,
+                                       model_used, provider_used, prompt_tokens, created_at) VALUES
+                ('m2', 't1', 'assistant', 'Sunny & 22°C', '{"steps":[{"content":"Look it up."}]}',
+                 '[{"id":"c1","name":"get_weather","parameters":{"city":"Paris"},"status":"complete"}]',
+                 'm1', 'gpt-4o', 'openai', 12, '2025-03-01 10:00:05');
+            "#,
+        )
+        .execute(db.pool())
+        .await
+        .unwrap();
+
+        let archive = project_archive(db.pool(), "p1").await.unwrap();
+        let json: Value = serde_json::from_str(&render(&archive, ExportFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["format"], ARCHIVE_FORMAT);
+        assert_eq!(json["project"]["name"], "Weather");
+        let messages = &json["threads"][0]["messages"];
+        assert_eq!(messages[0]["attachments"][0]["name"], "notes.txt");
+        assert_eq!(messages[1]["parent_message_id"], "m1");
+        assert_eq!(messages[1]["tool_calls"][0]["parameters"]["city"], "Paris");
+        assert_eq!(messages[1]["thinking"]["steps"][0]["content"], "Look it up.");
+        assert_eq!(messages[1]["prompt_tokens"], 12);
+
+        let markdown = render(&archive, ExportFormat::Markdown).unwrap();
+        assert!(markdown.starts_with("# Weather\n"));
+        assert!(markdown.contains("## Paris <today>"));
+        assert!(markdown.contains("### Assistant (openai::gpt-4o) · 2025-03-01 10:00:05"));
+        assert!(markdown.contains("<summary>Thinking</summary>\n\nLook it up."));
+        assert!(markdown.contains("**Tool call** `get_weather`\n\n```json\n{\n  \"city\": \"Paris\"\n}\n```"));
+        assert!(markdown.contains("*Attachments: notes.txt*"));
+
+        let html = render(&thread_archive(db.pool(), "t1").await.unwrap(), ExportFormat::Html).unwrap();
+        assert!(html.contains("<title>Paris &lt;today&gt;</title>"));
+        assert!(html.contains("<div class=\"content\">Sunny &amp; 22°C</div>"));
+        assert!(!html.contains("<today>"));
+
+        assert!(matches!(
+            thread_archive(db.pool(), "missing").await,
+            Err(DbError::NotFound { .. })
+        ));
+    }
+}
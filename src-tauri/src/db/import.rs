@@ -0,0 +1,1027 @@
+//! Import of conversations from our JSON export and from the official ChatGPT and Claude exports.
+//!
+//! Everything is mapped onto `ExportedThread`s first and inserted in one transaction, under new
+//! ids so importing the same file twice duplicates rather than collides. Parent links between
+//! messages, and so regenerated branches, are kept.
+
+use super::export::{ExportArchive, ExportedThread, ARCHIVE_FORMAT, ARCHIVE_VERSION};
+use super::messages::Message;
+use super::projects::{Project, DEFAULT_PROJECT};
+use super::rows::column_text;
+use super::threads::Thread;
+use super::{DbError, DbResult};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::{Sqlite, SqlitePool, Transaction};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::Read;
+use std::path::Path;
+
+/// Conversation file inside ChatGPT and Claude export archives
+const CONVERSATIONS_FILE: &str = "conversations.json";
+
+/// Largest export file read, JSON or `.zip`
+const MAX_FILE_SIZE: u64 = 512 * 1024 * 1024;
+
+/// Largest `conversations.json` read out of an archive, uncompressed
+const MAX_ENTRY_SIZE: u64 = 1024 * 1024 * 1024;
+
+/// Same format as SQLite's `CURRENT_TIMESTAMP`
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ImportSource {
+    /// Our own JSON export
+    Aye,
+    ChatGpt,
+    Claude,
+}
+
+/// Threads read from an export, ready to insert
+#[derive(Debug, Clone)]
+pub struct Conversations {
+    pub source: ImportSource,
+    /// The exported project, for our archives of a whole project
+    pub project: Option<Project>,
+    pub threads: Vec<ExportedThread>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportSummary {
+    pub source: ImportSource,
+    /// Project the threads were imported into
+    pub project_id: Option<String>,
+    /// New ids of the imported threads, in file order
+    pub thread_ids: Vec<String>,
+    pub messages: usize,
+}
+
+/// Read an export: our JSON archive, a `conversations.json`, or the `.zip` archive holding it
+pub async fn read_file(path: &Path) -> DbResult<Value> {
+    let path = path.to_path_buf();
+    tokio::task::spawn_blocking(move || read_file_limited(&path, MAX_FILE_SIZE, MAX_ENTRY_SIZE))
+        .await
+        .map_err(|e| DbError::Query {
+            message: format!("Import failed: {}", e),
+        })?
+}
+
+/// `read_file` with the file, and the archive entry, bounded to `max_file` and `max_entry` bytes
+fn read_file_limited(path: &Path, max_file: u64, max_entry: u64) -> DbResult<Value> {
+    let invalid = |e: &dyn std::fmt::Display| DbError::invalid_input(format!("{}: {}", path.display(), e));
+    let too_large = |limit: u64| invalid(&format!("larger than {} bytes", limit));
+    let file = std::fs::File::open(path).map_err(|e| invalid(&e))?;
+    if file.metadata().map_err(|e| invalid(&e))?.len() > max_file {
+        return Err(too_large(max_file));
+    }
+
+    if path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("zip")) {
+        let mut archive = zip::ZipArchive::new(file).map_err(|e| invalid(&e))?;
+        let name = archive
+            .file_names()
+            .filter(|name| name.rsplit('/').next() == Some(CONVERSATIONS_FILE))
+            .min_by_key(|name| name.len())
+            .map(str::to_string)
+            .ok_or_else(|| invalid(&format!("no {} in the archive", CONVERSATIONS_FILE)))?;
+        let entry = archive.by_name(&name).map_err(|e| invalid(&e))?;
+        if entry.size() > max_entry {
+            return Err(too_large(max_entry));
+        }
+        // The size in the archive header is not trusted: reading stops past the limit
+        let mut text = String::new();
+        entry
+            .take(max_entry + 1)
+            .read_to_string(&mut text)
+            .map_err(|e| invalid(&e))?;
+        if text.len() as u64 > max_entry {
+            return Err(too_large(max_entry));
+        }
+        return Ok(serde_json::from_str(&text)?);
+    }
+    Ok(serde_json::from_reader(std::io::BufReader::new(file))?)
+}
+
+/// Recognize an export by its shape and map it onto threads
+pub fn parse(value: Value) -> DbResult<Conversations> {
+    if value.get("format").and_then(Value::as_str) == Some(ARCHIVE_FORMAT) {
+        let archive: ExportArchive = serde_json::from_value(value)?;
+        if archive.version > ARCHIVE_VERSION {
+            return Err(DbError::invalid_input(format!(
+                "Export version {} is newer than this app supports ({})",
+                archive.version, ARCHIVE_VERSION
+            )));
+        }
+        return Ok(Conversations {
+            source: ImportSource::Aye,
+            project: archive.project,
+            threads: archive.threads,
+        });
+    }
+
+    let Value::Array(conversations) = value else {
+        return Err(DbError::invalid_input("Not a recognized conversation export"));
+    };
+    let source = match conversations.first() {
+        Some(first) if first.get("mapping").is_some() => ImportSource::ChatGpt,
+        Some(first) if first.get("chat_messages").is_some() => ImportSource::Claude,
+        Some(_) => return Err(DbError::invalid_input("Not a recognized conversation export")),
+        None => ImportSource::ChatGpt,
+    };
+    let threads = conversations
+        .into_iter()
+        .map(|conversation| -> DbResult<ExportedThread> {
+            match source {
+                ImportSource::Claude => Ok(claude_thread(serde_json::from_value(conversation)?)),
+                _ => Ok(chatgpt_thread(serde_json::from_value(conversation)?)),
+            }
+        })
+        .collect::<DbResult<Vec<_>>>()?;
+    Ok(Conversations {
+        source,
+        project: None,
+        threads,
+    })
+}
+
+/// Import an export file into `project_id`; see `import`
+pub async fn import_file(pool: &SqlitePool, path: &Path, project_id: Option<&str>) -> DbResult<ImportSummary> {
+    import(pool, parse(read_file(path).await?)?, project_id).await
+}
+
+/// Insert conversations under new ids, all or nothing.
+///
+/// A thread's message count, last message time and token and cost totals are recomputed from
+/// the messages imported with it. Threads go into `project_id` if given; otherwise an exported project is recreated, and
+/// threads without one go into the default project.
+pub async fn import(pool: &SqlitePool, conversations: Conversations, project_id: Option<&str>) -> DbResult<ImportSummary> {
+    let mut tx = pool.begin().await?;
+
+    let project_id = match (project_id, &conversations.project) {
+        (Some(id), _) => {
+            let exists: Option<String> = sqlx::query_scalar("SELECT id FROM projects WHERE id = ?")
+                .bind(id)
+                .fetch_optional(&mut *tx)
+                .await?;
+            Some(exists.ok_or_else(|| DbError::not_found("Project", id))?)
+        }
+        (None, Some(project)) => Some(insert_project(&mut tx, project).await?),
+        (None, None) => {
+            sqlx::query_scalar::<_, String>("SELECT id FROM projects WHERE id = ?")
+                .bind(DEFAULT_PROJECT)
+                .fetch_optional(&mut *tx)
+                .await?
+        }
+    };
+
+    let mut thread_ids: HashMap<&str, String> = HashMap::new();
+    let mut summary = ImportSummary {
+        source: conversations.source,
+        project_id: project_id.clone(),
+        thread_ids: Vec::with_capacity(conversations.threads.len()),
+        messages: 0,
+    };
+    for ExportedThread { thread, messages } in &conversations.threads {
+        let id = uuid::Uuid::new_v4().to_string();
+        // Only threads already imported can be linked; anything else was not part of the export
+        let parent_thread_id = thread
+            .parent_thread_id
+            .as_deref()
+            .and_then(|parent| thread_ids.get(parent).cloned());
+        insert_thread(&mut tx, &id, project_id.as_deref(), parent_thread_id.as_deref(), thread).await?;
+
+        let mut message_ids: HashMap<&str, String> = HashMap::new();
+        for message in parent_first(messages) {
+            let message_id = uuid::Uuid::new_v4().to_string();
+            let parent_id = message
+                .parent_message_id
+                .as_deref()
+                .and_then(|parent| message_ids.get(parent).cloned());
+            insert_message(&mut tx, &message_id, &id, parent_id.as_deref(), message).await?;
+            message_ids.insert(&message.id, message_id);
+        }
+        // Messages are inserted parent first, so the last one is not necessarily the latest
+        let active_message_id = thread
+            .active_message_id
+            .as_deref()
+            .and_then(|active| message_ids.get(active));
+        sqlx::query(
+            r#"
+            UPDATE chat_threads
+            SET (last_message_at, total_tokens, total_cost) = (
+                    SELECT MAX(created_at),
+                           COALESCE(SUM(COALESCE(prompt_tokens, 0) + COALESCE(completion_tokens, 0)), 0),
+                           COALESCE(SUM(cost), 0.0)
+                    FROM chat_messages
+                    WHERE thread_id = ?1
+                ),
+                active_message_id = COALESCE(?2, active_message_id)
+            WHERE id = ?1
+            "#,
+        )
+        .bind(&id)
+        .bind(active_message_id)
+        .execute(&mut *tx)
+        .await?;
+
+        summary.messages += messages.len();
+        thread_ids.insert(&thread.id, id.clone());
+        summary.thread_ids.push(id);
+    }
+
+    tx.commit().await?;
+    Ok(summary)
+}
+
+/// Messages ordered so each comes after its parent, otherwise in their original order
+fn parent_first(messages: &[Message]) -> Vec<&Message> {
+    let index: HashMap<&str, usize> = messages.iter().enumerate().map(|(i, m)| (m.id.as_str(), i)).collect();
+    let mut placed = vec![false; messages.len()];
+    let mut order = Vec::with_capacity(messages.len());
+    for start in 0..messages.len() {
+        // Walk up to the first placed ancestor; a cycle ends the walk too
+        let mut chain = Vec::new();
+        let mut seen = HashSet::new();
+        let mut next = Some(start);
+        while let Some(i) = next {
+            if placed[i] || !seen.insert(i) {
+                break;
+            }
+            chain.push(i);
+            next = messages[i]
+                .parent_message_id
+                .as_deref()
+                .and_then(|parent| index.get(parent).copied());
+        }
+        for i in chain.into_iter().rev() {
+            placed[i] = true;
+            order.push(&messages[i]);
+        }
+    }
+    order
+}
+
+async fn insert_project(tx: &mut Transaction<'_, Sqlite>, project: &Project) -> DbResult<String> {
+    let id = uuid::Uuid::new_v4().to_string();
+    sqlx::query(
+        r#"
+        INSERT INTO projects (id, name, description, system_prompt, default_model_provider, default_model_name,
+                              tool_presets, settings, is_archived, color, icon, priority, tags, folder_path,
+                              created_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, COALESCE(?10, '#3b82f6'), COALESCE(?11, 'folder'),
+                COALESCE(?12, 1), ?13, ?14, COALESCE(NULLIF(?15, ''), CURRENT_TIMESTAMP))
+        "#,
+    )
+    .bind(&id)
+    .bind(if project.name.trim().is_empty() { "Imported" } else { project.name.as_str() })
+    .bind(&project.description)
+    .bind(&project.system_prompt)
+    .bind(&project.default_model_provider)
+    .bind(&project.default_model_name)
+    .bind(column_text(&project.tool_presets, "[]"))
+    .bind(column_text(&project.settings, "{}"))
+    .bind(project.is_archived)
+    .bind(&project.color)
+    .bind(&project.icon)
+    .bind(project.priority)
+    .bind(column_text(&project.tags, "[]"))
+    .bind(&project.folder_path)
+    .bind(&project.created_at)
+    .execute(&mut **tx)
+    .await?;
+    Ok(id)
+}
+
+async fn insert_thread(
+    tx: &mut Transaction<'_, Sqlite>,
+    id: &str,
+    project_id: Option<&str>,
+    parent_thread_id: Option<&str>,
+    thread: &Thread,
+) -> DbResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO chat_threads (id, project_id, title, description, is_archived, is_pinned, model_provider,
+                                  model_name, system_prompt, tool_presets, settings, tags, status, thread_type,
+                                  priority, parent_thread_id, created_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, COALESCE(?13, 'active'), COALESCE(?14, 'chat'),
+                COALESCE(?15, 1), ?16, COALESCE(NULLIF(?17, ''), CURRENT_TIMESTAMP))
+        "#,
+    )
+    .bind(id)
+    .bind(project_id)
+    .bind(if thread.title.trim().is_empty() { "Untitled" } else { thread.title.as_str() })
+    .bind(&thread.description)
+    .bind(thread.is_archived)
+    .bind(thread.is_pinned)
+    .bind(&thread.model_provider)
+    .bind(&thread.model_name)
+    .bind(&thread.system_prompt)
+    .bind(column_text(&thread.tool_presets, "[]"))
+    .bind(column_text(&thread.settings, "{}"))
+    .bind(column_text(&thread.tags, "[]"))
+    .bind(&thread.status)
+    .bind(&thread.thread_type)
+    .bind(thread.priority)
+    .bind(parent_thread_id)
+    .bind(&thread.created_at)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+async fn insert_message(
+    tx: &mut Transaction<'_, Sqlite>,
+    id: &str,
+    thread_id: &str,
+    parent_message_id: Option<&str>,
+    message: &Message,
+) -> DbResult<()> {
+    sqlx::query(
+        r#"
+        INSERT INTO chat_messages (id, thread_id, role, content, attachments, thinking, tool_calls, metadata,
+                                   parent_message_id, response_time, model_used, provider_used, prompt_tokens,
+                                   completion_tokens, reasoning_tokens, cached_tokens, cost, is_edited,
+                                   edit_history, created_at, updated_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19,
+                COALESCE(NULLIF(?20, ''), CURRENT_TIMESTAMP),
+                COALESCE(NULLIF(?21, ''), NULLIF(?20, ''), CURRENT_TIMESTAMP))
+        "#,
+    )
+    .bind(id)
+    .bind(thread_id)
+    .bind(&message.role)
+    .bind(&message.content)
+    .bind(column_text(&message.attachments, "[]"))
+    .bind(column_text(&message.thinking, "{}"))
+    .bind(column_text(&message.tool_calls, "[]"))
+    .bind(column_text(&message.metadata, "{}"))
+    .bind(parent_message_id)
+    .bind(message.response_time)
+    .bind(&message.model_used)
+    .bind(&message.provider_used)
+    .bind(message.prompt_tokens)
+    .bind(message.completion_tokens)
+    .bind(message.reasoning_tokens)
+    .bind(message.cached_tokens)
+    .bind(message.cost)
+    .bind(message.is_edited)
+    .bind(column_text(&message.edit_history, "[]"))
+    .bind(&message.created_at)
+    .bind(&message.updated_at)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+fn unix_timestamp(seconds: Option<f64>) -> Option<String> {
+    DateTime::from_timestamp(seconds? as i64, 0).map(|time| time.format(TIMESTAMP_FORMAT).to_string())
+}
+
+fn rfc3339_timestamp(text: Option<&str>) -> Option<String> {
+    DateTime::parse_from_rfc3339(text?)
+        .ok()
+        .map(|time| time.with_timezone(&Utc).format(TIMESTAMP_FORMAT).to_string())
+}
+
+/// `ThinkingProcess` JSON of imported reasoning
+fn thinking_json(message_id: &str, steps: &[String], at: &str) -> Value {
+    let steps: Vec<Value> = steps
+        .iter()
+        .enumerate()
+        .map(|(i, content)| {
+            json!({
+                "id": format!("{}-{}", message_id, i),
+                "content": content,
+                "timestamp": at,
+                "type": "reasoning",
+            })
+        })
+        .collect();
+    json!({
+        "id": message_id,
+        "steps": steps,
+        "status": "complete",
+        "startTime": at,
+    })
+}
+
+/// `ChatAttachment` JSON of an imported file
+fn attachment_json(id: String, kind: &str, name: &str, size: u64, mime_type: &str, content: Option<&str>) -> Value {
+    let mut attachment = json!({
+        "id": id,
+        "type": kind,
+        "name": name,
+        "size": size,
+        "mimeType": mime_type,
+        "status": "ready",
+    });
+    if let Some(content) = content {
+        attachment["content"] = json!(content);
+    }
+    attachment
+}
+
+fn import_settings(source: &str, id: Option<&str>) -> Value {
+    json!({ "import": { "source": source, "id": id } })
+}
+
+// ChatGPT: `conversations.json` is a list of conversations, each a tree of nodes (`mapping`)
+// linked by `parent`/`children`; regenerated answers are sibling nodes.
+
+#[derive(Deserialize)]
+struct ChatGptConversation {
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    conversation_id: Option<String>,
+    #[serde(default)]
+    title: Option<String>,
+    #[serde(default)]
+    create_time: Option<f64>,
+    #[serde(default)]
+    default_model_slug: Option<String>,
+    #[serde(default)]
+    mapping: HashMap<String, ChatGptNode>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptNode {
+    #[serde(default)]
+    message: Option<ChatGptMessage>,
+    #[serde(default)]
+    parent: Option<String>,
+    #[serde(default)]
+    children: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptMessage {
+    author: ChatGptAuthor,
+    #[serde(default)]
+    create_time: Option<f64>,
+    #[serde(default)]
+    update_time: Option<f64>,
+    #[serde(default)]
+    content: Value,
+    #[serde(default)]
+    metadata: Value,
+    #[serde(default)]
+    recipient: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ChatGptAuthor {
+    role: String,
+    #[serde(default)]
+    name: Option<String>,
+}
+
+fn chatgpt_thread(conversation: ChatGptConversation) -> ExportedThread {
+    let mapping = &conversation.mapping;
+    let created_at = unix_timestamp(conversation.create_time).unwrap_or_else(|| Utc::now().format(TIMESTAMP_FORMAT).to_string());
+
+    // Breadth first from the roots, so parents come first and siblings keep their order
+    let mut roots: Vec<&String> = mapping
+        .iter()
+        .filter(|(_, node)| node.parent.as_ref().is_none_or(|parent| !mapping.contains_key(parent)))
+        .map(|(id, _)| id)
+        .collect();
+    roots.sort();
+    let mut queue: VecDeque<&String> = roots.into();
+    let mut visited = HashSet::new();
+    let mut kept = HashSet::new();
+    let mut messages = Vec::new();
+    while let Some(id) = queue.pop_front() {
+        if !visited.insert(id) {
+            continue;
+        }
+        let node = &mapping[id];
+        queue.extend(node.children.iter().filter(|child| mapping.contains_key(*child)));
+        let Some(mut message) = node.message.as_ref().and_then(|m| chatgpt_message(id, m, &created_at)) else {
+            continue;
+        };
+
+        // Link to the closest imported ancestor, picking up reasoning from the nodes in between
+        let mut thoughts = Vec::new();
+        let mut parent = node.parent.as_ref();
+        for _ in 0..mapping.len() {
+            let Some(id) = parent.filter(|id| !kept.contains(id)) else {
+                break;
+            };
+            let ancestor = mapping.get(id);
+            if let Some(ancestor) = ancestor.and_then(|node| node.message.as_ref()) {
+                thoughts.splice(0..0, chatgpt_thoughts(ancestor));
+            }
+            parent = ancestor.and_then(|node| node.parent.as_ref());
+        }
+        message.parent_message_id = parent.filter(|id| kept.contains(id)).cloned();
+        if message.role == "assistant" && !thoughts.is_empty() {
+            message.thinking = thinking_json(id, &thoughts, &message.created_at);
+        }
+        kept.insert(id);
+        messages.push(message);
+    }
+
+    let id = conversation.conversation_id.or(conversation.id);
+    ExportedThread {
+        thread: Thread {
+            title: conversation
+                .title
+                .filter(|title| !title.trim().is_empty())
+                .unwrap_or_else(|| "Untitled conversation".to_string()),
+            model_provider: "openai".to_string(),
+            model_name: conversation.default_model_slug.unwrap_or_default(),
+            settings: import_settings("chatgpt", id.as_deref()),
+            id: id.unwrap_or_default(),
+            created_at,
+            ..Thread::default()
+        },
+        messages,
+    }
+}
+
+/// Reasoning shown in a `thoughts` node
+fn chatgpt_thoughts(message: &ChatGptMessage) -> Vec<String> {
+    if message.content["content_type"] != "thoughts" {
+        return Vec::new();
+    }
+    message.content["thoughts"]
+        .as_array()
+        .map(|thoughts| {
+            thoughts
+                .iter()
+                .filter_map(|thought| {
+                    let content = thought["content"].as_str().filter(|c| !c.trim().is_empty())?;
+                    Some(match thought["summary"].as_str().filter(|s| !s.trim().is_empty()) {
+                        Some(summary) => format!("**{}**\n\n{}", summary, content),
+                        None => content.to_string(),
+                    })
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// A visible message, or `None` for hidden, empty and reasoning-only nodes
+fn chatgpt_message(id: &str, message: &ChatGptMessage, fallback_time: &str) -> Option<Message> {
+    if message.metadata["is_visually_hidden_from_conversation"] == true {
+        return None;
+    }
+    let role = match message.author.role.as_str() {
+        role @ ("user" | "assistant" | "system" | "tool") => role,
+        _ => return None,
+    };
+    let content = &message.content;
+    let mut attachments = Vec::new();
+    let mut text = match content["content_type"].as_str().unwrap_or("text") {
+        "thoughts" | "reasoning_recap" => return None,
+        "code" => {
+            let code = content["text"].as_str().unwrap_or_default();
+            match message.recipient.as_deref() {
+                Some("all") | None => format!("```{}\n{}\n```", content["language"].as_str().unwrap_or(""), code),
+                Some(_) => code.to_string(),
+            }
+        }
+        _ => {
+            let mut parts = Vec::new();
+            for part in content["parts"].as_array().into_iter().flatten() {
+                match part {
+                    Value::String(part) if !part.is_empty() => parts.push(part.as_str()),
+                    Value::Object(_) if part["content_type"] == "image_asset_pointer" => {
+                        let pointer = part["asset_pointer"].as_str().unwrap_or("image");
+                        attachments.push(attachment_json(
+                            format!("{}-{}", id, attachments.len()),
+                            "image",
+                            pointer.rsplit('/').next().unwrap_or(pointer),
+                            part["size_bytes"].as_u64().unwrap_or(0),
+                            "image/*",
+                            None,
+                        ));
+                    }
+                    _ => {}
+                }
+            }
+            match parts.join("\n") {
+                text if text.is_empty() => content["text"].as_str().unwrap_or_default().to_string(),
+                text => text,
+            }
+        }
+    };
+
+    let created_at = unix_timestamp(message.create_time).unwrap_or_else(|| fallback_time.to_string());
+    // An assistant message addressed to a tool is the call itself
+    let tool_calls = match message.recipient.as_deref() {
+        Some(recipient) if role == "assistant" && recipient != "all" => {
+            let parameters = serde_json::from_str::<Value>(&text)
+                .ok()
+                .filter(Value::is_object)
+                .unwrap_or_else(|| json!({ "input": text }));
+            text.clear();
+            json!([{
+                "id": id,
+                "name": recipient,
+                "parameters": parameters,
+                "status": "complete",
+                "startTime": created_at,
+            }])
+        }
+        _ => json!([]),
+    };
+    if text.trim().is_empty() && attachments.is_empty() && tool_calls.as_array().is_some_and(Vec::is_empty) {
+        return None;
+    }
+
+    let metadata = match (role, message.author.name.as_deref()) {
+        ("tool", Some(name)) => json!({ "tool_name": name }),
+        _ => json!({}),
+    };
+    let model_used = message.metadata["model_slug"].as_str().map(str::to_string);
+    Some(Message {
+        id: id.to_string(),
+        role: role.to_string(),
+        content: text,
+        attachments: Value::Array(attachments),
+        tool_calls,
+        metadata,
+        provider_used: model_used.as_ref().map(|_| "openai".to_string()),
+        model_used,
+        updated_at: unix_timestamp(message.update_time).unwrap_or_else(|| created_at.clone()),
+        created_at,
+        ..Message::default()
+    })
+}
+
+// Claude: `conversations.json` is a list of conversations with their messages in order;
+// newer exports link each message to its parent, which keeps edited branches.
+
+#[derive(Deserialize)]
+struct ClaudeConversation {
+    #[serde(default)]
+    uuid: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    summary: Option<String>,
+    #[serde(default)]
+    created_at: Option<String>,
+    #[serde(default)]
+    chat_messages: Vec<ClaudeMessage>,
+}
+
+#[derive(Deserialize)]
+struct ClaudeMessage {
+    uuid: String,
+    sender: String,
+    #[serde(default)]
+    text: String,
+    #[serde(default)]
+    content: Vec<Value>,
+    #[serde(default)]
+    created_at: Option<String>,
+    #[serde(default)]
+    updated_at: Option<String>,
+    #[serde(default)]
+    attachments: Vec<ClaudeAttachment>,
+    #[serde(default)]
+    files: Vec<Value>,
+    #[serde(default)]
+    parent_message_uuid: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ClaudeAttachment {
+    #[serde(default)]
+    file_name: String,
+    #[serde(default)]
+    file_size: Option<u64>,
+    #[serde(default)]
+    file_type: Option<String>,
+    #[serde(default)]
+    extracted_content: Option<String>,
+}
+
+fn claude_thread(conversation: ClaudeConversation) -> ExportedThread {
+    let created_at = rfc3339_timestamp(conversation.created_at.as_deref())
+        .unwrap_or_else(|| Utc::now().format(TIMESTAMP_FORMAT).to_string());
+
+    // Parents of skipped messages, so their children link past them
+    let mut skipped: HashMap<&str, Option<String>> = HashMap::new();
+    let mut kept: HashSet<&str> = HashSet::new();
+    let mut previous: Option<&str> = None;
+    let mut messages = Vec::new();
+    for message in &conversation.chat_messages {
+        let parent = match message.parent_message_uuid.as_deref() {
+            Some(parent) => Some(parent),
+            None => previous,
+        };
+        previous = Some(message.uuid.as_str());
+        let parent = parent.and_then(|parent| {
+            if kept.contains(parent) {
+                Some(parent.to_string())
+            } else {
+                skipped.get(parent).cloned().flatten()
+            }
+        });
+        match claude_message(message, &created_at) {
+            Some(mut converted) => {
+                converted.parent_message_id = parent;
+                kept.insert(&message.uuid);
+                messages.push(converted);
+            }
+            None => {
+                skipped.insert(&message.uuid, parent);
+            }
+        }
+    }
+
+    ExportedThread {
+        thread: Thread {
+            title: conversation
+                .name
+                .filter(|name| !name.trim().is_empty())
+                .unwrap_or_else(|| "Untitled conversation".to_string()),
+            description: conversation.summary.filter(|summary| !summary.trim().is_empty()),
+            model_provider: "anthropic".to_string(),
+            settings: import_settings("claude", conversation.uuid.as_deref()),
+            id: conversation.uuid.unwrap_or_default(),
+            created_at,
+            ..Thread::default()
+        },
+        messages,
+    }
+}
+
+fn claude_message(message: &ClaudeMessage, fallback_time: &str) -> Option<Message> {
+    let role = match message.sender.as_str() {
+        "human" => "user",
+        "assistant" => "assistant",
+        _ => return None,
+    };
+    let created_at = rfc3339_timestamp(message.created_at.as_deref()).unwrap_or_else(|| fallback_time.to_string());
+
+    let mut text = Vec::new();
+    let mut thoughts = Vec::new();
+    let mut tool_calls: Vec<Value> = Vec::new();
+    for block in &message.content {
+        match block["type"].as_str() {
+            Some("text") => text.extend(block["text"].as_str().filter(|t| !t.is_empty())),
+            Some("thinking") => thoughts.extend(block["thinking"].as_str().filter(|t| !t.trim().is_empty()).map(str::to_string)),
+            Some("tool_use") => tool_calls.push(json!({
+                "id": block["id"],
+                "name": block["name"],
+                "parameters": block["input"],
+                "status": "complete",
+                "startTime": created_at,
+            })),
+            Some("tool_result") => {
+                let result = match &block["content"] {
+                    Value::Array(parts) => Value::String(
+                        parts
+                            .iter()
+                            .filter_map(|part| part["text"].as_str())
+                            .collect::<Vec<_>>()
+                            .join("\n"),
+                    ),
+                    other => other.clone(),
+                };
+                if let Some(call) = tool_calls.iter_mut().find(|call| call["id"] == block["tool_use_id"]) {
+                    if block["is_error"] == true {
+                        call["status"] = json!("error");
+                        call["error"] = result;
+                    } else {
+                        call["result"] = result;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    let content = if text.is_empty() { message.text.clone() } else { text.join("\n\n") };
+
+    let mut attachments: Vec<Value> = message
+        .attachments
+        .iter()
+        .enumerate()
+        .map(|(i, attachment)| {
+            attachment_json(
+                format!("{}-{}", message.uuid, i),
+                "file",
+                &attachment.file_name,
+                attachment.file_size.unwrap_or(0),
+                attachment.file_type.as_deref().filter(|t| !t.is_empty()).unwrap_or("text/plain"),
+                attachment.extracted_content.as_deref(),
+            )
+        })
+        .collect();
+    for file in &message.files {
+        let Some(name) = file["file_name"].as_str() else {
+            continue;
+        };
+        attachments.push(attachment_json(
+            format!("{}-{}", message.uuid, attachments.len()),
+            "file",
+            name,
+            0,
+            "application/octet-stream",
+            None,
+        ));
+    }
+    if content.trim().is_empty() && attachments.is_empty() && tool_calls.is_empty() {
+        return None;
+    }
+
+    Some(Message {
+        id: message.uuid.clone(),
+        role: role.to_string(),
+        content,
+        attachments: Value::Array(attachments),
+        thinking: if thoughts.is_empty() { json!({}) } else { thinking_json(&message.uuid, &thoughts, &created_at) },
+        tool_calls: Value::Array(tool_calls),
+        metadata: json!({}),
+        provider_used: (role == "assistant").then(|| "anthropic".to_string()),
+        updated_at: rfc3339_timestamp(message.updated_at.as_deref()).unwrap_or_else(|| created_at.clone()),
+        created_at,
+        ..Message::default()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::export::{load_thread, project_archive};
+    use crate::db::Database;
+
+    #[tokio::test]
+    async fn test_json_export_round_trip() {
+        let db = Database::in_memory().await;
+        sqlx::raw_sql(
+            r#"
+            INSERT INTO projects (id, name, settings) VALUES ('p1', 'Weather', '{"fallback_models":["ollama::llama3"]}');
+            INSERT INTO chat_threads (id, project_id, title, total_tokens, total_cost) VALUES ('t1', 'p1', 'Paris', 999, 9.0);
+            INSERT INTO chat_messages (id, thread_id, role, content, created_at) VALUES
+                ('m1', 't1', 'user', 'Weather in Paris?', '2025-03-01 10:00:00');
+            INSERT INTO chat_messages (id, thread_id, role, content, tool_calls, thinking, parent_message_id,
+                                       prompt_tokens, completion_tokens, cost, created_at) VALUES
+                ('m2', 't1', 'assistant', 'Sunny', '[{"id":"c1","name":"get_weather","parameters":{"city":"Paris"}}]',
+                 '{"steps":[{"content":"Look it up."}]}', 'm1', 10, 5, 0.25, '2025-03-01 10:00:05'),
+                ('m3', 't1', 'assistant', 'Rainy', '[]', '{}', 'm1', 12, 3, 0.5, '2025-03-01 10:00:09');
+            "#,
+        )
+        .execute(db.pool())
+        .await
+        .unwrap();
+
+        let exported = serde_json::to_value(project_archive(db.pool(), "p1").await.unwrap()).unwrap();
+        let summary = import(db.pool(), parse(exported).unwrap(), None).await.unwrap();
+        assert_eq!(summary.source, ImportSource::Aye);
+        assert_eq!(summary.messages, 3);
+        let project_id = summary.project_id.unwrap();
+        assert_ne!(project_id, "p1", "the project is recreated");
+
+        let thread = load_thread(db.pool(), &summary.thread_ids[0]).await.unwrap();
+        assert_eq!(thread.thread.project_id.as_deref(), Some(project_id.as_str()));
+        assert_eq!(thread.thread.total_tokens, 30, "totals are recomputed from the messages");
+        assert_eq!(thread.thread.total_cost, 0.75);
+        let [user, first, second] = &thread.messages[..] else {
+            panic!("expected three messages");
+        };
+        assert_ne!(user.id, "m1");
+        assert_eq!(first.parent_message_id.as_ref(), Some(&user.id));
+        assert_eq!(second.parent_message_id.as_ref(), Some(&user.id));
+        assert_eq!(first.tool_calls[0]["parameters"]["city"], "Paris");
+        assert_eq!(first.thinking["steps"][0]["content"], "Look it up.");
+        assert_eq!(first.created_at, "2025-03-01 10:00:05");
+
+        // Importing into an existing project; unknown projects are rejected
+        let exported = serde_json::to_value(project_archive(db.pool(), "p1").await.unwrap()).unwrap();
+        let again = import(db.pool(), parse(exported.clone()).unwrap(), Some("p1")).await.unwrap();
+        assert_eq!(again.project_id.as_deref(), Some("p1"));
+        assert!(matches!(
+            import(db.pool(), parse(exported).unwrap(), Some("missing")).await,
+            Err(DbError::NotFound { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_import_chatgpt_and_claude_exports() {
+        let db = Database::in_memory().await;
+
+        // A regenerated answer: two assistant nodes under one user node, one after a thoughts node
+        let chatgpt = json!([{
+            "id": "conv-1",
+            "title": "Paris weather",
+            "create_time": 1740823200.0,
+            "default_model_slug": "gpt-4o",
+            "mapping": {
+                "root": { "message": null, "parent": null, "children": ["sys"] },
+                "sys": {
+                    "message": { "author": { "role": "system" }, "content": { "content_type": "text", "parts": [""] } },
+                    "parent": "root", "children": ["u1"]
+                },
+                "u1": {
+                    "message": { "author": { "role": "user" }, "create_time": 1740823210.0,
+                                 "content": { "content_type": "text", "parts": ["Weather in Paris?"] } },
+                    "parent": "sys", "children": ["a1", "th"]
+                },
+                "a1": {
+                    "message": { "author": { "role": "assistant" }, "create_time": 1740823215.0,
+                                 "content": { "content_type": "text", "parts": ["Sunny"] },
+                                 "metadata": { "model_slug": "gpt-4o" } },
+                    "parent": "u1", "children": []
+                },
+                "th": {
+                    "message": { "author": { "role": "assistant" },
+                                 "content": { "content_type": "thoughts",
+                                              "thoughts": [{ "summary": "Forecast", "content": "Check the season." }] } },
+                    "parent": "u1", "children": ["a2"]
+                },
+                "a2": {
+                    "message": { "author": { "role": "assistant" }, "create_time": 1740823220.0,
+                                 "content": { "content_type": "text", "parts": ["Rainy"] },
+                                 "metadata": { "model_slug": "o3" } },
+                    "parent": "th", "children": []
+                }
+            }
+        }]);
+        let conversations = parse(chatgpt).unwrap();
+        assert_eq!(conversations.source, ImportSource::ChatGpt);
+        let summary = import(db.pool(), conversations, None).await.unwrap();
+        assert_eq!(summary.project_id.as_deref(), Some(DEFAULT_PROJECT));
+        assert_eq!(summary.messages, 3, "the empty system node and the thoughts node are folded away");
+
+        let thread = load_thread(db.pool(), &summary.thread_ids[0]).await.unwrap();
+        assert_eq!(thread.thread.title, "Paris weather");
+        assert_eq!(thread.thread.settings["import"]["source"], "chatgpt");
+        let [user, sunny, rainy] = &thread.messages[..] else {
+            panic!("expected three messages");
+        };
+        assert_eq!(user.created_at, "2025-03-01 10:00:10");
+        assert_eq!(user.parent_message_id, None);
+        assert_eq!(sunny.parent_message_id.as_ref(), Some(&user.id));
+        assert_eq!(rainy.parent_message_id.as_ref(), Some(&user.id));
+        assert_eq!(rainy.model_used.as_deref(), Some("o3"));
+        assert_eq!(rainy.thinking["steps"][0]["content"], "**Forecast**\n\nCheck the season.");
+
+        let claude = json!([{
+            "uuid": "conv-2",
+            "name": "Trip notes",
+            "created_at": "2025-03-02T09:00:00.000000Z",
+            "chat_messages": [
+                { "uuid": "h1", "sender": "human", "text": "Summarize this", "created_at": "2025-03-02T09:00:01Z",
+                  "attachments": [{ "file_name": "notes.txt", "file_size": 5, "file_type": "txt", "extracted_content": "hello" }] },
+                { "uuid": "b1", "sender": "assistant", "text": "", "created_at": "2025-03-02T09:00:05Z",
+                  "content": [
+                      { "type": "thinking", "thinking": "Short file." },
+                      { "type": "tool_use", "id": "tu1", "name": "read_file", "input": { "path": "notes.txt" } },
+                      { "type": "tool_result", "tool_use_id": "tu1", "content": [{ "type": "text", "text": "hello" }] },
+                      { "type": "text", "text": "It says hello." }
+                  ] },
+                { "uuid": "h2", "sender": "human", "text": "Thanks", "parent_message_uuid": "b1",
+                  "created_at": "2025-03-02T09:01:00Z" }
+            ]
+        }]);
+        let summary = import(db.pool(), parse(claude).unwrap(), Some("default_project")).await.unwrap();
+        assert_eq!(summary.source, ImportSource::Claude);
+
+        let thread = load_thread(db.pool(), &summary.thread_ids[0]).await.unwrap();
+        assert_eq!(thread.thread.title, "Trip notes");
+        let [human, assistant, thanks] = &thread.messages[..] else {
+            panic!("expected three messages");
+        };
+        assert_eq!(human.role, "user");
+        assert_eq!(human.attachments[0]["content"], "hello");
+        assert_eq!(assistant.parent_message_id.as_ref(), Some(&human.id));
+        assert_eq!(assistant.content, "It says hello.");
+        assert_eq!(assistant.tool_calls[0]["result"], "hello");
+        assert_eq!(assistant.thinking["steps"][0]["content"], "Short file.");
+        assert_eq!(thanks.parent_message_id.as_ref(), Some(&assistant.id));
+
+        assert!(parse(json!({ "hello": "world" })).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_read_file_limits_sizes() {
+        use std::io::Write;
+
+        let dir = std::env::temp_dir().join(format!("aye-import-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let text = r#"[{"uuid":"conv-1","chat_messages":[]}]"#;
+        let json_path = dir.join(CONVERSATIONS_FILE);
+        std::fs::write(&json_path, text).unwrap();
+        let zip_path = dir.join("export.zip");
+        let mut zip = zip::ZipWriter::new(std::fs::File::create(&zip_path).unwrap());
+        zip.start_file(format!("export/{}", CONVERSATIONS_FILE), zip::write::SimpleFileOptions::default())
+            .unwrap();
+        zip.write_all(text.as_bytes()).unwrap();
+        zip.finish().unwrap();
+
+        assert_eq!(read_file(&json_path).await.unwrap()[0]["uuid"], "conv-1");
+        assert_eq!(read_file(&zip_path).await.unwrap()[0]["uuid"], "conv-1");
+        assert!(matches!(read_file_limited(&json_path, 16, 1024), Err(DbError::InvalidInput { .. })));
+        assert!(matches!(read_file_limited(&zip_path, 1024, 16), Err(DbError::InvalidInput { .. })));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
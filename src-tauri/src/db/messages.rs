@@ -0,0 +1,664 @@
+//! Chat messages: typed rows, CRUD and list, and the assistant messages written by the backend
+//! while a response streams
+
+use super::branches;
+use super::rows::{column_text, json_column, model_name, nullable, page_limit, Assignments, Page, SortOrder};
+use super::{DbError, DbResult};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Connection, QueryBuilder, Row, Sqlite, SqliteConnection, SqlitePool};
+
+/// Values of the `role` column
+pub const ROLES: [&str; 4] = ["user", "assistant", "system", "tool"];
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Message {
+    pub id: String,
+    pub thread_id: String,
+    pub parent_message_id: Option<String>,
+    pub role: String,
+    pub content: String,
+    pub attachments: Value,
+    pub thinking: Value,
+    pub tool_calls: Value,
+    pub metadata: Value,
+    /// Whether this is an edited version of a sibling
+    pub is_edited: bool,
+    /// Versions this message replaced, oldest first
+    pub edit_history: Value,
+    pub response_time: Option<f64>,
+    pub model_used: Option<String>,
+    pub provider_used: Option<String>,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub reasoning_tokens: i64,
+    pub cached_tokens: i64,
+    pub cost: f64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Message {
+    pub fn from_row(row: &SqliteRow) -> DbResult<Self> {
+        let count = |column: &str| -> DbResult<i64> { Ok(row.try_get::<Option<i64>, _>(column)?.unwrap_or(0)) };
+        Ok(Self {
+            id: row.try_get("id")?,
+            thread_id: row.try_get("thread_id")?,
+            parent_message_id: row.try_get("parent_message_id")?,
+            role: row.try_get("role")?,
+            content: row.try_get("content")?,
+            attachments: json_column(row, "attachments")?,
+            thinking: json_column(row, "thinking")?,
+            tool_calls: json_column(row, "tool_calls")?,
+            metadata: json_column(row, "metadata")?,
+            is_edited: row.try_get("is_edited")?,
+            edit_history: json_column(row, "edit_history")?,
+            response_time: row.try_get("response_time")?,
+            model_used: row.try_get("model_used")?,
+            provider_used: row.try_get("provider_used")?,
+            prompt_tokens: count("prompt_tokens")?,
+            completion_tokens: count("completion_tokens")?,
+            reasoning_tokens: count("reasoning_tokens")?,
+            cached_tokens: count("cached_tokens")?,
+            cost: row.try_get::<Option<f64>, _>("cost")?.unwrap_or(0.0),
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+
+    /// `provider::model` that wrote the message, if recorded
+    pub fn model(&self) -> Option<String> {
+        model_name(self.provider_used.as_deref(), self.model_used.as_deref())
+    }
+}
+
+/// A message to create; the id is generated when not given
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NewMessage {
+    pub id: Option<String>,
+    pub thread_id: String,
+    /// Message this one answers or follows; the leaf of the thread's active branch when not
+    /// given. The new message becomes the leaf of the active branch.
+    pub parent_message_id: Option<String>,
+    pub role: String,
+    pub content: String,
+    pub attachments: Value,
+    pub thinking: Value,
+    pub tool_calls: Value,
+    pub metadata: Value,
+    pub response_time: Option<f64>,
+    pub model_used: Option<String>,
+    pub provider_used: Option<String>,
+}
+
+/// Changes to a message, made in place; fields left out are kept, nullable fields given as
+/// `null` are cleared.
+///
+/// Edits that should keep the original as a branch go through `branches::edit_message`.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MessageUpdate {
+    pub content: Option<String>,
+    pub attachments: Option<Value>,
+    pub thinking: Option<Value>,
+    pub tool_calls: Option<Value>,
+    pub metadata: Option<Value>,
+    #[serde(deserialize_with = "nullable")]
+    pub response_time: Option<Option<f64>>,
+    #[serde(deserialize_with = "nullable")]
+    pub model_used: Option<Option<String>>,
+    #[serde(deserialize_with = "nullable")]
+    pub provider_used: Option<Option<String>>,
+}
+
+/// Messages to list, oldest first unless sorted otherwise
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct MessageFilter {
+    pub thread_id: Option<String>,
+    pub role: Option<String>,
+    /// Case-insensitive match on content
+    pub search: Option<String>,
+    /// Only messages created at or after this time (`YYYY-MM-DD HH:MM:SS`)
+    pub since: Option<String>,
+    /// Only messages created before this time
+    pub until: Option<String>,
+    pub sort_order: Option<SortOrder>,
+    pub limit: Option<u32>,
+    pub offset: u32,
+}
+
+fn check_role(role: &str) -> DbResult<()> {
+    if ROLES.contains(&role) {
+        Ok(())
+    } else {
+        Err(DbError::invalid_input(format!("Unknown message role '{}'", role)))
+    }
+}
+
+pub async fn get_message(conn: &mut SqliteConnection, id: &str) -> DbResult<Message> {
+    let row = sqlx::query("SELECT * FROM chat_messages WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| DbError::not_found("Message", id))?;
+    Message::from_row(&row)
+}
+
+pub async fn create_message(conn: &mut SqliteConnection, message: &NewMessage) -> DbResult<Message> {
+    check_role(&message.role)?;
+    let mut tx = conn.begin().await?;
+    // Also fails for an unknown thread
+    let parent = match &message.parent_message_id {
+        Some(parent) => Some(parent.clone()),
+        None => branches::active_leaf(&mut *tx, &message.thread_id).await?,
+    };
+
+    let id = message
+        .id
+        .clone()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    sqlx::query(
+        r#"
+        INSERT INTO chat_messages (id, thread_id, parent_message_id, role, content, attachments, thinking,
+                                   tool_calls, metadata, response_time, model_used, provider_used)
+        VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+        "#,
+    )
+    .bind(&id)
+    .bind(&message.thread_id)
+    .bind(&parent)
+    .bind(&message.role)
+    .bind(&message.content)
+    .bind(column_text(&message.attachments, "[]"))
+    .bind(column_text(&message.thinking, "{}"))
+    .bind(column_text(&message.tool_calls, "[]"))
+    .bind(column_text(&message.metadata, "{}"))
+    .bind(message.response_time)
+    .bind(&message.model_used)
+    .bind(&message.provider_used)
+    .execute(&mut *tx)
+    .await?;
+    branches::set_active_leaf(&mut *tx, &message.thread_id, &id).await?;
+    let created = get_message(&mut tx, &id).await?;
+    tx.commit().await?;
+    Ok(created)
+}
+
+pub async fn update_message(conn: &mut SqliteConnection, id: &str, update: &MessageUpdate) -> DbResult<Message> {
+    let json = |value: &Option<Value>| value.as_ref().map(|value| column_text(value, ""));
+    let mut assignments = Assignments::new("chat_messages");
+    assignments
+        .set("content", update.content.clone())
+        .set("attachments", json(&update.attachments))
+        .set("thinking", json(&update.thinking))
+        .set("tool_calls", json(&update.tool_calls))
+        .set("metadata", json(&update.metadata))
+        .set("response_time", update.response_time)
+        .set("model_used", update.model_used.clone())
+        .set("provider_used", update.provider_used.clone());
+    assignments.execute(&mut *conn, id).await?;
+    get_message(conn, id).await
+}
+
+/// Delete a message; its replies are kept and move up to its parent
+pub async fn delete_message(conn: &mut SqliteConnection, id: &str) -> DbResult<()> {
+    let mut tx = conn.begin().await?;
+    let parent_id: Option<String> = sqlx::query_scalar("SELECT parent_id FROM chat_message_parents WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| DbError::not_found("Message", id))?;
+    // Replies that follow it without naming a parent are moved too, or they would follow
+    // whichever message comes before them once it is gone
+    sqlx::query(
+        r#"
+        UPDATE chat_messages
+        SET parent_message_id = ?2
+        WHERE id IN (
+            SELECT id FROM chat_message_parents
+            WHERE thread_id = (SELECT thread_id FROM chat_messages WHERE id = ?1) AND parent_id = ?1
+        )
+        "#,
+    )
+    .bind(id)
+    .bind(&parent_id)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM chat_messages WHERE id = ?")
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    tx.commit().await?;
+    Ok(())
+}
+
+fn push_filter(query: &mut QueryBuilder<'_, Sqlite>, filter: &MessageFilter) {
+    query.push(" WHERE 1 = 1");
+    if let Some(thread_id) = &filter.thread_id {
+        query.push(" AND thread_id = ").push_bind(thread_id.clone());
+    }
+    if let Some(role) = &filter.role {
+        query.push(" AND role = ").push_bind(role.clone());
+    }
+    if let Some(search) = filter.search.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+        query
+            .push(" AND instr(lower(content), lower(")
+            .push_bind(search.to_string())
+            .push(")) > 0");
+    }
+    if let Some(since) = &filter.since {
+        query.push(" AND created_at >= ").push_bind(since.clone());
+    }
+    if let Some(until) = &filter.until {
+        query.push(" AND created_at < ").push_bind(until.clone());
+    }
+}
+
+pub async fn list_messages(conn: &mut SqliteConnection, filter: &MessageFilter) -> DbResult<Page<Message>> {
+    if let Some(role) = &filter.role {
+        check_role(role)?;
+    }
+    let limit = page_limit(filter.limit);
+
+    let mut count = QueryBuilder::new("SELECT COUNT(*) FROM chat_messages");
+    push_filter(&mut count, filter);
+    let total: i64 = count.build_query_scalar().fetch_one(&mut *conn).await?;
+
+    let order = filter.sort_order.unwrap_or(SortOrder::Asc).as_sql();
+    let mut query = QueryBuilder::new("SELECT * FROM chat_messages");
+    push_filter(&mut query, filter);
+    query
+        .push(format!(" ORDER BY created_at {}, rowid {} LIMIT ", order, order))
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(filter.offset);
+    let items = query
+        .build()
+        .fetch_all(&mut *conn)
+        .await?
+        .iter()
+        .map(Message::from_row)
+        .collect::<DbResult<_>>()?;
+    Ok(Page {
+        items,
+        total,
+        limit,
+        offset: filter.offset,
+    })
+}
+
+/// Lifecycle of a backend-written message, stored as `metadata.status`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageStatus {
+    Streaming,
+    Complete,
+    Error,
+    Cancelled,
+}
+
+impl MessageStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Streaming => "streaming",
+            Self::Complete => "complete",
+            Self::Error => "error",
+            Self::Cancelled => "cancelled",
+        }
+    }
+}
+
+/// Row created when a stream starts
+#[derive(Debug, Clone)]
+pub struct NewAssistantMessage {
+    pub id: String,
+    pub thread_id: String,
+    /// Message answered; the leaf of the thread's active branch when not given
+    pub parent_message_id: Option<String>,
+    /// Whether the message becomes the leaf of the active branch; side-by-side comparisons
+    /// leave that choice to the user
+    pub activate: bool,
+    pub provider: String,
+    pub model: String,
+    /// Extra metadata (stream id, options); `status` is set here
+    pub metadata: Value,
+}
+
+/// Final state of a streamed message
+#[derive(Debug, Clone)]
+pub struct FinishedMessage {
+    pub status: MessageStatus,
+    pub content: String,
+    /// `ThinkingProcess` JSON, or `{}` when the model did not reason
+    pub thinking: Value,
+    /// `ToolCall[]` JSON
+    pub tool_calls: Value,
+    /// Milliseconds from request to end of stream
+    pub response_time_ms: f64,
+    pub error: Option<String>,
+    /// Merged into the message metadata
+    pub metadata: Value,
+    /// Provider and model that answered, if a fallback replaced the one the row was created with
+    pub answered_by: Option<(String, String)>,
+}
+
+/// Insert an empty assistant message in `streaming` state
+pub async fn create_assistant_message(pool: &SqlitePool, message: &NewAssistantMessage) -> DbResult<()> {
+    let mut metadata = message.metadata.clone();
+    if !metadata.is_object() {
+        metadata = Value::Object(Default::default());
+    }
+    metadata["status"] = MessageStatus::Streaming.as_str().into();
+
+    let mut tx = pool.begin().await?;
+    let parent = match &message.parent_message_id {
+        Some(parent) => Some(parent.clone()),
+        None => branches::active_leaf(&mut *tx, &message.thread_id).await?,
+    };
+    sqlx::query(
+        r#"
+        INSERT INTO chat_messages (
+            id, thread_id, parent_message_id, role, content, metadata, model_used, provider_used
+        ) VALUES (?, ?, ?, 'assistant', '', ?, ?, ?)
+        "#,
+    )
+    .bind(&message.id)
+    .bind(&message.thread_id)
+    .bind(&parent)
+    .bind(metadata.to_string())
+    .bind(&message.model)
+    .bind(&message.provider)
+    .execute(&mut *tx)
+    .await?;
+    if message.activate {
+        branches::set_active_leaf(&mut *tx, &message.thread_id, &message.id).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Save the content and thinking received so far
+pub async fn update_message_progress(pool: &SqlitePool, id: &str, content: &str, thinking: &Value) -> DbResult<()> {
+    sqlx::query("UPDATE chat_messages SET content = ?, thinking = ? WHERE id = ?")
+        .bind(content)
+        .bind(thinking.to_string())
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Write the final state of a message and refresh its thread's response stats
+pub async fn finish_message(pool: &SqlitePool, id: &str, finished: &FinishedMessage) -> DbResult<()> {
+    let mut patch = finished.metadata.clone();
+    if !patch.is_object() {
+        patch = Value::Object(Default::default());
+    }
+    patch["status"] = finished.status.as_str().into();
+    if let Some(error) = &finished.error {
+        patch["error"] = error.as_str().into();
+    }
+
+    let mut tx = pool.begin().await?;
+    let thread_id: Option<String> = sqlx::query_scalar(
+        r#"
+        UPDATE chat_messages
+        SET content = ?, thinking = ?, tool_calls = ?, response_time = ?,
+            metadata = json_patch(COALESCE(json(metadata), '{}'), ?),
+            provider_used = COALESCE(?, provider_used),
+            model_used = COALESCE(?, model_used)
+        WHERE id = ?
+        RETURNING thread_id
+        "#,
+    )
+    .bind(&finished.content)
+    .bind(finished.thinking.to_string())
+    .bind(finished.tool_calls.to_string())
+    .bind(finished.response_time_ms)
+    .bind(patch.to_string())
+    .bind(finished.answered_by.as_ref().map(|(provider, _)| provider))
+    .bind(finished.answered_by.as_ref().map(|(_, model)| model))
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if let Some(thread_id) = thread_id {
+        sqlx::query(
+            r#"
+            UPDATE chat_threads
+            SET avg_response_time = (
+                    SELECT AVG(response_time) FROM chat_messages
+                    WHERE thread_id = ?1 AND role = 'assistant' AND response_time IS NOT NULL
+                ),
+                error_count = error_count + ?2
+            WHERE id = ?1
+            "#,
+        )
+        .bind(&thread_id)
+        .bind(i64::from(finished.status == MessageStatus::Error))
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Mark a message that stopped streaming without a final state (stopped by the user)
+pub async fn mark_message_status(pool: &SqlitePool, id: &str, status: MessageStatus) -> DbResult<()> {
+    sqlx::query(
+        r#"
+        UPDATE chat_messages
+        SET metadata = json_set(COALESCE(json(metadata), '{}'), '$.status', ?)
+        WHERE id = ? AND json_extract(metadata, '$.status') = 'streaming'
+        "#,
+    )
+    .bind(status.as_str())
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+    use serde_json::json;
+
+    fn new_message(id: &str) -> NewAssistantMessage {
+        NewAssistantMessage {
+            id: id.to_string(),
+            thread_id: "t1".to_string(),
+            parent_message_id: None,
+            activate: true,
+            provider: "openai".to_string(),
+            model: "gpt-4o".to_string(),
+            metadata: json!({ "stream_id": "s1" }),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_message_crud_and_list() {
+        let db = Database::with_thread().await;
+        let mut conn = db.pool().acquire().await.unwrap();
+        let message = |role: &str, content: &str| NewMessage {
+            thread_id: "t1".to_string(),
+            role: role.to_string(),
+            content: content.to_string(),
+            ..NewMessage::default()
+        };
+
+        let question = create_message(&mut conn, &message("user", "Weather in Paris?")).await.unwrap();
+        let answer = create_message(&mut conn, &message("assistant", "Sunny")).await.unwrap();
+        assert_eq!(answer.parent_message_id.as_ref(), Some(&question.id), "continues the active branch");
+        assert_eq!(answer.tool_calls, json!([]));
+        assert!(matches!(
+            create_message(&mut conn, &message("robot", "Hi")).await,
+            Err(DbError::InvalidInput { .. })
+        ));
+        assert!(matches!(
+            create_message(&mut conn, &NewMessage { thread_id: "missing".to_string(), ..message("user", "Hi") }).await,
+            Err(DbError::NotFound { .. })
+        ));
+
+        let update = MessageUpdate {
+            content: Some("Sunny, 22°C".to_string()),
+            metadata: Some(json!({ "status": "complete" })),
+            ..MessageUpdate::default()
+        };
+        let updated = update_message(&mut conn, &answer.id, &update).await.unwrap();
+        assert_eq!(updated.content, "Sunny, 22°C");
+        assert_eq!(updated.metadata["status"], "complete");
+
+        let filter = MessageFilter {
+            thread_id: Some("t1".to_string()),
+            sort_order: Some(SortOrder::Desc),
+            limit: Some(1),
+            ..MessageFilter::default()
+        };
+        let page = list_messages(&mut conn, &filter).await.unwrap();
+        assert_eq!(page.total, 2);
+        assert_eq!(page.items[0].id, answer.id);
+        let filter = MessageFilter {
+            role: Some("user".to_string()),
+            search: Some("paris".to_string()),
+            ..MessageFilter::default()
+        };
+        assert_eq!(list_messages(&mut conn, &filter).await.unwrap().items[0].id, question.id);
+
+        delete_message(&mut conn, &question.id).await.unwrap();
+        let answer = get_message(&mut conn, &answer.id).await.unwrap();
+        assert_eq!(answer.parent_message_id, None, "replies are kept");
+        let count: i64 = sqlx::query_scalar("SELECT message_count FROM chat_threads WHERE id = 't1'")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap();
+        assert_eq!(count, 1);
+    }
+
+    #[tokio::test]
+    async fn test_delete_moves_replies_to_the_parent() {
+        let db = Database::with_thread().await;
+        sqlx::raw_sql(
+            r#"
+            INSERT INTO chat_messages (id, thread_id, role, content, parent_message_id, created_at) VALUES
+                ('m1', 't1', 'user', 'Weather in Paris?', NULL, '2025-03-01 10:00:00'),
+                ('m2', 't1', 'assistant', 'Sunny', 'm1', '2025-03-01 10:00:05'),
+                ('m3', 't1', 'user', 'And tomorrow?', NULL, '2025-03-01 10:00:10'),
+                ('m4', 't1', 'user', 'And in Lyon?', 'm2', '2025-03-01 10:00:15'),
+                ('m5', 't1', 'assistant', 'Rainy', 'm1', '2025-03-01 10:00:20');
+            "#,
+        )
+        .execute(db.pool())
+        .await
+        .unwrap();
+        let mut conn = db.pool().acquire().await.unwrap();
+
+        delete_message(&mut conn, "m2").await.unwrap();
+        for id in ["m3", "m4"] {
+            let reply = get_message(&mut conn, id).await.unwrap();
+            assert_eq!(reply.parent_message_id.as_deref(), Some("m1"), "{} follows the deleted message", id);
+        }
+        assert_eq!(get_message(&mut conn, "m5").await.unwrap().parent_message_id.as_deref(), Some("m1"));
+        assert!(matches!(delete_message(&mut conn, "m2").await, Err(DbError::NotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_streamed_message_lifecycle() {
+        let db = Database::with_thread().await;
+        create_assistant_message(db.pool(), &new_message("m1")).await.unwrap();
+        update_message_progress(db.pool(), "m1", "Hel", &json!({})).await.unwrap();
+        finish_message(
+            db.pool(),
+            "m1",
+            &FinishedMessage {
+                status: MessageStatus::Complete,
+                content: "Hello".to_string(),
+                thinking: json!({}),
+                tool_calls: json!([]),
+                response_time_ms: 1200.0,
+                error: None,
+                metadata: json!({ "cost": 0.01 }),
+                answered_by: None,
+            },
+        )
+        .await
+        .unwrap();
+
+        let (content, metadata, model, provider, response_time): (String, String, String, String, f64) =
+            sqlx::query_as(
+                "SELECT content, metadata, model_used, provider_used, response_time FROM chat_messages WHERE id = 'm1'",
+            )
+            .fetch_one(db.pool())
+            .await
+            .unwrap();
+        let metadata: Value = serde_json::from_str(&metadata).unwrap();
+        assert_eq!(content, "Hello");
+        assert_eq!(metadata["status"], "complete");
+        assert_eq!(metadata["stream_id"], "s1");
+        assert_eq!(metadata["cost"], 0.01);
+        assert_eq!((model.as_str(), provider.as_str(), response_time), ("gpt-4o", "openai", 1200.0));
+
+        let (count, avg): (i64, f64) =
+            sqlx::query_as("SELECT message_count, avg_response_time FROM chat_threads WHERE id = 't1'")
+                .fetch_one(db.pool())
+                .await
+                .unwrap();
+        assert_eq!((count, avg), (1, 1200.0));
+    }
+
+    #[tokio::test]
+    async fn test_error_and_cancel_states() {
+        let db = Database::with_thread().await;
+        create_assistant_message(db.pool(), &new_message("m1")).await.unwrap();
+        finish_message(
+            db.pool(),
+            "m1",
+            &FinishedMessage {
+                status: MessageStatus::Error,
+                content: "partial".to_string(),
+                thinking: json!({}),
+                tool_calls: json!([]),
+                response_time_ms: 50.0,
+                error: Some("rate limited".to_string()),
+                metadata: json!({}),
+                answered_by: Some(("ollama".to_string(), "llama3".to_string())),
+            },
+        )
+        .await
+        .unwrap();
+        let model: String = sqlx::query_scalar("SELECT provider_used || '::' || model_used FROM chat_messages WHERE id = 'm1'")
+            .fetch_one(db.pool())
+            .await
+            .unwrap();
+        assert_eq!(model, "ollama::llama3");
+        // A finished message keeps its state
+        mark_message_status(db.pool(), "m1", MessageStatus::Cancelled).await.unwrap();
+
+        create_assistant_message(db.pool(), &new_message("m2")).await.unwrap();
+        mark_message_status(db.pool(), "m2", MessageStatus::Cancelled).await.unwrap();
+
+        let statuses: Vec<(String, Option<String>)> = sqlx::query_as(
+            "SELECT json_extract(metadata, '$.status'), json_extract(metadata, '$.error') FROM chat_messages ORDER BY id",
+        )
+        .fetch_all(db.pool())
+        .await
+        .unwrap();
+        assert_eq!(
+            statuses,
+            vec![
+                ("error".to_string(), Some("rate limited".to_string())),
+                ("cancelled".to_string(), None),
+            ]
+        );
+
+        let errors: i64 = sqlx::query_scalar("SELECT error_count FROM chat_threads WHERE id = 't1'")
+            .fetch_one(db.pool())
+            .await
+            .unwrap();
+        assert_eq!(errors, 1);
+    }
+}
@@ -0,0 +1,649 @@
+//! Schema migrations for aye_mcp.db, applied by the SQL plugin at startup
+
+use tauri_plugin_sql::{Migration, MigrationKind};
+
+/// All migrations, in version order
+pub fn all() -> Vec<Migration> {
+    vec![
+        // Migration 1: Create chat_threads table
+        Migration {
+            version: 1,
+            description: "create_chat_threads_table",
+            sql: r#"
+                    CREATE TABLE IF NOT EXISTS chat_threads (
+                        id TEXT PRIMARY KEY NOT NULL,
+                        project_id TEXT REFERENCES projects(id) ON DELETE SET NULL,
+                        title TEXT NOT NULL,
+                        description TEXT,
+                        is_archived BOOLEAN NOT NULL DEFAULT FALSE,
+                        is_pinned BOOLEAN NOT NULL DEFAULT FALSE,
+
+                        -- Thread-specific settings that can override project defaults
+                        model_provider TEXT NOT NULL DEFAULT '',
+                        model_name TEXT NOT NULL DEFAULT '',
+                        system_prompt TEXT,
+                        tool_presets TEXT DEFAULT '[]', -- JSON array of tool IDs
+                        settings TEXT DEFAULT '{}', -- JSON object for additional settings
+
+                        -- Thread metadata
+                        message_count INTEGER DEFAULT 0,
+                        total_tokens INTEGER DEFAULT 0,
+                        last_message_at TIMESTAMP,
+                        tags TEXT DEFAULT '[]', -- JSON array of tags,
+                        status TEXT DEFAULT 'active', -- active, completed, archived
+
+                        -- Performance tracking
+                        avg_response_time REAL DEFAULT 0.0,
+                        error_count INTEGER DEFAULT 0,
+
+                        -- Thread organization
+                        parent_thread_id TEXT REFERENCES chat_threads(id) ON DELETE SET NULL,
+                        thread_type TEXT DEFAULT 'chat', -- chat, task, brainstorm, review, etc.
+                        priority INTEGER DEFAULT 1, -- 1=Low, 2=Medium, 3=High
+
+                        -- Timestamps
+                        created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+                        updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+                    );
+                "#,
+            kind: MigrationKind::Up,
+        },
+        // Migration 2: Create chat_messages table
+        Migration {
+            version: 2,
+            description: "create_chat_messages_table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS chat_messages (
+                    id TEXT PRIMARY KEY NOT NULL,
+                    thread_id TEXT NOT NULL REFERENCES chat_threads(id) ON DELETE CASCADE,
+                    role TEXT NOT NULL CHECK (role IN ('user', 'assistant', 'system', 'tool')),
+                    content TEXT NOT NULL,
+                    attachments TEXT DEFAULT '[]', -- JSON array of attachments
+                    thinking TEXT DEFAULT '{}', -- JSON object for thinking process
+                    tool_calls TEXT DEFAULT '[]', -- JSON array for tool calls
+                    metadata TEXT DEFAULT '{}', -- JSON object for additional metadata
+
+                    -- Message organization
+                    parent_message_id TEXT REFERENCES chat_messages(id) ON DELETE SET NULL,
+                    -- is_edited BOOLEAN DEFAULT FALSE,
+                    -- edit_history TEXT DEFAULT '[]', -- JSON array of previous versions
+
+                    -- Performance tracking
+                    response_time REAL, -- Time taken to generate (for assistant messages)
+                    model_used TEXT, -- Track which model generated this message
+                    provider_used TEXT, -- Track which provider was used
+
+                    -- Timestamps
+                    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+                    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+                );
+            "#,
+            kind: MigrationKind::Up,
+        },
+        // Migration 3: Create projects table for future use
+        Migration {
+            version: 3,
+            description: "create_projects_table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS projects (
+                    id TEXT PRIMARY KEY NOT NULL,
+                    name TEXT NOT NULL,
+                    description TEXT,
+                    system_prompt TEXT,
+                    default_model_provider TEXT NOT NULL DEFAULT '',
+                    default_model_name TEXT NOT NULL DEFAULT '',
+                    tool_presets TEXT DEFAULT '[]', -- JSON array of tool IDs
+                    settings TEXT DEFAULT '{}', -- JSON object
+                    is_archived BOOLEAN DEFAULT FALSE,
+
+                    -- Additional fields for enhanced project management
+                    color TEXT DEFAULT '#3b82f6', -- Project color for visual organization
+                    icon TEXT DEFAULT 'folder', -- Icon identifier
+                    priority INTEGER DEFAULT 1, -- 1=Low, 2=Medium, 3=High
+                    tags TEXT DEFAULT '[]', -- JSON array of tags
+                    folder_path TEXT, -- Optional folder path for file associations
+
+                    -- Timestamps
+                    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+                    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+                );
+            "#,
+            kind: MigrationKind::Up,
+        },
+        // Migration 4: Create indexes for better performance
+        Migration {
+            version: 4,
+            description: "create_performance_indexes",
+            sql: r#"
+                -- Indexes for chat_message table
+                CREATE INDEX IF NOT EXISTS idx_chat_messages_thread_id ON chat_messages(thread_id);
+                CREATE INDEX IF NOT EXISTS idx_chat_messages_created_at ON chat_messages(created_at);
+                CREATE INDEX IF NOT EXISTS idx_chat_messages_role ON chat_messages(role);
+                
+                -- Indexes for chat_threads table
+                CREATE INDEX IF NOT EXISTS idx_chat_threads_created_at ON chat_threads(created_at);
+                CREATE INDEX IF NOT EXISTS idx_chat_threads_updated_at ON chat_threads(updated_at);
+                CREATE INDEX IF NOT EXISTS idx_chat_threads_project_id ON chat_threads(project_id);
+                CREATE INDEX IF NOT EXISTS idx_chat_threads_pinned ON chat_threads(is_pinned);
+
+                -- Index for projects
+                CREATE INDEX IF NOT EXISTS idx_projects_created_at ON projects(created_at DESC);
+                CREATE INDEX IF NOT EXISTS idx_projects_archived ON projects(is_archived);
+                CREATE INDEX IF NOT EXISTS idx_projects_updated_at ON projects(updated_at DESC);
+            "#,
+            kind: MigrationKind::Up,
+        },
+        // Migration 5: Create triggers for timestamp updates
+        Migration {
+            version: 5,
+            description: "create_timestamp_triggers",
+            sql: r#"
+                -- Trigger to update updated_at on chat_threads
+                CREATE TRIGGER IF NOT EXISTS update_chat_threads_timestamp
+                AFTER UPDATE ON chat_threads
+                BEGIN
+                    UPDATE chat_threads SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+                END;
+                
+                -- Trigger to update updated_at on chat_message
+                CREATE TRIGGER IF NOT EXISTS update_chat_message_timestamp
+                AFTER UPDATE ON chat_messages
+                BEGIN
+                    UPDATE chat_messages SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+                END;
+                
+                -- Trigger to update thread metadata when messages are added/updated
+                CREATE TRIGGER IF NOT EXISTS update_thread_on_message_insert
+                AFTER INSERT ON chat_messages
+                BEGIN
+                    UPDATE chat_threads 
+                    SET 
+                        message_count = message_count + 1,
+                        last_message_at = NEW.created_at,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE id = NEW.thread_id;
+                END;
+                
+                -- Trigger to update thread metadata when messages are deleted
+                CREATE TRIGGER IF NOT EXISTS update_thread_on_message_delete
+                AFTER DELETE ON chat_messages
+                BEGIN
+                    UPDATE chat_threads 
+                    SET 
+                        message_count = message_count - 1,
+                        updated_at = CURRENT_TIMESTAMP
+                    WHERE id = OLD.thread_id;
+                END;
+                
+                -- Trigger to update projects timestamp
+                CREATE TRIGGER IF NOT EXISTS update_projects_timestamp
+                AFTER UPDATE ON projects
+                BEGIN
+                    UPDATE projects SET updated_at = CURRENT_TIMESTAMP WHERE id = NEW.id;
+                END;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        // Migration 6: Create mcp_servers table
+        Migration {
+            version: 6,
+            description: "create_mcp_servers_table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS mcp_servers (
+                    id TEXT PRIMARY KEY,
+                    name TEXT NOT NULL,
+                    description TEXT,
+                    endpoint TEXT NOT NULL,
+                    server_type TEXT NOT NULL DEFAULT 'stdio',
+                    config TEXT DEFAULT '{}',
+                    is_enabled BOOLEAN DEFAULT TRUE,
+                    status TEXT DEFAULT 'disconnected',
+                    last_connected_at DATETIME,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP
+                );
+            "#,
+            kind: MigrationKind::Up,
+        },
+        // Migration 7: Create mcp_tools table
+        Migration {
+            version: 7,
+            description: "create_mcp_tools_table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS mcp_tools (
+                    id TEXT PRIMARY KEY,
+                    server_id TEXT NOT NULL,
+                    name TEXT NOT NULL,
+                    description TEXT,
+                    schema TEXT NOT NULL,
+                    is_enabled BOOLEAN DEFAULT TRUE,
+                    created_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    updated_at DATETIME DEFAULT CURRENT_TIMESTAMP,
+                    FOREIGN KEY (server_id) REFERENCES mcp_servers(id) ON DELETE CASCADE
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_tools_server ON mcp_tools(server_id);
+            "#,
+            kind: MigrationKind::Up,
+        },
+        // Migration 8: Create project_stats_view
+        Migration {
+            version: 8,
+            description: "create_project_stats_view",
+            sql: r#"
+                CREATE VIEW IF NOT EXISTS project_stats_view AS
+                SELECT 
+                    p.id,
+                    p.name,
+                    p.status,
+                    COUNT(DISTINCT t.id) as thread_count,
+                    COUNT(DISTINCT CASE WHEN t.status = 'active' THEN t.id END) as active_threads,
+                    COUNT(DISTINCT m.id) as total_messages,
+                    SUM(COALESCE(m.tokens, 0)) as total_tokens,
+                    MAX(t.last_message_at) as last_activity,
+                    p.created_at,
+                    p.updated_at,
+                    p.last_accessed_at
+                FROM projects p
+                LEFT JOIN chat_threads t ON p.id = t.project_id
+                LEFT JOIN chat_messages m ON t.id = m.thread_id
+                GROUP BY p.id;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        // Migration 9: Create recent_projects_view
+        Migration {
+            version: 9,
+            description: "create_recent_projects_view",
+            sql: r#"
+                CREATE VIEW IF NOT EXISTS recent_projects_view AS
+                SELECT *
+                FROM projects
+                WHERE is_archived = FALSE
+                ORDER BY 
+                    CASE WHEN last_accessed_at IS NOT NULL THEN last_accessed_at ELSE updated_at END DESC
+            "#,
+            kind: MigrationKind::Up,
+        },
+        // Migration 10: Create project_templates_table
+        Migration {
+            version: 10,
+            description: "create_project_templates_table",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS project_templates (
+                    id TEXT PRIMARY KEY,
+                    name TEXT NOT NULL,
+                    description TEXT,
+                    system_prompt TEXT,
+                    default_model_provider TEXT NOT NULL DEFAULT 'openai',
+                    default_model_name TEXT NOT NULL DEFAULT 'gpt-4',
+                    tool_presets TEXT NOT NULL DEFAULT '[]',
+                    settings TEXT NOT NULL DEFAULT '{}',
+                    color TEXT DEFAULT '#3b82f6',
+                    icon TEXT DEFAULT 'folder',
+                    tags TEXT DEFAULT '[]',
+                    usage_count INTEGER DEFAULT 0,
+                    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+                    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+                );
+            "#,
+            kind: MigrationKind::Up,
+        },
+        // Migration 11: Create default_project
+        Migration {
+            version: 11,
+            description: "create_default_project",
+            sql: r#"
+                INSERT INTO projects (
+                    id,
+                    name,
+                    description,
+                    system_prompt,
+                    default_model_provider,
+                    default_model_name,
+                    tool_presets,
+                    settings,
+                    is_archived,
+
+                    color,
+                    icon,
+                    priority,
+                    tags,
+                    folder_path,
+                    
+                    created_at,
+                    updated_at
+                ) VALUES (
+                    'default_project',
+                    'Default Project',
+                    'Auto-created project for default settings',
+                    '',
+                    '',
+                    '',
+                    '[]',
+                    '{}',
+                    0,
+
+
+                    '#3b82f6',
+                    'folder',
+                    1,
+                    '[]',
+                    '',
+
+                    datetime('now'),
+                    datetime('now')
+                );
+            "#,
+            kind: MigrationKind::Up,
+        },
+        // Migration 12: Token and cost accounting
+        Migration {
+            version: 12,
+            description: "create_usage_accounting",
+            sql: r#"
+                -- The stats views referenced columns that never existed; rebuild them below
+                DROP VIEW IF EXISTS project_stats_view;
+                DROP VIEW IF EXISTS recent_projects_view;
+
+                ALTER TABLE projects ADD COLUMN last_accessed_at TIMESTAMP;
+                ALTER TABLE chat_threads ADD COLUMN total_cost REAL DEFAULT 0.0;
+                ALTER TABLE chat_messages ADD COLUMN prompt_tokens INTEGER DEFAULT 0;
+                ALTER TABLE chat_messages ADD COLUMN completion_tokens INTEGER DEFAULT 0;
+                ALTER TABLE chat_messages ADD COLUMN reasoning_tokens INTEGER DEFAULT 0;
+                ALTER TABLE chat_messages ADD COLUMN cached_tokens INTEGER DEFAULT 0;
+                ALTER TABLE chat_messages ADD COLUMN cost REAL DEFAULT 0.0;
+
+                -- One row per provider response; kept when messages or threads are deleted
+                CREATE TABLE IF NOT EXISTS usage_records (
+                    id TEXT PRIMARY KEY NOT NULL,
+                    message_id TEXT, -- Assistant message (may be inserted after the record)
+                    thread_id TEXT REFERENCES chat_threads(id) ON DELETE SET NULL,
+                    project_id TEXT REFERENCES projects(id) ON DELETE SET NULL,
+                    provider TEXT NOT NULL,
+                    model TEXT NOT NULL,
+                    prompt_tokens INTEGER NOT NULL DEFAULT 0,
+                    completion_tokens INTEGER NOT NULL DEFAULT 0,
+                    reasoning_tokens INTEGER NOT NULL DEFAULT 0,
+                    cached_tokens INTEGER NOT NULL DEFAULT 0,
+                    total_tokens INTEGER NOT NULL DEFAULT 0,
+                    cost REAL, -- USD; NULL when the model has no known pricing
+                    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_usage_records_created_at ON usage_records(created_at);
+                CREATE INDEX IF NOT EXISTS idx_usage_records_thread_id ON usage_records(thread_id);
+                CREATE INDEX IF NOT EXISTS idx_usage_records_project_id ON usage_records(project_id);
+                CREATE INDEX IF NOT EXISTS idx_usage_records_message_id ON usage_records(message_id);
+
+                -- Copy usage onto the message and roll it up to the thread
+                CREATE TRIGGER IF NOT EXISTS apply_usage_on_insert
+                AFTER INSERT ON usage_records
+                BEGIN
+                    UPDATE chat_messages
+                    SET
+                        prompt_tokens = NEW.prompt_tokens,
+                        completion_tokens = NEW.completion_tokens,
+                        reasoning_tokens = NEW.reasoning_tokens,
+                        cached_tokens = NEW.cached_tokens,
+                        cost = COALESCE(NEW.cost, 0.0)
+                    WHERE id = NEW.message_id;
+
+                    UPDATE chat_threads
+                    SET
+                        total_tokens = COALESCE(total_tokens, 0) + NEW.total_tokens,
+                        total_cost = COALESCE(total_cost, 0.0) + COALESCE(NEW.cost, 0.0)
+                    WHERE id = NEW.thread_id;
+                END;
+
+                -- Messages saved after their usage was recorded pick it up on insert
+                CREATE TRIGGER IF NOT EXISTS apply_usage_on_message_insert
+                AFTER INSERT ON chat_messages
+                WHEN EXISTS (SELECT 1 FROM usage_records WHERE message_id = NEW.id)
+                BEGIN
+                    UPDATE chat_messages
+                    SET (prompt_tokens, completion_tokens, reasoning_tokens, cached_tokens, cost) = (
+                        SELECT prompt_tokens, completion_tokens, reasoning_tokens, cached_tokens, COALESCE(cost, 0.0)
+                        FROM usage_records
+                        WHERE message_id = NEW.id
+                        ORDER BY created_at DESC
+                        LIMIT 1
+                    )
+                    WHERE id = NEW.id;
+                END;
+
+                CREATE VIEW IF NOT EXISTS project_stats_view AS
+                SELECT
+                    p.id,
+                    p.name,
+                    p.is_archived,
+                    (SELECT COUNT(*) FROM chat_threads t WHERE t.project_id = p.id) as thread_count,
+                    (SELECT COUNT(*) FROM chat_threads t WHERE t.project_id = p.id AND t.status = 'active') as active_threads,
+                    (SELECT COUNT(*) FROM chat_messages m JOIN chat_threads t ON t.id = m.thread_id WHERE t.project_id = p.id) as total_messages,
+                    (SELECT COALESCE(SUM(u.total_tokens), 0) FROM usage_records u WHERE u.project_id = p.id) as total_tokens,
+                    (SELECT COALESCE(SUM(u.cost), 0.0) FROM usage_records u WHERE u.project_id = p.id) as total_cost,
+                    (SELECT MAX(t.last_message_at) FROM chat_threads t WHERE t.project_id = p.id) as last_activity,
+                    p.created_at,
+                    p.updated_at,
+                    p.last_accessed_at
+                FROM projects p;
+
+                CREATE VIEW IF NOT EXISTS recent_projects_view AS
+                SELECT *
+                FROM projects
+                WHERE is_archived = FALSE
+                ORDER BY
+                    CASE WHEN last_accessed_at IS NOT NULL THEN last_accessed_at ELSE updated_at END DESC;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        // Migration 13: A response can take several requests (repairs, fallbacks); message usage is their sum
+        Migration {
+            version: 13,
+            description: "sum_message_usage",
+            sql: r#"
+                DROP TRIGGER IF EXISTS apply_usage_on_insert;
+                DROP TRIGGER IF EXISTS apply_usage_on_message_insert;
+
+                CREATE TRIGGER IF NOT EXISTS apply_usage_on_insert
+                AFTER INSERT ON usage_records
+                BEGIN
+                    UPDATE chat_messages
+                    SET (prompt_tokens, completion_tokens, reasoning_tokens, cached_tokens, cost) = (
+                        SELECT SUM(prompt_tokens), SUM(completion_tokens), SUM(reasoning_tokens), SUM(cached_tokens), COALESCE(SUM(cost), 0.0)
+                        FROM usage_records
+                        WHERE message_id = NEW.message_id
+                    )
+                    WHERE id = NEW.message_id;
+
+                    UPDATE chat_threads
+                    SET
+                        total_tokens = COALESCE(total_tokens, 0) + NEW.total_tokens,
+                        total_cost = COALESCE(total_cost, 0.0) + COALESCE(NEW.cost, 0.0)
+                    WHERE id = NEW.thread_id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS apply_usage_on_message_insert
+                AFTER INSERT ON chat_messages
+                WHEN EXISTS (SELECT 1 FROM usage_records WHERE message_id = NEW.id)
+                BEGIN
+                    UPDATE chat_messages
+                    SET (prompt_tokens, completion_tokens, reasoning_tokens, cached_tokens, cost) = (
+                        SELECT SUM(prompt_tokens), SUM(completion_tokens), SUM(reasoning_tokens), SUM(cached_tokens), COALESCE(SUM(cost), 0.0)
+                        FROM usage_records
+                        WHERE message_id = NEW.id
+                    )
+                    WHERE id = NEW.id;
+                END;
+            "#,
+            kind: MigrationKind::Up,
+        },
+        // Migration 14: Message embeddings for semantic search, and the messages that could not be
+        // embedded, so indexing moves past them
+        Migration {
+            version: 14,
+            description: "create_message_embeddings",
+            sql: r#"
+                CREATE TABLE IF NOT EXISTS message_embeddings (
+                    message_id TEXT NOT NULL REFERENCES chat_messages(id) ON DELETE CASCADE,
+                    model TEXT NOT NULL, -- Embedding model, provider::model
+                    dimensions INTEGER NOT NULL,
+                    vector BLOB NOT NULL, -- Little-endian f32 values
+                    message_updated_at TIMESTAMP NOT NULL, -- updated_at of the message when embedded
+                    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+                    PRIMARY KEY (message_id, model)
+                );
+
+                CREATE INDEX IF NOT EXISTS idx_message_embeddings_model ON message_embeddings(model);
+
+                CREATE TABLE IF NOT EXISTS message_embedding_failures (
+                    message_id TEXT NOT NULL REFERENCES chat_messages(id) ON DELETE CASCADE,
+                    model TEXT NOT NULL, -- Embedding model, provider::model
+                    message_updated_at TIMESTAMP NOT NULL, -- updated_at of the message that failed
+                    attempts INTEGER NOT NULL DEFAULT 1,
+                    error TEXT,
+                    failed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP NOT NULL,
+                    PRIMARY KEY (message_id, model)
+                );
+            "#,
+            kind: MigrationKind::Up,
+        },
+        // Migration 15: Full-text search over message content and thread titles and descriptions
+        Migration {
+            version: 15,
+            description: "create_full_text_search",
+            sql: r#"
+                -- Each indexed row gets a search id. The tables' implicit rowid would not do: VACUUM
+                -- may renumber it on tables with a TEXT primary key.
+                CREATE TABLE IF NOT EXISTS chat_messages_fts_ids (
+                    search_id INTEGER PRIMARY KEY,
+                    message_id TEXT NOT NULL UNIQUE
+                );
+                CREATE TABLE IF NOT EXISTS chat_threads_fts_ids (
+                    search_id INTEGER PRIMARY KEY,
+                    thread_id TEXT NOT NULL UNIQUE
+                );
+                INSERT INTO chat_messages_fts_ids (message_id) SELECT id FROM chat_messages ORDER BY rowid;
+                INSERT INTO chat_threads_fts_ids (thread_id) SELECT id FROM chat_threads ORDER BY rowid;
+
+                -- The indexed text by search id, read by the indexes for snippets and rebuilds
+                CREATE VIEW IF NOT EXISTS chat_messages_fts_content AS
+                SELECT i.search_id, m.content
+                FROM chat_messages_fts_ids i JOIN chat_messages m ON m.id = i.message_id;
+                CREATE VIEW IF NOT EXISTS chat_threads_fts_content AS
+                SELECT i.search_id, t.title, t.description
+                FROM chat_threads_fts_ids i JOIN chat_threads t ON t.id = i.thread_id;
+
+                CREATE VIRTUAL TABLE IF NOT EXISTS chat_messages_fts USING fts5(
+                    content,
+                    content = 'chat_messages_fts_content',
+                    content_rowid = 'search_id',
+                    tokenize = 'unicode61 remove_diacritics 2'
+                );
+
+                CREATE VIRTUAL TABLE IF NOT EXISTS chat_threads_fts USING fts5(
+                    title,
+                    description,
+                    content = 'chat_threads_fts_content',
+                    content_rowid = 'search_id',
+                    tokenize = 'unicode61 remove_diacritics 2'
+                );
+
+                CREATE TRIGGER IF NOT EXISTS chat_messages_fts_insert
+                AFTER INSERT ON chat_messages
+                BEGIN
+                    INSERT INTO chat_messages_fts_ids (message_id) VALUES (NEW.id);
+                    INSERT INTO chat_messages_fts (rowid, content)
+                    SELECT search_id, NEW.content FROM chat_messages_fts_ids WHERE message_id = NEW.id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS chat_messages_fts_delete
+                AFTER DELETE ON chat_messages
+                BEGIN
+                    INSERT INTO chat_messages_fts (chat_messages_fts, rowid, content)
+                    SELECT 'delete', search_id, OLD.content FROM chat_messages_fts_ids WHERE message_id = OLD.id;
+                    DELETE FROM chat_messages_fts_ids WHERE message_id = OLD.id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS chat_messages_fts_update
+                AFTER UPDATE OF content ON chat_messages
+                BEGIN
+                    INSERT INTO chat_messages_fts (chat_messages_fts, rowid, content)
+                    SELECT 'delete', search_id, OLD.content FROM chat_messages_fts_ids WHERE message_id = OLD.id;
+                    INSERT INTO chat_messages_fts (rowid, content)
+                    SELECT search_id, NEW.content FROM chat_messages_fts_ids WHERE message_id = NEW.id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS chat_threads_fts_insert
+                AFTER INSERT ON chat_threads
+                BEGIN
+                    INSERT INTO chat_threads_fts_ids (thread_id) VALUES (NEW.id);
+                    INSERT INTO chat_threads_fts (rowid, title, description)
+                    SELECT search_id, NEW.title, NEW.description FROM chat_threads_fts_ids WHERE thread_id = NEW.id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS chat_threads_fts_delete
+                AFTER DELETE ON chat_threads
+                BEGIN
+                    INSERT INTO chat_threads_fts (chat_threads_fts, rowid, title, description)
+                    SELECT 'delete', search_id, OLD.title, OLD.description FROM chat_threads_fts_ids WHERE thread_id = OLD.id;
+                    DELETE FROM chat_threads_fts_ids WHERE thread_id = OLD.id;
+                END;
+
+                CREATE TRIGGER IF NOT EXISTS chat_threads_fts_update
+                AFTER UPDATE OF title, description ON chat_threads
+                BEGIN
+                    INSERT INTO chat_threads_fts (chat_threads_fts, rowid, title, description)
+                    SELECT 'delete', search_id, OLD.title, OLD.description FROM chat_threads_fts_ids WHERE thread_id = OLD.id;
+                    INSERT INTO chat_threads_fts (rowid, title, description)
+                    SELECT search_id, NEW.title, NEW.description FROM chat_threads_fts_ids WHERE thread_id = NEW.id;
+                END;
+
+                -- Index what is already there
+                INSERT INTO chat_messages_fts (chat_messages_fts) VALUES ('rebuild');
+                INSERT INTO chat_threads_fts (chat_threads_fts) VALUES ('rebuild');
+            "#,
+            kind: MigrationKind::Up,
+        },
+        // Migration 16: Conversation trees; edits and regenerations are sibling branches
+        Migration {
+            version: 16,
+            description: "create_message_branches",
+            sql: r#"
+                -- An edit is a new sibling of the edited message, carrying the versions it replaced
+                ALTER TABLE chat_messages ADD COLUMN is_edited BOOLEAN NOT NULL DEFAULT FALSE;
+                ALTER TABLE chat_messages ADD COLUMN edit_history TEXT DEFAULT '[]'; -- JSON array of previous versions
+
+                -- Leaf of the branch the thread shows; its latest message when unset
+                ALTER TABLE chat_threads ADD COLUMN active_message_id TEXT REFERENCES chat_messages(id) ON DELETE SET NULL;
+
+                CREATE INDEX IF NOT EXISTS idx_chat_messages_parent_message_id ON chat_messages(parent_message_id);
+                CREATE INDEX IF NOT EXISTS idx_chat_messages_thread_created ON chat_messages(thread_id, created_at);
+
+                -- Parent each message follows. Messages saved without one (all messages from before
+                -- branches, and those the frontend still writes) follow the message before them in
+                -- their thread; an edit saved without one is an alternative first message.
+                CREATE VIEW IF NOT EXISTS chat_message_parents AS
+                SELECT
+                    m.id,
+                    m.thread_id,
+                    m.created_at,
+                    m.rowid AS position,
+                    CASE
+                        WHEN m.parent_message_id IS NOT NULL THEN m.parent_message_id
+                        WHEN m.is_edited THEN NULL
+                        ELSE (
+                            SELECT p.id FROM chat_messages p
+                            WHERE p.thread_id = m.thread_id
+                              AND (p.created_at < m.created_at
+                                   OR (p.created_at = m.created_at AND p.rowid < m.rowid))
+                            ORDER BY p.created_at DESC, p.rowid DESC
+                            LIMIT 1
+                        )
+                    END AS parent_id
+                FROM chat_messages m;
+            "#,
+            kind: MigrationKind::Up,
+        },
+    ]
+}
@@ -0,0 +1,373 @@
+//! Backend access to the app database (aye_mcp.db).
+//!
+//! The schema is owned by the SQL plugin migrations; this module opens its own pool on the
+//! same file for data the backend writes and aggregates, and its connections wait until the
+//! plugin has applied every migration. `projects`, `threads` and `messages`
+//! are the typed repository over the conversation tables.
+
+use sqlx::sqlite::{SqliteConnectOptions, SqliteConnection, SqlitePool, SqlitePoolOptions};
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tauri::State;
+
+pub mod branches;
+pub mod embeddings;
+pub mod error;
+pub mod export;
+pub mod import;
+pub mod messages;
+pub mod migrations;
+pub mod projects;
+pub mod rows;
+pub mod search;
+pub mod threads;
+pub mod usage;
+
+pub use error::{DbError, DbResult};
+use branches::Branches;
+use export::ExportFormat;
+use import::ImportSummary;
+use messages::{Message, MessageFilter, MessageUpdate, NewMessage};
+use projects::{NewProject, Project, ProjectFilter, ProjectUpdate};
+use rows::Page;
+use search::{SearchQuery, SearchResults};
+use threads::{NewThread, Thread, ThreadFilter, ThreadUpdate};
+use usage::{SpendFilter, SpendGroup, SpendRow};
+
+/// Database file used by the SQL plugin (`sqlite:aye_mcp.db`), relative to the app config dir
+pub const DB_FILE: &str = "aye_mcp.db";
+
+/// How long a new connection waits for the SQL plugin to migrate the database
+const MIGRATION_WAIT: Duration = Duration::from_secs(30);
+
+/// Shared connection pool
+#[derive(Debug, Clone)]
+pub struct Database {
+    pool: SqlitePool,
+}
+
+impl Database {
+    /// Open a pool on the database file; connections are made on first use, once migrated
+    pub fn open(path: &Path) -> Self {
+        let options = SqliteConnectOptions::new()
+            .filename(path)
+            .create_if_missing(true)
+            .foreign_keys(true);
+        Self {
+            pool: SqlitePoolOptions::new()
+                .max_connections(4)
+                .after_connect(|conn, _| Box::pin(wait_for_migrations(conn, MIGRATION_WAIT)))
+                .connect_lazy_with(options),
+        }
+    }
+
+    pub fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
+    /// In-memory database with all migrations applied
+    #[cfg(test)]
+    pub async fn in_memory() -> Self {
+        let pool = SqlitePoolOptions::new()
+            .max_connections(1)
+            .connect("sqlite::memory:")
+            .await
+            .unwrap();
+        for migration in migrations::all() {
+            sqlx::raw_sql(migration.sql).execute(&pool).await.unwrap();
+        }
+        Self { pool }
+    }
+
+    /// `in_memory`, with one thread `t1` in the default project
+    #[cfg(test)]
+    pub async fn with_thread() -> Self {
+        let db = Self::in_memory().await;
+        sqlx::query("INSERT INTO chat_threads (id, project_id, title) VALUES ('t1', 'default_project', 'Thread')")
+            .execute(db.pool())
+            .await
+            .unwrap();
+        db
+    }
+}
+
+/// Wait up to `wait` until the SQL plugin has applied every migration, so nothing is read or
+/// written against an older schema
+async fn wait_for_migrations(conn: &mut SqliteConnection, wait: Duration) -> Result<(), sqlx::Error> {
+    let latest = migrations::all().iter().map(|migration| migration.version).max().unwrap_or(0);
+    let started = Instant::now();
+    loop {
+        // The table is missing until the plugin starts migrating
+        let applied: Option<i64> = sqlx::query_scalar("SELECT MAX(version) FROM _sqlx_migrations WHERE success")
+            .fetch_one(&mut *conn)
+            .await
+            .unwrap_or(None);
+        if applied.is_some_and(|version| version >= latest) {
+            return Ok(());
+        }
+        if started.elapsed() >= wait {
+            return Err(sqlx::Error::Protocol(format!(
+                "database migrations are not applied (at version {}, expected {})",
+                applied.unwrap_or(0),
+                latest
+            )));
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
+/// One page of projects, most recently used first
+#[tauri::command]
+pub async fn list_projects(
+    filter: Option<ProjectFilter>,
+    db: State<'_, Database>,
+) -> Result<Page<Project>, DbError> {
+    projects::list_projects(&mut *db.pool().acquire().await?, &filter.unwrap_or_default()).await
+}
+
+/// A project by id
+#[tauri::command]
+pub async fn get_project(id: String, db: State<'_, Database>) -> Result<Project, DbError> {
+    projects::get_project(&mut *db.pool().acquire().await?, &id).await
+}
+
+/// Create a project; returns it as stored
+#[tauri::command]
+pub async fn create_project(project: NewProject, db: State<'_, Database>) -> Result<Project, DbError> {
+    projects::create_project(&mut *db.pool().acquire().await?, &project).await
+}
+
+/// Change the given fields of a project; returns it as stored
+#[tauri::command]
+pub async fn update_project(
+    id: String,
+    update: ProjectUpdate,
+    db: State<'_, Database>,
+) -> Result<Project, DbError> {
+    projects::update_project(&mut *db.pool().acquire().await?, &id, &update).await
+}
+
+/// Delete a project, keeping its threads without one
+#[tauri::command]
+pub async fn delete_project(id: String, db: State<'_, Database>) -> Result<(), DbError> {
+    projects::delete_project(&mut *db.pool().acquire().await?, &id).await
+}
+
+/// One page of threads, filtered and sorted
+#[tauri::command]
+pub async fn list_threads(
+    filter: Option<ThreadFilter>,
+    db: State<'_, Database>,
+) -> Result<Page<Thread>, DbError> {
+    threads::list_threads(&mut *db.pool().acquire().await?, &filter.unwrap_or_default()).await
+}
+
+/// A thread by id
+#[tauri::command]
+pub async fn get_thread(id: String, db: State<'_, Database>) -> Result<Thread, DbError> {
+    threads::get_thread(&mut *db.pool().acquire().await?, &id).await
+}
+
+/// Create a thread; returns it as stored
+#[tauri::command]
+pub async fn create_thread(thread: NewThread, db: State<'_, Database>) -> Result<Thread, DbError> {
+    threads::create_thread(&mut *db.pool().acquire().await?, &thread).await
+}
+
+/// Change the given fields of a thread; returns it as stored
+#[tauri::command]
+pub async fn update_thread(
+    id: String,
+    update: ThreadUpdate,
+    db: State<'_, Database>,
+) -> Result<Thread, DbError> {
+    threads::update_thread(&mut *db.pool().acquire().await?, &id, &update).await
+}
+
+/// Delete a thread and its messages
+#[tauri::command]
+pub async fn delete_thread(id: String, db: State<'_, Database>) -> Result<(), DbError> {
+    threads::delete_thread(&mut *db.pool().acquire().await?, &id).await
+}
+
+/// One page of messages, oldest first unless sorted otherwise
+#[tauri::command]
+pub async fn list_messages(
+    filter: Option<MessageFilter>,
+    db: State<'_, Database>,
+) -> Result<Page<Message>, DbError> {
+    messages::list_messages(&mut *db.pool().acquire().await?, &filter.unwrap_or_default()).await
+}
+
+/// A message by id
+#[tauri::command]
+pub async fn get_message(id: String, db: State<'_, Database>) -> Result<Message, DbError> {
+    messages::get_message(&mut *db.pool().acquire().await?, &id).await
+}
+
+/// Add a message to a thread; returns it as stored
+#[tauri::command]
+pub async fn create_message(message: NewMessage, db: State<'_, Database>) -> Result<Message, DbError> {
+    messages::create_message(&mut *db.pool().acquire().await?, &message).await
+}
+
+/// Change the given fields of a message in place; returns it as stored
+#[tauri::command]
+pub async fn update_message(
+    id: String,
+    update: MessageUpdate,
+    db: State<'_, Database>,
+) -> Result<Message, DbError> {
+    messages::update_message(&mut *db.pool().acquire().await?, &id, &update).await
+}
+
+/// Delete a message, keeping its replies
+#[tauri::command]
+pub async fn delete_message(id: String, db: State<'_, Database>) -> Result<(), DbError> {
+    messages::delete_message(&mut *db.pool().acquire().await?, &id).await
+}
+
+/// Spend per day (`YYYY-MM-DD`)
+#[tauri::command]
+pub async fn get_spend_by_day(
+    filter: Option<SpendFilter>,
+    db: State<'_, Database>,
+) -> Result<Vec<SpendRow>, DbError> {
+    usage::spend(db.pool(), SpendGroup::Day, &filter.unwrap_or_default()).await
+}
+
+/// Spend per model (`provider::model`)
+#[tauri::command]
+pub async fn get_spend_by_model(
+    filter: Option<SpendFilter>,
+    db: State<'_, Database>,
+) -> Result<Vec<SpendRow>, DbError> {
+    usage::spend(db.pool(), SpendGroup::Model, &filter.unwrap_or_default()).await
+}
+
+/// Spend per project
+#[tauri::command]
+pub async fn get_spend_by_project(
+    filter: Option<SpendFilter>,
+    db: State<'_, Database>,
+) -> Result<Vec<SpendRow>, DbError> {
+    usage::spend(db.pool(), SpendGroup::Project, &filter.unwrap_or_default()).await
+}
+
+/// Full-text search over messages and thread titles and descriptions
+#[tauri::command]
+pub async fn search_chat_history(
+    query: SearchQuery,
+    db: State<'_, Database>,
+) -> Result<SearchResults, DbError> {
+    search::search(db.pool(), &query).await
+}
+
+/// A thread as Markdown, lossless JSON or a self-contained HTML page
+#[tauri::command]
+pub async fn export_thread(
+    thread_id: String,
+    format: ExportFormat,
+    db: State<'_, Database>,
+) -> Result<String, DbError> {
+    export::render(&export::thread_archive(db.pool(), &thread_id).await?, format)
+}
+
+/// A project and all its threads as Markdown, lossless JSON or a self-contained HTML page
+#[tauri::command]
+pub async fn export_project(
+    project_id: String,
+    format: ExportFormat,
+    db: State<'_, Database>,
+) -> Result<String, DbError> {
+    export::render(&export::project_archive(db.pool(), &project_id).await?, format)
+}
+
+/// Import our JSON export, or a ChatGPT or Claude export (`.zip` or `conversations.json`)
+#[tauri::command]
+pub async fn import_conversations(
+    path: String,
+    project_id: Option<String>,
+    db: State<'_, Database>,
+) -> Result<ImportSummary, DbError> {
+    import::import_file(db.pool(), Path::new(&path), project_id.as_deref()).await
+}
+
+/// The thread's active branch, root first: the history its next message continues
+#[tauri::command]
+pub async fn get_active_path(
+    thread_id: String,
+    db: State<'_, Database>,
+) -> Result<Vec<Message>, DbError> {
+    branches::active_path(db.pool(), &thread_id).await
+}
+
+/// A message and its siblings (edits, regenerations), marking the one on the active branch
+#[tauri::command]
+pub async fn list_branches(
+    message_id: String,
+    db: State<'_, Database>,
+) -> Result<Branches, DbError> {
+    branches::branches(db.pool(), &message_id).await
+}
+
+/// Make the branch through a message the active one; returns the new active path
+#[tauri::command]
+pub async fn switch_branch(
+    message_id: String,
+    db: State<'_, Database>,
+) -> Result<Vec<Message>, DbError> {
+    branches::switch_branch(db.pool(), &message_id).await
+}
+
+/// Edit a user message as a new sibling branch; returns the new message
+#[tauri::command]
+pub async fn edit_message(
+    message_id: String,
+    content: String,
+    db: State<'_, Database>,
+) -> Result<Message, DbError> {
+    branches::edit_message(db.pool(), &message_id, &content).await
+}
+
+/// Copy the conversation up to a message into a new thread; returns its id
+#[tauri::command]
+pub async fn fork_thread(
+    message_id: String,
+    title: Option<String>,
+    db: State<'_, Database>,
+) -> Result<String, DbError> {
+    branches::fork_thread(db.pool(), &message_id, title.as_deref()).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_connections_wait_for_migrations() {
+        let db = Database::in_memory().await;
+        let mut conn = db.pool().acquire().await.unwrap();
+        let wait = Duration::from_millis(200);
+        assert!(wait_for_migrations(&mut conn, wait).await.is_err(), "the plugin has not migrated yet");
+
+        let latest = migrations::all().iter().map(|migration| migration.version).max().unwrap();
+        sqlx::query("CREATE TABLE _sqlx_migrations (version BIGINT PRIMARY KEY, success BOOLEAN NOT NULL)")
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        sqlx::query("INSERT INTO _sqlx_migrations (version, success) VALUES (?, TRUE)")
+            .bind(latest - 1)
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        assert!(wait_for_migrations(&mut conn, wait).await.is_err(), "one migration is missing");
+
+        sqlx::query("INSERT INTO _sqlx_migrations (version, success) VALUES (?, TRUE)")
+            .bind(latest)
+            .execute(&mut *conn)
+            .await
+            .unwrap();
+        wait_for_migrations(&mut conn, wait).await.unwrap();
+    }
+}
@@ -0,0 +1,355 @@
+//! Projects: typed rows, CRUD and list, and settings read by the backend.
+//!
+//! Like `threads` and `messages`, functions take a connection so they compose inside a
+//! transaction: pass `&mut tx`, or a connection acquired from the pool.
+
+use super::rows::{column_text, json_column, nullable, page_limit, Assignments, Page};
+use super::{DbError, DbResult};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{QueryBuilder, Row, Sqlite, SqliteConnection, SqlitePool};
+
+/// Project created by the first migrations; threads without one go here
+pub const DEFAULT_PROJECT: &str = "default_project";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Project {
+    pub id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub system_prompt: Option<String>,
+    pub default_model_provider: String,
+    pub default_model_name: String,
+    pub tool_presets: Value,
+    pub settings: Value,
+    pub is_archived: bool,
+    pub color: Option<String>,
+    pub icon: Option<String>,
+    pub priority: Option<i64>,
+    pub tags: Value,
+    pub folder_path: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+    pub last_accessed_at: Option<String>,
+}
+
+impl Project {
+    pub fn from_row(row: &SqliteRow) -> DbResult<Self> {
+        Ok(Self {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            description: row.try_get("description")?,
+            system_prompt: row.try_get("system_prompt")?,
+            default_model_provider: row.try_get("default_model_provider")?,
+            default_model_name: row.try_get("default_model_name")?,
+            tool_presets: json_column(row, "tool_presets")?,
+            settings: json_column(row, "settings")?,
+            is_archived: row.try_get::<Option<bool>, _>("is_archived")?.unwrap_or(false),
+            color: row.try_get("color")?,
+            icon: row.try_get("icon")?,
+            priority: row.try_get("priority")?,
+            tags: json_column(row, "tags")?,
+            folder_path: row.try_get("folder_path")?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+            last_accessed_at: row.try_get("last_accessed_at")?,
+        })
+    }
+}
+
+/// A project to create; the id is generated when not given
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NewProject {
+    pub id: Option<String>,
+    pub name: String,
+    pub description: Option<String>,
+    pub system_prompt: Option<String>,
+    pub default_model_provider: String,
+    pub default_model_name: String,
+    pub tool_presets: Value,
+    pub settings: Value,
+    pub color: Option<String>,
+    pub icon: Option<String>,
+    pub priority: Option<i64>,
+    pub tags: Value,
+    pub folder_path: Option<String>,
+}
+
+/// Changes to a project; fields left out are kept, nullable fields given as `null` are cleared
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ProjectUpdate {
+    pub name: Option<String>,
+    #[serde(deserialize_with = "nullable")]
+    pub description: Option<Option<String>>,
+    #[serde(deserialize_with = "nullable")]
+    pub system_prompt: Option<Option<String>>,
+    pub default_model_provider: Option<String>,
+    pub default_model_name: Option<String>,
+    pub tool_presets: Option<Value>,
+    pub settings: Option<Value>,
+    pub is_archived: Option<bool>,
+    pub color: Option<String>,
+    pub icon: Option<String>,
+    pub priority: Option<i64>,
+    pub tags: Option<Value>,
+    #[serde(deserialize_with = "nullable")]
+    pub folder_path: Option<Option<String>>,
+}
+
+/// Projects to list, most recently used first
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ProjectFilter {
+    /// Only archived or only active projects; both when not given
+    pub archived: Option<bool>,
+    /// Case-insensitive match on name or description
+    pub search: Option<String>,
+    pub tag: Option<String>,
+    pub limit: Option<u32>,
+    pub offset: u32,
+}
+
+pub async fn get_project(conn: &mut SqliteConnection, id: &str) -> DbResult<Project> {
+    let row = sqlx::query("SELECT * FROM projects WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| DbError::not_found("Project", id))?;
+    Project::from_row(&row)
+}
+
+pub async fn create_project(conn: &mut SqliteConnection, project: &NewProject) -> DbResult<Project> {
+    if project.name.trim().is_empty() {
+        return Err(DbError::invalid_input("Project name is required"));
+    }
+    let id = project
+        .id
+        .clone()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    sqlx::query(
+        r#"
+        INSERT INTO projects (id, name, description, system_prompt, default_model_provider, default_model_name,
+                              tool_presets, settings, color, icon, priority, tags, folder_path)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, COALESCE(?9, '#3b82f6'), COALESCE(?10, 'folder'),
+                COALESCE(?11, 1), ?12, ?13)
+        "#,
+    )
+    .bind(&id)
+    .bind(project.name.trim())
+    .bind(&project.description)
+    .bind(&project.system_prompt)
+    .bind(&project.default_model_provider)
+    .bind(&project.default_model_name)
+    .bind(column_text(&project.tool_presets, "[]"))
+    .bind(column_text(&project.settings, "{}"))
+    .bind(&project.color)
+    .bind(&project.icon)
+    .bind(project.priority)
+    .bind(column_text(&project.tags, "[]"))
+    .bind(&project.folder_path)
+    .execute(&mut *conn)
+    .await?;
+    get_project(conn, &id).await
+}
+
+pub async fn update_project(conn: &mut SqliteConnection, id: &str, update: &ProjectUpdate) -> DbResult<Project> {
+    if update.name.as_deref().is_some_and(|name| name.trim().is_empty()) {
+        return Err(DbError::invalid_input("Project name is required"));
+    }
+    let json = |value: &Option<Value>| value.as_ref().map(|value| column_text(value, ""));
+    let mut assignments = Assignments::new("projects");
+    assignments
+        .set("name", update.name.as_deref().map(|name| name.trim().to_string()))
+        .set("description", update.description.clone())
+        .set("system_prompt", update.system_prompt.clone())
+        .set("default_model_provider", update.default_model_provider.clone())
+        .set("default_model_name", update.default_model_name.clone())
+        .set("tool_presets", json(&update.tool_presets))
+        .set("settings", json(&update.settings))
+        .set("is_archived", update.is_archived)
+        .set("color", update.color.clone())
+        .set("icon", update.icon.clone())
+        .set("priority", update.priority)
+        .set("tags", json(&update.tags))
+        .set("folder_path", update.folder_path.clone());
+    assignments.execute(&mut *conn, id).await?;
+    get_project(conn, id).await
+}
+
+/// Delete a project; its threads are kept without a project. The default project stays.
+pub async fn delete_project(conn: &mut SqliteConnection, id: &str) -> DbResult<()> {
+    if id == DEFAULT_PROJECT {
+        return Err(DbError::invalid_input("The default project can't be deleted"));
+    }
+    let deleted = sqlx::query("DELETE FROM projects WHERE id = ?")
+        .bind(id)
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+    if deleted == 0 {
+        return Err(DbError::not_found("Project", id));
+    }
+    Ok(())
+}
+
+fn push_filter(query: &mut QueryBuilder<'_, Sqlite>, filter: &ProjectFilter) {
+    query.push(" WHERE 1 = 1");
+    if let Some(archived) = filter.archived {
+        query.push(" AND COALESCE(is_archived, FALSE) = ").push_bind(archived);
+    }
+    if let Some(search) = filter.search.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+        query
+            .push(" AND (instr(lower(name), lower(")
+            .push_bind(search.to_string())
+            .push(")) > 0 OR instr(lower(COALESCE(description, '')), lower(")
+            .push_bind(search.to_string())
+            .push(")) > 0)");
+    }
+    if let Some(tag) = &filter.tag {
+        query
+            .push(" AND EXISTS (SELECT 1 FROM json_each(CASE WHEN json_valid(tags) THEN tags ELSE '[]' END) WHERE value = ")
+            .push_bind(tag.clone())
+            .push(")");
+    }
+}
+
+pub async fn list_projects(conn: &mut SqliteConnection, filter: &ProjectFilter) -> DbResult<Page<Project>> {
+    let limit = page_limit(filter.limit);
+
+    let mut count = QueryBuilder::new("SELECT COUNT(*) FROM projects");
+    push_filter(&mut count, filter);
+    let total: i64 = count.build_query_scalar().fetch_one(&mut *conn).await?;
+
+    let mut query = QueryBuilder::new("SELECT * FROM projects");
+    push_filter(&mut query, filter);
+    query
+        .push(" ORDER BY COALESCE(last_accessed_at, updated_at) DESC, rowid DESC LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(filter.offset);
+    let items = query
+        .build()
+        .fetch_all(&mut *conn)
+        .await?
+        .iter()
+        .map(Project::from_row)
+        .collect::<DbResult<_>>()?;
+    Ok(Page {
+        items,
+        total,
+        limit,
+        offset: filter.offset,
+    })
+}
+
+/// Fallback models from the settings of a thread's project.
+///
+/// `None` when the thread has no project, the project sets none, or the setting is not a list
+/// of model names.
+pub async fn fallback_models(pool: &SqlitePool, thread_id: &str) -> DbResult<Option<Vec<String>>> {
+    let models: Option<Option<String>> = sqlx::query_scalar(
+        r#"
+        SELECT json_extract(p.settings, '$.fallback_models')
+        FROM chat_threads t
+        JOIN projects p ON p.id = t.project_id
+        WHERE t.id = ? AND json_valid(p.settings)
+        "#,
+    )
+    .bind(thread_id)
+    .fetch_optional(pool)
+    .await?;
+    Ok(models
+        .flatten()
+        .and_then(|models| serde_json::from_str(&models).ok()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_fallback_models_from_project_settings() {
+        let db = Database::in_memory().await;
+        sqlx::raw_sql(
+            r#"
+            INSERT INTO projects (id, name, settings)
+            VALUES ('p1', 'Chain', '{"fallback_models": ["openai::gpt-4o", "ollama::llama3"]}');
+            INSERT INTO chat_threads (id, project_id, title) VALUES ('t1', 'p1', 'With chain');
+            INSERT INTO chat_threads (id, project_id, title) VALUES ('t2', 'default_project', 'Without');
+            "#,
+        )
+        .execute(db.pool())
+        .await
+        .unwrap();
+
+        assert_eq!(
+            fallback_models(db.pool(), "t1").await.unwrap(),
+            Some(vec!["openai::gpt-4o".to_string(), "ollama::llama3".to_string()])
+        );
+        assert_eq!(fallback_models(db.pool(), "t2").await.unwrap(), None);
+        assert_eq!(fallback_models(db.pool(), "missing").await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_project_crud_and_list() {
+        let db = Database::in_memory().await;
+        let mut conn = db.pool().acquire().await.unwrap();
+
+        let project = create_project(
+            &mut conn,
+            &NewProject {
+                name: " Weather ".to_string(),
+                description: Some("Forecast bot".to_string()),
+                settings: json!({ "fallback_models": ["ollama::llama3"] }),
+                tags: json!(["bots"]),
+                ..NewProject::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(project.name, "Weather");
+        assert_eq!(project.color.as_deref(), Some("#3b82f6"));
+        assert_eq!(project.settings["fallback_models"][0], "ollama::llama3");
+        assert!(matches!(
+            create_project(&mut conn, &NewProject::default()).await,
+            Err(DbError::InvalidInput { .. })
+        ));
+
+        let update = ProjectUpdate {
+            is_archived: Some(true),
+            tags: Some(json!(["bots", "old"])),
+            ..ProjectUpdate::default()
+        };
+        let archived = update_project(&mut conn, &project.id, &update).await.unwrap();
+        assert!(archived.is_archived);
+        assert_eq!(archived.description.as_deref(), Some("Forecast bot"), "fields left out are kept");
+        let cleared: ProjectUpdate = serde_json::from_value(json!({ "description": null })).unwrap();
+        let cleared = update_project(&mut conn, &project.id, &cleared).await.unwrap();
+        assert_eq!(cleared.description, None, "null clears a field");
+        assert!(cleared.is_archived);
+
+        let page = list_projects(&mut conn, &ProjectFilter::default()).await.unwrap();
+        assert_eq!(page.total, 2, "the default project and the new one");
+        let filter = ProjectFilter {
+            archived: Some(true),
+            search: Some("forecast".to_string()),
+            tag: Some("old".to_string()),
+            ..ProjectFilter::default()
+        };
+        let page = list_projects(&mut conn, &filter).await.unwrap();
+        assert_eq!((page.total, page.items[0].id.as_str()), (1, project.id.as_str()));
+
+        delete_project(&mut conn, &project.id).await.unwrap();
+        assert!(matches!(get_project(&mut conn, &project.id).await, Err(DbError::NotFound { .. })));
+        assert!(matches!(
+            delete_project(&mut conn, DEFAULT_PROJECT).await,
+            Err(DbError::InvalidInput { .. })
+        ));
+    }
+}
@@ -0,0 +1,120 @@
+//! Helpers shared by the typed rows of `projects`, `threads` and `messages`
+
+use super::DbResult;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{Encode, QueryBuilder, Row, Sqlite, SqliteConnection, Type};
+
+/// Page size when a list does not ask for one
+pub const DEFAULT_LIMIT: u32 = 50;
+
+/// Largest page a list returns
+pub const MAX_LIMIT: u32 = 500;
+
+/// One page of a list, with the number of matching rows across all pages
+#[derive(Debug, Clone, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    pub total: i64,
+    pub limit: u32,
+    pub offset: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    Asc,
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    pub fn as_sql(self) -> &'static str {
+        match self {
+            Self::Asc => "ASC",
+            Self::Desc => "DESC",
+        }
+    }
+}
+
+/// Requested page size, defaulted and clamped to `1..=MAX_LIMIT`
+pub fn page_limit(limit: Option<u32>) -> u32 {
+    limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT)
+}
+
+/// A field of a partial update that can be cleared: left out is `None`, `null` is `Some(None)`.
+///
+/// Use with `#[serde(default)]` on the struct, as `deserialize_with` is not called for missing fields.
+pub fn nullable<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+/// A JSON column as JSON; text that does not parse is kept as a string
+pub fn json_column(row: &SqliteRow, column: &str) -> DbResult<Value> {
+    let text: Option<String> = row.try_get(column)?;
+    Ok(text.map_or(Value::Null, |text| {
+        serde_json::from_str(&text).unwrap_or(Value::String(text))
+    }))
+}
+
+/// A JSON value as stored in a JSON column; `default` when the value is absent
+pub fn column_text(value: &Value, default: &str) -> String {
+    match value {
+        Value::Null => default.to_string(),
+        Value::String(text) => text.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// `provider::model`, or just the model when the provider is unknown
+pub fn model_name(provider: Option<&str>, model: Option<&str>) -> Option<String> {
+    match (provider, model) {
+        (_, None | Some("")) => None,
+        (None | Some(""), Some(model)) => Some(model.to_string()),
+        (Some(provider), Some(model)) => Some(format!("{}::{}", provider, model)),
+    }
+}
+
+/// `UPDATE ... SET` of the fields a partial update gives; fields left out are kept
+pub struct Assignments<'a> {
+    query: QueryBuilder<'a, Sqlite>,
+    count: usize,
+}
+
+impl<'a> Assignments<'a> {
+    pub fn new(table: &str) -> Self {
+        Self {
+            query: QueryBuilder::new(format!("UPDATE {} SET ", table)),
+            count: 0,
+        }
+    }
+
+    /// Set `column` when a value is given
+    pub fn set<T>(&mut self, column: &str, value: Option<T>) -> &mut Self
+    where
+        T: 'a + Encode<'a, Sqlite> + Type<Sqlite> + Send,
+    {
+        if let Some(value) = value {
+            if self.count > 0 {
+                self.query.push(", ");
+            }
+            self.query.push(column).push(" = ").push_bind(value);
+            self.count += 1;
+        }
+        self
+    }
+
+    /// Apply to the row with `id`; does nothing when no field was given
+    pub async fn execute(mut self, conn: &mut SqliteConnection, id: &str) -> DbResult<()> {
+        if self.count > 0 {
+            self.query.push(" WHERE id = ").push_bind(id.to_string());
+            self.query.build().execute(conn).await?;
+        }
+        Ok(())
+    }
+}
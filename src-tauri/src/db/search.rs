@@ -0,0 +1,346 @@
+//! Full-text search over message content and thread titles and descriptions (FTS5)
+
+use super::DbResult;
+use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+
+/// A search over messages and threads; dates are `YYYY-MM-DD` (or timestamps) and inclusive.
+///
+/// In `query`, `"quoted words"` match as a phrase and `word*` as a prefix; all terms must match.
+/// Thread matches honor the project filter only.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct SearchQuery {
+    pub query: String,
+    pub project_id: Option<String>,
+    /// Message roles to include (`user`, `assistant`, ...); all if empty
+    pub roles: Vec<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// Model that wrote the message, bare or as `provider::model`
+    pub model: Option<String>,
+    /// Markers around matched terms in snippets, inserted as given; the text around them is
+    /// HTML-escaped
+    pub highlight_start: String,
+    pub highlight_end: String,
+    pub limit: u32,
+    pub offset: u32,
+}
+
+impl Default for SearchQuery {
+    fn default() -> Self {
+        Self {
+            query: String::new(),
+            project_id: None,
+            roles: Vec::new(),
+            from: None,
+            to: None,
+            model: None,
+            highlight_start: "<mark>".to_string(),
+            highlight_end: "</mark>".to_string(),
+            limit: 50,
+            offset: 0,
+        }
+    }
+}
+
+/// A message containing the query
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct MessageMatch {
+    pub message_id: String,
+    pub thread_id: String,
+    pub thread_title: String,
+    pub project_id: Option<String>,
+    pub role: String,
+    /// `provider::model`, for assistant messages
+    pub model: Option<String>,
+    pub created_at: String,
+    /// Content around the matched terms, HTML-escaped, with the terms highlighted
+    pub snippet: String,
+    /// BM25 relevance, lower is better
+    pub rank: f64,
+}
+
+/// A thread whose title or description contains the query
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct ThreadMatch {
+    pub thread_id: String,
+    pub project_id: Option<String>,
+    /// Title, HTML-escaped, with the matched terms highlighted
+    pub title: String,
+    /// Description around the matched terms, if it has any, escaped and highlighted alike
+    pub description: Option<String>,
+    pub rank: f64,
+}
+
+/// Put around matched terms by SQLite, and swapped for the highlight markers once the text
+/// around them is escaped
+const MATCH_START: char = '\u{E000}';
+const MATCH_END: char = '\u{E001}';
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct SearchResults {
+    pub messages: Vec<MessageMatch>,
+    pub threads: Vec<ThreadMatch>,
+}
+
+/// Turn user input into an FTS5 query: quoted phrases and `word*` prefixes are kept, everything
+/// else is quoted so punctuation cannot break the syntax. `None` if nothing is left to match.
+pub fn fts_query(input: &str) -> Option<String> {
+    let mut terms = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        let text: String = if c == '"' {
+            chars.next();
+            chars.by_ref().take_while(|&c| c != '"').collect()
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '"' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            word
+        };
+        let mut text = text.trim();
+        let mut prefix = false;
+        while let Some(stripped) = text.strip_suffix('*') {
+            text = stripped;
+            prefix = true;
+        }
+        if chars.peek() == Some(&'*') {
+            chars.next();
+            prefix = true;
+        }
+        if text.trim().is_empty() {
+            continue;
+        }
+        terms.push(format!("\"{}\"{}", text.trim(), if prefix { "*" } else { "" }));
+    }
+    (!terms.is_empty()).then(|| terms.join(" "))
+}
+
+/// Messages and threads matching a search, best first
+pub async fn search(pool: &SqlitePool, search: &SearchQuery) -> DbResult<SearchResults> {
+    let Some(query) = fts_query(&search.query) else {
+        return Ok(SearchResults::default());
+    };
+    let limit = search.limit.clamp(1, 500);
+
+    let mut messages: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT m.id AS message_id, m.thread_id, t.title AS thread_title, t.project_id, m.role, ");
+    messages
+        .push("CASE WHEN m.model_used IS NULL OR m.model_used = '' THEN NULL ")
+        .push("WHEN m.provider_used IS NULL OR m.provider_used = '' THEN m.model_used ")
+        .push("ELSE m.provider_used || '::' || m.model_used END AS model, ")
+        .push("m.created_at, snippet(chat_messages_fts, 0, ")
+        .push_bind(MATCH_START.to_string())
+        .push(", ")
+        .push_bind(MATCH_END.to_string())
+        .push(", '…', 16) AS snippet, bm25(chat_messages_fts) AS rank ")
+        .push("FROM chat_messages_fts f ")
+        .push("JOIN chat_messages_fts_ids i ON i.search_id = f.rowid ")
+        .push("JOIN chat_messages m ON m.id = i.message_id ")
+        .push("JOIN chat_threads t ON t.id = m.thread_id ")
+        .push("WHERE chat_messages_fts MATCH ")
+        .push_bind(query.clone());
+    if let Some(project_id) = &search.project_id {
+        messages.push(" AND t.project_id = ").push_bind(project_id.clone());
+    }
+    if !search.roles.is_empty() {
+        messages.push(" AND m.role IN (");
+        let mut roles = messages.separated(", ");
+        for role in &search.roles {
+            roles.push_bind(role.clone());
+        }
+        messages.push(")");
+    }
+    if let Some(from) = &search.from {
+        messages.push(" AND date(m.created_at) >= date(").push_bind(from.clone()).push(")");
+    }
+    if let Some(to) = &search.to {
+        messages.push(" AND date(m.created_at) <= date(").push_bind(to.clone()).push(")");
+    }
+    if let Some(model) = &search.model {
+        messages
+            .push(" AND (m.model_used = ")
+            .push_bind(model.clone())
+            .push(" OR m.provider_used || '::' || m.model_used = ")
+            .push_bind(model.clone())
+            .push(")");
+    }
+    messages
+        .push(" ORDER BY rank LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(search.offset);
+
+    let mut threads: QueryBuilder<Sqlite> = QueryBuilder::new("SELECT t.id AS thread_id, t.project_id, highlight(chat_threads_fts, 0, ");
+    threads
+        .push_bind(MATCH_START.to_string())
+        .push(", ")
+        .push_bind(MATCH_END.to_string())
+        .push(") AS title, CASE WHEN t.description IS NULL OR t.description = '' THEN NULL ELSE snippet(chat_threads_fts, 1, ")
+        .push_bind(MATCH_START.to_string())
+        .push(", ")
+        .push_bind(MATCH_END.to_string())
+        .push(", '…', 16) END AS description, bm25(chat_threads_fts) AS rank ")
+        .push("FROM chat_threads_fts f ")
+        .push("JOIN chat_threads_fts_ids i ON i.search_id = f.rowid ")
+        .push("JOIN chat_threads t ON t.id = i.thread_id ")
+        .push("WHERE chat_threads_fts MATCH ")
+        .push_bind(query);
+    if let Some(project_id) = &search.project_id {
+        threads.push(" AND t.project_id = ").push_bind(project_id.clone());
+    }
+    threads
+        .push(" ORDER BY rank LIMIT ")
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(search.offset);
+
+    let mut results = SearchResults {
+        messages: messages.build_query_as().fetch_all(pool).await?,
+        threads: threads.build_query_as().fetch_all(pool).await?,
+    };
+    for message in &mut results.messages {
+        message.snippet = highlight(&message.snippet, search);
+    }
+    for thread in &mut results.threads {
+        thread.title = highlight(&thread.title, search);
+        thread.description = thread.description.as_deref().map(|text| highlight(text, search));
+    }
+    Ok(results)
+}
+
+/// HTML-escape `text` and swap SQLite's match markers for the requested ones
+fn highlight(text: &str, search: &SearchQuery) -> String {
+    let mut html = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            MATCH_START => html.push_str(&search.highlight_start),
+            MATCH_END => html.push_str(&search.highlight_end),
+            '&' => html.push_str("&amp;"),
+            '<' => html.push_str("&lt;"),
+            '>' => html.push_str("&gt;"),
+            '"' => html.push_str("&quot;"),
+            '\'' => html.push_str("&#39;"),
+            c => html.push(c),
+        }
+    }
+    html
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+
+    #[test]
+    fn test_fts_query() {
+        assert_eq!(fts_query(r#"pool "dead lock" conn*"#).unwrap(), r#""pool" "dead lock" "conn"*"#);
+        assert_eq!(fts_query(r#"c++ OR -x "max:*"#).unwrap(), r#""c++" "OR" "-x" "max:"*"#);
+        assert_eq!(fts_query(r#""unclosed phrase"#).unwrap(), r#""unclosed phrase""#);
+        assert_eq!(fts_query(r#""phrase"* *"#).unwrap(), r#""phrase"*"#);
+        assert!(fts_query("  \"\" * ").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_search_messages_and_threads() {
+        let db = Database::in_memory().await;
+        sqlx::raw_sql(
+            r#"
+            INSERT INTO projects (id, name) VALUES ('p1', 'Backend');
+            INSERT INTO chat_threads (id, project_id, title, description)
+            VALUES ('t1', 'p1', 'Connection pool deadlock', 'Debugging sqlx under load');
+            INSERT INTO chat_threads (id, project_id, title) VALUES ('t2', 'default_project', 'Recipes');
+            INSERT INTO chat_messages (id, thread_id, role, content, model_used, provider_used, created_at) VALUES
+                ('m1', 't1', 'user', 'The pool deadlocks when two queries run at once', NULL, NULL, '2025-03-01 10:00:00'),
+                ('m2', 't1', 'assistant', 'Raise max_connections; the pool deadlocks because one connection is held', 'gpt-4o', 'openai', '2025-03-02 10:00:00'),
+                ('m3', 't2', 'user', 'A pool of boiling water', NULL, NULL, '2025-03-03 10:00:00');
+            "#,
+        )
+        .execute(db.pool())
+        .await
+        .unwrap();
+
+        let query = |text: &str| SearchQuery {
+            query: text.to_string(),
+            ..SearchQuery::default()
+        };
+
+        let results = search(db.pool(), &query(r#""pool deadlocks""#)).await.unwrap();
+        let ids: Vec<&str> = results.messages.iter().map(|m| m.message_id.as_str()).collect();
+        assert_eq!(ids.len(), 2);
+        assert!(!ids.contains(&"m3"));
+        assert!(results.messages[0].snippet.contains("<mark>pool deadlocks</mark>"));
+
+        let results = search(db.pool(), &query("deadl*")).await.unwrap();
+        assert_eq!(results.messages.len(), 2);
+        assert_eq!(results.threads.len(), 1);
+        assert_eq!(results.threads[0].title, "Connection pool <mark>deadlock</mark>");
+
+        let filtered = search(
+            db.pool(),
+            &SearchQuery {
+                roles: vec!["assistant".to_string()],
+                model: Some("openai::gpt-4o".to_string()),
+                from: Some("2025-03-02".to_string()),
+                ..query("pool")
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(filtered.messages.len(), 1);
+        assert_eq!(filtered.messages[0].message_id, "m2");
+        assert_eq!(filtered.messages[0].model.as_deref(), Some("openai::gpt-4o"));
+
+        let scoped = search(
+            db.pool(),
+            &SearchQuery {
+                project_id: Some("default_project".to_string()),
+                ..query("pool")
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(scoped.messages.len(), 1);
+        assert_eq!(scoped.messages[0].message_id, "m3");
+
+        // Triggers keep the index in sync with edits and deletes
+        sqlx::raw_sql(
+            r#"
+            UPDATE chat_messages SET content = 'Solved by a smaller transaction' WHERE id = 'm1';
+            DELETE FROM chat_messages WHERE id = 'm2';
+            UPDATE chat_threads SET title = 'Connection pool' WHERE id = 't1';
+            "#,
+        )
+        .execute(db.pool())
+        .await
+        .unwrap();
+        let results = search(db.pool(), &query("deadlocks")).await.unwrap();
+        assert!(results.messages.is_empty());
+        assert!(search(db.pool(), &query("deadlock")).await.unwrap().threads.is_empty());
+        assert_eq!(search(db.pool(), &query("transaction")).await.unwrap().messages[0].message_id, "m1");
+
+        // Stored text is escaped, so a snippet cannot inject markup
+        sqlx::raw_sql(
+            r#"
+            INSERT INTO chat_messages (id, thread_id, role, content) VALUES
+                ('m4', 't2', 'user', '<img src=x onerror="alert(1)"> kettle & lid');
+            "#,
+        )
+        .execute(db.pool())
+        .await
+        .unwrap();
+        assert_eq!(
+            search(db.pool(), &query("kettle")).await.unwrap().messages[0].snippet,
+            "&lt;img src=x onerror=&quot;alert(1)&quot;&gt; <mark>kettle</mark> &amp; lid"
+        );
+    }
+}
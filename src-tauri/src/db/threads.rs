@@ -0,0 +1,423 @@
+//! Chat threads: typed rows, CRUD and list.
+//!
+//! Message counters (`message_count`, `last_message_at`) are kept by triggers on `chat_messages`,
+//! so they are read here but never written.
+
+use super::projects::DEFAULT_PROJECT;
+use super::rows::{column_text, json_column, model_name, nullable, page_limit, Assignments, Page, SortOrder};
+use super::{DbError, DbResult};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::sqlite::SqliteRow;
+use sqlx::{QueryBuilder, Row, Sqlite, SqliteConnection};
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Thread {
+    pub id: String,
+    pub project_id: Option<String>,
+    pub title: String,
+    pub description: Option<String>,
+    pub is_archived: bool,
+    pub is_pinned: bool,
+    pub model_provider: String,
+    pub model_name: String,
+    pub system_prompt: Option<String>,
+    pub tool_presets: Value,
+    pub settings: Value,
+    pub tags: Value,
+    pub status: Option<String>,
+    pub thread_type: Option<String>,
+    pub priority: Option<i64>,
+    pub parent_thread_id: Option<String>,
+    /// Leaf of the branch the thread shows
+    pub active_message_id: Option<String>,
+    pub message_count: i64,
+    pub last_message_at: Option<String>,
+    pub avg_response_time: f64,
+    pub error_count: i64,
+    pub total_tokens: i64,
+    pub total_cost: f64,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Thread {
+    pub fn from_row(row: &SqliteRow) -> DbResult<Self> {
+        let count = |column: &str| -> DbResult<i64> { Ok(row.try_get::<Option<i64>, _>(column)?.unwrap_or(0)) };
+        Ok(Self {
+            id: row.try_get("id")?,
+            project_id: row.try_get("project_id")?,
+            title: row.try_get("title")?,
+            description: row.try_get("description")?,
+            is_archived: row.try_get("is_archived")?,
+            is_pinned: row.try_get("is_pinned")?,
+            model_provider: row.try_get("model_provider")?,
+            model_name: row.try_get("model_name")?,
+            system_prompt: row.try_get("system_prompt")?,
+            tool_presets: json_column(row, "tool_presets")?,
+            settings: json_column(row, "settings")?,
+            tags: json_column(row, "tags")?,
+            status: row.try_get("status")?,
+            thread_type: row.try_get("thread_type")?,
+            priority: row.try_get("priority")?,
+            parent_thread_id: row.try_get("parent_thread_id")?,
+            active_message_id: row.try_get("active_message_id")?,
+            message_count: count("message_count")?,
+            last_message_at: row.try_get("last_message_at")?,
+            avg_response_time: row.try_get::<Option<f64>, _>("avg_response_time")?.unwrap_or(0.0),
+            error_count: count("error_count")?,
+            total_tokens: count("total_tokens")?,
+            total_cost: row.try_get::<Option<f64>, _>("total_cost")?.unwrap_or(0.0),
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+        })
+    }
+
+    /// `provider::model`, if the thread has a model
+    pub fn model(&self) -> Option<String> {
+        model_name(Some(self.model_provider.as_str()), Some(self.model_name.as_str()))
+    }
+}
+
+/// A thread to create; the id is generated when not given, and the default project is used
+/// when no project is
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct NewThread {
+    pub id: Option<String>,
+    pub project_id: Option<String>,
+    pub title: String,
+    pub description: Option<String>,
+    pub model_provider: String,
+    pub model_name: String,
+    pub system_prompt: Option<String>,
+    pub tool_presets: Value,
+    pub settings: Value,
+    pub tags: Value,
+    pub thread_type: Option<String>,
+    pub priority: Option<i64>,
+    pub parent_thread_id: Option<String>,
+}
+
+/// Changes to a thread; fields left out are kept, nullable fields given as `null` are cleared
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ThreadUpdate {
+    #[serde(deserialize_with = "nullable")]
+    pub project_id: Option<Option<String>>,
+    pub title: Option<String>,
+    #[serde(deserialize_with = "nullable")]
+    pub description: Option<Option<String>>,
+    pub is_archived: Option<bool>,
+    pub is_pinned: Option<bool>,
+    pub model_provider: Option<String>,
+    pub model_name: Option<String>,
+    #[serde(deserialize_with = "nullable")]
+    pub system_prompt: Option<Option<String>>,
+    pub tool_presets: Option<Value>,
+    pub settings: Option<Value>,
+    pub tags: Option<Value>,
+    pub status: Option<String>,
+    pub thread_type: Option<String>,
+    pub priority: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ThreadSort {
+    CreatedAt,
+    #[default]
+    UpdatedAt,
+    LastMessageAt,
+    Title,
+}
+
+impl ThreadSort {
+    fn column(self) -> &'static str {
+        match self {
+            Self::CreatedAt => "created_at",
+            Self::UpdatedAt => "updated_at",
+            Self::LastMessageAt => "COALESCE(last_message_at, created_at)",
+            Self::Title => "title COLLATE NOCASE",
+        }
+    }
+}
+
+/// Threads to list, most recently updated first unless sorted otherwise
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct ThreadFilter {
+    pub project_id: Option<String>,
+    /// Only archived or only active threads; both when not given
+    pub archived: Option<bool>,
+    pub pinned: Option<bool>,
+    pub status: Option<String>,
+    pub thread_type: Option<String>,
+    pub tag: Option<String>,
+    /// Case-insensitive match on title or description
+    pub search: Option<String>,
+    pub sort_by: ThreadSort,
+    pub sort_order: SortOrder,
+    pub limit: Option<u32>,
+    pub offset: u32,
+}
+
+pub async fn get_thread(conn: &mut SqliteConnection, id: &str) -> DbResult<Thread> {
+    let row = sqlx::query("SELECT * FROM chat_threads WHERE id = ?")
+        .bind(id)
+        .fetch_optional(&mut *conn)
+        .await?
+        .ok_or_else(|| DbError::not_found("Thread", id))?;
+    Thread::from_row(&row)
+}
+
+pub async fn create_thread(conn: &mut SqliteConnection, thread: &NewThread) -> DbResult<Thread> {
+    let id = thread
+        .id
+        .clone()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    sqlx::query(
+        r#"
+        INSERT INTO chat_threads (id, project_id, title, description, model_provider, model_name, system_prompt,
+                                  tool_presets, settings, tags, thread_type, priority, parent_thread_id)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, COALESCE(?11, 'chat'), COALESCE(?12, 1), ?13)
+        "#,
+    )
+    .bind(&id)
+    .bind(thread.project_id.as_deref().unwrap_or(DEFAULT_PROJECT))
+    .bind(if thread.title.trim().is_empty() { "Untitled" } else { thread.title.trim() })
+    .bind(&thread.description)
+    .bind(&thread.model_provider)
+    .bind(&thread.model_name)
+    .bind(&thread.system_prompt)
+    .bind(column_text(&thread.tool_presets, "[]"))
+    .bind(column_text(&thread.settings, "{}"))
+    .bind(column_text(&thread.tags, "[]"))
+    .bind(&thread.thread_type)
+    .bind(thread.priority)
+    .bind(&thread.parent_thread_id)
+    .execute(&mut *conn)
+    .await?;
+    get_thread(conn, &id).await
+}
+
+pub async fn update_thread(conn: &mut SqliteConnection, id: &str, update: &ThreadUpdate) -> DbResult<Thread> {
+    if update.title.as_deref().is_some_and(|title| title.trim().is_empty()) {
+        return Err(DbError::invalid_input("Thread title is required"));
+    }
+    let json = |value: &Option<Value>| value.as_ref().map(|value| column_text(value, ""));
+    let mut assignments = Assignments::new("chat_threads");
+    assignments
+        .set("project_id", update.project_id.clone())
+        .set("title", update.title.as_deref().map(|title| title.trim().to_string()))
+        .set("description", update.description.clone())
+        .set("is_archived", update.is_archived)
+        .set("is_pinned", update.is_pinned)
+        .set("model_provider", update.model_provider.clone())
+        .set("model_name", update.model_name.clone())
+        .set("system_prompt", update.system_prompt.clone())
+        .set("tool_presets", json(&update.tool_presets))
+        .set("settings", json(&update.settings))
+        .set("tags", json(&update.tags))
+        .set("status", update.status.clone())
+        .set("thread_type", update.thread_type.clone())
+        .set("priority", update.priority);
+    assignments.execute(&mut *conn, id).await?;
+    get_thread(conn, id).await
+}
+
+/// Delete a thread with its messages; threads forked from it are kept
+pub async fn delete_thread(conn: &mut SqliteConnection, id: &str) -> DbResult<()> {
+    let deleted = sqlx::query("DELETE FROM chat_threads WHERE id = ?")
+        .bind(id)
+        .execute(&mut *conn)
+        .await?
+        .rows_affected();
+    if deleted == 0 {
+        return Err(DbError::not_found("Thread", id));
+    }
+    Ok(())
+}
+
+/// System prompt a thread's conversation runs with: the thread's own, else its project's
+pub async fn system_prompt(conn: &mut SqliteConnection, thread_id: &str) -> DbResult<Option<String>> {
+    let prompt: Option<Option<String>> = sqlx::query_scalar(
+        r#"
+        SELECT COALESCE(NULLIF(TRIM(t.system_prompt), ''), NULLIF(TRIM(p.system_prompt), ''))
+        FROM chat_threads t
+        LEFT JOIN projects p ON p.id = t.project_id
+        WHERE t.id = ?
+        "#,
+    )
+    .bind(thread_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    Ok(prompt.flatten())
+}
+
+fn push_filter(query: &mut QueryBuilder<'_, Sqlite>, filter: &ThreadFilter) {
+    query.push(" WHERE 1 = 1");
+    if let Some(project_id) = &filter.project_id {
+        query.push(" AND project_id = ").push_bind(project_id.clone());
+    }
+    if let Some(archived) = filter.archived {
+        query.push(" AND is_archived = ").push_bind(archived);
+    }
+    if let Some(pinned) = filter.pinned {
+        query.push(" AND is_pinned = ").push_bind(pinned);
+    }
+    if let Some(status) = &filter.status {
+        query.push(" AND status = ").push_bind(status.clone());
+    }
+    if let Some(thread_type) = &filter.thread_type {
+        query.push(" AND thread_type = ").push_bind(thread_type.clone());
+    }
+    if let Some(tag) = &filter.tag {
+        query
+            .push(" AND EXISTS (SELECT 1 FROM json_each(CASE WHEN json_valid(tags) THEN tags ELSE '[]' END) WHERE value = ")
+            .push_bind(tag.clone())
+            .push(")");
+    }
+    if let Some(search) = filter.search.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+        query
+            .push(" AND (instr(lower(title), lower(")
+            .push_bind(search.to_string())
+            .push(")) > 0 OR instr(lower(COALESCE(description, '')), lower(")
+            .push_bind(search.to_string())
+            .push(")) > 0)");
+    }
+}
+
+pub async fn list_threads(conn: &mut SqliteConnection, filter: &ThreadFilter) -> DbResult<Page<Thread>> {
+    let limit = page_limit(filter.limit);
+
+    let mut count = QueryBuilder::new("SELECT COUNT(*) FROM chat_threads");
+    push_filter(&mut count, filter);
+    let total: i64 = count.build_query_scalar().fetch_one(&mut *conn).await?;
+
+    let order = filter.sort_order.as_sql();
+    let mut query = QueryBuilder::new("SELECT * FROM chat_threads");
+    push_filter(&mut query, filter);
+    query
+        .push(format!(" ORDER BY {} {}, rowid {} LIMIT ", filter.sort_by.column(), order, order))
+        .push_bind(limit)
+        .push(" OFFSET ")
+        .push_bind(filter.offset);
+    let items = query
+        .build()
+        .fetch_all(&mut *conn)
+        .await?
+        .iter()
+        .map(Thread::from_row)
+        .collect::<DbResult<_>>()?;
+    Ok(Page {
+        items,
+        total,
+        limit,
+        offset: filter.offset,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_thread_filters_pages_and_transactions() {
+        let db = Database::in_memory().await;
+        let mut conn = db.pool().acquire().await.unwrap();
+        for (title, tags) in [("Paris weather", json!(["travel"])), ("Rust lifetimes", json!([])), ("Lyon trip", json!(["travel"]))] {
+            create_thread(
+                &mut conn,
+                &NewThread {
+                    title: title.to_string(),
+                    tags,
+                    ..NewThread::default()
+                },
+            )
+            .await
+            .unwrap();
+        }
+
+        let filter = ThreadFilter {
+            tag: Some("travel".to_string()),
+            sort_by: ThreadSort::Title,
+            sort_order: SortOrder::Asc,
+            limit: Some(1),
+            offset: 1,
+            ..ThreadFilter::default()
+        };
+        let page = list_threads(&mut conn, &filter).await.unwrap();
+        assert_eq!((page.total, page.limit, page.offset), (2, 1, 1));
+        assert_eq!(page.items[0].title, "Paris weather");
+        assert_eq!(page.items[0].project_id.as_deref(), Some(DEFAULT_PROJECT));
+
+        let search = ThreadFilter {
+            search: Some("RUST".to_string()),
+            ..ThreadFilter::default()
+        };
+        let rust = list_threads(&mut conn, &search).await.unwrap().items.remove(0);
+        let update = ThreadUpdate {
+            is_pinned: Some(true),
+            title: Some("Borrow checker".to_string()),
+            ..ThreadUpdate::default()
+        };
+        let pinned = update_thread(&mut conn, &rust.id, &update).await.unwrap();
+        assert!(pinned.is_pinned);
+        assert_eq!(pinned.title, "Borrow checker");
+        let blank = ThreadUpdate {
+            title: Some("  ".to_string()),
+            ..ThreadUpdate::default()
+        };
+        assert!(matches!(
+            update_thread(&mut conn, &rust.id, &blank).await,
+            Err(DbError::InvalidInput { .. })
+        ));
+        let moved: ThreadUpdate = serde_json::from_value(json!({ "project_id": null })).unwrap();
+        let moved = update_thread(&mut conn, &rust.id, &moved).await.unwrap();
+        assert_eq!(moved.project_id, None, "null clears a field");
+        assert_eq!(moved.title, "Borrow checker");
+        let page = list_threads(&mut conn, &ThreadFilter { pinned: Some(true), ..ThreadFilter::default() })
+            .await
+            .unwrap();
+        assert_eq!(page.total, 1);
+
+        // Writes in a transaction are all rolled back together
+        drop(conn);
+        let mut tx = db.pool().begin().await.unwrap();
+        delete_thread(&mut tx, &pinned.id).await.unwrap();
+        create_thread(&mut tx, &NewThread { title: "Draft".to_string(), ..NewThread::default() })
+            .await
+            .unwrap();
+        tx.rollback().await.unwrap();
+        let mut conn = db.pool().acquire().await.unwrap();
+        let page = list_threads(&mut conn, &ThreadFilter::default()).await.unwrap();
+        assert_eq!(page.total, 3);
+        assert!(matches!(delete_thread(&mut conn, "missing").await, Err(DbError::NotFound { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_system_prompt_falls_back_to_the_project() {
+        let db = Database::in_memory().await;
+        sqlx::raw_sql(
+            r#"
+            INSERT INTO projects (id, name, system_prompt) VALUES ('p1', 'Travel', 'You plan trips.');
+            INSERT INTO chat_threads (id, project_id, title, system_prompt) VALUES ('t1', 'p1', 'Own', 'Answer in French.');
+            INSERT INTO chat_threads (id, project_id, title, system_prompt) VALUES ('t2', 'p1', 'Inherited', ' ');
+            INSERT INTO chat_threads (id, project_id, title) VALUES ('t3', NULL, 'None');
+            "#,
+        )
+        .execute(db.pool())
+        .await
+        .unwrap();
+        let mut conn = db.pool().acquire().await.unwrap();
+
+        assert_eq!(system_prompt(&mut conn, "t1").await.unwrap().as_deref(), Some("Answer in French."));
+        assert_eq!(system_prompt(&mut conn, "t2").await.unwrap().as_deref(), Some("You plan trips."));
+        assert_eq!(system_prompt(&mut conn, "t3").await.unwrap(), None);
+        assert_eq!(system_prompt(&mut conn, "missing").await.unwrap(), None);
+    }
+}
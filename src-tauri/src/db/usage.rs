@@ -0,0 +1,273 @@
+//! Token and cost accounting per message, thread and project
+
+use super::DbResult;
+use genai::chat::Usage;
+use serde::{Deserialize, Serialize};
+use sqlx::{QueryBuilder, Sqlite, SqlitePool};
+use uuid::Uuid;
+
+/// Token counts for one provider response
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TokenUsage {
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    /// Reasoning/thinking tokens (included in completion tokens)
+    pub reasoning_tokens: i64,
+    /// Prompt tokens served from the provider's cache (included in prompt tokens)
+    pub cached_tokens: i64,
+    pub total_tokens: i64,
+}
+
+impl From<&Usage> for TokenUsage {
+    fn from(usage: &Usage) -> Self {
+        let prompt_tokens = usage.prompt_tokens.unwrap_or(0).max(0) as i64;
+        let completion_tokens = usage.completion_tokens.unwrap_or(0).max(0) as i64;
+        Self {
+            prompt_tokens,
+            completion_tokens,
+            reasoning_tokens: usage
+                .completion_tokens_details
+                .as_ref()
+                .and_then(|details| details.reasoning_tokens)
+                .unwrap_or(0) as i64,
+            cached_tokens: usage
+                .prompt_tokens_details
+                .as_ref()
+                .and_then(|details| details.cached_tokens)
+                .unwrap_or(0) as i64,
+            total_tokens: usage
+                .total_tokens
+                .map(i64::from)
+                .unwrap_or(prompt_tokens + completion_tokens),
+        }
+    }
+}
+
+/// Usage of one provider response, attributed to a message and thread when known
+#[derive(Debug, Clone)]
+pub struct UsageRecord {
+    pub message_id: Option<String>,
+    pub thread_id: Option<String>,
+    pub provider: String,
+    pub model: String,
+    pub usage: TokenUsage,
+    /// Cost in USD, if the model's pricing is known
+    pub cost: Option<f64>,
+}
+
+/// Store a usage record; triggers copy it onto the message and roll it up to the thread.
+///
+/// The project is taken from the thread. Unknown thread ids are stored as NULL.
+pub async fn record_usage(pool: &SqlitePool, record: &UsageRecord) -> DbResult<String> {
+    let id = Uuid::new_v4().to_string();
+    sqlx::query(
+        r#"
+        INSERT INTO usage_records (
+            id, message_id, thread_id, project_id, provider, model,
+            prompt_tokens, completion_tokens, reasoning_tokens, cached_tokens, total_tokens, cost
+        ) VALUES (
+            ?, ?,
+            (SELECT id FROM chat_threads WHERE id = ?),
+            (SELECT project_id FROM chat_threads WHERE id = ?),
+            ?, ?, ?, ?, ?, ?, ?, ?
+        )
+        "#,
+    )
+    .bind(&id)
+    .bind(&record.message_id)
+    .bind(&record.thread_id)
+    .bind(&record.thread_id)
+    .bind(&record.provider)
+    .bind(&record.model)
+    .bind(record.usage.prompt_tokens)
+    .bind(record.usage.completion_tokens)
+    .bind(record.usage.reasoning_tokens)
+    .bind(record.usage.cached_tokens)
+    .bind(record.usage.total_tokens)
+    .bind(record.cost)
+    .execute(pool)
+    .await?;
+
+    Ok(id)
+}
+
+/// Filters for spend queries; dates are `YYYY-MM-DD` (or timestamps) and inclusive
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct SpendFilter {
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub project_id: Option<String>,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+}
+
+/// Grouping for spend queries
+#[derive(Debug, Clone, Copy)]
+pub enum SpendGroup {
+    Day,
+    Model,
+    Project,
+}
+
+/// Aggregated usage for one group
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct SpendRow {
+    /// Day, `provider::model`, or project id (empty for usage outside a project)
+    pub key: String,
+    /// Project name, for project grouping
+    pub label: Option<String>,
+    pub requests: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub reasoning_tokens: i64,
+    pub cached_tokens: i64,
+    pub total_tokens: i64,
+    /// Cost in USD of responses with known pricing
+    pub cost: f64,
+}
+
+/// Aggregate usage by day, model or project
+pub async fn spend(pool: &SqlitePool, group: SpendGroup, filter: &SpendFilter) -> DbResult<Vec<SpendRow>> {
+    let (key, label, order) = match group {
+        SpendGroup::Day => ("date(u.created_at)", "NULL", "key ASC"),
+        SpendGroup::Model => ("u.provider || '::' || u.model", "NULL", "cost DESC, key ASC"),
+        SpendGroup::Project => ("COALESCE(u.project_id, '')", "MAX(p.name)", "cost DESC, key ASC"),
+    };
+
+    let mut query: QueryBuilder<Sqlite> = QueryBuilder::new(format!(
+        r#"
+        SELECT
+            {key} AS key,
+            {label} AS label,
+            COUNT(*) AS requests,
+            COALESCE(SUM(u.prompt_tokens), 0) AS prompt_tokens,
+            COALESCE(SUM(u.completion_tokens), 0) AS completion_tokens,
+            COALESCE(SUM(u.reasoning_tokens), 0) AS reasoning_tokens,
+            COALESCE(SUM(u.cached_tokens), 0) AS cached_tokens,
+            COALESCE(SUM(u.total_tokens), 0) AS total_tokens,
+            COALESCE(SUM(u.cost), 0.0) AS cost
+        FROM usage_records u
+        LEFT JOIN projects p ON p.id = u.project_id
+        WHERE 1 = 1
+        "#
+    ));
+    if let Some(from) = &filter.from {
+        query.push(" AND date(u.created_at) >= date(").push_bind(from.clone()).push(")");
+    }
+    if let Some(to) = &filter.to {
+        query.push(" AND date(u.created_at) <= date(").push_bind(to.clone()).push(")");
+    }
+    if let Some(project_id) = &filter.project_id {
+        query.push(" AND u.project_id = ").push_bind(project_id.clone());
+    }
+    if let Some(provider) = &filter.provider {
+        query.push(" AND u.provider = ").push_bind(provider.clone());
+    }
+    if let Some(model) = &filter.model {
+        query.push(" AND u.model = ").push_bind(model.clone());
+    }
+    query.push(format!(" GROUP BY key ORDER BY {order}"));
+
+    Ok(query.build_query_as::<SpendRow>().fetch_all(pool).await?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::Database;
+
+    fn record(thread_id: Option<&str>, model: &str, total: i64, cost: Option<f64>) -> UsageRecord {
+        UsageRecord {
+            message_id: None,
+            thread_id: thread_id.map(str::to_string),
+            provider: "openai".to_string(),
+            model: model.to_string(),
+            usage: TokenUsage {
+                prompt_tokens: total - 10,
+                completion_tokens: 10,
+                reasoning_tokens: 4,
+                cached_tokens: 0,
+                total_tokens: total,
+            },
+            cost,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_usage_rolls_up_to_message_and_thread() {
+        let db = Database::with_thread().await;
+        let mut first = record(Some("t1"), "gpt-4o", 100, Some(0.5));
+        first.message_id = Some("m1".to_string());
+        record_usage(db.pool(), &first).await.unwrap();
+        record_usage(db.pool(), &record(Some("t1"), "gpt-4o", 50, None)).await.unwrap();
+
+        // The message is saved after its usage was recorded
+        sqlx::query("INSERT INTO chat_messages (id, thread_id, role, content) VALUES ('m1', 't1', 'assistant', 'hi')")
+            .execute(db.pool())
+            .await
+            .unwrap();
+
+        let (tokens, cost): (i64, f64) = sqlx::query_as("SELECT total_tokens, total_cost FROM chat_threads WHERE id = 't1'")
+            .fetch_one(db.pool())
+            .await
+            .unwrap();
+        assert_eq!(tokens, 150);
+        assert_eq!(cost, 0.5);
+
+        let (completion, reasoning): (i64, i64) =
+            sqlx::query_as("SELECT completion_tokens, reasoning_tokens FROM chat_messages WHERE id = 'm1'")
+                .fetch_one(db.pool())
+                .await
+                .unwrap();
+        assert_eq!((completion, reasoning), (10, 4));
+    }
+
+    #[tokio::test]
+    async fn test_spend_grouping_and_filters() {
+        let db = Database::with_thread().await;
+        record_usage(db.pool(), &record(Some("t1"), "gpt-4o", 100, Some(0.5))).await.unwrap();
+        record_usage(db.pool(), &record(Some("t1"), "gpt-4o-mini", 40, Some(0.01))).await.unwrap();
+        record_usage(db.pool(), &record(Some("missing"), "gpt-4o", 10, Some(0.1))).await.unwrap();
+
+        let by_model = spend(db.pool(), SpendGroup::Model, &SpendFilter::default()).await.unwrap();
+        assert_eq!(by_model[0].key, "openai::gpt-4o");
+        assert_eq!(by_model[0].requests, 2);
+        assert_eq!(by_model[0].total_tokens, 110);
+
+        let by_project = spend(db.pool(), SpendGroup::Project, &SpendFilter::default()).await.unwrap();
+        let default_project = by_project.iter().find(|r| r.key == "default_project").unwrap();
+        assert_eq!(default_project.label.as_deref(), Some("Default Project"));
+        assert_eq!(default_project.total_tokens, 140);
+        // Usage for an unknown thread is kept outside any project
+        assert!(by_project.iter().any(|r| r.key.is_empty()));
+
+        let by_day = spend(db.pool(), SpendGroup::Day, &SpendFilter::default()).await.unwrap();
+        assert_eq!(by_day.len(), 1);
+        assert_eq!(by_day[0].requests, 3);
+
+        let filtered = spend(
+            db.pool(),
+            SpendGroup::Model,
+            &SpendFilter {
+                model: Some("gpt-4o-mini".to_string()),
+                ..SpendFilter::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert_eq!(filtered.len(), 1);
+
+        let future = spend(
+            db.pool(),
+            SpendGroup::Day,
+            &SpendFilter {
+                from: Some("2999-01-01".to_string()),
+                ..SpendFilter::default()
+            },
+        )
+        .await
+        .unwrap();
+        assert!(future.is_empty());
+    }
+}
@@ -0,0 +1,69 @@
+//! Recording token usage and cost for provider responses
+
+use super::context::estimate_tokens;
+use super::models::ModelPricing;
+use super::router::ModelRef;
+use crate::db::usage::{self, TokenUsage, UsageRecord};
+use crate::db::Database;
+use genai::chat::Usage;
+use tauri::{AppHandle, Manager, Runtime};
+
+/// Where a response's usage is attributed, captured when the request starts
+#[derive(Debug, Clone)]
+pub struct UsageTarget {
+    pub model: ModelRef,
+    pub pricing: Option<ModelPricing>,
+    pub thread_id: Option<String>,
+    pub message_id: Option<String>,
+}
+
+impl UsageTarget {
+    /// Persist usage for a response and return its token counts and cost.
+    ///
+    /// Storage failures are logged rather than returned so they never fail the chat itself.
+    pub async fn record<R: Runtime>(&self, app: &AppHandle<R>, usage: &Usage) -> (TokenUsage, Option<f64>) {
+        self.record_tokens(app, TokenUsage::from(usage)).await
+    }
+
+    /// Like `record`, estimating reasoning tokens from the reasoning text when the provider
+    /// does not report them (Anthropic and Gemini bill thinking as plain output tokens)
+    pub async fn record_with_reasoning<R: Runtime>(
+        &self,
+        app: &AppHandle<R>,
+        usage: &Usage,
+        reasoning: &str,
+    ) -> (TokenUsage, Option<f64>) {
+        let mut tokens = TokenUsage::from(usage);
+        if tokens.reasoning_tokens == 0 && !reasoning.is_empty() {
+            let estimate = estimate_tokens(self.model.adapter, reasoning) as i64;
+            tokens.reasoning_tokens = if tokens.completion_tokens > 0 {
+                estimate.min(tokens.completion_tokens)
+            } else {
+                estimate
+            };
+        }
+        self.record_tokens(app, tokens).await
+    }
+
+    async fn record_tokens<R: Runtime>(&self, app: &AppHandle<R>, tokens: TokenUsage) -> (TokenUsage, Option<f64>) {
+        let cost = self.pricing.map(|pricing| {
+            pricing.cost(tokens.prompt_tokens as u64, tokens.completion_tokens as u64)
+        });
+
+        if let Some(db) = app.try_state::<Database>() {
+            let record = UsageRecord {
+                message_id: self.message_id.clone(),
+                thread_id: self.thread_id.clone(),
+                provider: self.model.provider.clone(),
+                model: self.model.model.clone(),
+                usage: tokens.clone(),
+                cost,
+            };
+            if let Err(e) = usage::record_usage(db.pool(), &record).await {
+                tracing::warn!("Failed to record usage for {}: {}", self.model.qualified(), e);
+            }
+        }
+
+        (tokens, cost)
+    }
+}
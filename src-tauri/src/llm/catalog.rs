@@ -0,0 +1,303 @@
+//! Model capability catalog: context window, output limit, features and pricing per model
+
+use super::error::{GenAIError, GenAIResult};
+use super::models::{ModelCapabilities, ModelPricing};
+use super::router::ModelRef;
+use genai::chat::ChatOptions;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+/// Catalog shipped with the app
+const BUNDLED_CATALOG: &str = include_str!("model_catalog.json");
+
+/// Name of the user override file in the app config directory
+pub const USER_CATALOG_FILE: &str = "model_catalog.json";
+
+/// Known data for the models matching `pattern` on `provider`; unset fields are unknown
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ModelSpec {
+    /// Provider key (built-in name or custom provider id)
+    pub provider: String,
+    /// Model name pattern; `*` matches any run of characters
+    pub pattern: String,
+    #[serde(default)]
+    pub context_window: Option<u32>,
+    #[serde(default)]
+    pub max_output_tokens: Option<u32>,
+    #[serde(default)]
+    pub tools: Option<bool>,
+    #[serde(default)]
+    pub vision: Option<bool>,
+    #[serde(default)]
+    pub reasoning: Option<bool>,
+    #[serde(default)]
+    pub json_mode: Option<bool>,
+    /// USD per million input tokens
+    #[serde(default)]
+    pub input_price_per_mtok: Option<f64>,
+    /// USD per million output tokens
+    #[serde(default)]
+    pub output_price_per_mtok: Option<f64>,
+}
+
+impl ModelSpec {
+    /// Whether this entry covers `model` on `provider`
+    pub fn matches(&self, provider: &str, model: &str) -> bool {
+        self.provider == provider && glob_match(&self.pattern, model)
+    }
+
+    /// Non-wildcard length of the pattern; the most specific match wins
+    fn specificity(&self) -> usize {
+        self.pattern.chars().filter(|c| *c != '*').count()
+    }
+
+    /// Apply the fields set on `top` over this entry
+    fn overlay(&mut self, top: &ModelSpec) {
+        macro_rules! take {
+            ($($field:ident),*) => {
+                $(if top.$field.is_some() {
+                    self.$field = top.$field;
+                })*
+            };
+        }
+        take!(
+            context_window,
+            max_output_tokens,
+            tools,
+            vision,
+            reasoning,
+            json_mode,
+            input_price_per_mtok,
+            output_price_per_mtok
+        );
+    }
+
+    /// Capabilities for this model, with unknown fields taken from `fallback`
+    pub fn capabilities(&self, fallback: &ModelCapabilities) -> ModelCapabilities {
+        let vision = self.vision.unwrap_or(fallback.vision);
+        let mut modalities = fallback.modalities.clone();
+        if vision && !modalities.iter().any(|m| m == "image") {
+            modalities.push("image".to_string());
+        } else if !vision {
+            modalities.retain(|m| m != "image");
+        }
+
+        ModelCapabilities {
+            streaming: fallback.streaming,
+            tools: self.tools.unwrap_or(fallback.tools),
+            vision,
+            max_context_length: self.context_window.or(fallback.max_context_length),
+            max_output_tokens: self.max_output_tokens.or(fallback.max_output_tokens),
+            reasoning: self.reasoning.unwrap_or(fallback.reasoning),
+            json_mode: self.json_mode.unwrap_or(fallback.json_mode),
+            modalities,
+        }
+    }
+
+    /// Pricing, when both input and output prices are known
+    pub fn pricing(&self) -> Option<ModelPricing> {
+        Some(ModelPricing {
+            input_per_mtok: self.input_price_per_mtok?,
+            output_per_mtok: self.output_price_per_mtok?,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct CatalogFile {
+    version: u32,
+    models: Vec<ModelSpec>,
+}
+
+/// Versioned set of model specs, bundled with user overrides applied
+#[derive(Debug, Clone)]
+pub struct ModelCatalog {
+    /// Version of the bundled catalog
+    pub version: u32,
+    models: Vec<ModelSpec>,
+    /// User override file the catalog was loaded with
+    user_path: Option<PathBuf>,
+}
+
+impl Default for ModelCatalog {
+    fn default() -> Self {
+        Self::bundled()
+    }
+}
+
+impl ModelCatalog {
+    /// The catalog shipped with the app
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED_CATALOG, None).expect("bundled model_catalog.json is valid")
+    }
+
+    /// Bundled catalog with a user override file applied, if it exists.
+    ///
+    /// An invalid override is logged and ignored.
+    pub fn load(user_path: &Path) -> Self {
+        let mut catalog = match std::fs::read_to_string(user_path) {
+            Ok(overrides) => match Self::parse(BUNDLED_CATALOG, Some(&overrides)) {
+                Ok(catalog) => {
+                    tracing::info!("Loaded model catalog overrides from {}", user_path.display());
+                    catalog
+                }
+                Err(e) => {
+                    tracing::warn!("Ignoring invalid model catalog overrides in {}: {}", user_path.display(), e);
+                    Self::bundled()
+                }
+            },
+            Err(_) => Self::bundled(),
+        };
+        catalog.user_path = Some(user_path.to_path_buf());
+        catalog
+    }
+
+    /// Re-read the user override file this catalog was loaded with
+    pub fn reload(&self) -> Self {
+        match &self.user_path {
+            Some(path) => Self::load(path),
+            None => Self::bundled(),
+        }
+    }
+
+    /// Parse a catalog; override entries with the same provider and pattern are merged field
+    /// by field, others are added.
+    fn parse(bundled: &str, overrides: Option<&str>) -> GenAIResult<Self> {
+        let file: CatalogFile = serde_json::from_str(bundled)?;
+        let mut models = file.models;
+
+        if let Some(overrides) = overrides {
+            let overrides: CatalogFile = serde_json::from_str(overrides)?;
+            for spec in overrides.models {
+                match models
+                    .iter_mut()
+                    .find(|m| m.provider == spec.provider && m.pattern == spec.pattern)
+                {
+                    Some(existing) => existing.overlay(&spec),
+                    None => models.push(spec),
+                }
+            }
+        }
+
+        Ok(Self {
+            version: file.version,
+            models,
+            user_path: None,
+        })
+    }
+
+    /// Most specific spec for a model, if any
+    pub fn lookup(&self, model: &ModelRef) -> Option<&ModelSpec> {
+        self.models
+            .iter()
+            .filter(|spec| spec.matches(&model.provider, &model.model))
+            .max_by_key(|spec| spec.specificity())
+    }
+
+    /// Reject requests the model is known not to support
+    pub fn validate(&self, model: &ModelRef, options: &ChatOptions, has_tools: bool) -> GenAIResult<()> {
+        let Some(spec) = self.lookup(model) else {
+            return Ok(());
+        };
+
+        if has_tools && spec.tools == Some(false) {
+            return Err(GenAIError::invalid_request(format!(
+                "{} does not support tool calling",
+                model.qualified()
+            )));
+        }
+        if let (Some(requested), Some(limit)) = (options.max_tokens, spec.max_output_tokens) {
+            if requested > limit {
+                return Err(GenAIError::invalid_request(format!(
+                    "max_tokens {} exceeds the output limit of {} ({} tokens)",
+                    requested,
+                    model.qualified(),
+                    limit
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Match `text` against a pattern where `*` matches any run of characters
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or("");
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No wildcard: exact match
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use genai::adapter::AdapterKind;
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("gpt-4o*", "gpt-4o-2024-08-06"));
+        assert!(glob_match("command", "command"));
+        assert!(!glob_match("command", "command-light"));
+        assert!(glob_match("*", "anything"));
+        assert!(glob_match("claude-*-sonnet*", "claude-3-5-sonnet-20241022"));
+        assert!(!glob_match("o3*", "gpt-o3"));
+    }
+
+    #[test]
+    fn test_most_specific_match_wins() {
+        let catalog = ModelCatalog::bundled();
+        let mini = catalog
+            .lookup(&ModelRef::builtin(AdapterKind::OpenAI, "gpt-4o-mini-2024-07-18"))
+            .unwrap();
+        assert_eq!(mini.pattern, "gpt-4o-mini*");
+        assert_eq!(mini.input_price_per_mtok, Some(0.15));
+
+        assert!(catalog
+            .lookup(&ModelRef::builtin(AdapterKind::OpenAI, "unknown-model"))
+            .is_none());
+    }
+
+    #[test]
+    fn test_overrides_merge_and_add() {
+        let overrides = r#"{"version": 1, "models": [
+            {"provider": "openai", "pattern": "gpt-4o*", "input_price_per_mtok": 1.0},
+            {"provider": "vllm", "pattern": "qwen*", "context_window": 32768, "tools": false}
+        ]}"#;
+        let catalog = ModelCatalog::parse(BUNDLED_CATALOG, Some(overrides)).unwrap();
+
+        let gpt4o = catalog.lookup(&ModelRef::builtin(AdapterKind::OpenAI, "gpt-4o")).unwrap();
+        assert_eq!(gpt4o.input_price_per_mtok, Some(1.0));
+        assert_eq!(gpt4o.context_window, Some(128000));
+
+        let qwen = ModelRef {
+            provider: "vllm".to_string(),
+            adapter: AdapterKind::OpenAI,
+            model: "qwen2.5-coder".to_string(),
+        };
+        assert_eq!(catalog.lookup(&qwen).unwrap().context_window, Some(32768));
+    }
+
+    #[test]
+    fn test_validate() {
+        let catalog = ModelCatalog::bundled();
+        let haiku = ModelRef::builtin(AdapterKind::Anthropic, "claude-3-haiku-20240307");
+        assert!(catalog.validate(&haiku, &ChatOptions::default().with_max_tokens(4096), true).is_ok());
+        assert!(catalog.validate(&haiku, &ChatOptions::default().with_max_tokens(8192), false).is_err());
+
+        let reasoner = ModelRef::builtin(AdapterKind::DeepSeek, "deepseek-reasoner");
+        assert!(catalog.validate(&reasoner, &ChatOptions::default(), true).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+//! Fan-out comparison: one conversation streamed to several models at once
+
+use super::error::GenAIError;
+use super::models::{StreamState, StreamingEventPayload, StreamingEventType};
+use super::sessions::StreamRegistry;
+use super::stream;
+use crate::db::usage::TokenUsage;
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use uuid::Uuid;
+
+/// How one model did in a comparison, read from its stream's events
+#[derive(Debug, Clone, Serialize)]
+pub struct ModelComparison {
+    /// Model as requested
+    pub model: String,
+    /// `None` if the stream could not be started
+    pub stream_id: Option<Uuid>,
+    /// Stored assistant message, when the comparison runs in a thread
+    pub message_id: Option<String>,
+    pub state: StreamState,
+    /// Time from the start of the stream to the first content, reasoning or tool call
+    pub first_token_ms: Option<i64>,
+    /// Time from the start of the stream to its end
+    pub total_ms: Option<i64>,
+    pub usage: Option<TokenUsage>,
+    /// Cost in USD, if the model's pricing is known
+    pub cost: Option<f64>,
+    pub error: Option<String>,
+}
+
+/// Result of `compare_models`, models in the order they were requested
+#[derive(Debug, Clone, Serialize)]
+pub struct ComparisonResult {
+    pub comparison_id: Uuid,
+    pub models: Vec<ModelComparison>,
+    /// Time until the slowest stream ended
+    pub total_ms: i64,
+    /// Sum of the known costs
+    pub cost: Option<f64>,
+}
+
+impl ComparisonResult {
+    pub fn new(comparison_id: Uuid, models: Vec<ModelComparison>, started_at: DateTime<Utc>) -> Self {
+        let cost = models.iter().filter_map(|model| model.cost).reduce(|a, b| a + b);
+        Self {
+            comparison_id,
+            models,
+            total_ms: (Utc::now() - started_at).num_milliseconds(),
+            cost,
+        }
+    }
+}
+
+impl ModelComparison {
+    fn new(model: String, stream_id: Option<Uuid>, message_id: Option<String>) -> Self {
+        Self {
+            model,
+            stream_id,
+            message_id,
+            state: StreamState::Running,
+            first_token_ms: None,
+            total_ms: None,
+            usage: None,
+            cost: None,
+            error: None,
+        }
+    }
+
+    /// A model whose stream failed to start
+    pub fn not_started(model: String, error: &GenAIError) -> Self {
+        Self {
+            state: StreamState::Failed,
+            error: Some(error.to_string()),
+            ..Self::new(model, None, None)
+        }
+    }
+
+    /// Take what an event says about the end of the stream, its usage or failure
+    pub fn observe(&mut self, event: &StreamingEventPayload, started_at: DateTime<Utc>) {
+        let elapsed_ms = (event.timestamp - started_at).num_milliseconds();
+        match event.event_type {
+            StreamingEventType::End => {
+                self.state = StreamState::Completed;
+                self.total_ms = Some(elapsed_ms);
+                self.usage = serde_json::from_value(event.data["usage"].clone()).ok();
+                self.cost = event.data["cost"].as_f64();
+            }
+            StreamingEventType::Error => {
+                self.state = StreamState::Failed;
+                self.total_ms = Some(elapsed_ms);
+                self.error = event.data["error"].as_str().map(str::to_string);
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Follow a started stream to its end and summarize it, timed from the start of the stream.
+///
+/// A stream that ends without `End` or `Error` was stopped. The first token time comes from
+/// the session, as the journal may have dropped the first chunks by the time it is read.
+pub async fn watch(registry: &StreamRegistry, model: String, started: Result<Uuid, GenAIError>) -> ModelComparison {
+    let stream_id = match started {
+        Ok(stream_id) => stream_id,
+        Err(e) => return ModelComparison::not_started(model, &e),
+    };
+    let session = registry
+        .read()
+        .await
+        .get(&stream_id)
+        .map(|session| (session.journal.clone(), session.message_id.clone(), session.started_at));
+    let Some((journal, message_id, started_at)) = session else {
+        return ModelComparison::not_started(model, &GenAIError::session(format!("Stream {} is gone", stream_id)));
+    };
+
+    let mut comparison = ModelComparison::new(model, Some(stream_id), message_id);
+    let replay = journal.replay(0);
+    stream::follow(journal, replay, |event| {
+        comparison.observe(&event, started_at);
+        true
+    })
+    .await;
+    let first_token_at = registry.read().await.get(&stream_id).and_then(|session| session.first_token_at);
+    comparison.first_token_ms = first_token_at.map(|at| (at - started_at).num_milliseconds());
+    if comparison.state == StreamState::Running {
+        comparison.state = StreamState::Cancelled;
+    }
+    comparison
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::stream::StreamJournal;
+    use serde_json::json;
+
+    #[test]
+    fn test_observe_stream_events() {
+        let started_at = Utc::now();
+        let journal = StreamJournal::new(Uuid::new_v4(), 16);
+        let mut comparison = ModelComparison::new("gpt-4o".to_string(), None, None);
+        for (event_type, data) in [
+            (StreamingEventType::Start, json!({})),
+            (StreamingEventType::Chunk, json!({ "content": "Hi" })),
+            (StreamingEventType::Chunk, json!({ "content": " there" })),
+            (
+                StreamingEventType::End,
+                json!({
+                    "usage": { "prompt_tokens": 10, "completion_tokens": 2, "reasoning_tokens": 0,
+                               "cached_tokens": 0, "total_tokens": 12 },
+                    "cost": 0.0004,
+                }),
+            ),
+        ] {
+            comparison.observe(&journal.record(event_type, data), started_at);
+        }
+
+        assert_eq!(comparison.state, StreamState::Completed);
+        assert!(comparison.total_ms.unwrap() >= 0);
+        assert_eq!(comparison.first_token_ms, None);
+        assert_eq!(comparison.usage.as_ref().unwrap().total_tokens, 12);
+        assert_eq!(comparison.cost, Some(0.0004));
+
+        let failed = ModelComparison::not_started("nope".to_string(), &GenAIError::authentication("no key"));
+        let result = ComparisonResult::new(Uuid::new_v4(), vec![comparison, failed], started_at);
+        assert_eq!(result.models[1].state, StreamState::Failed);
+        assert_eq!(result.cost, Some(0.0004));
+    }
+}
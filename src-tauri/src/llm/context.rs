@@ -0,0 +1,332 @@
+//! Context-window fitting for outgoing conversations
+
+use super::error::{GenAIError, GenAIResult};
+use super::models::MessageInput;
+use genai::adapter::AdapterKind;
+use genai::chat::{ChatMessage, ChatOptions, ChatRequest};
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+use tiktoken_rs::CoreBPE;
+
+/// Per-message framing overhead (role markers, separators) in tokens
+const MESSAGE_OVERHEAD_TOKENS: usize = 4;
+
+/// How to shrink a conversation that doesn't fit the context window
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextStrategy {
+    /// Send everything; the provider rejects oversized requests
+    None,
+    /// Drop the oldest messages, whatever their role
+    DropOldest,
+    /// Drop the oldest messages, keeping system and pinned messages
+    KeepSystemAndPinned,
+    /// Replace the oldest droppable turns with a model-written summary
+    SummarizeMiddle,
+}
+
+/// Context fitting settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ContextConfig {
+    pub strategy: ContextStrategy,
+    /// Tokens reserved for the response when `max_tokens` is not set
+    pub reserve_output_tokens: u32,
+    /// Token budget for a generated summary
+    pub summary_max_tokens: u32,
+}
+
+impl Default for ContextConfig {
+    fn default() -> Self {
+        Self {
+            strategy: ContextStrategy::KeepSystemAndPinned,
+            reserve_output_tokens: 1024,
+            summary_max_tokens: 512,
+        }
+    }
+}
+
+/// What was trimmed to fit the context window, reported in response metadata
+#[derive(Debug, Clone, Serialize)]
+pub struct ContextReport {
+    pub strategy: ContextStrategy,
+    pub context_window: u32,
+    /// Tokens available for the prompt after reserving output
+    pub budget_tokens: usize,
+    pub estimated_tokens_before: usize,
+    pub estimated_tokens_after: usize,
+    /// Indices (in the request's message list) of messages not sent
+    pub dropped: Vec<usize>,
+    /// Whether the dropped messages were replaced by a summary
+    pub summarized: bool,
+    /// How the token counts were obtained
+    pub token_counter: TokenCounter,
+}
+
+/// How token counts are obtained for a provider
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenCounter {
+    /// OpenAI's `o200k_base` encoding, used by its current models
+    O200kBase,
+    /// Estimate from characters per token, for providers whose tokenizer is not bundled
+    Heuristic,
+}
+
+/// Result of planning: which messages to keep and which to drop
+#[derive(Debug)]
+pub struct ContextPlan {
+    pub keep: Vec<usize>,
+    pub dropped: Vec<usize>,
+    pub estimated_before: usize,
+    pub estimated_after: usize,
+}
+
+/// `o200k_base`, loaded on first use; `None` if it could not be loaded
+fn o200k_base() -> Option<&'static CoreBPE> {
+    static BPE: OnceLock<Option<CoreBPE>> = OnceLock::new();
+    BPE.get_or_init(|| {
+        tiktoken_rs::o200k_base()
+            .map_err(|e| tracing::warn!("Could not load the o200k_base tokenizer, estimating instead: {}", e))
+            .ok()
+    })
+    .as_ref()
+}
+
+/// The tokenizer of a provider, when it is bundled
+fn tokenizer(adapter: AdapterKind) -> Option<&'static CoreBPE> {
+    match adapter {
+        AdapterKind::OpenAI => o200k_base(),
+        _ => None,
+    }
+}
+
+/// How `estimate_tokens` counts for a provider
+pub fn token_counter(adapter: AdapterKind) -> TokenCounter {
+    match tokenizer(adapter) {
+        Some(_) => TokenCounter::O200kBase,
+        None => TokenCounter::Heuristic,
+    }
+}
+
+/// Token count of `text` for a provider: exact for OpenAI models, estimated for the others
+pub fn estimate_tokens(adapter: AdapterKind, text: &str) -> usize {
+    match tokenizer(adapter) {
+        Some(bpe) => bpe.encode_ordinary(text).len(),
+        None => heuristic_tokens(adapter, text),
+    }
+}
+
+/// Estimate the token count of `text` for a provider without its tokenizer.
+///
+/// Approximates each provider's tokenizer by its average characters per token for
+/// English text; non-ASCII characters are counted as one token each, which is close
+/// for CJK and conservative elsewhere.
+fn heuristic_tokens(adapter: AdapterKind, text: &str) -> usize {
+    let chars_per_token = match adapter {
+        // Claude's tokenizer produces more tokens than cl100k/o200k for the same text
+        AdapterKind::Anthropic => 3.5,
+        // Llama/Mistral sentencepiece vocabularies
+        AdapterKind::Ollama | AdapterKind::Groq => 3.7,
+        _ => 4.0,
+    };
+    let ascii = text.bytes().filter(|b| b.is_ascii()).count();
+    let other = text.chars().filter(|c| !c.is_ascii()).count();
+    (ascii as f64 / chars_per_token).ceil() as usize + other
+}
+
+/// Estimated tokens for one message, including framing
+pub fn estimate_message_tokens(adapter: AdapterKind, message: &MessageInput) -> usize {
+    estimate_tokens(adapter, &message.content) + MESSAGE_OVERHEAD_TOKENS
+}
+
+/// Decide which messages fit in `budget` tokens.
+///
+/// The last message (the prompt being answered) is never dropped. Returns an error when
+/// the protected messages alone exceed the budget.
+pub fn plan(
+    messages: &[MessageInput],
+    adapter: AdapterKind,
+    budget: usize,
+    strategy: ContextStrategy,
+) -> GenAIResult<ContextPlan> {
+    let sizes: Vec<usize> = messages.iter().map(|m| estimate_message_tokens(adapter, m)).collect();
+    let estimated_before: usize = sizes.iter().sum();
+
+    if estimated_before <= budget || strategy == ContextStrategy::None {
+        return Ok(ContextPlan {
+            keep: (0..messages.len()).collect(),
+            dropped: vec![],
+            estimated_before,
+            estimated_after: estimated_before,
+        });
+    }
+
+    let last = messages.len().saturating_sub(1);
+    let protected = |i: usize| {
+        i == last
+            || (strategy != ContextStrategy::DropOldest
+                && (messages[i].role == "system" || messages[i].pinned))
+    };
+
+    let mut total = estimated_before;
+    let mut dropped = Vec::new();
+    for (i, size) in sizes.iter().enumerate() {
+        if total <= budget {
+            break;
+        }
+        if !protected(i) {
+            total -= size;
+            dropped.push(i);
+        }
+    }
+
+    if total > budget {
+        return Err(GenAIError::invalid_request(format!(
+            "Conversation does not fit the context window: ~{} tokens needed after trimming, {} available",
+            total, budget
+        )));
+    }
+
+    Ok(ContextPlan {
+        keep: (0..messages.len()).filter(|i| !dropped.contains(i)).collect(),
+        dropped,
+        estimated_before,
+        estimated_after: total,
+    })
+}
+
+/// The messages a plan keeps, with `summary` where the first dropped message was.
+///
+/// The summary is sent as a user message: providers that take the system prompt separately
+/// would move a system message to the start of the conversation.
+pub fn with_summary(messages: &[MessageInput], plan: &ContextPlan, summary: Option<String>) -> Vec<MessageInput> {
+    let mut summary = summary.map(|text| MessageInput {
+        role: "user".to_string(),
+        content: format!("Summary of earlier conversation:\n{}", text),
+        pinned: false,
+    });
+    let first_dropped = plan.dropped.first().copied().unwrap_or(messages.len());
+    let mut kept = Vec::with_capacity(plan.keep.len() + 1);
+    for &i in &plan.keep {
+        if i > first_dropped {
+            kept.extend(summary.take());
+        }
+        kept.push(messages[i].clone());
+    }
+    kept.extend(summary);
+    kept
+}
+
+/// Ask the model to summarize `messages` in at most `max_tokens` tokens
+pub async fn summarize(
+    client: &genai::Client,
+    model: &str,
+    messages: &[&MessageInput],
+    max_tokens: u32,
+) -> GenAIResult<String> {
+    let transcript = messages
+        .iter()
+        .map(|m| format!("{}: {}", m.role, m.content))
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    let chat_req = ChatRequest::new(vec![
+        ChatMessage::system(
+            "Summarize the following conversation excerpt. Keep facts, decisions, names, numbers \
+             and open questions; omit pleasantries. Reply with the summary only.",
+        ),
+        ChatMessage::user(transcript),
+    ]);
+    let options = ChatOptions::default().with_max_tokens(max_tokens);
+
+    let res = client.exec_chat(model, chat_req, Some(&options)).await?;
+    res.first_text()
+        .map(|text| text.trim().to_string())
+        .filter(|text| !text.is_empty())
+        .ok_or_else(|| GenAIError::api("Summary response was empty"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A provider counted by characters, so message sizes are predictable
+    const ESTIMATED: AdapterKind = AdapterKind::Gemini;
+
+    fn msg(role: &str, content: &str, pinned: bool) -> MessageInput {
+        MessageInput {
+            role: role.to_string(),
+            content: content.to_string(),
+            pinned,
+        }
+    }
+
+    fn conversation() -> Vec<MessageInput> {
+        let long = "x".repeat(400); // ~100 tokens
+        vec![
+            msg("system", "You are helpful.", false),
+            msg("user", &long, false),
+            msg("assistant", &long, false),
+            msg("user", &long, true),
+            msg("assistant", &long, false),
+            msg("user", "And now?", false),
+        ]
+    }
+
+    #[test]
+    fn test_estimate_tokens_by_provider() {
+        assert_eq!(token_counter(AdapterKind::OpenAI), TokenCounter::O200kBase);
+        assert_eq!(estimate_tokens(AdapterKind::OpenAI, "Hello world"), 2);
+
+        let text = "a".repeat(400);
+        assert_eq!(token_counter(AdapterKind::Anthropic), TokenCounter::Heuristic);
+        assert_eq!(estimate_tokens(AdapterKind::Gemini, &text), 100);
+        assert!(estimate_tokens(AdapterKind::Anthropic, &text) > 100);
+        assert_eq!(estimate_tokens(AdapterKind::Gemini, "日本語"), 3);
+    }
+
+    #[test]
+    fn test_fits_without_trimming() {
+        let plan = plan(&conversation(), ESTIMATED, 10_000, ContextStrategy::DropOldest).unwrap();
+        assert!(plan.dropped.is_empty());
+        assert_eq!(plan.keep.len(), 6);
+    }
+
+    #[test]
+    fn test_drop_oldest_drops_system_first() {
+        let plan = plan(&conversation(), ESTIMATED, 150, ContextStrategy::DropOldest).unwrap();
+        assert_eq!(plan.dropped, vec![0, 1, 2, 3]);
+        assert!(plan.estimated_after <= 150);
+    }
+
+    #[test]
+    fn test_keep_system_and_pinned() {
+        let plan = plan(
+            &conversation(),
+            ESTIMATED,
+            150,
+            ContextStrategy::KeepSystemAndPinned,
+        )
+        .unwrap();
+        assert_eq!(plan.dropped, vec![1, 2, 4]);
+        assert_eq!(plan.keep, vec![0, 3, 5]);
+    }
+
+    #[test]
+    fn test_summary_takes_the_place_of_dropped_messages() {
+        let messages = conversation();
+        let plan = plan(&messages, ESTIMATED, 150, ContextStrategy::KeepSystemAndPinned).unwrap();
+        let kept = with_summary(&messages, &plan, Some("Asked twice.".to_string()));
+        let roles: Vec<&str> = kept.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["system", "user", "user", "user"]);
+        assert_eq!(kept[1].content, "Summary of earlier conversation:\nAsked twice.");
+        assert!(kept[2].pinned);
+        assert_eq!(with_summary(&messages, &plan, None).len(), 3);
+    }
+
+    #[test]
+    fn test_protected_messages_over_budget_fail() {
+        let result = plan(&conversation(), ESTIMATED, 50, ContextStrategy::KeepSystemAndPinned);
+        assert!(result.is_err());
+    }
+}
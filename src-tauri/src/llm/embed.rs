@@ -0,0 +1,217 @@
+//! Text embeddings from a local Ollama model or a provider's OpenAI-compatible endpoint, and the
+//! background indexer that embeds chat messages for semantic search
+
+use super::error::{GenAIError, GenAIResult};
+use super::ollama::OllamaClient;
+use super::GenAIState;
+use crate::db::{embeddings, Database};
+use reqwest::header::HeaderMap;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sqlx::SqlitePool;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, Runtime};
+
+/// Embedding model used unless configured otherwise; runs locally, so history stays on the machine
+pub const DEFAULT_EMBEDDING_MODEL: &str = "ollama::nomic-embed-text";
+
+/// Longest text sent for embedding, in characters; longer messages are embedded by their start
+const MAX_INPUT_CHARS: usize = 8000;
+
+/// Time allowed to reach a provider, and for a whole embedding request
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Embedding model and background indexing settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EmbeddingConfig {
+    /// Model used for messages and search queries, as `provider::model`
+    pub model: String,
+    /// Embed new and edited messages in the background
+    pub auto_index: bool,
+    /// Messages embedded per request
+    pub batch_size: usize,
+    /// Seconds between indexing passes
+    pub index_interval_secs: u64,
+}
+
+impl Default for EmbeddingConfig {
+    fn default() -> Self {
+        Self {
+            model: DEFAULT_EMBEDDING_MODEL.to_string(),
+            auto_index: true,
+            batch_size: 32,
+            index_interval_secs: 60,
+        }
+    }
+}
+
+/// Where embedding requests for a model go
+#[derive(Debug, Clone)]
+pub enum EmbeddingEndpoint {
+    /// Ollama's native `/api/embed`
+    Ollama { base_url: String },
+    /// An OpenAI-compatible `/embeddings` endpoint, with its auth headers
+    OpenAI { url: String, headers: HeaderMap },
+}
+
+impl EmbeddingEndpoint {
+    /// Embed texts, one vector per input, in input order
+    pub async fn embed(&self, model: &str, inputs: &[String]) -> GenAIResult<Vec<Vec<f32>>> {
+        let vectors = match self {
+            Self::Ollama { base_url } => OllamaClient::new(base_url).embed(model, inputs).await?,
+            Self::OpenAI { url, headers } => openai_embed(url, headers, model, inputs).await?,
+        };
+        if vectors.len() != inputs.len() {
+            return Err(GenAIError::api(format!(
+                "Expected {} embeddings from {}, got {}",
+                inputs.len(),
+                model,
+                vectors.len()
+            )));
+        }
+        Ok(vectors)
+    }
+}
+
+#[derive(Deserialize)]
+struct OpenAIEmbeddings {
+    data: Vec<OpenAIEmbedding>,
+}
+
+#[derive(Deserialize)]
+struct OpenAIEmbedding {
+    #[serde(default)]
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+async fn openai_embed(url: &str, headers: &HeaderMap, model: &str, inputs: &[String]) -> GenAIResult<Vec<Vec<f32>>> {
+    let started = Instant::now();
+    let request_error = |e: reqwest::Error| match (e.is_timeout(), e.is_connect()) {
+        (true, true) => GenAIError::timeout_elapsed("connect", CONNECT_TIMEOUT, started.elapsed()),
+        (true, false) => GenAIError::timeout_elapsed("total", REQUEST_TIMEOUT, started.elapsed()),
+        _ => GenAIError::api(e.to_string()),
+    };
+    let res = super::build_http_client(CONNECT_TIMEOUT, headers.clone())?
+        .post(url)
+        .timeout(REQUEST_TIMEOUT)
+        .header("content-type", "application/json")
+        .body(json!({ "model": model, "input": inputs }).to_string())
+        .send()
+        .await
+        .map_err(request_error)?;
+
+    let status = res.status();
+    let body = res.bytes().await.map_err(request_error)?;
+    if !status.is_success() {
+        return Err(GenAIError::from_http_status(status.as_u16(), &String::from_utf8_lossy(&body), None));
+    }
+    let mut parsed: OpenAIEmbeddings = serde_json::from_slice(&body)?;
+    parsed.data.sort_by_key(|embedding| embedding.index);
+    Ok(parsed.data.into_iter().map(|embedding| embedding.embedding).collect())
+}
+
+/// Text as sent for embedding: trimmed, and cut to `MAX_INPUT_CHARS`
+pub fn embedding_input(text: &str) -> String {
+    text.trim().chars().take(MAX_INPUT_CHARS).collect()
+}
+
+/// Embed one batch of messages that are new or edited since they were embedded with the
+/// configured model; returns how many messages were tried.
+///
+/// When the batch fails, its messages are embedded one at a time and those that still fail
+/// are recorded, so a message the model rejects does not hold up the rest. Only when every
+/// message fails with an error worth retrying (the endpoint is down) is nothing recorded.
+pub async fn index_pending(state: &GenAIState, pool: &SqlitePool) -> GenAIResult<usize> {
+    let config = state.config.read().await.settings.embedding.clone();
+    let model = state.resolve_model(&config.model).await?;
+    let model_id = model.qualified();
+
+    let pending = embeddings::pending_messages(pool, &model_id, config.batch_size.max(1)).await?;
+    if pending.is_empty() {
+        return Ok(0);
+    }
+    let endpoint = state.embedding_endpoint(&model).await?;
+    let inputs: Vec<String> = pending.iter().map(|message| embedding_input(&message.content)).collect();
+    let batch_error = match endpoint.embed(&model.model, &inputs).await {
+        Ok(vectors) => {
+            for (message, vector) in pending.iter().zip(&vectors) {
+                embeddings::store_embedding(pool, &message.id, &model_id, &message.updated_at, vector).await?;
+            }
+            return Ok(pending.len());
+        }
+        Err(e) => e,
+    };
+
+    // Find the messages the model rejects
+    let mut failures = Vec::new();
+    if pending.len() == 1 {
+        failures.push((&pending[0], batch_error));
+    } else {
+        for (message, input) in pending.iter().zip(inputs) {
+            match endpoint.embed(&model.model, &[input]).await {
+                Ok(vectors) => {
+                    embeddings::store_embedding(pool, &message.id, &model_id, &message.updated_at, &vectors[0]).await?
+                }
+                Err(e) => failures.push((message, e)),
+            }
+        }
+    }
+    if failures.len() == pending.len() && failures.iter().all(|(_, e)| e.is_retryable()) {
+        return Err(failures.swap_remove(0).1);
+    }
+    for (message, e) in &failures {
+        tracing::debug!("Could not embed message {} with {}: {}", message.id, model_id, e);
+        embeddings::record_failure(pool, &message.id, &model_id, &message.updated_at, &e.to_string()).await?;
+    }
+    Ok(pending.len())
+}
+
+/// Embed new and edited messages in the background while `embedding.auto_index` is on.
+///
+/// Each pass works through the backlog batch by batch; a failure to reach the model ends the
+/// pass and is logged once until it changes, so an absent Ollama does not flood the log.
+pub fn spawn_indexer<R: Runtime>(app: AppHandle<R>) {
+    tauri::async_runtime::spawn(async move {
+        let mut last_error: Option<String> = None;
+        loop {
+            let Some(state) = app.try_state::<GenAIState>() else {
+                return;
+            };
+            let config = state.config.read().await.settings.embedding.clone();
+            tokio::time::sleep(Duration::from_secs(config.index_interval_secs.max(1))).await;
+            if !config.auto_index {
+                continue;
+            }
+            let Some(db) = app.try_state::<Database>() else {
+                continue;
+            };
+
+            let mut indexed = 0;
+            loop {
+                match index_pending(&state, db.pool()).await {
+                    Ok(count) => {
+                        last_error = None;
+                        indexed += count;
+                        if count < config.batch_size.max(1) {
+                            break;
+                        }
+                    }
+                    Err(e) => {
+                        let message = e.to_string();
+                        if last_error.as_ref() != Some(&message) {
+                            tracing::warn!("Message indexing with {} failed: {}", config.model, message);
+                        }
+                        last_error = Some(message);
+                        break;
+                    }
+                }
+            }
+            if indexed > 0 {
+                tracing::debug!("Embedded {} messages with {}", indexed, config.model);
+            }
+        }
+    });
+}
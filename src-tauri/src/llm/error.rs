@@ -2,6 +2,7 @@
 
 use genai::Error as GErr;
 use serde::{Deserialize, Serialize};
+use std::time::Duration;
 use thiserror::Error;
 
 /// Main error type for the GenAI Tauri integration
@@ -33,7 +34,12 @@ pub enum GenAIError {
 
     /// Network/API errors
     #[error("API error: {message}")]
-    Api { message: String },
+    Api {
+        message: String,
+        /// HTTP status returned by the provider, if any
+        #[serde(default)]
+        status: Option<u16>,
+    },
 
     /// Serialization/deserialization errors
     #[error("Serialization error: {message}")]
@@ -61,11 +67,24 @@ pub enum GenAIError {
 
     /// Rate limit exceeded
     #[error("Rate limit exceeded: {details}")]
-    RateLimit { details: String },
+    RateLimit {
+        details: String,
+        /// Delay requested by the provider through `Retry-After`
+        #[serde(default)]
+        retry_after_ms: Option<u64>,
+    },
 
     /// Timeout error
-    #[error("Request timeout: {timeout_seconds}s")]
-    Timeout { timeout_seconds: u64 },
+    #[error("Request timeout: {timeout_seconds}s ({phase}, elapsed {elapsed_ms}ms)")]
+    Timeout {
+        timeout_seconds: u64,
+        /// Time spent before giving up
+        #[serde(default)]
+        elapsed_ms: u64,
+        /// Which limit expired (connect, first_token, total, idle)
+        #[serde(default)]
+        phase: String,
+    },
 
     /// Insufficient permissions
     #[error("Insufficient permissions: {operation}")]
@@ -122,6 +141,15 @@ impl GenAIError {
     pub fn api(message: impl Into<String>) -> Self {
         Self::Api {
             message: message.into(),
+            status: None,
+        }
+    }
+
+    /// Create an API error carrying the provider's HTTP status
+    pub fn api_status(status: u16, message: impl Into<String>) -> Self {
+        Self::Api {
+            message: message.into(),
+            status: Some(status),
         }
     }
 
@@ -157,12 +185,25 @@ impl GenAIError {
     pub fn rate_limit(details: impl Into<String>) -> Self {
         Self::RateLimit {
             details: details.into(),
+            retry_after_ms: None,
+        }
+    }
+
+    /// Create a rate limit error with the provider-requested retry delay
+    pub fn rate_limit_after(details: impl Into<String>, retry_after: Option<Duration>) -> Self {
+        Self::RateLimit {
+            details: details.into(),
+            retry_after_ms: retry_after.map(|d| d.as_millis() as u64),
         }
     }
 
-    /// Create a timeout error
-    pub fn timeout(timeout_seconds: u64) -> Self {
-        Self::Timeout { timeout_seconds }
+    /// Create a timeout error for a specific phase with the elapsed time
+    pub fn timeout_elapsed(phase: impl Into<String>, limit: Duration, elapsed: Duration) -> Self {
+        Self::Timeout {
+            timeout_seconds: limit.as_secs_f64().ceil() as u64,
+            elapsed_ms: elapsed.as_millis() as u64,
+            phase: phase.into(),
+        }
     }
 
     /// Create a permission error
@@ -196,19 +237,101 @@ impl GenAIError {
 
     /// Check if the error is retryable
     pub fn is_retryable(&self) -> bool {
-        matches!(
-            self,
-            Self::Api { .. } | Self::Timeout { .. } | Self::RateLimit { .. }
-        )
+        match self {
+            // Client errors (bad request, not found, ...) won't succeed on retry
+            Self::Api { status, .. } => status.is_none_or(|s| s >= 500 || s == 408 || s == 409),
+            Self::Timeout { .. } | Self::RateLimit { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Check if the next model in a fallback chain should be tried: the provider is down,
+    /// rejected the credentials or is rate limiting. Bad requests would fail everywhere.
+    pub fn is_fallback_trigger(&self) -> bool {
+        match self {
+            Self::Api { status, .. } => status.is_none_or(|s| s >= 500),
+            Self::Authentication { .. }
+            | Self::RateLimit { .. }
+            | Self::Timeout { .. }
+            | Self::ModelNotAvailable { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Delay requested by the provider before retrying, if any
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            Self::RateLimit { retry_after_ms, .. } => retry_after_ms.map(Duration::from_millis),
+            _ => None,
+        }
+    }
+
+    /// Classify an HTTP failure from a provider by status code
+    pub fn from_http_status(status: u16, body: &str, retry_after: Option<Duration>) -> Self {
+        let message = format!("HTTP {}: {}", status, body);
+        match status {
+            401 | 403 => Self::authentication(message),
+            429 => Self::rate_limit_after(message, retry_after),
+            400 | 404 | 413 | 422 => Self::invalid_request(message),
+            _ => Self::api_status(status, message),
+        }
+    }
+}
+
+/// Parse a `Retry-After` header value, given either as seconds or as an HTTP date
+pub fn parse_retry_after(value: &str) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<f64>() {
+        return Duration::try_from_secs_f64(seconds).ok();
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let delta = date.with_timezone(&chrono::Utc) - chrono::Utc::now();
+    Some(delta.to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Classify a transport-level error from the genai web client
+fn from_webc_error(err: &genai::webc::Error) -> Option<GenAIError> {
+    use genai::webc::Error as WebcErr;
+
+    match err {
+        WebcErr::ResponseFailedStatus { status, body, headers } => {
+            let retry_after = headers
+                .get("retry-after")
+                .and_then(|v| v.to_str().ok())
+                .and_then(parse_retry_after);
+            Some(GenAIError::from_http_status(status.as_u16(), body, retry_after))
+        }
+        // The limit and the time spent are not known here; `Timeouts::run` fills them in
+        WebcErr::Reqwest(e) if e.is_timeout() => Some(GenAIError::Timeout {
+            timeout_seconds: 0,
+            elapsed_ms: 0,
+            phase: if e.is_connect() { "connect" } else { "total" }.to_string(),
+        }),
+        // Connection refused or reset while sending: the only failures without a status worth retrying
+        WebcErr::Reqwest(e) if e.is_connect() || e.is_request() => Some(GenAIError::api(e.to_string())),
+        _ => None,
     }
 }
 
 // Convert from genai errors
 impl From<GErr> for GenAIError {
     fn from(err: GErr) -> Self {
-        Self::Api {
-            message: err.to_string(),
-        }
+        let classified = match &err {
+            GErr::WebModelCall { webc_error, .. } | GErr::WebAdapterCall { webc_error, .. } => {
+                from_webc_error(webc_error)
+            }
+            GErr::RequiresApiKey { .. }
+            | GErr::NoAuthData { .. }
+            | GErr::NoAuthResolver { .. }
+            | GErr::Resolver { .. } => Some(Self::authentication(err.to_string())),
+            GErr::ChatReqHasNoMessages { .. } | GErr::LastChatMessageIsNotUser { .. } => {
+                Some(Self::invalid_request(err.to_string()))
+            }
+            _ => None,
+        };
+
+        // Parse errors, unsupported features and the like fail the same way on every attempt
+        classified.unwrap_or_else(|| Self::generic(err.to_string()))
     }
 }
 
@@ -230,6 +353,15 @@ impl From<std::io::Error> for GenAIError {
     }
 }
 
+// Convert from database errors
+impl From<crate::db::DbError> for GenAIError {
+    fn from(err: crate::db::DbError) -> Self {
+        Self::Generic {
+            message: err.to_string(),
+        }
+    }
+}
+
 // Convert from anyhow errors
 impl From<anyhow::Error> for GenAIError {
     fn from(err: anyhow::Error) -> Self {
@@ -292,11 +424,55 @@ mod tests {
 
     #[test]
     fn test_retryable_errors() {
-        let timeout_err = GenAIError::timeout(30);
+        let timeout_err = GenAIError::timeout_elapsed("total", Duration::from_secs(30), Duration::from_secs(30));
         assert!(timeout_err.is_retryable());
 
         let session_err = GenAIError::session("test");
         assert!(!session_err.is_retryable());
+
+        // Unclassified genai errors are deterministic, so they are neither retried nor passed on
+        let internal_err = GenAIError::from(GErr::Internal("unexpected response shape".to_string()));
+        assert!(!internal_err.is_retryable());
+        assert!(!internal_err.is_fallback_trigger());
+    }
+
+    #[test]
+    fn test_fallback_triggers() {
+        assert!(GenAIError::from_http_status(401, "bad key", None).is_fallback_trigger());
+        assert!(GenAIError::from_http_status(429, "slow down", None).is_fallback_trigger());
+        assert!(GenAIError::from_http_status(503, "overloaded", None).is_fallback_trigger());
+        assert!(!GenAIError::from_http_status(400, "bad schema", None).is_fallback_trigger());
+        assert!(!GenAIError::invalid_request("too long").is_fallback_trigger());
+    }
+
+    #[test]
+    fn test_http_status_classification() {
+        let err = GenAIError::from_http_status(429, "slow down", Some(Duration::from_secs(2)));
+        assert_eq!(err.category(), "rate_limit");
+        assert!(err.is_retryable());
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(2)));
+
+        let err = GenAIError::from_http_status(401, "bad key", None);
+        assert_eq!(err.category(), "authentication");
+        assert!(!err.is_retryable());
+
+        let err = GenAIError::from_http_status(400, "bad request", None);
+        assert!(!err.is_retryable());
+
+        let err = GenAIError::from_http_status(529, "overloaded", None);
+        assert_eq!(err.category(), "api");
+        assert!(err.is_retryable());
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        assert_eq!(parse_retry_after("12"), Some(Duration::from_secs(12)));
+        assert_eq!(parse_retry_after("Wed, 21 Oct 2015 07:28:00 GMT"), Some(Duration::ZERO));
+        assert_eq!(parse_retry_after("soon"), None);
+        assert_eq!(parse_retry_after("-1"), None);
+        assert_eq!(parse_retry_after("inf"), None);
+        assert_eq!(parse_retry_after("NaN"), None);
+        assert_eq!(parse_retry_after("1e30"), None);
     }
 
     #[test]
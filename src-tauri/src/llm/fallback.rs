@@ -0,0 +1,112 @@
+//! Fallback chains: models tried in order when a provider is down, rejects the key or rate limits
+
+use super::error::GenAIError;
+use super::models::GenAIConfig;
+use crate::db::{projects, Database};
+use serde::Serialize;
+use tauri::{AppHandle, Manager, Runtime};
+
+/// A model that failed before another one answered
+#[derive(Debug, Clone, Serialize)]
+pub struct FallbackAttempt {
+    pub model: String,
+    pub error: String,
+    pub category: &'static str,
+}
+
+impl FallbackAttempt {
+    pub fn new(model: &str, error: &GenAIError) -> Self {
+        Self {
+            model: model.to_string(),
+            error: error.to_string(),
+            category: error.category(),
+        }
+    }
+}
+
+/// Models to try for a request, `primary` first.
+///
+/// Fallbacks come from the request if it lists any (an empty list disables falling back), else
+/// from the thread's project, else from the primary model's configuration. Repeats are dropped.
+pub fn chain(primary: &str, requested: Option<&[String]>, project: Option<&[String]>, config: &GenAIConfig) -> Vec<String> {
+    let fallbacks = requested
+        .or(project)
+        .or_else(|| config.model_config(primary).map(|model| model.fallbacks.as_slice()))
+        .unwrap_or_default();
+
+    let mut chain = vec![primary.to_string()];
+    for model in fallbacks {
+        if !model.is_empty() && !chain.contains(model) {
+            chain.push(model.clone());
+        }
+    }
+    chain
+}
+
+/// Fallbacks configured in the settings of a thread's project (`settings.fallback_models`)
+pub async fn project_fallbacks<R: Runtime>(app: &AppHandle<R>, thread_id: &str) -> Option<Vec<String>> {
+    let db = app.try_state::<Database>()?;
+    match projects::fallback_models(db.pool(), thread_id).await {
+        Ok(models) => models,
+        Err(e) => {
+            tracing::warn!("Failed to read fallback models for thread {}: {}", thread_id, e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::models::ModelConfig;
+    use crate::llm::timeout::TimeoutConfig;
+    use genai::chat::ChatOptions;
+    use std::collections::HashMap;
+
+    fn config_with_fallbacks(model: &str, fallbacks: &[&str]) -> GenAIConfig {
+        let mut config = GenAIConfig::default();
+        config.model_configs.insert(
+            model.to_string(),
+            ModelConfig {
+                model: model.to_string(),
+                options: ChatOptions::default(),
+                parameters: HashMap::new(),
+                enabled: true,
+                timeouts: TimeoutConfig::default(),
+                fallbacks: fallbacks.iter().map(|model| model.to_string()).collect(),
+            },
+        );
+        config
+    }
+
+    fn strings(models: &[&str]) -> Vec<String> {
+        models.iter().map(|model| model.to_string()).collect()
+    }
+
+    #[test]
+    fn test_chain_precedence() {
+        let config = config_with_fallbacks("claude-sonnet-4", &["openai::gpt-4o", "ollama::llama3"]);
+        assert_eq!(
+            chain("anthropic::claude-sonnet-4", None, None, &config),
+            strings(&["anthropic::claude-sonnet-4", "openai::gpt-4o", "ollama::llama3"])
+        );
+
+        let project = strings(&["groq::llama-3.3-70b"]);
+        assert_eq!(
+            chain("claude-sonnet-4", None, Some(&project), &config),
+            strings(&["claude-sonnet-4", "groq::llama-3.3-70b"])
+        );
+
+        let none: Vec<String> = Vec::new();
+        assert_eq!(chain("claude-sonnet-4", Some(&none), Some(&project), &config), strings(&["claude-sonnet-4"]));
+    }
+
+    #[test]
+    fn test_chain_drops_repeats() {
+        let requested = strings(&["gpt-4o", "gpt-4o", "", "gpt-4o-mini"]);
+        assert_eq!(
+            chain("gpt-4o", Some(&requested), None, &GenAIConfig::default()),
+            strings(&["gpt-4o", "gpt-4o-mini"])
+        );
+    }
+}
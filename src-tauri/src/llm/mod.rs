@@ -1,35 +1,156 @@
 use error::{GenAIError, GenAIResult};
-use genai::{chat::{ChatMessage, ChatOptions, ChatRequest, ChatStreamEvent, ToolCall }, resolver::{AuthData, AuthResolver}, Client, ModelIden};
+use genai::{chat::{ChatMessage, ChatOptions, ChatRequest, Tool, ToolCall }, resolver::{AuthData, AuthResolver}, Client, ModelIden};
 use models::{
+    ApiDialect,
+    AuthHeaderStyle,
     AuthProvider, 
+    AuthProviderType,
+    CustomProvider,
     GenAIConfig, 
+    MessageInput,
     ModelConfig, 
     ModelInfo, 
     ModelCapabilities, 
+    ModelPricing,
     ProviderConfig, 
     ProviderOperationResponse, 
     SaveProviderKeyRequest, 
+    ActiveStream,
+    StreamingEventPayload,
     StreamingEventType,
     StreamingSession,
+    StreamState,
+    StreamSubscription,
     ChatResponse,
+    CompareRequest,
     ResponseMetadata,
     DirectChatRequest,
+    EmbedRequest,
+    EmbedResponse,
     StreamingRequest,
-    StreamingEventPayload,
 };
-use utils::repair_json;
-use std::collections::HashMap;
+use genai::adapter::AdapterKind;
+use genai::resolver::Endpoint;
+use genai::ServiceTarget;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use ollama::{KeepAlive, OllamaClient, OllamaModel, OllamaModelSettings};
+use accounting::UsageTarget;
+use catalog::ModelCatalog;
+use compare::ComparisonResult;
+use context::{ContextReport, ContextStrategy};
+use crate::db::embeddings::{self as message_embeddings, SemanticHit};
+use crate::db::{branches, threads, Database};
+use embed::EmbeddingEndpoint;
+use fallback::FallbackAttempt;
+use options::resolve_chat_options;
+use persist::{tool_calls_json, MessageWriter, ThinkingTrace};
+use reasoning::ReasoningConfig;
+use recorder::{record_stream, RecordTarget};
+use crate::db::messages::{FinishedMessage, MessageStatus, NewAssistantMessage};
+use registry::ProviderRegistry;
+use replay::{Fixture, ReplayStore};
+use retry::{with_retry, RetryPolicy};
+use router::{parse_provider, ModelRef};
+use sessions::{StreamRegistry, StreamSessionInfo};
+use structured::{NativeFormat, ResponseSchema};
+use stream::{ChunkBuffer, StreamEmitter};
+use timeout::Timeouts;
+use upstream::{Completion, ToolCallAssembler, UpstreamEvent, UpstreamStream};
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use tauri::{Emitter, State};
+use std::time::{Duration, Instant};
+use tauri::{Emitter, Manager, Runtime, State};
 use tokio::sync::RwLock;
 use uuid::Uuid;
 use futures::StreamExt;
 use serde_json::json;
 
+pub mod accounting;
+pub mod catalog;
+pub mod compare;
+pub mod context;
+pub mod embed;
 pub mod error;
+pub mod fallback;
 pub mod models;
+pub mod ollama;
+pub mod options;
+pub mod persist;
+pub mod reasoning;
+pub mod recorder;
+pub mod registry;
+pub mod replay;
+pub mod retry;
+pub mod router;
+pub mod sessions;
+pub mod stream;
+pub mod structured;
+pub mod timeout;
+pub mod upstream;
 pub mod utils;
 
+/// Event emitted when a non-streaming request is retried
+const RETRY_EVENT: &str = "genai-retry-event";
+
+/// Event emitted when a non-streaming request moves on to a fallback model
+const FALLBACK_EVENT: &str = "genai-fallback-event";
+
+/// Event emitted for Ollama pull progress
+const OLLAMA_PULL_EVENT: &str = "ollama-pull-progress";
+
+/// Lower bound for the coalescing/idle check tick, so a 0ms window doesn't spin
+const MIN_FLUSH_TICK_MS: u64 = 10;
+
+/// Request fields a chat is prepared from, kept so each model in a fallback chain can be prepared
+#[derive(Debug, Clone)]
+pub struct ChatParts {
+    pub messages: Vec<MessageInput>,
+    pub options: Option<ChatOptions>,
+    pub thread_options: Option<ChatOptions>,
+    pub context_strategy: Option<ContextStrategy>,
+    pub tools: Option<Vec<Tool>>,
+    pub response_schema: Option<ResponseSchema>,
+    pub reasoning: Option<ReasoningConfig>,
+    /// Streams capture tool calls; whole responses have inlined reasoning moved out of the text
+    pub streaming: bool,
+}
+
+/// A chat request resolved, validated and fitted for one model
+pub struct PreparedChat {
+    pub model_ref: ModelRef,
+    pub model_id: String,
+    /// Model name sent to the provider; an Ollama model with a context size goes to a derived model
+    pub upstream_model: String,
+    /// Ollama keep_alive to apply again once the request is done
+    pub keep_alive: Option<KeepAlive>,
+    /// Options after layering, as reported back to the caller
+    pub effective_options: ChatOptions,
+    /// Options sent to the provider
+    pub chat_options: ChatOptions,
+    pub client: genai::Client,
+    pub timeouts: Timeouts,
+    pub chat_req: ChatRequest,
+    pub context_report: Option<ContextReport>,
+    pub structured: Option<(ResponseSchema, jsonschema::Validator, NativeFormat)>,
+    pub pricing: Option<ModelPricing>,
+    /// Fixture served instead of calling `client`, for the replay provider
+    pub replay: Option<Arc<Fixture>>,
+    /// Where the provider's traffic is recorded, when fixture recording is on
+    pub record: Option<RecordTarget>,
+}
+
+impl PreparedChat {
+    /// Where the usage of this model's response is recorded
+    pub fn usage_target(&self, thread_id: Option<String>, message_id: Option<String>) -> UsageTarget {
+        UsageTarget {
+            model: self.model_ref.clone(),
+            pricing: self.pricing,
+            thread_id,
+            message_id,
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct GenAIState {
     /// The genai client instance
@@ -37,11 +158,21 @@ pub struct GenAIState {
     /// Global configuration
     pub config: Arc<RwLock<GenAIConfig>>,
     /// Active streaming connections (still needed for stream management)
-    pub streams: Arc<RwLock<HashMap<Uuid, StreamingSession>>>,
+    pub streams: StreamRegistry,
     /// Cached provider keys (in memory, loaded from Stronghold)
     provider_keys: Arc<RwLock<HashMap<String, String>>>,
-    /// Active client with provider-specific authentication
-    active_client: Arc<RwLock<Option<genai::Client>>>,
+    /// Per-provider clients, keyed by provider and connect timeout
+    clients: Arc<RwLock<HashMap<(String, Duration), genai::Client>>>,
+    /// Built-in provider metadata (bundled, with user overrides)
+    pub registry: Arc<RwLock<ProviderRegistry>>,
+    /// Per-model capabilities and pricing (bundled, with user overrides)
+    pub catalog: Arc<RwLock<ModelCatalog>>,
+    /// Fixtures served by the replay provider
+    pub replays: Arc<RwLock<ReplayStore>>,
+    /// Ollama models derived with a context size that are known to exist, keyed by host and name
+    ollama_created: Arc<RwLock<HashSet<(String, String)>>>,
+    /// File user-defined providers are saved to; kept in memory only when unset
+    custom_providers_path: Option<std::path::PathBuf>,
 }
 
 impl GenAIState {
@@ -51,7 +182,12 @@ impl GenAIState {
             config: Arc::new(RwLock::new(GenAIConfig::default())),
             streams: Arc::new(RwLock::new(HashMap::new())),
             provider_keys: Arc::new(RwLock::new(HashMap::new())),
-            active_client: Arc::new(RwLock::new(None)),
+            clients: Arc::new(RwLock::new(HashMap::new())),
+            registry: Arc::new(RwLock::new(ProviderRegistry::bundled())),
+            catalog: Arc::new(RwLock::new(ModelCatalog::bundled())),
+            replays: Arc::new(RwLock::new(ReplayStore::default())),
+            ollama_created: Arc::new(RwLock::new(HashSet::new())),
+            custom_providers_path: None,
         }
     }
 
@@ -61,49 +197,82 @@ impl GenAIState {
             config: Arc::new(RwLock::new(GenAIConfig::default())),
             streams: Arc::new(RwLock::new(HashMap::new())),
             provider_keys: Arc::new(RwLock::new(HashMap::new())),
-            active_client: Arc::new(RwLock::new(None)),
+            clients: Arc::new(RwLock::new(HashMap::new())),
+            registry: Arc::new(RwLock::new(ProviderRegistry::bundled())),
+            catalog: Arc::new(RwLock::new(ModelCatalog::bundled())),
+            replays: Arc::new(RwLock::new(ReplayStore::default())),
+            ollama_created: Arc::new(RwLock::new(HashSet::new())),
+            custom_providers_path: None,
+        }
+    }
+
+    /// Use the given provider registry instead of the bundled one
+    pub fn with_registry(mut self, registry: ProviderRegistry) -> Self {
+        self.registry = Arc::new(RwLock::new(registry));
+        self
+    }
+
+    /// Use the given model catalog instead of the bundled one
+    pub fn with_catalog(mut self, catalog: ModelCatalog) -> Self {
+        self.catalog = Arc::new(RwLock::new(catalog));
+        self
+    }
+
+    /// Load user-defined providers from `path`, and save them there when they change
+    pub fn with_custom_providers_file(mut self, path: std::path::PathBuf) -> Self {
+        let providers = registry::load_custom_providers(&path);
+        if let Ok(mut config) = self.config.try_write() {
+            config.custom_providers = providers;
+        }
+        self.custom_providers_path = Some(path);
+        self
+    }
+
+    /// Save user-defined providers to their file, if they have one
+    async fn save_custom_providers(&self) -> GenAIResult<()> {
+        match &self.custom_providers_path {
+            Some(path) => registry::save_custom_providers(path, &self.config.read().await.custom_providers).await,
+            None => Ok(()),
         }
     }
 
+    /// Read replay fixtures that are not registered in memory from `dir`
+    pub fn with_replay_dir(mut self, dir: std::path::PathBuf) -> Self {
+        self.replays = Arc::new(RwLock::new(ReplayStore::new(Some(dir))));
+        self
+    }
+
     /// Initialize with Stronghold-backed authentication
     pub async fn init_with_stronghold_auth(&self) -> GenAIResult<AuthResolver> {
         // Create an auth resolver that uses Stronghold-stored keys
         let provider_keys = self.provider_keys.clone();
+        let registry = self.registry.clone();
         
         let auth_resolver = AuthResolver::from_resolver_fn(
             move |model_iden: ModelIden| -> Result<Option<AuthData>, genai::resolver::Error> {
-                let adapter_kind_str = model_iden.adapter_kind.to_string().to_lowercase();
-                let key_name = adapter_kind_str.clone() + "_api_key";
-                
-                println!("Adapter kind: {}", key_name);
+                let key_name = format!("{}_api_key", model_iden.adapter_kind.to_string().to_lowercase());
                 
                 // Try to get key from our cached provider keys
                 if let Ok(keys) = provider_keys.try_read() {
-                    println!("Provider keys: {:#?}", keys);
                     if let Some(api_key) = keys.get(&key_name) {
-                        println!("Found API key for {}", adapter_kind_str);
+                        tracing::debug!("Using stored key {}", key_name);
                         return Ok(Some(AuthData::from_single(api_key.clone())));
                     }
                 }
                 
                 // Fallback to environment variables
-                let env_name = match model_iden.adapter_kind {
-                    genai::adapter::AdapterKind::OpenAI => "OPENAI_API_KEY",
-                    genai::adapter::AdapterKind::Anthropic => "ANTHROPIC_API_KEY",
-                    genai::adapter::AdapterKind::Gemini => "GEMINI_API_KEY",
-                    genai::adapter::AdapterKind::Cohere => "COHERE_API_KEY",
-                    genai::adapter::AdapterKind::Groq => "GROQ_API_KEY",
-                    genai::adapter::AdapterKind::Xai => "XAI_API_KEY",
-                    genai::adapter::AdapterKind::DeepSeek => "DEEPSEEK_API_KEY",
-                    genai::adapter::AdapterKind::Ollama => return Ok(None),
-                    _ => return Ok(None),
+                let env_name = registry.try_read().ok().and_then(|registry| {
+                    registry
+                        .for_adapter(model_iden.adapter_kind)
+                        .and_then(|entry| entry.env_var.clone())
+                });
+                let Some(env_name) = env_name else {
+                    return Ok(None);
                 };
                 
-                match std::env::var(env_name) {
+                match std::env::var(&env_name) {
                     Ok(key) => Ok(Some(AuthData::from_single(key))),
-                    Err(_) => Err(genai::resolver::Error::ApiKeyEnvNotFound {
-                        env_name: env_name.to_string(),
-                    }),
+                    Err(_) => Err(genai::resolver::Error::ApiKeyEnvNotFound { env_name }),
                 }
             },
         );
@@ -112,18 +281,396 @@ impl GenAIState {
         Ok(auth_resolver)
     }
 
-    /// Set active client with provider authentication
-    pub async fn set_active_client(&self, client: genai::Client) {
-        *self.active_client.write().await = Some(client);
+    /// Build a client for a provider (built-in or custom) with the given connect timeout
+    pub async fn build_client(
+        &self,
+        provider: &str,
+        connect_timeout: Duration,
+    ) -> GenAIResult<genai::Client> {
+        let custom = self.config.read().await.custom_providers.get(provider).cloned();
+        match custom {
+            Some(custom) => self.build_custom_client(&custom, connect_timeout).await,
+            None => self.build_builtin_client(parse_provider(provider)?, connect_timeout).await,
+        }
     }
 
-    /// Get active client or fallback to default
-    pub async fn get_active_client(&self) -> genai::Client {
-        if let Some(client) = self.active_client.read().await.as_ref() {
-            client.clone()
-        } else {
-            self.client.clone()
+    /// Build a client for a built-in provider with Stronghold-backed auth.
+    ///
+    /// Every model sent through the client is pinned to `adapter`, so a qualified id like
+    /// `ollama::gpt-oss` is not re-inferred from its name.
+    async fn build_builtin_client(
+        &self,
+        adapter: AdapterKind,
+        connect_timeout: Duration,
+    ) -> GenAIResult<genai::Client> {
+        let auth_resolver = self.init_with_stronghold_auth().await?;
+        let http = build_http_client(connect_timeout, HeaderMap::new())?;
+
+        let mut builder = Client::builder()
+            .with_auth_resolver(auth_resolver)
+            .with_model_mapper_fn(move |model_iden: ModelIden| -> Result<ModelIden, genai::resolver::Error> {
+                Ok(ModelIden::new(adapter, model_iden.model_name))
+            })
+            .with_reqwest(http);
+
+        if adapter == AdapterKind::Ollama {
+            // Point the Ollama adapter at the configured host
+            let endpoint = self.config.read().await.ollama.openai_endpoint();
+            builder = builder.with_service_target_resolver_fn(
+                move |target: ServiceTarget| -> Result<ServiceTarget, genai::resolver::Error> {
+                    Ok(ServiceTarget {
+                        endpoint: Endpoint::from_owned(endpoint.clone()),
+                        ..target
+                    })
+                },
+            );
+        }
+
+        Ok(builder.build())
+    }
+
+    /// Build a client for a user-defined provider, pointing the dialect's adapter at its base URL
+    async fn build_custom_client(
+        &self,
+        provider: &CustomProvider,
+        connect_timeout: Duration,
+    ) -> GenAIResult<genai::Client> {
+        let api_key = {
+            let keys = self.provider_keys.read().await;
+            keys.get(&format!("{}_api_key", provider.id))
+                .or_else(|| keys.get(&provider.id))
+                .cloned()
+        };
+
+        let adapter = provider.dialect.adapter_kind();
+        let endpoint = normalize_base_url(&provider.base_url);
+
+        // The adapters always send their key header, even with an empty key. Other styles
+        // replace the request's headers with just the ones the endpoint expects.
+        let auth = match &provider.auth {
+            AuthHeaderStyle::Bearer => AuthData::from_single(api_key.unwrap_or_default()),
+            AuthHeaderStyle::Header { name } => {
+                let mut headers = provider.dialect.required_headers();
+                if let Some(key) = api_key {
+                    HeaderName::from_bytes(name.as_bytes())
+                        .map_err(|e| GenAIError::configuration(format!("Invalid auth header name: {}", e)))?;
+                    HeaderValue::from_str(&key)
+                        .map_err(|e| GenAIError::configuration(format!("Invalid auth header value: {}", e)))?;
+                    headers.push((name.clone(), key));
+                }
+                AuthData::RequestOverride {
+                    url: format!("{}{}", endpoint, provider.dialect.chat_path()),
+                    headers: headers.into(),
+                }
+            }
+            AuthHeaderStyle::None => AuthData::RequestOverride {
+                url: format!("{}{}", endpoint, provider.dialect.chat_path()),
+                headers: provider.dialect.required_headers().into(),
+            },
+        };
+        let http = build_http_client(connect_timeout, HeaderMap::new())?;
+        let resolver_auth = auth.clone();
+
+        Ok(Client::builder()
+            .with_auth_resolver_fn(move |_: ModelIden| -> Result<Option<AuthData>, genai::resolver::Error> {
+                Ok(Some(resolver_auth.clone()))
+            })
+            .with_model_mapper_fn(move |model_iden: ModelIden| -> Result<ModelIden, genai::resolver::Error> {
+                Ok(ModelIden::new(adapter, model_iden.model_name))
+            })
+            .with_service_target_resolver_fn(move |target: ServiceTarget| -> Result<ServiceTarget, genai::resolver::Error> {
+                Ok(ServiceTarget {
+                    endpoint: Endpoint::from_owned(endpoint.clone()),
+                    auth: auth.clone(),
+                    model: ModelIden::new(adapter, target.model.model_name),
+                })
+            })
+            .with_reqwest(http)
+            .build())
+    }
+
+    /// Where embedding requests for a model go: Ollama's native API, a custom provider speaking
+    /// the OpenAI dialect, or a built-in provider's registered embeddings endpoint
+    pub async fn embedding_endpoint(&self, model: &ModelRef) -> GenAIResult<EmbeddingEndpoint> {
+        let config = self.config.read().await;
+        if model.provider == "ollama" {
+            return Ok(EmbeddingEndpoint::Ollama {
+                base_url: config.ollama.base_url.clone(),
+            });
+        }
+
+        let keys = self.provider_keys.read().await;
+        let mut headers = HeaderMap::new();
+        let (url, api_key) = match config.custom_providers.get(&model.provider) {
+            Some(custom) => {
+                if !matches!(custom.dialect, ApiDialect::OpenAI) {
+                    return Err(GenAIError::configuration(format!(
+                        "{} does not offer OpenAI-compatible embeddings",
+                        custom.display_name
+                    )));
+                }
+                let api_key = keys
+                    .get(&format!("{}_api_key", custom.id))
+                    .or_else(|| keys.get(&custom.id))
+                    .cloned();
+                let api_key = match (&custom.auth, api_key) {
+                    (AuthHeaderStyle::Header { name }, Some(key)) => {
+                        let name = HeaderName::from_bytes(name.as_bytes())
+                            .map_err(|e| GenAIError::configuration(format!("Invalid auth header name: {}", e)))?;
+                        let value = HeaderValue::from_str(&key)
+                            .map_err(|e| GenAIError::configuration(format!("Invalid auth header value: {}", e)))?;
+                        headers.insert(name, value);
+                        None
+                    }
+                    (AuthHeaderStyle::Bearer, key) => key,
+                    _ => None,
+                };
+                (format!("{}embeddings", normalize_base_url(&custom.base_url)), api_key)
+            }
+            None => {
+                let registry = self.registry.read().await;
+                let entry = registry.get(&model.provider);
+                let url = entry.and_then(|entry| entry.embeddings_url.clone()).ok_or_else(|| {
+                    GenAIError::configuration(format!("No embeddings endpoint known for {}", model.provider))
+                })?;
+                let api_key = keys.get(&format!("{}_api_key", model.provider)).cloned().or_else(|| {
+                    entry
+                        .and_then(|entry| entry.env_var.as_ref())
+                        .and_then(|env_name| std::env::var(env_name).ok())
+                });
+                (url, api_key)
+            }
+        };
+        if let Some(key) = api_key {
+            let value = HeaderValue::from_str(&format!("Bearer {}", key))
+                .map_err(|e| GenAIError::configuration(format!("Invalid API key: {}", e)))?;
+            headers.insert(reqwest::header::AUTHORIZATION, value);
+        }
+        Ok(EmbeddingEndpoint::OpenAI { url, headers })
+    }
+
+    /// Embed texts with `model`, or the configured embedding model
+    pub async fn embed_texts(&self, model: Option<&str>, inputs: &[String]) -> GenAIResult<(ModelRef, Vec<Vec<f32>>)> {
+        let model = match model {
+            Some(model) => model.to_string(),
+            None => self.config.read().await.settings.embedding.model.clone(),
+        };
+        let model = self.resolve_model(&model).await?;
+        let vectors = self.embedding_endpoint(&model).await?.embed(&model.model, inputs).await?;
+        Ok((model, vectors))
+    }
+
+    /// Resolve a (possibly qualified) model id, including user-defined providers
+    pub async fn resolve_model(&self, model: &str) -> GenAIResult<ModelRef> {
+        ModelRef::resolve(model, &self.config.read().await.custom_providers)
+    }
+
+    /// Effective timeouts for a model
+    pub async fn timeouts_for(&self, model: &ModelRef) -> Timeouts {
+        let config = self.config.read().await;
+        Timeouts::resolve(&config.settings, config.model_config(&model.qualified()))
+    }
+
+    /// Client for a provider, built once per provider and connect timeout and then reused.
+    ///
+    /// Requests for different providers get different clients, so concurrent threads
+    /// never share or swap authentication state.
+    pub async fn client_for(
+        &self,
+        provider: &str,
+        timeouts: &Timeouts,
+    ) -> GenAIResult<genai::Client> {
+        let key = (provider.to_string(), timeouts.connect);
+        if let Some(client) = self.clients.read().await.get(&key) {
+            return Ok(client.clone());
+        }
+
+        let client = self.build_client(provider, timeouts.connect).await?;
+        self.clients.write().await.insert(key, client.clone());
+        Ok(client)
+    }
+
+    /// Client for the native Ollama API at the configured host
+    pub async fn ollama_client(&self) -> OllamaClient {
+        OllamaClient::new(&self.config.read().await.ollama.base_url)
+    }
+
+    /// Model name to send a chat to, and for Ollama models with a keep_alive, the keep_alive to
+    /// apply again once the request is done.
+    ///
+    /// An Ollama model with a context size is chatted with through a derived model, created on
+    /// first use. Best effort: if that fails, the request goes to the model with its defaults.
+    pub async fn prepare_ollama_model(&self, model: &ModelRef) -> (String, Option<KeepAlive>) {
+        let (base_url, settings) = {
+            let ollama = &self.config.read().await.ollama;
+            (ollama.base_url.clone(), ollama.models.get(&model.model).cloned())
+        };
+        let Some(settings) = settings.filter(|_| model.adapter == AdapterKind::Ollama) else {
+            return (model.model.clone(), None);
+        };
+        let chat_model = match self.create_ollama_model(&base_url, &model.model, &settings).await {
+            Ok(chat_model) => chat_model,
+            Err(e) => {
+                tracing::warn!("Failed to apply Ollama settings for {}: {}", model.model, e);
+                model.model.clone()
+            }
+        };
+        let keep_alive = settings.keep_alive.is_some().then(|| KeepAlive {
+            base_url,
+            model: chat_model.clone(),
+            settings,
+        });
+        (chat_model, keep_alive)
+    }
+
+    /// Create the model derived from `model` with the context size in `settings`, unless it
+    /// exists already; returns the name to chat with
+    async fn create_ollama_model(
+        &self,
+        base_url: &str,
+        model: &str,
+        settings: &OllamaModelSettings,
+    ) -> GenAIResult<String> {
+        let chat_model = settings.chat_model(model);
+        let Some(num_ctx) = settings.num_ctx else {
+            return Ok(chat_model);
+        };
+        let key = (base_url.to_string(), chat_model.clone());
+        if self.ollama_created.read().await.contains(&key) {
+            return Ok(chat_model);
+        }
+        OllamaClient::new(base_url).create_model(&chat_model, model, num_ctx).await?;
+        self.ollama_created.write().await.insert(key);
+        Ok(chat_model)
+    }
+
+    /// Create the models derived from `model` again on their next use
+    async fn forget_ollama_model(&self, model: &str) {
+        let derived = format!("{}{}", model, if model.contains(':') { "-ctx" } else { ":ctx" });
+        self.ollama_created
+            .write()
+            .await
+            .retain(|(_, created)| !created.starts_with(&derived));
+    }
+
+    /// Context window for a model: Ollama `num_ctx`, then the model catalog, then the provider registry
+    pub async fn context_window(&self, model: &ModelRef) -> Option<u32> {
+        if model.adapter == AdapterKind::Ollama {
+            let num_ctx = self
+                .config
+                .read()
+                .await
+                .ollama
+                .models
+                .get(&model.model)
+                .and_then(|settings| settings.num_ctx);
+            if num_ctx.is_some() {
+                return num_ctx;
+            }
+        }
+        if let Some(window) = self.catalog.read().await.lookup(model).and_then(|spec| spec.context_window) {
+            return Some(window);
+        }
+        self.registry
+            .read()
+            .await
+            .get(&model.provider)
+            .and_then(|entry| entry.capabilities.max_context_length)
+    }
+
+    /// Fit `messages` into the model's context window with the configured (or given) strategy.
+    ///
+    /// The output allowed by `options`, the tools and any `instructions` added to the request
+    /// afterwards come out of the window first. Returns the messages to send, and a report when
+    /// anything was trimmed. Models with an unknown context window are sent as-is.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn fit_context(
+        &self,
+        model: &ModelRef,
+        upstream_model: &str,
+        client: &genai::Client,
+        timeouts: &Timeouts,
+        messages: Vec<MessageInput>,
+        options: &ChatOptions,
+        tools: Option<&[Tool]>,
+        instructions: Option<&str>,
+        strategy: Option<ContextStrategy>,
+    ) -> GenAIResult<(Vec<ChatMessage>, Option<ContextReport>)> {
+        let context_config = self.config.read().await.settings.context.clone();
+        let strategy = strategy.unwrap_or(context_config.strategy);
+        let context_window = match self.context_window(model).await {
+            Some(window) if strategy != ContextStrategy::None => window,
+            _ => return Ok((messages.iter().map(to_chat_message).collect(), None)),
+        };
+
+        let reserve = options.max_tokens.unwrap_or(context_config.reserve_output_tokens) as usize;
+        let tool_tokens = tools
+            .map(|tools| context::estimate_tokens(model.adapter, &serde_json::to_string(tools).unwrap_or_default()))
+            .unwrap_or(0);
+        let instruction_tokens = instructions
+            .map(|text| context::estimate_tokens(model.adapter, text))
+            .unwrap_or(0);
+        let budget = (context_window as usize).saturating_sub(reserve + tool_tokens + instruction_tokens);
+
+        let mut plan = context::plan(&messages, model.adapter, budget, strategy)?;
+        if plan.dropped.is_empty() {
+            return Ok((messages.iter().map(to_chat_message).collect(), None));
         }
+
+        // Leave room for the summary, then summarize what no longer fits
+        let mut summary = None;
+        if strategy == ContextStrategy::SummarizeMiddle {
+            let summary_budget = context_config.summary_max_tokens as usize;
+            plan = context::plan(&messages, model.adapter, budget.saturating_sub(summary_budget), strategy)?;
+            let dropped: Vec<&MessageInput> = plan.dropped.iter().map(|&i| &messages[i]).collect();
+            let result = timeouts
+                .run(
+                    "total",
+                    timeouts.total,
+                    context::summarize(client, upstream_model, &dropped, context_config.summary_max_tokens),
+                )
+                .await;
+            match result {
+                Ok(text) => summary = Some(text),
+                Err(e) => {
+                    tracing::warn!("Context summary failed, dropping turns instead: {}", e);
+                    plan = context::plan(&messages, model.adapter, budget, strategy)?;
+                }
+            }
+        }
+
+        let summary_tokens = summary
+            .as_deref()
+            .map(|text| context::estimate_tokens(model.adapter, text))
+            .unwrap_or(0);
+        let report = ContextReport {
+            strategy,
+            context_window,
+            budget_tokens: budget,
+            estimated_tokens_before: plan.estimated_before,
+            estimated_tokens_after: plan.estimated_after + summary_tokens,
+            dropped: plan.dropped.clone(),
+            summarized: summary.is_some(),
+            token_counter: context::token_counter(model.adapter),
+        };
+        tracing::info!(
+            "Trimmed {} of {} messages to fit {} ({} tokens)",
+            plan.dropped.len(),
+            messages.len(),
+            model.qualified(),
+            context_window
+        );
+
+        let chat_messages = context::with_summary(&messages, &plan, summary)
+            .iter()
+            .map(to_chat_message)
+            .collect();
+        Ok((chat_messages, Some(report)))
+    }
+
+    /// Drop cached clients for a provider so they are rebuilt on next use
+    pub async fn invalidate_clients(&self, provider: &str) {
+        self.clients.write().await.retain(|(p, _), _| p != provider);
     }
 
     /// Update provider keys in memory (called after Stronghold operations)
@@ -149,14 +696,231 @@ impl GenAIState {
             .ok();
     }
 
-    /// Cleanup only streaming sessions
+    /// Compile a response schema and pick the model's native JSON format for it
+    pub async fn prepare_structured(
+        &self,
+        model: &ModelRef,
+        schema: Option<ResponseSchema>,
+    ) -> GenAIResult<Option<(ResponseSchema, jsonschema::Validator, NativeFormat)>> {
+        let Some(schema) = schema else {
+            return Ok(None);
+        };
+        let validator = structured::compile(&schema)?;
+        let json_mode = self.catalog.read().await.lookup(model).and_then(|spec| spec.json_mode);
+        let format = structured::native_format(model, json_mode, &schema);
+        Ok(Some((schema, validator, format)))
+    }
+
+    /// Resolve, validate and build the chat request for one model.
+    ///
+    /// Options, format, reasoning and context fitting all depend on the model, so this runs
+    /// again for every model tried in a fallback chain.
+    pub async fn prepare_chat(&self, model: &str, parts: &ChatParts) -> GenAIResult<PreparedChat> {
+        let model_ref = self.resolve_model(model).await?;
+        let model_id = model_ref.qualified();
+
+        let effective_options = resolve_chat_options(
+            &*self.config.read().await,
+            &model_id,
+            parts.thread_options.as_ref(),
+            parts.options.as_ref(),
+        );
+        let mut chat_options = effective_options.clone().with_capture_usage(true);
+        if parts.streaming {
+            if parts.tools.is_some() {
+                chat_options = chat_options.with_capture_tool_calls(true);
+            }
+        } else {
+            // Reasoning inlined as <think> tags is moved out of the text
+            chat_options = chat_options.with_normalize_reasoning_content(true);
+        }
+        self.catalog
+            .read()
+            .await
+            .validate(&model_ref, &effective_options, parts.tools.is_some())?;
+        let structured_output = self.prepare_structured(&model_ref, parts.response_schema.clone()).await?;
+        if let Some((schema, _, format)) = &structured_output {
+            chat_options = structured::apply_format(chat_options, *format, schema);
+        }
+        let (supports_reasoning, pricing) = {
+            let catalog = self.catalog.read().await;
+            let spec = catalog.lookup(&model_ref);
+            (spec.and_then(|spec| spec.reasoning), spec.and_then(|spec| spec.pricing()))
+        };
+        chat_options = reasoning::apply(chat_options, &model_ref, supports_reasoning, parts.reasoning.as_ref())?;
+
+        // Each model gets its provider's client, so providers run independently
+        let (upstream_model, keep_alive) = self.prepare_ollama_model(&model_ref).await;
+        let timeouts = self.timeouts_for(&model_ref).await;
+        let (client, replay) = if model_ref.provider == replay::PROVIDER {
+            (self.client.clone(), Some(self.replays.read().await.get(&model_ref.model)?))
+        } else {
+            (self.client_for(&model_ref.provider, &timeouts).await?, None)
+        };
+
+        // Build chat request from the messages that fit the context window, with the room the
+        // thinking budget and the schema instructions take
+        let instructions = structured_output
+            .as_ref()
+            .map(|(schema, _, _)| structured::instructions(schema));
+        let (chat_messages, context_report) = self
+            .fit_context(
+                &model_ref,
+                &upstream_model,
+                &client,
+                &timeouts,
+                parts.messages.clone(),
+                &chat_options,
+                parts.tools.as_deref(),
+                instructions.as_deref(),
+                parts.context_strategy,
+            )
+            .await?;
+        let mut chat_req = ChatRequest::new(chat_messages);
+        if let Some(tools) = &parts.tools {
+            chat_req = chat_req.with_tools(tools.clone());
+        }
+        if let Some((schema, _, _)) = &structured_output {
+            structured::add_instructions(&mut chat_req, schema);
+        }
+        let record = if replay.is_none() && self.config.read().await.settings.record_fixtures {
+            self.record_target(&model_id, &chat_req, &chat_options).await
+        } else {
+            None
+        };
+
+        Ok(PreparedChat {
+            model_ref,
+            model_id,
+            upstream_model,
+            keep_alive,
+            effective_options,
+            chat_options,
+            client,
+            timeouts,
+            chat_req,
+            context_report,
+            structured: structured_output,
+            pricing,
+            replay,
+            record,
+        })
+    }
+
+    /// Where to record a chat sent to `model_id`; `None` without a fixture directory
+    async fn record_target(
+        &self,
+        model_id: &str,
+        chat_req: &ChatRequest,
+        chat_options: &ChatOptions,
+    ) -> Option<RecordTarget> {
+        let dir = self.replays.read().await.dir()?.clone();
+        let mut secrets: Vec<String> = self.provider_keys.read().await.values().cloned().collect();
+        secrets.extend(self.config.read().await.auth_providers.values().filter_map(|provider| {
+            match &provider.provider_type {
+                AuthProviderType::ApiKey { key } => Some(key.clone()),
+                AuthProviderType::OAuth { client_secret, .. } => Some(client_secret.clone()),
+                _ => None,
+            }
+        }));
+        Some(RecordTarget {
+            dir,
+            model: model_id.to_string(),
+            request: json!({
+                "model": model_id,
+                "chat_request": chat_req,
+                "options": chat_options,
+            }),
+            secrets: Arc::new(secrets),
+        })
+    }
+
+    /// Prepare the first model of `chain[*next..]` that can be prepared, advancing `next` past it.
+    ///
+    /// Models failing with a fallback trigger are recorded in `fallbacks` and skipped; any other
+    /// error, or the last model's, is returned.
+    pub async fn prepare_next(
+        &self,
+        chain: &[String],
+        next: &mut usize,
+        parts: &ChatParts,
+        fallbacks: &mut Vec<FallbackAttempt>,
+    ) -> GenAIResult<PreparedChat> {
+        loop {
+            let Some(model) = chain.get(*next) else {
+                return Err(GenAIError::invalid_request("No model left in the fallback chain"));
+            };
+            *next += 1;
+            match self.prepare_chat(model, parts).await {
+                Err(e) if e.is_fallback_trigger() && *next < chain.len() => {
+                    tracing::warn!("Skipping {} in the fallback chain: {}", model, e);
+                    fallbacks.push(FallbackAttempt::new(model, &e));
+                }
+                result => return result,
+            }
+        }
+    }
+
+    /// Models to try for a request, `model` first; see [`fallback::chain`] for precedence
+    pub async fn fallback_chain<R: Runtime>(
+        &self,
+        app: &tauri::AppHandle<R>,
+        model: &str,
+        requested: Option<&[String]>,
+        thread_id: Option<&str>,
+    ) -> Vec<String> {
+        let project = match (requested, thread_id) {
+            (None, Some(thread_id)) => fallback::project_fallbacks(app, thread_id).await,
+            _ => None,
+        };
+        fallback::chain(model, requested, project.as_deref(), &*self.config.read().await)
+    }
+
+    /// Remove finished streaming sessions past their retention
     pub async fn cleanup(&self) -> GenAIResult<()> {
-        let mut streams = self.streams.write().await;
-        streams.retain(|_, stream| stream.active);
+        sessions::reap(&mut *self.streams.write().await, sessions::FINISHED_RETENTION);
         Ok(())
     }
 }
 
+/// Sum of two optional costs; unknown when both are
+fn add_costs(a: Option<f64>, b: Option<f64>) -> Option<f64> {
+    match (a, b) {
+        (None, None) => None,
+        (a, b) => Some(a.unwrap_or(0.0) + b.unwrap_or(0.0)),
+    }
+}
+
+/// Build the HTTP client used by genai, with a connect timeout and default headers
+fn build_http_client(connect_timeout: Duration, headers: HeaderMap) -> GenAIResult<reqwest::Client> {
+    let mut http = reqwest::Client::builder().default_headers(headers);
+    if !connect_timeout.is_zero() {
+        http = http.connect_timeout(connect_timeout);
+    }
+    http.build()
+        .map_err(|e| GenAIError::configuration(format!("Failed to build HTTP client: {}", e)))
+}
+
+/// Convert a UI message into a genai chat message
+fn to_chat_message(msg: &MessageInput) -> ChatMessage {
+    match msg.role.as_str() {
+        "system" => ChatMessage::system(&msg.content),
+        "user" => ChatMessage::user(&msg.content),
+        "assistant" => ChatMessage::assistant(&msg.content),
+        _ => ChatMessage::user(&msg.content),
+    }
+}
+
+/// genai joins endpoint paths onto the base URL, which requires a trailing slash
+fn normalize_base_url(base_url: &str) -> String {
+    let trimmed = base_url.trim();
+    if trimmed.ends_with('/') {
+        trimmed.to_string()
+    } else {
+        format!("{}/", trimmed)
+    }
+}
+
 /// Select and initialize a provider-specific client
 #[tauri::command]
 pub async fn select_provider(
@@ -165,12 +929,11 @@ pub async fn select_provider(
 ) -> Result<(), GenAIError> {
     tracing::info!("Selecting provider: {}", provider);
     
-    let auth_resolver = state.init_with_stronghold_auth().await?;
-    let new_client = Client::builder()
-        .with_auth_resolver(auth_resolver)
-        .build();
-    
-    state.set_active_client(new_client).await;
+    let timeouts = Timeouts::resolve(&state.config.read().await.settings, None);
+
+    // Rebuild the provider's client so it picks up the latest keys
+    state.invalidate_clients(&provider).await;
+    state.client_for(&provider, &timeouts).await?;
     tracing::info!("Provider {} selected and client initialized", provider);
     
     Ok(())
@@ -178,52 +941,99 @@ pub async fn select_provider(
 
 /// Send a direct chat message without session management
 #[tauri::command]
-pub async fn send_message(
+pub async fn send_message<R: Runtime>(
     request: DirectChatRequest,
+    app: tauri::AppHandle<R>,
     state: tauri::State<'_, GenAIState>,
 ) -> Result<ChatResponse, GenAIError> {
     let start_time = std::time::Instant::now();
+    let started_at = chrono::Utc::now();
+    let chain = state
+        .fallback_chain(&app, &request.model, request.fallbacks.as_deref(), request.thread_id.as_deref())
+        .await;
+    let parts = ChatParts {
+        messages: request.messages,
+        options: request.options,
+        thread_options: request.thread_options,
+        context_strategy: request.context_strategy,
+        tools: request.tools,
+        response_schema: request.response_schema,
+        reasoning: request.reasoning,
+        streaming: false,
+    };
+    let retry_policy = state.config.read().await.settings.retry.clone();
 
-    let chat_messages: Vec<ChatMessage> = request.messages
-    .into_iter()
-    .map(|msg| match msg.role.as_str() {
-        "system" => ChatMessage::system(&msg.content),
-        "user" => ChatMessage::user(&msg.content),
-        "assistant" => ChatMessage::assistant(&msg.content),
-        _ => ChatMessage::user(&msg.content),
-    })
-    .collect(); 
-
-    // Build chat request from provided messages
-    let mut chat_req = ChatRequest::new(chat_messages);
-    let mut chat_options = ChatOptions::default().with_capture_usage(true);
-
-    if let Some(tools) = request.tools {
-        chat_req = chat_req.with_tools(tools);
+    // Move down the chain until a model answers; other errors fail the request right away
+    let mut fallbacks: Vec<FallbackAttempt> = Vec::new();
+    let mut outcome = Err(GenAIError::invalid_request("No model to send the message to"));
+    for (i, candidate) in chain.iter().enumerate() {
+        if let Some(failed) = fallbacks.last() {
+            let _ = app.emit(FALLBACK_EVENT, json!({
+                "from": failed.model,
+                "to": candidate,
+                "error": failed.error,
+                "category": failed.category,
+            }));
+        }
+        outcome = async {
+            let prepared = state.prepare_chat(candidate, &parts).await?;
+            let completion = exec_prepared(&app, &retry_policy, &prepared).await?;
+            Ok::<_, GenAIError>((prepared, completion))
+        }
+        .await;
+        match &outcome {
+            Err(e) if e.is_fallback_trigger() && i + 1 < chain.len() => {
+                tracing::warn!("{} failed, falling back to {}: {}", candidate, chain[i + 1], e);
+                fallbacks.push(FallbackAttempt::new(candidate, e));
+            }
+            _ => break,
+        }
     }
+    let (prepared, completion) = outcome?;
+    let usage_target = prepared.usage_target(request.thread_id, request.message_id);
 
-    // Use active client with provider authentication
-    let client = state.get_active_client().await;
-
-    // Execute the chat request
-    let chat_res = client
-        .exec_chat(&request.model, chat_req, Some(&chat_options))
-        .await
-        .map_err(|e| GenAIError::api(e.to_string()))?;
-
-    let response_text = chat_res
-        .first_text()
+    let mut response_text = completion
+        .text
+        .as_deref()
         .unwrap_or("No response")
         .to_string();
     println!("Response: {}", response_text);
-    let usage = chat_res.usage.clone();
+    let usage = completion.usage.clone();
+    let reasoning_text = completion.reasoning.clone().unwrap_or_default();
+    let (tokens, mut cost) = usage_target.record_with_reasoning(&app, &usage, &reasoning_text).await;
+    let mut thinking = ThinkingTrace::from_text(&reasoning_text, started_at, chrono::Utc::now());
+    thinking.set_tokens(tokens.reasoning_tokens);
 
-    let tool_calls = if !chat_res
-        .clone()
-        .into_tool_calls()
-        .is_empty()
-    {
-        Some(chat_res.into_tool_calls())
+    // Validate against the response schema, asking the model to repair invalid output
+    let structured = match &prepared.structured {
+        Some((schema, validator, format)) => {
+            let mut repair_usages = Vec::new();
+            let output = structured::validate_with_repair(
+                &prepared.client,
+                &prepared.upstream_model,
+                &prepared.chat_req,
+                &prepared.chat_options,
+                &prepared.timeouts,
+                schema,
+                validator,
+                *format,
+                response_text.clone(),
+                &mut repair_usages,
+            )
+            .await;
+            for repair_usage in &repair_usages {
+                let (_, repair_cost) = usage_target.record(&app, repair_usage).await;
+                cost = add_costs(cost, repair_cost);
+            }
+            let output = output?;
+            response_text = output.text.clone();
+            Some(output)
+        }
+        None => None,
+    };
+
+    let tool_calls = if !completion.tool_calls.is_empty() {
+        Some(completion.tool_calls)
     } else {
         None
     };
@@ -233,276 +1043,732 @@ pub async fn send_message(
 
     Ok(ChatResponse {
         message: response_text,
-        model: request.model,
+        model: prepared.model_id,
         metadata: ResponseMetadata {
             timestamp: chrono::Utc::now(),
             usage: Some(usage),
             response_time_ms: response_time,
             streamed: false,
+            options: prepared.effective_options,
+            context: prepared.context_report,
+            cost,
+            fallbacks,
         },
         tool_calls,
+        structured,
+        thinking: (!thinking.is_empty()).then(|| thinking.to_json(false)),
     })
 }
 
+/// Execute a prepared chat, retrying transient failures; the attempts share one total timeout
+async fn exec_prepared<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    retry_policy: &RetryPolicy,
+    prepared: &PreparedChat,
+) -> GenAIResult<Completion> {
+    let (client, model, chat_req, chat_options) =
+        (&prepared.client, &prepared.upstream_model, &prepared.chat_req, &prepared.chat_options);
+    let (timeouts, replay) = (&prepared.timeouts, prepared.replay.as_deref());
+    let recorder = prepared.record.as_ref().map(RecordTarget::start);
+    let started = Instant::now();
+    let completion = with_retry(
+        retry_policy,
+        || async move {
+            timeouts
+                .run_since("total", timeouts.total, started, async {
+                    match replay {
+                        Some(fixture) => fixture.complete().await,
+                        None => client
+                            .exec_chat(model, chat_req.clone(), Some(chat_options))
+                            .await
+                            .map(Completion::from)
+                            .map_err(GenAIError::from),
+                    }
+                })
+                .await
+        },
+        |attempt| {
+            let _ = app.emit(RETRY_EVENT, json!({
+                "model": prepared.model_id,
+                "attempt": attempt,
+            }));
+        },
+    )
+    .await;
+    if let Some(keep_alive) = &prepared.keep_alive {
+        keep_alive.reapply();
+    }
+    if let Some(mut recorder) = recorder {
+        match &completion {
+            Ok(completion) => completion.events().iter().for_each(|event| recorder.step(Ok(event))),
+            Err(e) => recorder.step(Err(e)),
+        }
+        recorder.finish().await;
+    }
+    completion
+}
+
+/// Open a stream on a prepared chat; only setup is retried, once chunks flow errors end the stream.
+///
+/// The attempts share the total timeout counted from `started`; the stream comes back with the
+/// start of the attempt that opened it, which the first token and idle timeouts count from.
+///
+/// When recording, the fixture holds the final outcome of the setup, not each retried attempt.
+async fn open_stream<R: Runtime>(
+    retry_policy: &RetryPolicy,
+    prepared: &PreparedChat,
+    started: Instant,
+    emitter: &mut StreamEmitter<R>,
+) -> GenAIResult<(UpstreamStream, Instant)> {
+    let (client, model, chat_req, chat_options) =
+        (&prepared.client, &prepared.upstream_model, &prepared.chat_req, &prepared.chat_options);
+    let (timeouts, replay) = (&prepared.timeouts, prepared.replay.as_deref());
+    let recorder = prepared.record.as_ref().map(RecordTarget::start);
+    let stream = with_retry(
+        retry_policy,
+        || async move {
+            let attempt = Instant::now();
+            let opened = timeouts.run("first_token", timeouts.first_token, async {
+                match replay {
+                    Some(fixture) => fixture.stream().await,
+                    None => client
+                        .exec_chat_stream(model, chat_req.clone(), Some(chat_options))
+                        .await
+                        .map(upstream::from_genai_stream)
+                        .map_err(GenAIError::from),
+                }
+            });
+            timeouts
+                .run_since("total", timeouts.total, started, opened)
+                .await
+                .map(|stream| (stream, attempt))
+        },
+        |attempt| emitter.emit(StreamingEventType::Retry, json!({ "attempt": attempt })),
+    )
+    .await;
+    match (stream, recorder) {
+        (Ok((stream, attempt)), Some(recorder)) => Ok((record_stream(stream, recorder), attempt)),
+        (Err(e), Some(mut recorder)) => {
+            recorder.step(Err(&e));
+            recorder.finish().await;
+            Err(e)
+        }
+        (stream, None) => stream,
+    }
+}
+
+/// History for a request that sends none: the stored path from the thread's root to `leaf_id`,
+/// so answering an edit or regenerating a reply only needs the message to continue from.
+///
+/// The thread's system prompt (or its project's) is not stored as a message, so it is put first
+/// unless the path starts with a system message of its own.
+async fn stored_history<R: Runtime>(
+    app: &tauri::AppHandle<R>,
+    thread_id: &str,
+    leaf_id: &str,
+) -> GenAIResult<Vec<MessageInput>> {
+    let db = app
+        .try_state::<Database>()
+        .ok_or_else(|| GenAIError::configuration("Database is not available"))?;
+    let mut history: Vec<MessageInput> = branches::path_to(db.pool(), leaf_id)
+        .await?
+        .into_iter()
+        .filter(|message| matches!(message.role.as_str(), "system" | "user" | "assistant"))
+        .filter(|message| !message.content.trim().is_empty())
+        .map(|message| MessageInput {
+            role: message.role,
+            content: message.content,
+            pinned: false,
+        })
+        .collect();
+    if history.first().is_none_or(|message| message.role != "system") {
+        let mut conn = db.pool().acquire().await.map_err(crate::db::DbError::from)?;
+        if let Some(prompt) = threads::system_prompt(&mut conn, thread_id).await? {
+            history.insert(
+                0,
+                MessageInput {
+                    role: "system".to_string(),
+                    content: prompt,
+                    pinned: false,
+                },
+            );
+        }
+    }
+    Ok(history)
+}
+
 #[tauri::command]
-pub async fn stream_message(
+pub async fn stream_message<R: Runtime>(
     request: StreamingRequest,
-    app: tauri::AppHandle,
+    app: tauri::AppHandle<R>,
     state: tauri::State<'_, GenAIState>,
 ) -> Result<Uuid, GenAIError> {
+    start_stream(request, app, &state).await
+}
 
-    let chat_messages: Vec<ChatMessage> = request.messages
-    .into_iter()
-    .map(|msg| match msg.role.as_str() {
-        "system" => ChatMessage::system(&msg.content),
-        "user" => ChatMessage::user(&msg.content),
-        "assistant" => ChatMessage::assistant(&msg.content),
-        _ => ChatMessage::user(&msg.content),
-    })
-    .collect(); 
+/// Start streaming a response in the background and return its stream id
+async fn start_stream<R: Runtime>(
+    request: StreamingRequest,
+    app: tauri::AppHandle<R>,
+    state: &GenAIState,
+) -> GenAIResult<Uuid> {
+    let chain = state
+        .fallback_chain(&app, &request.model, request.fallbacks.as_deref(), request.thread_id.as_deref())
+        .await;
+    let messages = match (&request.thread_id, &request.parent_message_id) {
+        (Some(thread_id), Some(parent)) if request.messages.is_empty() => {
+            stored_history(&app, thread_id, parent).await?
+        }
+        _ => request.messages,
+    };
+    let parts = ChatParts {
+        messages,
+        options: request.options,
+        thread_options: request.thread_options,
+        context_strategy: request.context_strategy,
+        tools: request.tools,
+        response_schema: request.response_schema,
+        reasoning: request.reasoning,
+        streaming: true,
+    };
 
-    // Build chat request
-    let mut chat_req = ChatRequest::new(chat_messages);
-    let mut chat_options = ChatOptions::default().with_capture_usage(true);
-    if let Some(tools) = request.tools {
-        chat_req = chat_req.with_tools(tools);
-        chat_options = chat_options.with_capture_tool_calls(true);
-    }
+    // Models that cannot even be prepared (missing key, provider down) are skipped up front
+    let mut fallbacks: Vec<FallbackAttempt> = Vec::new();
+    let mut next = 0;
+    let prepared = state.prepare_next(&chain, &mut next, &parts, &mut fallbacks).await?;
+    let model_id = prepared.model_id.clone();
 
-    let model = request.model.clone();
+    // With a thread id the response is stored as an assistant message in that thread
+    let message_id = request
+        .message_id
+        .or_else(|| request.thread_id.as_ref().map(|_| Uuid::new_v4().to_string()));
+    let thread_id = request.thread_id.clone();
+    let comparison_id = request.comparison_id;
+    let stream_config = request.stream_config.unwrap_or_default();
 
     // Create streaming session
-    let stream_id = Uuid::new_v4();
-    let mut streaming_session = StreamingSession::new(&model);
+    let mut streaming_session = StreamingSession::with_config(&model_id, stream_config.clone());
     streaming_session.active = true;
+    let stream_id = streaming_session.id;
+    let (retry_policy, max_sessions) = {
+        let config = state.config.read().await;
+        (config.settings.retry.clone(), config.settings.max_sessions)
+    };
+
+    let new_message = match (&request.thread_id, &message_id) {
+        (Some(thread_id), Some(message_id)) => Some(NewAssistantMessage {
+            id: message_id.clone(),
+            thread_id: thread_id.clone(),
+            parent_message_id: request.parent_message_id,
+            activate: comparison_id.is_none(),
+            provider: prepared.model_ref.provider.clone(),
+            model: prepared.model_ref.model.clone(),
+            metadata: json!({
+                "stream_id": stream_id,
+                "options": prepared.effective_options,
+                "comparison_id": comparison_id,
+            }),
+        }),
+        _ => None,
+    };
+    streaming_session.message_id = new_message.as_ref().map(|message| message.id.clone());
+    let journal = streaming_session.journal.clone();
+    let cancel = streaming_session.cancel.clone();
+
+    // Register before the task starts so it always finds its session when it ends
+    {
+        let mut streams = state.streams.write().await;
+        sessions::reserve(&mut streams, max_sessions)?;
+        streams.insert(stream_id, streaming_session);
+    }
+    let registry = state.streams.clone();
 
-    // Use active client with provider authentication
-    let client = state.get_active_client().await;
-    
     let handle = tokio::spawn(async move {
-        match client
-            .exec_chat_stream(&model, chat_req, Some(&chat_options))
-            .await
+        let usage_app = app.clone();
+        let state = usage_app.state::<GenAIState>();
+        let mut writer = match new_message {
+            Some(message) => MessageWriter::start(&usage_app, message).await,
+            None => None,
+        };
+        let mut emitter = StreamEmitter::new(app, journal);
+        let started = Instant::now();
+        let mut buffer = ChunkBuffer::new(&stream_config);
+        let mut thinking = ThinkingTrace::default();
+        let mut tool_calls: Vec<ToolCall> = Vec::new();
+        // Error payload, emitted once the message is finalized
+        let mut failure: Option<serde_json::Value> = None;
+        // Set once the task has claimed the end of the stream from `stop_streaming_message`
+        let mut finishing = false;
+
+        // A model whose stream cannot be opened hands over to the next one in the chain
+        let mut prepared = prepared;
+        // Start of the total timeout; each model in the chain gets its own
+        let mut model_started = started;
+        let setup = loop {
+            match open_stream(&retry_policy, &prepared, model_started, &mut emitter).await {
+                Err(e) if e.is_fallback_trigger() && next < chain.len() => {
+                    tracing::warn!("{} failed, falling back: {}", prepared.model_id, e);
+                    let failed = FallbackAttempt::new(&prepared.model_id, &e);
+                    fallbacks.push(failed.clone());
+                    match state.prepare_next(&chain, &mut next, &parts, &mut fallbacks).await {
+                        Ok(fallback) => {
+                            emitter.emit(StreamingEventType::Fallback, json!({
+                                "from": failed.model,
+                                "to": fallback.model_id,
+                                "error": failed.error,
+                                "category": failed.category,
+                            }));
+                            prepared = fallback;
+                            model_started = Instant::now();
+                        }
+                        Err(e) => break Err(e),
+                    }
+                }
+                result => break result,
+            }
+        };
+        let usage_target = prepared.usage_target(thread_id, message_id);
+        let (client_ref, model_ref, chat_req, chat_options) =
+            (&prepared.client, &prepared.upstream_model, &prepared.chat_req, &prepared.chat_options);
+        let timeouts_ref = &prepared.timeouts;
+        let structured_output = &prepared.structured;
+        let answered_by = (!fallbacks.is_empty())
+            .then(|| (prepared.model_ref.provider.clone(), prepared.model_ref.model.clone()));
+
+        match setup
         {
-            Ok(mut chat_stream) => {
-                let mut accumulated_response = String::new();
-                let mut tool_calls: Vec<ToolCall> = Vec::new();
+            Ok((mut chat_stream, attempt_started)) => {
                 // Tool call accumulation state
-                let mut accumulated_tool_calls: HashMap<String, ToolCall> = HashMap::new();
+                let mut assembler = ToolCallAssembler::default();
 
-                // Emit start event
-                let _ = app.emit(
-                    "genai-stream-event",
-                    StreamingEventPayload {
-                        event_type: StreamingEventType::Start,
-                        stream_id: stream_id.to_string(),
-                        data: json!({
-                            "model": model
-                        }),
-                        timestamp: chrono::Utc::now(),
-                    },
+                let idle_timeout = Duration::from_millis(stream_config.timeout_ms);
+                let mut last_activity = attempt_started;
+                let mut first_token_received = false;
+                let mut flush_tick = tokio::time::interval(
+                    Duration::from_millis(stream_config.flush_interval_ms.max(MIN_FLUSH_TICK_MS)),
                 );
+                flush_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
 
-                while let Some(result) = chat_stream.stream.next().await {
-                    match result {
-                        Ok(ChatStreamEvent::Start) => {
-                            // Already emitted start event above
-                        }
-                        Ok(ChatStreamEvent::Chunk(chunk)) => {
-                            accumulated_response.push_str(&chunk.content);
-                            let _ = app.emit(
-                                "genai-stream-event",
-                                StreamingEventPayload {
-                                    event_type: StreamingEventType::Chunk,
-                                    stream_id: stream_id.to_string(),
-                                    data: json!({
-                                        "content": chunk.content,
-                                        "accumulated": accumulated_response.clone(),
-                                    }),
-                                    timestamp: chrono::Utc::now(),
-                                }
-                            );
-                        }
-                        Ok(ChatStreamEvent::ToolCallChunk(tool_chunk)) => {
-                            let call_id = tool_chunk.tool_call.call_id.clone();
-                            
-                            // Handle the pattern where first chunk has metadata, subsequent chunks have "call_0" with arguments
-                            if !tool_chunk.tool_call.fn_name.is_empty() {
-                                // This is likely the first chunk with metadata (fn_name and real call_id)
-                                accumulated_tool_calls.insert(call_id.clone(), tool_chunk.tool_call.clone());
-                            } else if call_id == "call_0" || call_id.is_empty() {
-                                // This is likely an argument chunk - find the most recent tool call to append to
-                                if let Some((_, existing_call)) = accumulated_tool_calls.iter_mut().last() {
-                                    // Append the argument string to existing arguments
-                                    let new_arg_str = match &tool_chunk.tool_call.fn_arguments {
-                                        serde_json::Value::String(s) => s.clone(),
-                                        other => other.to_string().trim_matches('"').to_string(),
-                                    };
-                                    
-                                    let current_args_str = match &existing_call.fn_arguments {
-                                        serde_json::Value::String(s) => s.clone(),
-                                        other => other.to_string().trim_matches('"').to_string(),
-                                    };
-                                    
-                                    let concatenated_args = format!("{}{}", current_args_str, new_arg_str);
-                                    existing_call.fn_arguments = serde_json::Value::String(concatenated_args);
+                // Emit start event
+                emitter.emit(StreamingEventType::Start, json!({
+                    "model": prepared.model_id,
+                    "options": prepared.effective_options,
+                    "context": prepared.context_report,
+                    "message_id": writer.as_ref().map(|writer| writer.message_id()),
+                    "fallbacks": fallbacks,
+                    "comparison_id": comparison_id,
+                }));
+
+                loop {
+                    let result = tokio::select! {
+                        next = chat_stream.next() => match next {
+                            Some(result) => result,
+                            None => break,
+                        },
+                        _ = flush_tick.tick() => {
+                            if buffer.is_due() {
+                                if let Some(chunk) = buffer.take() {
+                                    emitter.emit(StreamingEventType::Chunk, chunk);
                                 }
+                            }
+                            if let Some(writer) = writer.as_mut() {
+                                writer.save_if_due(buffer.accumulated(), &thinking).await;
+                            }
+                            let expired = if !idle_timeout.is_zero() && last_activity.elapsed() >= idle_timeout {
+                                Some(GenAIError::timeout_elapsed("idle", idle_timeout, last_activity.elapsed()))
+                            } else if !first_token_received
+                                && !timeouts_ref.first_token.is_zero()
+                                && attempt_started.elapsed() >= timeouts_ref.first_token
+                            {
+                                Some(GenAIError::timeout_elapsed("first_token", timeouts_ref.first_token, attempt_started.elapsed()))
+                            } else if !timeouts_ref.total.is_zero() && model_started.elapsed() >= timeouts_ref.total {
+                                Some(GenAIError::timeout_elapsed("total", timeouts_ref.total, model_started.elapsed()))
                             } else {
-                                // Standard case - same call_id for all chunks
-                                if let Some(existing_call) = accumulated_tool_calls.get_mut(&call_id) {
-                                    // Append arguments
-                                    let new_arg_str = match &tool_chunk.tool_call.fn_arguments {
-                                        serde_json::Value::String(s) => s.clone(),
-                                        other => other.to_string().trim_matches('"').to_string(),
-                                    };
-                                    
-                                    let current_args_str = match &existing_call.fn_arguments {
-                                        serde_json::Value::String(s) => s.clone(),
-                                        other => other.to_string().trim_matches('"').to_string(),
-                                    };
-                                    
-                                    let concatenated_args = format!("{}{}", current_args_str, new_arg_str);
-                                    existing_call.fn_arguments = serde_json::Value::String(concatenated_args);
-                                } else {
-                                    // New tool call
-                                    accumulated_tool_calls.insert(call_id.clone(), tool_chunk.tool_call.clone());
+                                None
+                            };
+                            if let Some(err) = expired {
+                                if let Some(chunk) = buffer.take() {
+                                    emitter.emit(StreamingEventType::Chunk, chunk);
                                 }
+                                failure = Some(json!({
+                                    "error": err.to_string(),
+                                    "category": err.category(),
+                                }));
+                                break;
                             }
+                            continue;
+                        }
+                    };
+                    last_activity = Instant::now();
+                    if !first_token_received && result.as_ref().is_ok_and(UpstreamEvent::is_output) {
+                        first_token_received = true;
+                        sessions::first_token(&registry, stream_id).await;
+                    }
 
-                            
-                            // Check all accumulated tool calls for completion
-                            let mut completed_calls = Vec::new();
-                            for (id, call) in &accumulated_tool_calls {
-                                let args_str = match &call.fn_arguments {
-                                    serde_json::Value::String(s) => s.clone(),
-                                    other => other.to_string(),
-                                };
-                                
-                                // Check if arguments form valid JSON and look complete
-                                if !call.fn_name.is_empty() && 
-                                   !args_str.is_empty() && 
-                                   args_str.starts_with('{') && 
-                                   args_str.ends_with('}') {
-                                    if let Some(parsed_json) = repair_json(&args_str) {
-                                        // Create completed tool call with parsed JSON
-                                        let mut completed_call = call.clone();
-                                        completed_call.fn_arguments = parsed_json;
-                                        completed_calls.push((id.clone(), completed_call));
-                                    }
+                    // Anything other than a content delta flushes pending content first to keep ordering
+                    if !matches!(result, Ok(UpstreamEvent::Chunk { .. })) {
+                        if let Some(chunk) = buffer.take() {
+                            emitter.emit(StreamingEventType::Chunk, chunk);
+                        }
+                    }
+
+                    match result {
+                        Ok(UpstreamEvent::Start) => {
+                            // Already emitted start event above
+                        }
+                        Ok(UpstreamEvent::Chunk { content }) => {
+                            if !content.is_empty() {
+                                thinking.close();
+                                if let Some(writer) = writer.as_mut() {
+                                    writer.touch();
                                 }
                             }
-                            
-                            // Emit completed tool calls and remove from accumulation
-                            for (call_id, completed_call) in completed_calls {
-                                tool_calls.push(completed_call.clone());
-                                
-                                let _ = app.emit("genai-stream-event", StreamingEventPayload {
-                                    event_type: StreamingEventType::ToolCall,
-                                    stream_id: stream_id.to_string(),
-                                    data: json!({
-                                        "tool_call": completed_call
-                                    }),
-                                    timestamp: chrono::Utc::now(),
-                                });
-                                
-                                accumulated_tool_calls.remove(&call_id);
+                            if buffer.push(&content) {
+                                if let Some(chunk) = buffer.take() {
+                                    emitter.emit(StreamingEventType::Chunk, chunk);
+                                }
                             }
                         }
-                        Ok(ChatStreamEvent::ReasoningChunk(reasoning)) => {
-                            let _ = app.emit("genai-stream-event", StreamingEventPayload {
-                                event_type: StreamingEventType::Reasoning,
-                                stream_id: stream_id.to_string(),
-                                data: json!({ "content": reasoning.content }),
-                                timestamp: chrono::Utc::now(),
-                            });
+                        Ok(UpstreamEvent::ToolCallChunk { tool_call }) => {
+                            thinking.close();
+                            for completed_call in assembler.push(tool_call) {
+                                emitter.emit(StreamingEventType::ToolCall, json!({
+                                    "tool_call": completed_call
+                                }));
+                                tool_calls.push(completed_call);
+                            }
+                        }
+                        Ok(UpstreamEvent::Reasoning { content }) => {
+                            thinking.push(&content);
+                            if let Some(writer) = writer.as_mut() {
+                                writer.touch();
+                            }
+                            emitter.emit(StreamingEventType::Reasoning, json!({
+                                "content": content
+                            }));
                         }
-                        Ok(ChatStreamEvent::End(end_data)) => {
+                        Ok(UpstreamEvent::End { usage, tool_calls: captured_tools }) => {
+                            thinking.close();
+                            let (usage, mut cost) = match &usage {
+                                Some(usage) => {
+                                    let (tokens, cost) = usage_target
+                                        .record_with_reasoning(&usage_app, usage, &thinking.text())
+                                        .await;
+                                    thinking.set_tokens(tokens.reasoning_tokens);
+                                    (Some(tokens), cost)
+                                }
+                                None => (None, None),
+                            };
+
                             // Check for captured tool calls
-                            if let Some(captured_tools) = end_data.captured_into_tool_calls() {
-                                tool_calls.extend(captured_tools);
+                            tool_calls.extend(captured_tools);
+
+                            // Validate against the response schema; repairs are not streamed
+                            let structured = match structured_output {
+                                Some((schema, validator, format)) => {
+                                    let mut repair_usages = Vec::new();
+                                    let output = structured::validate_with_repair(
+                                        client_ref,
+                                        model_ref,
+                                        chat_req,
+                                        chat_options,
+                                        timeouts_ref,
+                                        schema,
+                                        validator,
+                                        *format,
+                                        buffer.accumulated().to_string(),
+                                        &mut repair_usages,
+                                    )
+                                    .await;
+                                    for repair_usage in &repair_usages {
+                                        let (_, repair_cost) = usage_target.record(&usage_app, repair_usage).await;
+                                        cost = add_costs(cost, repair_cost);
+                                    }
+                                    match output {
+                                        Ok(output) => Some(output),
+                                        Err(e) => {
+                                            failure = Some(json!({
+                                                "error": e.to_string(),
+                                                "category": e.category(),
+                                            }));
+                                            break;
+                                        }
+                                    }
+                                }
+                                None => None,
+                            };
+
+                            // Claim the end before writing it, so a stop can no longer abort the write
+                            if !cancel.finish() {
+                                return;
+                            }
+                            finishing = true;
+
+                            // Store the final message before listeners are told the stream ended
+                            if let Some(writer) = writer.take() {
+                                writer
+                                    .finish(FinishedMessage {
+                                        status: MessageStatus::Complete,
+                                        content: structured
+                                            .as_ref()
+                                            .map_or(buffer.accumulated(), |output| output.text.as_str())
+                                            .to_string(),
+                                        thinking: thinking.to_json(false),
+                                        tool_calls: tool_calls_json(&tool_calls, chrono::Utc::now()),
+                                        response_time_ms: started.elapsed().as_secs_f64() * 1000.0,
+                                        error: None,
+                                        metadata: json!({
+                                            "stream_id": stream_id,
+                                            "cost": cost,
+                                            "structured": structured.as_ref().map(|output| &output.value),
+                                            "fallbacks": fallbacks,
+                                        }),
+                                        answered_by: answered_by.clone(),
+                                    })
+                                    .await;
                             }
                             
-                            let _ = app.emit(
-                                "genai-stream-event",
-                                StreamingEventPayload {
-                                    event_type: StreamingEventType::End,
-                                    stream_id: stream_id.to_string(),
-                                    data: json!({
-                                        "final_response": accumulated_response,
-                                        "tool_calls": tool_calls,
-                                    }),
-                                    timestamp: chrono::Utc::now(),
-                                },
-                            );
+                            emitter.emit(StreamingEventType::End, json!({
+                                "model": prepared.model_id,
+                                "final_response": buffer.accumulated(),
+                                "tool_calls": tool_calls,
+                                "usage": usage,
+                                "cost": cost,
+                                "structured": structured,
+                                "thinking": thinking.to_json(false),
+                            }));
                         }
                         Err(e) => {
-                            let _ = app.emit(
-                                "genai-stream-event",
-                                StreamingEventPayload {
-                                    event_type: StreamingEventType::Error,
-                                    stream_id: stream_id.to_string(),
-                                    data: json!({
-                                        "error": e.to_string()
-                                    }),
-                                    timestamp: chrono::Utc::now(),
-                                },
-                            );
+                            failure = Some(json!({
+                                "error": e.to_string(),
+                                "category": e.category(),
+                            }));
                             break;
                         }
-                        _ => {}
                     }
                 }
             }
             Err(e) => {
-                let _ = app.emit(
-                    "genai-stream-event",
-                    StreamingEventPayload {
-                        event_type: StreamingEventType::Error,
-                        stream_id: stream_id.to_string(),
-                        data: json!({
-                            "error": e.to_string()
-                        }),
-                        timestamp: chrono::Utc::now(),
-                });
+                failure = Some(json!({
+                    "error": e.to_string(),
+                    "category": e.category(),
+                }));
             }
         }
+
+        if let Some(keep_alive) = &prepared.keep_alive {
+            keep_alive.reapply();
+        }
+
+        // Content still buffered when the stream closed without an `End` event
+        if let Some(chunk) = buffer.take() {
+            emitter.emit(StreamingEventType::Chunk, chunk);
+        }
+
+        // A stop that came first marks the message cancelled and ends this task
+        if !finishing && !cancel.finish() {
+            return;
+        }
+
+        // Streams that ended without an `End` event are finalized with what arrived
+        if let Some(writer) = writer.take() {
+            let error = failure
+                .as_ref()
+                .map(|payload| payload["error"].as_str().unwrap_or_default().to_string());
+            writer
+                .finish(FinishedMessage {
+                    status: if error.is_some() { MessageStatus::Error } else { MessageStatus::Complete },
+                    content: buffer.accumulated().to_string(),
+                    thinking: thinking.to_json(error.is_some()),
+                    tool_calls: tool_calls_json(&tool_calls, chrono::Utc::now()),
+                    response_time_ms: started.elapsed().as_secs_f64() * 1000.0,
+                    error,
+                    metadata: json!({ "stream_id": stream_id, "fallbacks": fallbacks }),
+                    answered_by,
+                })
+                .await;
+        }
+        let final_state = if failure.is_some() { StreamState::Failed } else { StreamState::Completed };
+        if let Some(payload) = failure {
+            emitter.emit(StreamingEventType::Error, payload);
+        }
+        drop(emitter);
+        sessions::complete(registry, stream_id, final_state).await;
     });
 
-    streaming_session.handle = Some(handle);
-    state
-        .streams
-        .write()
-        .await
-        .insert(stream_id, streaming_session);
+    if let Some(session) = state.streams.write().await.get_mut(&stream_id) {
+        session.handle = Some(handle);
+    }
 
     Ok(stream_id)
 }
 
+/// Stream one conversation to several models at once, grouped under a comparison id.
+///
+/// Each model runs as a normal stream, so events, reattaching and message storage work as for
+/// `stream_message`. Returns once every stream has ended, with each model's latency, tokens and cost.
+#[tauri::command]
+pub async fn compare_models(
+    request: CompareRequest,
+    app: tauri::AppHandle,
+    state: tauri::State<'_, GenAIState>,
+) -> Result<ComparisonResult, GenAIError> {
+    if request.models.is_empty() {
+        return Err(GenAIError::invalid_request("No models to compare"));
+    }
+    let comparison_id = request.comparison_id.unwrap_or_else(Uuid::new_v4);
+    let started_at = chrono::Utc::now();
+
+    // Start every stream before following any, so the models run side by side
+    let started = futures::future::join_all(request.models.iter().map(|model| async {
+        let stream_request = StreamingRequest {
+            model: model.clone(),
+            messages: request.messages.clone(),
+            options: request.options.clone(),
+            thread_options: request.thread_options.clone(),
+            context_strategy: request.context_strategy,
+            thread_id: request.thread_id.clone(),
+            message_id: None,
+            parent_message_id: request.parent_message_id.clone(),
+            tools: request.tools.clone(),
+            response_schema: request.response_schema.clone(),
+            reasoning: request.reasoning.clone(),
+            fallbacks: Some(Vec::new()),
+            context_id: None,
+            comparison_id: Some(comparison_id),
+            stream_config: request.stream_config.clone(),
+        };
+        let result = start_stream(stream_request, app.clone(), &state).await;
+        if let Err(e) = &result {
+            tracing::warn!("Comparison {} could not start {}: {}", comparison_id, model, e);
+        }
+        (model.clone(), result)
+    }))
+    .await;
+
+    let registry = &state.streams;
+    let models = futures::future::join_all(
+        started
+            .into_iter()
+            .map(|(model, result)| compare::watch(registry, model, result)),
+    )
+    .await;
+    Ok(ComparisonResult::new(comparison_id, models, started_at))
+}
+
 /// Stop streaming by stream ID
 #[tauri::command]
 pub async fn stop_streaming_message(
     stream_id: Uuid,
+    app: tauri::AppHandle,
     state: tauri::State<'_, GenAIState>,
 ) -> Result<(), GenAIError> {
-    let mut streams = state.streams.write().await;
-    if let Some(mut stream_session) = streams.remove(&stream_id) {
-        stream_session.active = false;
-        if let Some(handle) = stream_session.handle {
-            handle.abort();
+    // The session stays registered as cancelled until the reaper removes it. A stream whose
+    // task is already writing its final state is left to finish.
+    let message_id = match state.streams.write().await.get_mut(&stream_id) {
+        Some(stream_session) if stream_session.state == StreamState::Running && stream_session.cancel.cancel() => {
+            sessions::finish(stream_session, StreamState::Cancelled);
+            if let Some(handle) = stream_session.handle.take() {
+                handle.abort();
+            }
+            stream_session.message_id.clone()
         }
+        _ => None,
+    };
+    // Keep the partial response saved by the last progress write
+    if let Some(message_id) = message_id {
+        persist::cancel_message(&app, &message_id).await;
     }
     Ok(())
 }
 
+/// Attach to a stream: send journaled events from `from_seq` on, then live events until it ends.
+///
+/// Lets a reloaded or second window catch up on a stream that is in progress; events carry
+/// their `seq`, so a window already listening to the broadcast can drop duplicates.
+#[tauri::command]
+pub async fn subscribe_stream(
+    stream_id: Uuid,
+    from_seq: Option<u64>,
+    on_event: tauri::ipc::Channel<StreamingEventPayload>,
+    state: tauri::State<'_, GenAIState>,
+) -> Result<StreamSubscription, GenAIError> {
+    let journal = state
+        .streams
+        .read()
+        .await
+        .get(&stream_id)
+        .map(|session| session.journal.clone())
+        .ok_or_else(|| GenAIError::invalid_request(format!("Unknown stream {}", stream_id)))?;
+
+    let replay = journal.replay(from_seq.unwrap_or(0));
+    let subscription = StreamSubscription {
+        stream_id,
+        replayed: replay.events.len(),
+        truncated: replay.truncated,
+        finished: replay.live.is_none(),
+    };
+    tokio::spawn(stream::follow(journal, replay, move |event| on_event.send(event).is_ok()));
+
+    Ok(subscription)
+}
+
+/// All registered streams, running and recently finished, newest first
+#[tauri::command]
+pub async fn list_stream_sessions(
+    state: tauri::State<'_, GenAIState>,
+) -> Result<Vec<StreamSessionInfo>, GenAIError> {
+    let mut infos: Vec<StreamSessionInfo> = state
+        .streams
+        .read()
+        .await
+        .values()
+        .map(StreamSessionInfo::from)
+        .collect();
+    infos.sort_by(|a, b| b.started_at.cmp(&a.started_at));
+    Ok(infos)
+}
+
+/// Streams that are still running
+#[tauri::command]
+pub async fn list_active_streams(
+    state: tauri::State<'_, GenAIState>,
+) -> Result<Vec<ActiveStream>, GenAIError> {
+    let mut active: Vec<ActiveStream> = state
+        .streams
+        .read()
+        .await
+        .values()
+        .filter(|session| !session.journal.is_finished())
+        .map(|session| ActiveStream {
+            stream_id: session.id,
+            model: session.model.clone(),
+            message_id: session.message_id.clone(),
+            started_at: session.started_at,
+            last_seq: session.journal.last_seq(),
+        })
+        .collect();
+    active.sort_by_key(|stream| stream.started_at);
+    Ok(active)
+}
+
 // Save provider API key to Stronghold
 #[tauri::command]
 pub async fn save_provider_key(
     request: SaveProviderKeyRequest,
     state: tauri::State<'_, GenAIState>,
 ) -> Result<ProviderOperationResponse, GenAIError> {
+    state
+        .registry
+        .read()
+        .await
+        .validate_key(request.provider.trim_end_matches("_api_key"), &request.api_key)?;
+
     // The actual Stronghold operations would be handled in the frontend
     // Here we just update our in-memory cache
     state.update_provider_key(&request.provider, &request.api_key).await;
+    // Custom providers read their key when the client is built
+    state.invalidate_clients(request.provider.trim_end_matches("_api_key")).await;
     
     tracing::info!("Provider key saved for: {}", request.provider);
     
@@ -520,6 +1786,7 @@ pub async fn remove_provider_key(
     state: tauri::State<'_, GenAIState>,
 ) -> Result<ProviderOperationResponse, GenAIError> {
     state.remove_provider_key(&provider).await;
+    state.invalidate_clients(provider.trim_end_matches("_api_key")).await;
     
     tracing::info!("Provider key removed for: {}", provider);
     
@@ -537,103 +1804,176 @@ pub async fn get_provider_configs(
 ) -> Result<Vec<ProviderConfig>, GenAIError> {
     let configured_providers = state.get_configured_providers().await;
     
-    let providers = vec![
-        ProviderConfig {
-            name: "openai".to_string(),
-            display_name: "OpenAI".to_string(),
-            description: "GPT-4, GPT-4o, and ChatGPT models".to_string(),
-            key_format: "sk-...".to_string(),
-            website: "https://platform.openai.com/api-keys".to_string(),
-            models: vec!["gpt-4o".to_string(), "gpt-4o-mini".to_string(), "gpt-4-turbo".to_string()],
-            is_configured: configured_providers.contains(&"openai".to_string()),
-        },
-        ProviderConfig {
-            name: "anthropic".to_string(),
-            display_name: "Anthropic".to_string(),
-            description: "Claude 3.5 Sonnet, Claude 3 Haiku, and Claude 3 Opus".to_string(),
-            key_format: "sk-ant-...".to_string(),
-            website: "https://console.anthropic.com/settings/keys".to_string(),
-            models: vec!["claude-3-5-sonnet-20241022".to_string(), "claude-3-haiku-20240307".to_string()],
-            is_configured: configured_providers.contains(&"anthropic".to_string()),
-        },
-        ProviderConfig {
-            name: "gemini".to_string(),
-            display_name: "Google Gemini".to_string(),
-            description: "Gemini 2.0 Flash, Gemini 1.5 Pro models".to_string(),
-            key_format: "AI...".to_string(),
-            website: "https://aistudio.google.com/app/apikey".to_string(),
-            models: vec!["gemini-2.0-flash".to_string(), "gemini-1.5-pro".to_string()],
-            is_configured: configured_providers.contains(&"gemini".to_string()),
-        },
-        ProviderConfig {
-            name: "cohere".to_string(),
-            display_name: "Cohere".to_string(),
-            description: "Command and Command Light models".to_string(),
-            key_format: "co-...".to_string(),
-            website: "https://dashboard.cohere.com/api-keys".to_string(),
-            models: vec!["command".to_string(), "command-light".to_string()],
-            is_configured: configured_providers.contains(&"cohere".to_string()),
-        },
-        ProviderConfig {
-            name: "groq".to_string(),
-            display_name: "Groq".to_string(),
-            description: "Ultra-fast inference for Llama, Mixtral models".to_string(),
-            key_format: "gsk_...".to_string(),
-            website: "https://console.groq.com/keys".to_string(),
-            models: vec!["llama-3.1-8b-instant".to_string(), "mixtral-8x7b-32768".to_string()],
-            is_configured: configured_providers.contains(&"groq".to_string()),
-        },
-        ProviderConfig {
-            name: "xai".to_string(),
-            display_name: "xAI".to_string(),
-            description: "Grok models from xAI".to_string(),
-            key_format: "xai-...".to_string(),
-            website: "https://console.x.ai/".to_string(),
-            models: vec!["grok-beta".to_string()],
-            is_configured: configured_providers.contains(&"xai".to_string()),
-        },
-        ProviderConfig {
-            name: "deepseek".to_string(),
-            display_name: "DeepSeek".to_string(),
-            description: "DeepSeek Chat and Coder models".to_string(),
-            key_format: "sk-...".to_string(),
-            website: "https://platform.deepseek.com/api_keys".to_string(),
-            models: vec!["deepseek-chat".to_string(), "deepseek-coder".to_string()],
-            is_configured: configured_providers.contains(&"deepseek".to_string()),
-        },
-    ];
+    let registry = state.registry.read().await;
+    let mut providers: Vec<ProviderConfig> = registry
+        .entries()
+        .iter()
+        .map(|entry| ProviderConfig {
+            name: entry.name.clone(),
+            display_name: entry.display_name.clone(),
+            description: entry.description.clone(),
+            key_format: entry.key_format.clone(),
+            key_pattern: entry.key_pattern.clone(),
+            website: entry.website.clone(),
+            models: entry.models.clone(),
+            is_custom: false,
+            is_configured: !entry.requires_key
+                || configured_providers.contains(&entry.name)
+                || configured_providers.contains(&format!("{}_api_key", entry.name)),
+            capabilities: entry.capabilities.clone(),
+        })
+        .collect();
+
+    let config = state.config.read().await;
+    for custom in config.custom_providers.values() {
+        let key_name = format!("{}_api_key", custom.id);
+        providers.push(ProviderConfig {
+            name: custom.id.clone(),
+            display_name: custom.display_name.clone(),
+            description: format!("Custom endpoint at {}", custom.base_url),
+            key_format: match &custom.auth {
+                AuthHeaderStyle::None => "No key required".to_string(),
+                AuthHeaderStyle::Bearer => "Bearer token".to_string(),
+                AuthHeaderStyle::Header { name } => format!("{} header", name),
+            },
+            key_pattern: None,
+            website: custom.base_url.clone(),
+            models: custom.models.clone(),
+            is_custom: true,
+            is_configured: matches!(custom.auth, AuthHeaderStyle::None)
+                || configured_providers.contains(&key_name)
+                || configured_providers.contains(&custom.id),
+            capabilities: ModelCapabilities {
+                streaming: true,
+                modalities: vec!["text".to_string()],
+                ..ModelCapabilities::default()
+            },
+        });
+    }
     
     Ok(providers)
 }
 
+/// Add or replace a user-defined provider (OpenAI/Anthropic-compatible endpoint)
+#[tauri::command]
+pub async fn add_custom_provider(
+    provider: CustomProvider,
+    state: State<'_, GenAIState>,
+) -> Result<ProviderOperationResponse, GenAIError> {
+    let valid_id = !provider.id.is_empty()
+        && provider.id.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if !valid_id {
+        return Err(GenAIError::invalid_request(format!(
+            "Invalid provider id '{}': use lowercase letters, digits, '-' or '_'",
+            provider.id
+        )));
+    }
+    if parse_provider(&provider.id).is_ok() {
+        return Err(GenAIError::invalid_request(format!(
+            "Provider id '{}' is reserved for a built-in provider",
+            provider.id
+        )));
+    }
+    if !(provider.base_url.starts_with("http://") || provider.base_url.starts_with("https://")) {
+        return Err(GenAIError::invalid_request(format!(
+            "Base URL must start with http:// or https://: {}",
+            provider.base_url
+        )));
+    }
+
+    let id = provider.id.clone();
+    state
+        .config
+        .write()
+        .await
+        .custom_providers
+        .insert(id.clone(), provider);
+    state.invalidate_clients(&id).await;
+    state.save_custom_providers().await?;
+
+    tracing::info!("Added custom provider {}", id);
+    Ok(ProviderOperationResponse {
+        success: true,
+        message: format!("Custom provider {} saved", id),
+        provider: id,
+    })
+}
+
+/// Remove a user-defined provider
+#[tauri::command]
+pub async fn remove_custom_provider(
+    provider: String,
+    state: State<'_, GenAIState>,
+) -> Result<ProviderOperationResponse, GenAIError> {
+    let removed = state
+        .config
+        .write()
+        .await
+        .custom_providers
+        .remove(&provider)
+        .is_some();
+    state.invalidate_clients(&provider).await;
+    if removed {
+        state.save_custom_providers().await?;
+    }
+
+    tracing::info!("Removed custom provider {}", provider);
+    Ok(ProviderOperationResponse {
+        success: removed,
+        message: if removed {
+            format!("Custom provider {} removed", provider)
+        } else {
+            format!("Custom provider {} not found", provider)
+        },
+        provider,
+    })
+}
+
 /// Test provider connection
 #[tauri::command]
 pub async fn test_provider_connection(
     provider: String,
     state: tauri::State<'_, GenAIState>,
 ) -> Result<bool, GenAIError> {
-    // Get a default model for the provider
-    let test_model = match provider.as_str() {
-        "openai" => "gpt-4o-mini",
-        "anthropic" => "claude-3-haiku-20240307",
-        "gemini" => "gemini-2.0-flash",
-        "cohere" => "command-light",
-        "groq" => "llama-3.1-8b-instant",
-        "xai" => "grok-beta",
-        "deepseek" => "deepseek-chat",
-        _ => return Err(GenAIError::invalid_request(format!("Unknown provider: {}", provider))),
+    // Test with the provider's first model, or the registry's test model for a built-in one
+    let custom = state.config.read().await.custom_providers.get(&provider).cloned();
+    let model_ref = match custom {
+        Some(custom) => {
+            let model = custom.models.first().ok_or_else(|| {
+                GenAIError::invalid_request(format!("Custom provider {} has no models to test with", provider))
+            })?;
+            ModelRef {
+                provider: custom.id.clone(),
+                adapter: custom.dialect.adapter_kind(),
+                model: model.clone(),
+            }
+        }
+        None => {
+            let registry = state.registry.read().await;
+            let entry = registry
+                .get(&provider)
+                .ok_or_else(|| GenAIError::invalid_request(format!("Unknown provider: {}", provider)))?;
+            ModelRef::builtin(entry.adapter_kind()?, entry.test_model.clone())
+        }
     };
     println!("Testing connection for provider: {}", provider);
 
-    let auth_resolver = state.init_with_stronghold_auth().await?;
-
-    let new_client = Client::builder()
-        .with_auth_resolver(auth_resolver)
-        .build();
+    let timeouts = state.timeouts_for(&model_ref).await;
+    // Fresh client so the test always uses the latest keys
+    let new_client = state.build_client(&model_ref.provider, timeouts.connect).await?;
     
     let test_request = ChatRequest::new(vec![ChatMessage::user("Hello")]);
     
-    match new_client.exec_chat(test_model, test_request, None).await {
+    let result = timeouts
+        .run("total", timeouts.total, async {
+            new_client
+                .exec_chat(&model_ref.model, test_request, None)
+                .await
+                .map_err(GenAIError::from)
+        })
+        .await;
+
+    match result {
         Ok(_) => Ok(true),
         Err(e) => {
             tracing::warn!("Connection test failed for {}: {}", provider, e);
@@ -659,22 +1999,30 @@ pub async fn load_provider_keys_from_stronghold(
 pub async fn list_available_models(
     state: tauri::State<'_, GenAIState>,
 ) -> Result<HashMap<String, Vec<String>>, GenAIError> {
-    use genai::adapter::AdapterKind;
+    let timeouts = Timeouts::resolve(&state.config.read().await.settings, None);
 
     let mut models = HashMap::new();
-    let adapter_kinds = [
-        AdapterKind::OpenAI,
-        AdapterKind::Anthropic,
-        AdapterKind::Gemini,
-        AdapterKind::Cohere,
-        AdapterKind::Groq,
-        AdapterKind::Ollama,
-        AdapterKind::Xai,
-        AdapterKind::DeepSeek,
-    ];
+    let adapter_kinds: Vec<AdapterKind> = state
+        .registry
+        .read()
+        .await
+        .entries()
+        .iter()
+        .filter_map(|entry| entry.adapter_kind().ok())
+        .collect();
 
     for kind in adapter_kinds {
-        match state.client.all_model_names(kind).await {
+        if kind == AdapterKind::Ollama {
+            // genai lists Ollama models from the default host only
+            let names = match state.ollama_client().await.list_models().await {
+                Ok(installed) => installed.into_iter().map(|m| m.name).collect(),
+                Err(_) => vec![],
+            };
+            models.insert(kind.to_string(), names);
+            continue;
+        }
+        let client = state.client_for(kind.as_lower_str(), &timeouts).await?;
+        match client.all_model_names(kind).await {
             Ok(model_names) => {
                 models.insert(kind.to_string(), model_names);
             }
@@ -684,6 +2032,15 @@ pub async fn list_available_models(
         }
     }
 
+    for custom in state.config.read().await.custom_providers.values() {
+        models.insert(custom.id.clone(), custom.models.clone());
+    }
+
+    let fixtures = state.replays.read().await.names();
+    if !fixtures.is_empty() {
+        models.insert(replay::PROVIDER.to_string(), fixtures);
+    }
+
     Ok(models)
 }
 
@@ -692,43 +2049,70 @@ pub async fn get_model_info(
     model: String,
     state: State<'_, GenAIState>,
 ) -> Result<ModelInfo, GenAIError> {
+    let unavailable = |name: String| ModelInfo {
+        name,
+        provider: "unknown".to_string(),
+        available: false,
+        capabilities: ModelCapabilities::default(),
+        pricing: None,
+        metadata: HashMap::new(),
+    };
+    let Ok(model_ref) = state.resolve_model(&model).await else {
+        return Ok(unavailable(model));
+    };
+    if model_ref.provider == replay::PROVIDER {
+        return Ok(ModelInfo {
+            provider: replay::PROVIDER.to_string(),
+            available: state.replays.read().await.get(&model_ref.model).is_ok(),
+            capabilities: ModelCapabilities {
+                streaming: true,
+                tools: true,
+                modalities: vec!["text".to_string()],
+                ..ModelCapabilities::default()
+            },
+            ..unavailable(model)
+        });
+    }
+    let timeouts = state.timeouts_for(&model_ref).await;
+    let client = state.client_for(&model_ref.provider, &timeouts).await?;
+
     // Try to resolve the model to get adapter information
-    match state.client.resolve_service_target(&model).await {
+    match client.resolve_service_target(&model_ref.model).await {
         Ok(target) => {
-            Ok(ModelInfo {
-                name: model.clone(),
-                provider: target.model.adapter_kind.to_string(),
-                available: true,
-                capabilities: ModelCapabilities {
+            // Provider-wide capabilities, refined by the model catalog
+            let provider_capabilities = state
+                .registry
+                .read()
+                .await
+                .get(&model_ref.provider)
+                .map(|entry| entry.capabilities.clone())
+                .unwrap_or_else(|| ModelCapabilities {
                     streaming: true, // Most models support streaming
-                    tools: matches!(
-                        target.model.adapter_kind,
-                        genai::adapter::AdapterKind::OpenAI |
-                        genai::adapter::AdapterKind::Anthropic |
-                        genai::adapter::AdapterKind::Gemini
-                    ),
-                    vision: model.contains("vision") || model.contains("4o"),
-                    max_context_length: None, // Would need model-specific data
                     modalities: vec!["text".to_string()],
-                },
-                metadata: HashMap::new(),
-            })
-        }
-        Err(_) => {
+                    ..ModelCapabilities::default()
+                });
+            let catalog = state.catalog.read().await;
+            let spec = catalog.lookup(&model_ref);
+
+            let mut metadata = HashMap::new();
+            metadata.insert("catalog_version".to_string(), json!(catalog.version));
+            if let Some(spec) = spec {
+                metadata.insert("catalog_pattern".to_string(), json!(spec.pattern));
+            }
+
             Ok(ModelInfo {
                 name: model.clone(),
-                provider: "unknown".to_string(),
-                available: false,
-                capabilities: ModelCapabilities {
-                    streaming: false,
-                    tools: false,
-                    vision: false,
-                    max_context_length: None,
-                    modalities: vec![],
+                provider: target.model.adapter_kind.to_string(),
+                available: true,
+                capabilities: match spec {
+                    Some(spec) => spec.capabilities(&provider_capabilities),
+                    None => provider_capabilities,
                 },
-                metadata: HashMap::new(),
+                pricing: spec.and_then(|spec| spec.pricing()),
+                metadata,
             })
         }
+        Err(_) => Ok(unavailable(model)),
     }
 }
 
@@ -738,21 +2122,39 @@ pub async fn test_model_connection(
     model: String,
     state: tauri::State<'_, GenAIState>,
 ) -> Result<bool, GenAIError> {
+    let model_ref = state.resolve_model(&model).await?;
+    let timeouts = state.timeouts_for(&model_ref).await;
+    let client = state.client_for(&model_ref.provider, &timeouts).await?;
     let test_request = ChatRequest::new(vec![ChatMessage::user("Hello")]);
 
-    match state.client.exec_chat(&model, test_request, None).await {
+    let result = timeouts
+        .run("total", timeouts.total, async {
+            client
+                .exec_chat(&model_ref.model, test_request, None)
+                .await
+                .map_err(GenAIError::from)
+        })
+        .await;
+
+    match result {
         Ok(_) => Ok(true),
         Err(_) => Ok(false),
     }
 }
 
-/// Configuration commands (unchanged)
+/// Replace the configuration, except for custom providers and Ollama settings.
+///
+/// Those are changed through their own commands, which save them and rebuild the clients they
+/// affect; a caller that leaves them out would otherwise reset them to defaults.
 #[tauri::command]
 pub async fn update_config(
-    config: GenAIConfig,
+    mut config: GenAIConfig,
     state: tauri::State<'_, GenAIState>,
 ) -> Result<(), GenAIError> {
-    *state.config.write().await = config;
+    let mut current = state.config.write().await;
+    config.custom_providers = std::mem::take(&mut current.custom_providers);
+    config.ollama = std::mem::take(&mut current.ollama);
+    *current = config;
     Ok(())
 }
 
@@ -811,4 +2213,182 @@ pub async fn remove_auth_provider(
 
     tracing::info!("Removed auth provider {}", provider_name);
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Set the Ollama host (e.g. `http://192.168.1.20:11434`)
+#[tauri::command]
+pub async fn set_ollama_host(
+    base_url: String,
+    state: State<'_, GenAIState>,
+) -> Result<(), GenAIError> {
+    if !(base_url.starts_with("http://") || base_url.starts_with("https://")) {
+        return Err(GenAIError::invalid_request(format!(
+            "Ollama URL must start with http:// or https://: {}",
+            base_url
+        )));
+    }
+    state.config.write().await.ollama.base_url = base_url.trim_end_matches('/').to_string();
+    state.invalidate_clients(AdapterKind::Ollama.as_lower_str()).await;
+
+    tracing::info!("Ollama host set to {}", base_url);
+    Ok(())
+}
+
+/// List locally installed Ollama models
+#[tauri::command]
+pub async fn list_ollama_models(
+    state: State<'_, GenAIState>,
+) -> Result<Vec<OllamaModel>, GenAIError> {
+    state.ollama_client().await.list_models().await
+}
+
+/// Pull an Ollama model, emitting progress events until it completes
+#[tauri::command]
+pub async fn pull_ollama_model(
+    model: String,
+    app: tauri::AppHandle,
+    state: State<'_, GenAIState>,
+) -> Result<(), GenAIError> {
+    let client = state.ollama_client().await;
+    client
+        .pull_model(&model, |progress| {
+            let _ = app.emit(OLLAMA_PULL_EVENT, progress);
+        })
+        .await?;
+
+    tracing::info!("Pulled Ollama model {}", model);
+    Ok(())
+}
+
+/// Delete an installed Ollama model
+#[tauri::command]
+pub async fn delete_ollama_model(
+    model: String,
+    state: State<'_, GenAIState>,
+) -> Result<(), GenAIError> {
+    let client = state.ollama_client().await;
+    client.delete_model(&model).await?;
+    let settings = state.config.write().await.ollama.models.remove(&model);
+    if let Some(derived) = settings.filter(|settings| settings.num_ctx.is_some()) {
+        remove_derived_model(&client, &derived.chat_model(&model)).await;
+    }
+    state.forget_ollama_model(&model).await;
+
+    tracing::info!("Deleted Ollama model {}", model);
+    Ok(())
+}
+
+/// Set keep_alive and the context size for an Ollama model, and load the model with them
+#[tauri::command]
+pub async fn set_ollama_model_options(
+    model: String,
+    settings: OllamaModelSettings,
+    state: State<'_, GenAIState>,
+) -> Result<(), GenAIError> {
+    // Create the derived model again, in case it was deleted outside the app
+    state.forget_ollama_model(&model).await;
+    let client = state.ollama_client().await;
+    let base_url = state.config.read().await.ollama.base_url.clone();
+    let chat_model = state.create_ollama_model(&base_url, &model, &settings).await?;
+    client.load_model(&chat_model, &settings).await?;
+    let previous = state
+        .config
+        .write()
+        .await
+        .ollama
+        .models
+        .insert(model.clone(), settings);
+
+    // A derived model for a context size no longer in use is removed
+    if let Some(previous) = previous.filter(|previous| previous.num_ctx.is_some()) {
+        let previous_model = previous.chat_model(&model);
+        if previous_model != chat_model {
+            remove_derived_model(&client, &previous_model).await;
+        }
+    }
+
+    tracing::info!("Set Ollama options for {}", model);
+    Ok(())
+}
+
+/// Delete a model derived with a context size. Best effort: a failure is logged.
+async fn remove_derived_model(client: &OllamaClient, derived: &str) {
+    if let Err(e) = client.delete_model(derived).await {
+        tracing::warn!("Failed to delete Ollama model {}: {}", derived, e);
+    }
+}
+
+/// Re-read the user provider registry override file
+#[tauri::command]
+pub async fn reload_provider_registry(
+    state: State<'_, GenAIState>,
+) -> Result<Vec<String>, GenAIError> {
+    let reloaded = state.registry.read().await.reload();
+    let names = reloaded.entries().iter().map(|e| e.name.clone()).collect();
+    *state.registry.write().await = reloaded;
+    state.clients.write().await.clear();
+
+    tracing::info!("Provider registry reloaded");
+    Ok(names)
+}
+
+/// Embed texts with an embedding model (the configured one by default)
+#[tauri::command]
+pub async fn embed(
+    request: EmbedRequest,
+    state: State<'_, GenAIState>,
+) -> Result<EmbedResponse, GenAIError> {
+    if request.inputs.is_empty() {
+        return Err(GenAIError::invalid_request("No texts to embed"));
+    }
+    let (model, embeddings) = state.embed_texts(request.model.as_deref(), &request.inputs).await?;
+    Ok(EmbedResponse {
+        model: model.qualified(),
+        dimensions: embeddings.first().map_or(0, Vec::len),
+        embeddings,
+    })
+}
+
+/// Messages closest in meaning to `query`, optionally within a project, each with the thread it
+/// belongs to and the messages around it.
+///
+/// Only messages the indexer has embedded with the configured model are searched.
+#[tauri::command]
+pub async fn semantic_search<R: Runtime>(
+    query: String,
+    project_id: Option<String>,
+    limit: Option<usize>,
+    app: tauri::AppHandle<R>,
+    state: State<'_, GenAIState>,
+) -> Result<Vec<SemanticHit>, GenAIError> {
+    if query.trim().is_empty() {
+        return Err(GenAIError::invalid_request("Search query is empty"));
+    }
+    let db = app
+        .try_state::<Database>()
+        .ok_or_else(|| GenAIError::configuration("Database is not available"))?;
+    let (model, mut vectors) = state.embed_texts(None, &[embed::embedding_input(&query)]).await?;
+    let vector = vectors.pop().unwrap_or_default();
+    Ok(message_embeddings::semantic_search(
+        db.pool(),
+        &model.qualified(),
+        &vector,
+        project_id.as_deref(),
+        limit.unwrap_or(20),
+        1,
+    )
+    .await?)
+}
+
+/// Re-read the user model catalog override file
+#[tauri::command]
+pub async fn reload_model_catalog(
+    state: State<'_, GenAIState>,
+) -> Result<u32, GenAIError> {
+    let reloaded = state.catalog.read().await.reload();
+    let version = reloaded.version;
+    *state.catalog.write().await = reloaded;
+
+    tracing::info!("Model catalog reloaded");
+    Ok(version)
+}
@@ -1,15 +1,28 @@
 //! Data models and types for the GenAI Tauri integration
 
+use super::context::{ContextConfig, ContextReport, ContextStrategy};
+use super::embed::EmbeddingConfig;
+use super::fallback::FallbackAttempt;
+use super::ollama::OllamaSettings;
+use super::retry::RetryPolicy;
+use super::sessions::CancelToken;
+use super::stream::{StreamJournal, DEFAULT_JOURNAL_SIZE};
+use super::reasoning::ReasoningConfig;
+use super::structured::{ResponseSchema, StructuredOutput};
+use super::timeout::TimeoutConfig;
 use genai::chat::{ChatOptions, Tool, ToolCall, Usage};
 use serde::{Deserialize, Serialize};
 use tokio::sync::mpsc;
 use std::collections::HashMap;
 use uuid::Uuid;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct MessageInput {
     pub role: String,
     pub content: String,
+    /// Pinned messages survive context trimming
+    #[serde(default)]
+    pub pinned: bool,
 }
 
 /// Configuration for the GenAI integration
@@ -25,6 +38,23 @@ pub struct GenAIConfig {
     pub model_configs: HashMap<String, ModelConfig>,
     /// Global settings
     pub settings: GlobalSettings,
+    /// User-defined providers (OpenAI/Anthropic-compatible endpoints), keyed by id
+    #[serde(default)]
+    pub custom_providers: HashMap<String, CustomProvider>,
+    /// Ollama host and per-model runtime settings
+    #[serde(default)]
+    pub ollama: OllamaSettings,
+}
+
+impl GenAIConfig {
+    /// Model config for a qualified model id, falling back to the bare model name
+    pub fn model_config(&self, model_id: &str) -> Option<&ModelConfig> {
+        self.model_configs.get(model_id).or_else(|| {
+            model_id
+                .split_once(super::router::PROVIDER_SEPARATOR)
+                .and_then(|(_, model)| self.model_configs.get(model))
+        })
+    }
 }
 
 impl Default for GenAIConfig {
@@ -35,6 +65,8 @@ impl Default for GenAIConfig {
             auth_providers: HashMap::new(),
             model_configs: HashMap::new(),
             settings: GlobalSettings::default(),
+            custom_providers: HashMap::new(),
+            ollama: OllamaSettings::default(),
         }
     }
 }
@@ -68,6 +100,73 @@ pub enum AuthProviderType {
     Custom { config: HashMap<String, String> },
 }
 
+/// User-defined provider served from a custom base URL
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CustomProvider {
+    /// Provider id, used as the `id::model` qualifier and the key name (`{id}_api_key`)
+    pub id: String,
+    /// Name shown in the UI
+    pub display_name: String,
+    /// API base URL, e.g. `http://localhost:8000/v1/`
+    pub base_url: String,
+    /// How the API key is sent
+    pub auth: AuthHeaderStyle,
+    /// API dialect spoken by the endpoint
+    pub dialect: ApiDialect,
+    /// Models offered by the endpoint
+    #[serde(default)]
+    pub models: Vec<String>,
+}
+
+/// How a custom provider expects its API key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AuthHeaderStyle {
+    /// No authentication (typical for local servers)
+    None,
+    /// The dialect's native header (`Authorization: Bearer` for OpenAI, `x-api-key` for Anthropic)
+    Bearer,
+    /// Key sent in a custom header, e.g. `api-key`
+    Header { name: String },
+}
+
+/// API dialect of a custom provider
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ApiDialect {
+    /// OpenAI chat completions compatible (vLLM, LM Studio, llama.cpp, ...)
+    #[serde(rename = "openai")]
+    OpenAI,
+    /// Anthropic messages compatible
+    Anthropic,
+}
+
+impl ApiDialect {
+    /// Adapter used to speak this dialect
+    pub fn adapter_kind(&self) -> genai::adapter::AdapterKind {
+        match self {
+            Self::OpenAI => genai::adapter::AdapterKind::OpenAI,
+            Self::Anthropic => genai::adapter::AdapterKind::Anthropic,
+        }
+    }
+
+    /// Chat endpoint, relative to the provider's base URL
+    pub fn chat_path(&self) -> &'static str {
+        match self {
+            Self::OpenAI => "chat/completions",
+            Self::Anthropic => "messages",
+        }
+    }
+
+    /// Headers the dialect needs besides the API key
+    pub fn required_headers(&self) -> Vec<(String, String)> {
+        match self {
+            Self::OpenAI => Vec::new(),
+            Self::Anthropic => vec![("anthropic-version".to_string(), "2023-06-01".to_string())],
+        }
+    }
+}
+
 /// Model-specific configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ModelConfig {
@@ -79,6 +178,12 @@ pub struct ModelConfig {
     pub parameters: HashMap<String, serde_json::Value>,
     /// Whether this model is enabled
     pub enabled: bool,
+    /// Model-specific timeout overrides
+    #[serde(default)]
+    pub timeouts: TimeoutConfig,
+    /// Models tried in order when this one fails with an outage, auth error or rate limit
+    #[serde(default)]
+    pub fallbacks: Vec<String>,
 }
 
 /// Global settings for the GenAI integration
@@ -86,14 +191,29 @@ pub struct ModelConfig {
 pub struct GlobalSettings {
     /// Enable debug logging
     pub debug_logging: bool,
-    /// Request timeout in seconds
+    /// Request timeout in seconds (total limit unless `timeouts.total_ms` is set)
     pub request_timeout: u64,
+    /// Connect, first-token and total timeouts
+    #[serde(default)]
+    pub timeouts: TimeoutConfig,
     /// Maximum number of sessions to keep in memory
     pub max_sessions: usize,
     /// Auto-save sessions
     pub auto_save_sessions: bool,
     /// Session storage path
     pub session_storage_path: Option<String>,
+    /// Retry policy for provider calls
+    #[serde(default)]
+    pub retry: RetryPolicy,
+    /// How conversations are fitted into the model's context window
+    #[serde(default)]
+    pub context: ContextConfig,
+    /// Record provider requests and responses as replay fixtures
+    #[serde(default)]
+    pub record_fixtures: bool,
+    /// Embedding model and background indexing of messages
+    #[serde(default)]
+    pub embedding: EmbeddingConfig,
 }
 
 impl Default for GlobalSettings {
@@ -101,9 +221,14 @@ impl Default for GlobalSettings {
         Self {
             debug_logging: false,
             request_timeout: 60,
+            timeouts: TimeoutConfig::default(),
             max_sessions: 100,
             auto_save_sessions: false,
             session_storage_path: None,
+            retry: RetryPolicy::default(),
+            context: ContextConfig::default(),
+            record_fixtures: false,
+            embedding: EmbeddingConfig::default(),
         }
     }
 }
@@ -119,12 +244,30 @@ pub struct ModelInfo {
     pub available: bool,
     /// Model capabilities
     pub capabilities: ModelCapabilities,
+    /// Price per million tokens, when known
+    #[serde(default)]
+    pub pricing: Option<ModelPricing>,
     /// Additional metadata
     pub metadata: HashMap<String, serde_json::Value>,
 }
 
+/// Model pricing in USD per million tokens
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPricing {
+    pub input_per_mtok: f64,
+    pub output_per_mtok: f64,
+}
+
+impl ModelPricing {
+    /// Cost in USD for the given token counts
+    pub fn cost(&self, input_tokens: u64, output_tokens: u64) -> f64 {
+        (input_tokens as f64 * self.input_per_mtok + output_tokens as f64 * self.output_per_mtok) / 1_000_000.0
+    }
+}
+
 /// Model capabilities
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 pub struct ModelCapabilities {
     /// Supports streaming responses
     pub streaming: bool,
@@ -134,6 +277,12 @@ pub struct ModelCapabilities {
     pub vision: bool,
     /// Maximum context length
     pub max_context_length: Option<u32>,
+    /// Maximum output tokens per response
+    pub max_output_tokens: Option<u32>,
+    /// Supports reasoning/thinking
+    pub reasoning: bool,
+    /// Supports native JSON output mode
+    pub json_mode: bool,
     /// Supported modalities
     pub modalities: Vec<String>,
 }
@@ -147,8 +296,45 @@ pub struct DirectChatRequest {
     pub messages: Vec<MessageInput>,
     /// Optional chat options
     pub options: Option<ChatOptions>,
+    /// Optional thread-level options (from the thread settings), applied below `options`
+    pub thread_options: Option<ChatOptions>,
+    /// Overrides the configured context fitting strategy
+    #[serde(default)]
+    pub context_strategy: Option<ContextStrategy>,
+    /// Thread the response belongs to, for usage accounting
+    #[serde(default)]
+    pub thread_id: Option<String>,
+    /// Id of the assistant message that will hold the response, for usage accounting
+    #[serde(default)]
+    pub message_id: Option<String>,
     /// Optional tools for this conversation
     pub tools: Option<Vec<Tool>>,
+    /// JSON Schema the response must conform to
+    #[serde(default)]
+    pub response_schema: Option<ResponseSchema>,
+    /// Reasoning effort or thinking budget
+    #[serde(default)]
+    pub reasoning: Option<ReasoningConfig>,
+    /// Models to fall back to, overriding the project's and the model's chain (empty disables)
+    #[serde(default)]
+    pub fallbacks: Option<Vec<String>>,
+}
+
+/// Texts to embed
+#[derive(Debug, Deserialize)]
+pub struct EmbedRequest {
+    pub inputs: Vec<String>,
+    /// Embedding model; the configured one if not given
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// One vector per input, in input order
+#[derive(Debug, Serialize)]
+pub struct EmbedResponse {
+    pub model: String,
+    pub dimensions: usize,
+    pub embeddings: Vec<Vec<f32>>,
 }
 
 /// Response for direct chat
@@ -162,6 +348,10 @@ pub struct ChatResponse {
     pub metadata: ResponseMetadata,
     /// Tool calls if any
     pub tool_calls: Option<Vec<ToolCall>>,
+    /// Parsed response, when a response schema was given
+    pub structured: Option<StructuredOutput>,
+    /// Reasoning as a `ThinkingProcess`, when the model returned any
+    pub thinking: Option<serde_json::Value>,
 }
 
 #[derive(Debug, serde::Serialize)]
@@ -174,6 +364,14 @@ pub struct ResponseMetadata {
     pub response_time_ms: u64,
     /// Whether the response was streamed
     pub streamed: bool,
+    /// Effective chat options after layering defaults, model, thread and request options
+    pub options: ChatOptions,
+    /// Messages trimmed to fit the context window, if any
+    pub context: Option<ContextReport>,
+    /// Cost in USD, if the model's pricing is known
+    pub cost: Option<f64>,
+    /// Models that failed before `model` answered
+    pub fallbacks: Vec<FallbackAttempt>,
 }
 
 /// Streaming request without session dependency
@@ -181,30 +379,89 @@ pub struct ResponseMetadata {
 pub struct StreamingRequest {
     /// Model to use
     pub model: String,
-    /// Conversation messages
+    /// Conversation messages; when empty, the stored branch ending at `parent_message_id`
     // pub messages: Vec<ChatMessage>,
     pub messages: Vec<MessageInput>,
     /// Optional chat options
     pub options: Option<ChatOptions>,
+    /// Optional thread-level options (from the thread settings), applied below `options`
+    pub thread_options: Option<ChatOptions>,
+    /// Overrides the configured context fitting strategy
+    #[serde(default)]
+    pub context_strategy: Option<ContextStrategy>,
+    /// Thread the response belongs to; the backend stores the assistant message in it
+    #[serde(default)]
+    pub thread_id: Option<String>,
+    /// Id for the stored assistant message (generated when omitted)
+    #[serde(default)]
+    pub message_id: Option<String>,
+    /// Message the response replies to; the stored assistant message becomes its child, a sibling
+    /// of any earlier reply
+    #[serde(default)]
+    pub parent_message_id: Option<String>,
     /// Optional tools
     pub tools: Option<Vec<Tool>>,
+    /// JSON Schema the response must conform to
+    #[serde(default)]
+    pub response_schema: Option<ResponseSchema>,
+    /// Reasoning effort or thinking budget
+    #[serde(default)]
+    pub reasoning: Option<ReasoningConfig>,
+    /// Models to fall back to, overriding the project's and the model's chain (empty disables)
+    #[serde(default)]
+    pub fallbacks: Option<Vec<String>>,
     /// Optional context identifier (for UI correlation)
     pub context_id: Option<String>,
+    /// Comparison the stream belongs to, when started by `compare_models`
+    #[serde(default)]
+    pub comparison_id: Option<Uuid>,
     /// Optional stream configuration
     pub stream_config: Option<StreamConfig>,
 }
 
+/// One conversation sent to several models at once
+#[derive(Debug, Deserialize)]
+pub struct CompareRequest {
+    /// Models to compare, each streamed as is (no fallbacks)
+    pub models: Vec<String>,
+    /// Conversation messages; when empty, the stored branch ending at `parent_message_id`
+    pub messages: Vec<MessageInput>,
+    pub options: Option<ChatOptions>,
+    pub thread_options: Option<ChatOptions>,
+    #[serde(default)]
+    pub context_strategy: Option<ContextStrategy>,
+    /// Thread to store each model's response in, as sibling messages under `parent_message_id`
+    #[serde(default)]
+    pub thread_id: Option<String>,
+    #[serde(default)]
+    pub parent_message_id: Option<String>,
+    pub tools: Option<Vec<Tool>>,
+    #[serde(default)]
+    pub response_schema: Option<ResponseSchema>,
+    #[serde(default)]
+    pub reasoning: Option<ReasoningConfig>,
+    /// Id grouping the streams (generated when omitted); carried in each stream's start event
+    #[serde(default)]
+    pub comparison_id: Option<Uuid>,
+    pub stream_config: Option<StreamConfig>,
+}
+
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct StreamConfig {
     /// Include accumulated text in chunk events
     pub include_accumulated: bool,
-    /// Buffer size for streaming
+    /// Number of buffered bytes that forces a chunk to be emitted (0 emits every delta)
     pub buffer_size: usize,
-    /// Timeout for stream operations in milliseconds
+    /// Maximum time in milliseconds a delta is held back for coalescing
+    pub flush_interval_ms: u64,
+    /// Idle timeout in milliseconds; the stream errors if no event arrives in time (0 disables)
     pub timeout_ms: u64,
     /// Whether to capture tool calls
     pub capture_tool_calls: bool,
+    /// Number of events kept for subscribers that attach late
+    pub journal_size: usize,
 }
 
 impl Default for StreamConfig {
@@ -212,21 +469,32 @@ impl Default for StreamConfig {
         Self {
             include_accumulated: false,
             buffer_size: 1024,
+            flush_interval_ms: 50,
             timeout_ms: 30000,
             capture_tool_calls: true,
+            journal_size: DEFAULT_JOURNAL_SIZE,
         }
     }
 }
 
-#[derive(Serialize, Clone)]
+#[derive(Debug, Serialize, Clone)]
 pub struct StreamingEventPayload {
     pub event_type: StreamingEventType,
     pub stream_id: String,
     // context_id: String,
+    /// Sequence number within the stream, starting at 0
+    pub seq: u64,
     pub data: serde_json::Value,
     pub timestamp: chrono::DateTime<chrono::Utc>,
 }
 
+impl StreamingEventPayload {
+    /// Whether this is the last event of its stream
+    pub fn is_terminal(&self) -> bool {
+        matches!(self.event_type, StreamingEventType::End | StreamingEventType::Error)
+    }
+}
+
 /// Types of streaming events
 #[derive(Debug, Clone, Serialize)]
 pub enum StreamingEventType {
@@ -238,6 +506,10 @@ pub enum StreamingEventType {
     ToolCall,
     /// Reasoning content (for models that support it)
     Reasoning,
+    /// Stream setup failed with a retryable error and is being retried
+    Retry,
+    /// The model failed and the next one in its fallback chain takes over
+    Fallback,
     /// Stream ended
     End,
     /// Error occurred
@@ -272,33 +544,82 @@ pub struct StreamingSession {
     pub control_tx: Option<mpsc::UnboundedSender<StreamControlMessage>>,
     /// Stream configuration
     pub config: StreamConfig,
+    /// Assistant message the stream is writing to, if it is persisted
+    pub message_id: Option<String>,
+    /// Lifecycle state; the streaming task sets it when it ends
+    pub state: StreamState,
+    /// When the stream was started
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    /// When the first content, reasoning or tool call arrived
+    pub first_token_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// When the stream ended, failed or was stopped
+    pub finished_at: Option<chrono::DateTime<chrono::Utc>>,
+    /// Recent events, replayed to subscribers that attach late
+    pub journal: StreamJournal,
+    /// Shared with the streaming task, which finishes the stream unless it was stopped first
+    pub cancel: CancelToken,
 }
 
 impl StreamingSession {
     /// Create a new streaming session
     pub fn new(model: &str) -> Self {
+        Self::with_config(model, StreamConfig::default())
+    }
+
+    /// Create a new streaming session with a specific stream configuration
+    pub fn with_config(model: &str, config: StreamConfig) -> Self {
+        let id = Uuid::new_v4();
         Self {
-            id: Uuid::new_v4(),
+            id,
             chat_session_id: None,
             model: model.to_string(),
             active: false,
             handle: None,
             control_tx: None,
-            config: StreamConfig::default(),
+            journal: StreamJournal::new(id, config.journal_size),
+            config,
+            message_id: None,
+            state: StreamState::Running,
+            started_at: chrono::Utc::now(),
+            first_token_at: None,
+            finished_at: None,
+            cancel: CancelToken::default(),
         }
     }
+}
 
-    // pub fn with_config(model: &str, config: StreamConfig) -> Self {
-    //     Self {
-    //         id: Uuid::new_v4(),
-    //         chat_session_id: None,
-    //         model: model.to_string(),
-    //         active: false,
-    //         handle: None,
-    //         control_tx: None,
-    //         config,
-    //     }
-    // }
+/// Lifecycle state of a streaming session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StreamState {
+    Running,
+    Completed,
+    Failed,
+    Cancelled,
+}
+
+/// A running stream, as listed for windows that want to attach to it
+#[derive(Debug, Clone, Serialize)]
+pub struct ActiveStream {
+    pub stream_id: Uuid,
+    pub model: String,
+    /// Assistant message the stream is writing to, if it is persisted
+    pub message_id: Option<String>,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    /// Sequence number of the latest event
+    pub last_seq: Option<u64>,
+}
+
+/// Result of attaching to a stream
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamSubscription {
+    pub stream_id: Uuid,
+    /// Number of journaled events sent before live ones
+    pub replayed: usize,
+    /// Whether some requested events were no longer in the journal
+    pub truncated: bool,
+    /// Whether the stream was already over; only the replay is sent
+    pub finished: bool,
 }
 
 
@@ -308,9 +629,18 @@ pub struct ProviderConfig {
     pub display_name: String,
     pub description: String,
     pub key_format: String,
+    /// Regex a valid API key must match
+    #[serde(default)]
+    pub key_pattern: Option<String>,
     pub website: String,
     pub models: Vec<String>,
     pub is_configured: bool,
+    /// Whether this is a user-defined provider
+    #[serde(default)]
+    pub is_custom: bool,
+    /// Provider-wide capabilities
+    #[serde(default)]
+    pub capabilities: ModelCapabilities,
 }
 
 /// Request to save a provider key
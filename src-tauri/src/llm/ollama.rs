@@ -0,0 +1,560 @@
+//! Minimal client for the native Ollama API (model management)
+
+use super::error::{GenAIError, GenAIResult};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Default Ollama host
+pub const DEFAULT_OLLAMA_URL: &str = "http://localhost:11434";
+
+/// Time allowed to reach the server
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Time allowed for a request; loading a large model into memory can take a while
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+const LOAD_TIMEOUT: Duration = Duration::from_secs(300);
+
+/// Time a pull may go without a progress line; the download as a whole has no limit
+const PULL_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Ollama host and per-model runtime settings
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OllamaSettings {
+    /// Base URL of the Ollama server, without the `/v1` suffix
+    pub base_url: String,
+    /// Runtime settings per model name
+    pub models: HashMap<String, OllamaModelSettings>,
+}
+
+impl Default for OllamaSettings {
+    fn default() -> Self {
+        Self {
+            base_url: DEFAULT_OLLAMA_URL.to_string(),
+            models: HashMap::new(),
+        }
+    }
+}
+
+impl OllamaSettings {
+    /// OpenAI-compatible endpoint used by the genai Ollama adapter
+    pub fn openai_endpoint(&self) -> String {
+        format!("{}/v1/", self.base_url.trim_end_matches('/'))
+    }
+}
+
+/// Runtime settings for a model.
+///
+/// Chat goes through the OpenAI-compatible endpoint, which takes neither setting. The context
+/// size is baked into a derived model that chat is sent to instead, and keep_alive is applied
+/// again after each request, since every request resets it to the server default.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OllamaModelSettings {
+    /// How long the model stays loaded, e.g. "10m", "-1" (forever) or "0" (unload)
+    pub keep_alive: Option<String>,
+    /// Context window size in tokens
+    pub num_ctx: Option<u32>,
+}
+
+impl OllamaModelSettings {
+    /// Model that chat with `model` is sent to: the model itself, or one derived with the context size
+    pub fn chat_model(&self, model: &str) -> String {
+        match self.num_ctx {
+            Some(num_ctx) if model.contains(':') => format!("{}-ctx{}", model, num_ctx),
+            Some(num_ctx) => format!("{}:ctx{}", model, num_ctx),
+            None => model.to_string(),
+        }
+    }
+}
+
+/// A model whose keep_alive is applied again once a chat request with it is done
+#[derive(Debug, Clone)]
+pub struct KeepAlive {
+    pub base_url: String,
+    pub model: String,
+    pub settings: OllamaModelSettings,
+}
+
+impl KeepAlive {
+    /// Reset the model's unload timer in the background. Best effort: a failure is logged.
+    pub fn reapply(&self) {
+        let keep_alive = self.clone();
+        tokio::spawn(async move {
+            let client = OllamaClient::new(&keep_alive.base_url);
+            if let Err(e) = client.load_model(&keep_alive.model, &keep_alive.settings).await {
+                tracing::warn!("Failed to apply keep_alive to {}: {}", keep_alive.model, e);
+            }
+        });
+    }
+}
+
+/// A locally installed model
+#[derive(Debug, Clone, Serialize)]
+pub struct OllamaModel {
+    pub name: String,
+    /// Size on disk in bytes
+    pub size: u64,
+    pub quantization: Option<String>,
+    pub parameter_size: Option<String>,
+    pub family: Option<String>,
+    pub modified_at: Option<String>,
+}
+
+/// Progress update while pulling a model
+#[derive(Debug, Clone, Serialize)]
+pub struct OllamaPullProgress {
+    pub model: String,
+    pub status: String,
+    pub digest: Option<String>,
+    pub total: Option<u64>,
+    pub completed: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct TagsResponse {
+    #[serde(default)]
+    models: Vec<TagEntry>,
+}
+
+#[derive(Deserialize)]
+struct TagEntry {
+    name: String,
+    #[serde(default)]
+    size: u64,
+    modified_at: Option<String>,
+    #[serde(default)]
+    details: TagDetails,
+}
+
+#[derive(Default, Deserialize)]
+struct TagDetails {
+    quantization_level: Option<String>,
+    parameter_size: Option<String>,
+    family: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct EmbedResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+#[derive(Deserialize)]
+struct PullLine {
+    #[serde(default)]
+    status: String,
+    digest: Option<String>,
+    total: Option<u64>,
+    completed: Option<u64>,
+    error: Option<String>,
+}
+
+/// Client for the native Ollama REST API
+pub struct OllamaClient {
+    base_url: String,
+    http: reqwest::Client,
+}
+
+impl OllamaClient {
+    pub fn new(base_url: &str) -> Self {
+        let http = reqwest::Client::builder()
+            .connect_timeout(CONNECT_TIMEOUT)
+            .build()
+            .unwrap_or_default();
+        Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            http,
+        }
+    }
+
+    /// List installed models with size and quantization
+    pub async fn list_models(&self) -> GenAIResult<Vec<OllamaModel>> {
+        let started = Instant::now();
+        let res = self.send(self.http.get(self.url("/api/tags")), Some(REQUEST_TIMEOUT)).await?;
+        let body = res
+            .bytes()
+            .await
+            .map_err(|e| request_error(e, REQUEST_TIMEOUT, started.elapsed()))?;
+        let tags: TagsResponse = serde_json::from_slice(&body)?;
+
+        Ok(tags
+            .models
+            .into_iter()
+            .map(|m| OllamaModel {
+                name: m.name,
+                size: m.size,
+                quantization: m.details.quantization_level,
+                parameter_size: m.details.parameter_size,
+                family: m.details.family,
+                modified_at: m.modified_at,
+            })
+            .collect())
+    }
+
+    /// Pull a model, reporting each progress line from the server
+    pub async fn pull_model(
+        &self,
+        model: &str,
+        mut on_progress: impl FnMut(OllamaPullProgress),
+    ) -> GenAIResult<()> {
+        let request = self
+            .http
+            .post(self.url("/api/pull"))
+            .body(json!({ "model": model, "stream": true }).to_string());
+        let mut res = self.send(request, None).await?;
+
+        // The response is newline-delimited JSON; lines, and characters, may span chunks
+        let mut pending: Vec<u8> = Vec::new();
+        loop {
+            let waiting = Instant::now();
+            let chunk = tokio::time::timeout(PULL_IDLE_TIMEOUT, res.chunk())
+                .await
+                .map_err(|_| GenAIError::timeout_elapsed("idle", PULL_IDLE_TIMEOUT, waiting.elapsed()))?
+                .map_err(|e| request_error(e, PULL_IDLE_TIMEOUT, waiting.elapsed()))?;
+            let Some(chunk) = chunk else {
+                break;
+            };
+            pending.extend_from_slice(&chunk);
+            while let Some(pos) = pending.iter().position(|&b| b == b'\n') {
+                let line: Vec<u8> = pending.drain(..=pos).collect();
+                handle_pull_line(model, &line, &mut on_progress)?;
+            }
+        }
+        handle_pull_line(model, &pending, &mut on_progress)
+    }
+
+    /// Delete an installed model
+    pub async fn delete_model(&self, model: &str) -> GenAIResult<()> {
+        let request = self
+            .http
+            .delete(self.url("/api/delete"))
+            .body(json!({ "model": model }).to_string());
+        self.send(request, Some(REQUEST_TIMEOUT)).await?;
+        Ok(())
+    }
+
+    /// Create `name` from `from` with a context size of `num_ctx` tokens
+    pub async fn create_model(&self, name: &str, from: &str, num_ctx: u32) -> GenAIResult<()> {
+        let body = json!({
+            "model": name,
+            "from": from,
+            "parameters": { "num_ctx": num_ctx },
+            "stream": false,
+        });
+        let request = self.http.post(self.url("/api/create")).body(body.to_string());
+        self.send(request, Some(LOAD_TIMEOUT)).await?;
+        Ok(())
+    }
+
+    /// Load a model with its keep_alive (an empty generate request)
+    pub async fn load_model(&self, model: &str, settings: &OllamaModelSettings) -> GenAIResult<()> {
+        let mut body = json!({ "model": model });
+        if let Some(keep_alive) = &settings.keep_alive {
+            // Numeric strings are durations in seconds for Ollama
+            body["keep_alive"] = keep_alive
+                .parse::<i64>()
+                .map(|n| json!(n))
+                .unwrap_or_else(|_| json!(keep_alive));
+        }
+
+        let request = self.http.post(self.url("/api/generate")).body(body.to_string());
+        self.send(request, Some(LOAD_TIMEOUT)).await?;
+        Ok(())
+    }
+
+    /// Embed texts with a local embedding model, one vector per input
+    pub async fn embed(&self, model: &str, inputs: &[String]) -> GenAIResult<Vec<Vec<f32>>> {
+        let request = self
+            .http
+            .post(self.url("/api/embed"))
+            .body(json!({ "model": model, "input": inputs }).to_string());
+        let started = Instant::now();
+        let res = self.send(request, Some(REQUEST_TIMEOUT)).await?;
+        let body = res
+            .bytes()
+            .await
+            .map_err(|e| request_error(e, REQUEST_TIMEOUT, started.elapsed()))?;
+        let parsed: EmbedResponse = serde_json::from_slice(&body)?;
+        Ok(parsed.embeddings)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    /// Send a JSON request; `timeout` limits the whole request, body included
+    async fn send(
+        &self,
+        mut request: reqwest::RequestBuilder,
+        timeout: Option<Duration>,
+    ) -> GenAIResult<reqwest::Response> {
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
+        }
+        let started = Instant::now();
+        let res = request
+            .header("content-type", "application/json")
+            .send()
+            .await
+            .map_err(|e| request_error(e, timeout.unwrap_or(CONNECT_TIMEOUT), started.elapsed()))?;
+
+        let status = res.status();
+        if status.is_success() {
+            return Ok(res);
+        }
+        let body = res.text().await.unwrap_or_default();
+        Err(GenAIError::from_http_status(status.as_u16(), &body, None))
+    }
+}
+
+fn handle_pull_line(
+    model: &str,
+    line: &[u8],
+    on_progress: &mut impl FnMut(OllamaPullProgress),
+) -> GenAIResult<()> {
+    if line.trim_ascii().is_empty() {
+        return Ok(());
+    }
+    let parsed: PullLine = serde_json::from_slice(line)?;
+    if let Some(error) = parsed.error {
+        return Err(GenAIError::api(format!("Failed to pull {}: {}", model, error)));
+    }
+    on_progress(OllamaPullProgress {
+        model: model.to_string(),
+        status: parsed.status,
+        digest: parsed.digest,
+        total: parsed.total,
+        completed: parsed.completed,
+    });
+    Ok(())
+}
+
+/// Map a transport error; `limit` is the limit that applied if it timed out
+fn request_error(err: reqwest::Error, limit: Duration, elapsed: Duration) -> GenAIError {
+    if err.is_timeout() && err.is_connect() {
+        GenAIError::timeout_elapsed("connect", CONNECT_TIMEOUT, elapsed)
+    } else if err.is_timeout() {
+        GenAIError::timeout_elapsed("total", limit, elapsed)
+    } else if err.is_connect() {
+        GenAIError::api(format!("Ollama is not reachable: {}", err))
+    } else {
+        GenAIError::api(err.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Stand-in for an Ollama server: answers by method and path, records request bodies
+    async fn stub_server(
+        routes: Vec<(&'static str, &'static str, u16, &'static str)>,
+    ) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+
+        tokio::spawn(async move {
+            loop {
+                let Ok((mut socket, _)) = listener.accept().await else { break };
+                let mut buf = Vec::new();
+                let mut chunk = [0u8; 4096];
+                // Read headers, then the body according to content-length
+                let (head, body) = loop {
+                    let n = socket.read(&mut chunk).await.unwrap();
+                    buf.extend_from_slice(&chunk[..n]);
+                    let text = String::from_utf8_lossy(&buf).to_string();
+                    if let Some(end) = text.find("\r\n\r\n") {
+                        let head = text[..end].to_string();
+                        let len = head
+                            .lines()
+                            .find_map(|l| l.to_lowercase().strip_prefix("content-length:").map(|v| v.trim().parse::<usize>().unwrap()))
+                            .unwrap_or(0);
+                        while buf.len() < end + 4 + len {
+                            let n = socket.read(&mut chunk).await.unwrap();
+                            buf.extend_from_slice(&chunk[..n]);
+                        }
+                        break (head, String::from_utf8_lossy(&buf[end + 4..]).to_string());
+                    }
+                };
+
+                let mut parts = head.split_whitespace();
+                let (method, path) = (parts.next().unwrap_or(""), parts.next().unwrap_or(""));
+                seen.lock().unwrap().push(format!("{} {} {}", method, path, body));
+
+                let (status, response) = routes
+                    .iter()
+                    .find(|(m, p, _, _)| *m == method && *p == path)
+                    .map(|(_, _, s, r)| (*s, *r))
+                    .unwrap_or((404, "{\"error\":\"not found\"}"));
+                let reply = format!(
+                    "HTTP/1.1 {} X\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    status,
+                    response.len(),
+                    response
+                );
+                let _ = socket.write_all(reply.as_bytes()).await;
+            }
+        });
+
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn test_list_models() {
+        let (url, _) = stub_server(vec![(
+            "GET",
+            "/api/tags",
+            200,
+            r#"{"models":[{"name":"llama3.2:3b","size":2019393189,"modified_at":"2024-10-01T00:00:00Z","details":{"family":"llama","parameter_size":"3.2B","quantization_level":"Q4_K_M"}}]}"#,
+        )])
+        .await;
+
+        let models = OllamaClient::new(&url).list_models().await.unwrap();
+        assert_eq!(models.len(), 1);
+        assert_eq!(models[0].name, "llama3.2:3b");
+        assert_eq!(models[0].size, 2019393189);
+        assert_eq!(models[0].quantization.as_deref(), Some("Q4_K_M"));
+    }
+
+    #[tokio::test]
+    async fn test_pull_reports_progress() {
+        let (url, _) = stub_server(vec![(
+            "POST",
+            "/api/pull",
+            200,
+            "{\"status\":\"pulling manifest\"}\n{\"status\":\"downloading\",\"digest\":\"sha256:abc\",\"total\":100,\"completed\":50}\n{\"status\":\"success\"}\n",
+        )])
+        .await;
+
+        let mut progress = Vec::new();
+        OllamaClient::new(&url)
+            .pull_model("llama3.2:3b", |p| progress.push(p))
+            .await
+            .unwrap();
+
+        assert_eq!(progress.len(), 3);
+        assert_eq!(progress[1].completed, Some(50));
+        assert_eq!(progress[2].status, "success");
+    }
+
+    #[tokio::test]
+    async fn test_pull_keeps_characters_split_across_chunks() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 4096];
+            let _ = socket.read(&mut buf).await.unwrap();
+            let body = "{\"status\":\"téléchargement\"}\n".as_bytes();
+            // Split inside the two-byte "é"
+            let split = body.iter().position(|&b| b == 0xC3).unwrap() + 1;
+            let head = "HTTP/1.1 200 OK\r\ntransfer-encoding: chunked\r\nconnection: close\r\n\r\n";
+            socket.write_all(head.as_bytes()).await.unwrap();
+            for part in [&body[..split], &body[split..]] {
+                socket.write_all(format!("{:x}\r\n", part.len()).as_bytes()).await.unwrap();
+                socket.write_all(part).await.unwrap();
+                socket.write_all(b"\r\n").await.unwrap();
+                socket.flush().await.unwrap();
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+            socket.write_all(b"0\r\n\r\n").await.unwrap();
+        });
+
+        let mut progress = Vec::new();
+        OllamaClient::new(&url)
+            .pull_model("llama3.2:3b", |p| progress.push(p))
+            .await
+            .unwrap();
+        assert_eq!(progress.len(), 1);
+        assert_eq!(progress[0].status, "téléchargement");
+    }
+
+    #[tokio::test]
+    async fn test_pull_error_line_fails() {
+        let (url, _) = stub_server(vec![(
+            "POST",
+            "/api/pull",
+            200,
+            "{\"error\":\"pull model manifest: file does not exist\"}\n",
+        )])
+        .await;
+
+        let result = OllamaClient::new(&url).pull_model("nope", |_| {}).await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_delete_and_load() {
+        let (url, requests) = stub_server(vec![
+            ("DELETE", "/api/delete", 200, ""),
+            ("POST", "/api/generate", 200, "{\"done\":true}"),
+        ])
+        .await;
+        let client = OllamaClient::new(&url);
+
+        client.delete_model("old:latest").await.unwrap();
+        client
+            .load_model(
+                "llama3.2:3b",
+                &OllamaModelSettings {
+                    keep_alive: Some("-1".to_string()),
+                    num_ctx: None,
+                },
+            )
+            .await
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert!(requests[0].starts_with("DELETE /api/delete"));
+        assert!(requests[1].contains("\"keep_alive\":-1"));
+    }
+
+    #[tokio::test]
+    async fn test_create_model_with_context_size() {
+        let (url, requests) = stub_server(vec![("POST", "/api/create", 200, "{\"status\":\"success\"}")]).await;
+        let settings = OllamaModelSettings {
+            keep_alive: None,
+            num_ctx: Some(16384),
+        };
+        assert_eq!(settings.chat_model("llama3.2:3b"), "llama3.2:3b-ctx16384");
+        assert_eq!(settings.chat_model("mistral"), "mistral:ctx16384");
+        assert_eq!(OllamaModelSettings::default().chat_model("mistral"), "mistral");
+
+        OllamaClient::new(&url)
+            .create_model("llama3.2:3b-ctx16384", "llama3.2:3b", 16384)
+            .await
+            .unwrap();
+        let request = &requests.lock().unwrap()[0];
+        assert!(request.contains(r#""from":"llama3.2:3b""#));
+        assert!(request.contains(r#""num_ctx":16384"#));
+    }
+
+    #[tokio::test]
+    async fn test_embed() {
+        let (url, requests) = stub_server(vec![(
+            "POST",
+            "/api/embed",
+            200,
+            r#"{"model":"nomic-embed-text","embeddings":[[0.1,0.2,0.3],[0.4,0.5,0.6]]}"#,
+        )])
+        .await;
+
+        let inputs = vec!["first".to_string(), "second".to_string()];
+        let vectors = OllamaClient::new(&url).embed("nomic-embed-text", &inputs).await.unwrap();
+        assert_eq!(vectors, vec![vec![0.1, 0.2, 0.3], vec![0.4, 0.5, 0.6]]);
+        assert!(requests.lock().unwrap()[0].contains(r#""input":["first","second"]"#));
+    }
+
+    #[tokio::test]
+    async fn test_missing_model_maps_to_invalid_request() {
+        let (url, _) = stub_server(vec![]).await;
+        let err = OllamaClient::new(&url).delete_model("missing").await.unwrap_err();
+        assert_eq!(err.category(), "request");
+    }
+}
@@ -0,0 +1,127 @@
+//! Layered chat option resolution
+
+use super::models::GenAIConfig;
+use genai::chat::ChatOptions;
+
+/// Resolve the effective chat options for a request.
+///
+/// Layers are applied lowest to highest precedence: global defaults, model config,
+/// thread settings, then the request itself. A field set in a higher layer wins.
+/// `model` may be qualified (`provider::model`); a bare-name model config also applies.
+pub fn resolve_chat_options(
+    config: &GenAIConfig,
+    model: &str,
+    thread_options: Option<&ChatOptions>,
+    request_options: Option<&ChatOptions>,
+) -> ChatOptions {
+    let mut options = config.default_options.clone();
+
+    if let Some(model_config) = config.model_config(model).filter(|c| c.enabled) {
+        options = overlay(options, &model_config.options);
+    }
+    if let Some(thread_options) = thread_options {
+        options = overlay(options, thread_options);
+    }
+    if let Some(request_options) = request_options {
+        options = overlay(options, request_options);
+    }
+
+    options
+}
+
+/// Apply every field set on `top` over `base`
+fn overlay(mut base: ChatOptions, top: &ChatOptions) -> ChatOptions {
+    if top.temperature.is_some() {
+        base.temperature = top.temperature;
+    }
+    if top.max_tokens.is_some() {
+        base.max_tokens = top.max_tokens;
+    }
+    if top.top_p.is_some() {
+        base.top_p = top.top_p;
+    }
+    if !top.stop_sequences.is_empty() {
+        base.stop_sequences = top.stop_sequences.clone();
+    }
+    if top.reasoning_effort.is_some() {
+        base.reasoning_effort = top.reasoning_effort.clone();
+    }
+    base
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::models::ModelConfig;
+    use crate::llm::timeout::TimeoutConfig;
+    use std::collections::HashMap;
+
+    fn model_config(options: ChatOptions, enabled: bool) -> ModelConfig {
+        ModelConfig {
+            model: "gpt-4o".to_string(),
+            options,
+            parameters: HashMap::new(),
+            enabled,
+            timeouts: TimeoutConfig::default(),
+            fallbacks: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_layers_apply_in_order() {
+        let mut config = GenAIConfig::default();
+        config.default_options = ChatOptions::default()
+            .with_temperature(0.1)
+            .with_max_tokens(256)
+            .with_top_p(0.5);
+        config.model_configs.insert(
+            "gpt-4o".to_string(),
+            model_config(ChatOptions::default().with_max_tokens(1024), true),
+        );
+        let thread = ChatOptions::default().with_top_p(0.9);
+        let request = ChatOptions::default().with_temperature(0.7);
+
+        let options = resolve_chat_options(&config, "gpt-4o", Some(&thread), Some(&request));
+        assert_eq!(options.temperature, Some(0.7));
+        assert_eq!(options.max_tokens, Some(1024));
+        assert_eq!(options.top_p, Some(0.9));
+    }
+
+    #[test]
+    fn test_bare_model_config_applies_to_qualified_id() {
+        let mut config = GenAIConfig::default();
+        config.model_configs.insert(
+            "gpt-4o".to_string(),
+            model_config(ChatOptions::default().with_max_tokens(2048), true),
+        );
+
+        let options = resolve_chat_options(&config, "openai::gpt-4o", None, None);
+        assert_eq!(options.max_tokens, Some(2048));
+    }
+
+    #[test]
+    fn test_disabled_model_config_is_skipped() {
+        let mut config = GenAIConfig::default();
+        config.model_configs.insert(
+            "gpt-4o".to_string(),
+            model_config(ChatOptions::default().with_temperature(1.5), false),
+        );
+
+        let options = resolve_chat_options(&config, "gpt-4o", None, None);
+        assert_eq!(options.temperature, None);
+    }
+
+    #[test]
+    fn test_stop_sequences_replace_lower_layers() {
+        let mut config = GenAIConfig::default();
+        config.default_options =
+            ChatOptions::default().with_stop_sequences(vec!["END".to_string()]);
+        let request = ChatOptions::default().with_stop_sequences(vec!["STOP".to_string()]);
+
+        let options = resolve_chat_options(&config, "gpt-4o", None, Some(&request));
+        assert_eq!(options.stop_sequences, vec!["STOP".to_string()]);
+
+        let options = resolve_chat_options(&config, "gpt-4o", None, None);
+        assert_eq!(options.stop_sequences, vec!["END".to_string()]);
+    }
+}
@@ -0,0 +1,257 @@
+//! Writing streamed assistant messages to the database as they arrive
+
+use crate::db::messages::{self, FinishedMessage, MessageStatus, NewAssistantMessage};
+use crate::db::Database;
+use chrono::{DateTime, Utc};
+use genai::chat::ToolCall;
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Manager, Runtime};
+use uuid::Uuid;
+
+/// Minimum time between progress writes while a message streams
+pub const PROGRESS_INTERVAL: Duration = Duration::from_millis(1000);
+
+/// Reasoning received for a message, stored in the `ThinkingProcess` shape the UI reads.
+///
+/// Each uninterrupted run of reasoning (until content or a tool call arrives) is one timed block.
+#[derive(Debug)]
+pub struct ThinkingTrace {
+    id: String,
+    blocks: Vec<ThinkingBlock>,
+    /// Reasoning tokens reported (or estimated) for the response
+    tokens: Option<i64>,
+}
+
+#[derive(Debug)]
+struct ThinkingBlock {
+    content: String,
+    started_at: DateTime<Utc>,
+    ended_at: Option<DateTime<Utc>>,
+}
+
+impl Default for ThinkingTrace {
+    fn default() -> Self {
+        Self {
+            id: Uuid::new_v4().to_string(),
+            blocks: Vec::new(),
+            tokens: None,
+        }
+    }
+}
+
+impl ThinkingTrace {
+    /// Reasoning returned whole by a non-streaming request, timed by the request
+    pub fn from_text(text: &str, started_at: DateTime<Utc>, ended_at: DateTime<Utc>) -> Self {
+        let mut trace = Self::default();
+        if !text.is_empty() {
+            trace.blocks.push(ThinkingBlock {
+                content: text.to_string(),
+                started_at,
+                ended_at: Some(ended_at),
+            });
+        }
+        trace
+    }
+
+    pub fn push(&mut self, delta: &str) {
+        match self.blocks.last_mut() {
+            Some(block) if block.ended_at.is_none() => block.content.push_str(delta),
+            _ => self.blocks.push(ThinkingBlock {
+                content: delta.to_string(),
+                started_at: Utc::now(),
+                ended_at: None,
+            }),
+        }
+    }
+
+    /// End the current block, if one is open
+    pub fn close(&mut self) {
+        if let Some(block) = self.blocks.last_mut() {
+            if block.ended_at.is_none() {
+                block.ended_at = Some(Utc::now());
+            }
+        }
+    }
+
+    pub fn set_tokens(&mut self, tokens: i64) {
+        if tokens > 0 {
+            self.tokens = Some(tokens);
+        }
+    }
+
+    /// All reasoning text, blocks separated by blank lines
+    pub fn text(&self) -> String {
+        self.blocks
+            .iter()
+            .map(|block| block.content.as_str())
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// `ThinkingProcess` JSON, or `{}` when nothing was received
+    pub fn to_json(&self, failed: bool) -> Value {
+        let (Some(first), Some(last)) = (self.blocks.first(), self.blocks.last()) else {
+            return json!({});
+        };
+        let status = match (failed, last.ended_at) {
+            (true, _) => "error",
+            (false, Some(_)) => "complete",
+            (false, None) => "thinking",
+        };
+        let steps: Vec<Value> = self
+            .blocks
+            .iter()
+            .enumerate()
+            .map(|(i, block)| {
+                json!({
+                    "id": format!("{}-{}", self.id, i),
+                    "content": block.content,
+                    "timestamp": block.started_at,
+                    "endTime": block.ended_at,
+                    "durationMs": block.ended_at.map(|end| (end - block.started_at).num_milliseconds()),
+                    "type": "reasoning",
+                })
+            })
+            .collect();
+        json!({
+            "id": self.id,
+            "steps": steps,
+            "status": status,
+            "startTime": first.started_at,
+            "endTime": last.ended_at,
+            "tokens": self.tokens,
+        })
+    }
+}
+
+/// Requested tool calls in the `ToolCall[]` shape the UI reads; they run in the frontend
+pub fn tool_calls_json(calls: &[ToolCall], requested_at: DateTime<Utc>) -> Value {
+    Value::Array(
+        calls
+            .iter()
+            .map(|call| {
+                json!({
+                    "id": call.call_id,
+                    "name": call.fn_name,
+                    "parameters": call.fn_arguments,
+                    "status": "running",
+                    "startTime": requested_at,
+                })
+            })
+            .collect(),
+    )
+}
+
+/// Owns the database row of one streaming assistant message.
+///
+/// A failed write is logged and the stream goes on; the next progress write or `finish`
+/// stores the whole message again, so only the latest state matters.
+pub struct MessageWriter {
+    pool: SqlitePool,
+    message_id: String,
+    last_write: Instant,
+    dirty: bool,
+}
+
+impl MessageWriter {
+    /// Create the message row; `None` if the database is unavailable or the insert fails
+    pub async fn start<R: Runtime>(app: &AppHandle<R>, message: NewAssistantMessage) -> Option<Self> {
+        let pool = app.try_state::<Database>()?.pool().clone();
+        if let Err(e) = messages::create_assistant_message(&pool, &message).await {
+            tracing::warn!("Failed to create message {} in thread {}: {}", message.id, message.thread_id, e);
+            return None;
+        }
+        Some(Self {
+            pool,
+            message_id: message.id,
+            last_write: Instant::now(),
+            dirty: false,
+        })
+    }
+
+    pub fn message_id(&self) -> &str {
+        &self.message_id
+    }
+
+    /// Note that content or reasoning changed since the last write
+    pub fn touch(&mut self) {
+        self.dirty = true;
+    }
+
+    /// Write progress if anything changed and the interval has passed
+    pub async fn save_if_due(&mut self, content: &str, thinking: &ThinkingTrace) {
+        if !self.dirty || self.last_write.elapsed() < PROGRESS_INTERVAL {
+            return;
+        }
+        if let Err(e) =
+            messages::update_message_progress(&self.pool, &self.message_id, content, &thinking.to_json(false)).await
+        {
+            tracing::warn!("Failed to save progress of message {}: {}", self.message_id, e);
+        }
+        self.last_write = Instant::now();
+        self.dirty = false;
+    }
+
+    /// Write the final state of the message
+    pub async fn finish(self, finished: FinishedMessage) {
+        if let Err(e) = messages::finish_message(&self.pool, &self.message_id, &finished).await {
+            tracing::warn!("Failed to finalize message {}: {}", self.message_id, e);
+        }
+    }
+}
+
+/// Mark a message as cancelled when its stream is stopped before it finished
+pub async fn cancel_message<R: Runtime>(app: &AppHandle<R>, message_id: &str) {
+    if let Some(db) = app.try_state::<Database>() {
+        if let Err(e) = messages::mark_message_status(db.pool(), message_id, MessageStatus::Cancelled).await {
+            tracing::warn!("Failed to mark message {} as cancelled: {}", message_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_thinking_trace_json() {
+        let mut thinking = ThinkingTrace::default();
+        assert_eq!(thinking.to_json(false), json!({}));
+
+        thinking.push("First, ");
+        thinking.push("check the units.");
+        let open = thinking.to_json(false);
+        assert_eq!(open["status"], "thinking");
+        assert_eq!(open["steps"][0]["content"], "First, check the units.");
+        assert!(open["endTime"].is_null());
+
+        thinking.close();
+        let closed = thinking.to_json(false);
+        assert_eq!(closed["status"], "complete");
+        assert!(closed["endTime"].is_string());
+        assert_eq!(thinking.to_json(true)["status"], "error");
+    }
+
+    #[test]
+    fn test_interrupted_reasoning_starts_a_new_block() {
+        let mut thinking = ThinkingTrace::default();
+        thinking.push("Plan the call.");
+        thinking.close();
+        thinking.push("Check the result.");
+        thinking.close();
+        thinking.set_tokens(42);
+
+        let json = thinking.to_json(false);
+        assert_eq!(json["steps"].as_array().unwrap().len(), 2);
+        assert_eq!(json["steps"][1]["content"], "Check the result.");
+        assert!(json["steps"][0]["durationMs"].is_i64());
+        assert_eq!(json["tokens"], 42);
+        assert_eq!(thinking.text(), "Plan the call.\n\nCheck the result.");
+    }
+}
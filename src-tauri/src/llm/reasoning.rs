@@ -0,0 +1,165 @@
+//! Request-level reasoning controls, mapped to each provider's parameters
+
+use super::error::{GenAIError, GenAIResult};
+use super::router::ModelRef;
+use genai::adapter::AdapterKind;
+use genai::chat::{ChatOptions, ReasoningEffort};
+use serde::{Deserialize, Serialize};
+
+/// Budgets used when a provider takes a token budget but only a level was given
+const LOW_BUDGET: u32 = 1024;
+const MEDIUM_BUDGET: u32 = 8192;
+const HIGH_BUDGET: u32 = 24576;
+
+/// Room left for the answer when `max_tokens` is set from a thinking budget
+const ANSWER_TOKENS: u32 = 4096;
+
+/// Coarse reasoning effort
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReasoningLevel {
+    Low,
+    Medium,
+    High,
+}
+
+impl ReasoningLevel {
+    fn budget(self) -> u32 {
+        match self {
+            Self::Low => LOW_BUDGET,
+            Self::Medium => MEDIUM_BUDGET,
+            Self::High => HIGH_BUDGET,
+        }
+    }
+
+    /// Closest level for a token budget
+    fn from_budget(budget: u32) -> Self {
+        if budget <= LOW_BUDGET * 2 {
+            Self::Low
+        } else if budget <= MEDIUM_BUDGET * 2 {
+            Self::Medium
+        } else {
+            Self::High
+        }
+    }
+}
+
+/// How much the model should think before answering.
+///
+/// Providers take either a level (OpenAI, xAI, Groq) or a token budget (Anthropic, Gemini);
+/// whichever was not given is derived from the other.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReasoningConfig {
+    pub effort: Option<ReasoningLevel>,
+    pub budget_tokens: Option<u32>,
+}
+
+/// Provider parameter a reasoning config was mapped to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReasoningParam {
+    Effort(ReasoningLevel),
+    Budget(u32),
+    /// The provider has no control; reasoning models think on their own
+    Unsupported,
+}
+
+/// Map a reasoning config onto a provider's parameter
+pub fn map(adapter: AdapterKind, config: &ReasoningConfig) -> ReasoningParam {
+    let level = config
+        .effort
+        .or(config.budget_tokens.map(ReasoningLevel::from_budget));
+    let budget = config.budget_tokens.or(config.effort.map(ReasoningLevel::budget));
+
+    match adapter {
+        AdapterKind::Anthropic | AdapterKind::Gemini => budget.map_or(ReasoningParam::Unsupported, ReasoningParam::Budget),
+        AdapterKind::OpenAI | AdapterKind::Xai | AdapterKind::Groq => {
+            level.map_or(ReasoningParam::Unsupported, ReasoningParam::Effort)
+        }
+        _ => ReasoningParam::Unsupported,
+    }
+}
+
+/// Apply reasoning controls to `options`.
+///
+/// Models the catalog knows not to reason are left unchanged. A thinking budget must leave
+/// room for the answer within `max_tokens`; without one, `max_tokens` is set to the budget plus
+/// `ANSWER_TOKENS`, as the provider's default can be smaller than the budget.
+pub fn apply(
+    options: ChatOptions,
+    model: &ModelRef,
+    supports_reasoning: Option<bool>,
+    config: Option<&ReasoningConfig>,
+) -> GenAIResult<ChatOptions> {
+    let Some(config) = config else {
+        return Ok(options);
+    };
+    if supports_reasoning == Some(false) {
+        tracing::debug!("Ignoring reasoning controls for {}, which does not reason", model.qualified());
+        return Ok(options);
+    }
+
+    match map(model.adapter, config) {
+        ReasoningParam::Effort(level) => Ok(options.with_reasoning_effort(match level {
+            ReasoningLevel::Low => ReasoningEffort::Low,
+            ReasoningLevel::Medium => ReasoningEffort::Medium,
+            ReasoningLevel::High => ReasoningEffort::High,
+        })),
+        ReasoningParam::Budget(budget) => {
+            let options = match options.max_tokens {
+                Some(max_tokens) if max_tokens <= budget => {
+                    return Err(GenAIError::invalid_request(format!(
+                        "max_tokens {} must be larger than the thinking budget of {} tokens",
+                        max_tokens, budget
+                    )));
+                }
+                Some(_) => options,
+                None => options.with_max_tokens(budget.saturating_add(ANSWER_TOKENS)),
+            };
+            Ok(options.with_reasoning_effort(ReasoningEffort::Budget(budget)))
+        }
+        ReasoningParam::Unsupported => Ok(options),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(effort: Option<ReasoningLevel>, budget_tokens: Option<u32>) -> ReasoningConfig {
+        ReasoningConfig { effort, budget_tokens }
+    }
+
+    #[test]
+    fn test_map_by_provider() {
+        let high = config(Some(ReasoningLevel::High), None);
+        assert_eq!(map(AdapterKind::OpenAI, &high), ReasoningParam::Effort(ReasoningLevel::High));
+        assert_eq!(map(AdapterKind::Anthropic, &high), ReasoningParam::Budget(HIGH_BUDGET));
+        assert_eq!(map(AdapterKind::DeepSeek, &high), ReasoningParam::Unsupported);
+
+        let budget = config(None, Some(4000));
+        assert_eq!(map(AdapterKind::Gemini, &budget), ReasoningParam::Budget(4000));
+        assert_eq!(map(AdapterKind::OpenAI, &budget), ReasoningParam::Effort(ReasoningLevel::Medium));
+        assert_eq!(map(AdapterKind::OpenAI, &config(None, None)), ReasoningParam::Unsupported);
+    }
+
+    #[test]
+    fn test_budget_must_fit_max_tokens() {
+        let claude = ModelRef::builtin(AdapterKind::Anthropic, "claude-sonnet-4");
+        let budget = config(None, Some(2048));
+
+        let options = ChatOptions::default().with_max_tokens(1024);
+        assert!(apply(options, &claude, Some(true), Some(&budget)).is_err());
+
+        let options = ChatOptions::default().with_max_tokens(4096);
+        assert!(apply(options, &claude, Some(true), Some(&budget)).unwrap().reasoning_effort.is_some());
+
+        let high = config(Some(ReasoningLevel::High), None);
+        let options = apply(ChatOptions::default(), &claude, Some(true), Some(&high)).unwrap();
+        assert_eq!(options.max_tokens, Some(HIGH_BUDGET + ANSWER_TOKENS), "room for the answer");
+
+        let options = ChatOptions::default().with_max_tokens(1024);
+        let unchanged = apply(options, &claude, Some(false), Some(&budget)).unwrap();
+        assert!(unchanged.reasoning_effort.is_none());
+    }
+}
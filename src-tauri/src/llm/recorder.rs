@@ -0,0 +1,251 @@
+//! Fixture recorder: provider traffic written as replay fixtures, so a bug report can carry the
+//! exact upstream event sequence instead of a description of it.
+//!
+//! Recording is opt-in (`settings.record_fixtures`). Each provider call is written to the fixture
+//! directory, with API keys and secret-looking fields redacted, and replays as `replay::<name>`.
+
+use super::error::{GenAIError, GenAIResult};
+use super::replay::{Fixture, FixtureStep};
+use super::upstream::{UpstreamEvent, UpstreamStream};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde_json::Value;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+
+/// Replaces redacted values
+pub const REDACTED: &str = "[redacted]";
+
+/// Fields whose values are always redacted, whatever they hold
+const SECRET_FIELDS: &[&str] = &[
+    "api_key",
+    "apikey",
+    "api-key",
+    "x-api-key",
+    "authorization",
+    "password",
+    "secret",
+    "client_secret",
+    "access_token",
+    "refresh_token",
+];
+
+/// Where and how one chat's traffic is recorded
+#[derive(Debug, Clone)]
+pub struct RecordTarget {
+    pub dir: PathBuf,
+    /// Qualified model id, part of the fixture name
+    pub model: String,
+    /// The request as sent, stored with the fixture for reference
+    pub request: Value,
+    /// Key values known to the app, redacted wherever they appear
+    pub secrets: Arc<Vec<String>>,
+}
+
+impl RecordTarget {
+    /// Start recording a provider call
+    pub fn start(&self) -> Recorder {
+        Recorder {
+            target: self.clone(),
+            started_at: Utc::now(),
+            last: Instant::now(),
+            steps: Vec::new(),
+            finished: false,
+        }
+    }
+}
+
+/// Steps of one provider call as they happen; the fixture is written by `finish` when the call
+/// is over. A recorder dropped before that, as when a stream is cut short, writes what it got
+/// in the background.
+pub struct Recorder {
+    target: RecordTarget,
+    started_at: DateTime<Utc>,
+    last: Instant,
+    steps: Vec<FixtureStep>,
+    finished: bool,
+}
+
+impl Recorder {
+    /// Record an event or error, timed from the previous step (or the start of the call)
+    pub fn step(&mut self, result: Result<&UpstreamEvent, &GenAIError>) {
+        let now = Instant::now();
+        let delay_ms = now.duration_since(self.last).as_millis() as u64;
+        self.last = now;
+        self.steps.push(match result {
+            Ok(event) => FixtureStep {
+                delay_ms,
+                event: Some(event.clone()),
+                error: None,
+            },
+            Err(e) => FixtureStep {
+                delay_ms,
+                event: None,
+                error: Some(e.into()),
+            },
+        });
+    }
+
+    /// The recording so far, redacted
+    pub fn fixture(&self) -> GenAIResult<Fixture> {
+        let mut value = serde_json::to_value(Fixture {
+            request: Some(self.target.request.clone()),
+            steps: self.steps.clone(),
+        })?;
+        redact(&mut value, &self.target.secrets);
+        Ok(serde_json::from_value(value)?)
+    }
+
+    /// Fixture name: start time and model, usable as `replay::<name>`
+    pub fn name(&self) -> String {
+        let model: String = self
+            .target
+            .model
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' })
+            .collect();
+        format!("{}-{}", self.started_at.format("%Y%m%d-%H%M%S-%3f"), model)
+    }
+
+    /// Write the fixture to `<dir>/<name>.json`
+    pub async fn save(&self) -> GenAIResult<PathBuf> {
+        let text = serde_json::to_string_pretty(&self.fixture()?)?;
+        tokio::fs::create_dir_all(&self.target.dir).await?;
+        let path = self.target.dir.join(format!("{}.json", self.name()));
+        tokio::fs::write(&path, text).await?;
+        Ok(path)
+    }
+
+    /// Write the fixture of the finished call
+    pub async fn finish(mut self) {
+        self.finished = true;
+        match self.save().await {
+            Ok(path) => tracing::info!("Recorded fixture {}", path.display()),
+            Err(e) => tracing::warn!("Failed to record fixture for {}: {}", self.target.model, e),
+        }
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            tracing::warn!("Fixture for {} was dropped outside the runtime, not recorded", self.target.model);
+            return;
+        };
+        let unfinished = Recorder {
+            target: self.target.clone(),
+            started_at: self.started_at,
+            last: self.last,
+            steps: std::mem::take(&mut self.steps),
+            finished: false,
+        };
+        runtime.spawn(unfinished.finish());
+    }
+}
+
+/// Record a stream's events as they pass through; the fixture is written when the stream ends
+pub fn record_stream(stream: UpstreamStream, recorder: Recorder) -> UpstreamStream {
+    futures::stream::unfold((stream, Some(recorder)), |(mut stream, mut recorder)| async move {
+        match stream.next().await {
+            Some(result) => {
+                if let Some(recorder) = recorder.as_mut() {
+                    recorder.step(result.as_ref());
+                }
+                Some((result, (stream, recorder)))
+            }
+            None => {
+                if let Some(recorder) = recorder.take() {
+                    recorder.finish().await;
+                }
+                None
+            }
+        }
+    })
+    .boxed()
+}
+
+/// Redact secret fields, and known secrets wherever they appear in a string
+pub fn redact(value: &mut Value, secrets: &[String]) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                if SECRET_FIELDS.contains(&key.to_lowercase().as_str()) && !value.is_null() {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    redact(value, secrets);
+                }
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(|value| redact(value, secrets)),
+        Value::String(text) => {
+            for secret in secrets.iter().filter(|secret| !secret.is_empty()) {
+                if text.contains(secret.as_str()) {
+                    *text = text.replace(secret.as_str(), REDACTED);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_recording_replays_and_is_redacted() {
+        let dir = std::env::temp_dir().join(format!("aye-fixtures-{}", uuid::Uuid::new_v4()));
+        let target = RecordTarget {
+            dir: dir.clone(),
+            model: "openai::gpt-4o".to_string(),
+            request: json!({
+                "messages": [{ "role": "user", "content": "my key is sk-live-123, why 401?" }],
+                "headers": { "Authorization": "Bearer sk-live-123" },
+            }),
+            secrets: Arc::new(vec!["sk-live-123".to_string()]),
+        };
+
+        let recorder = target.start();
+        let name = recorder.name();
+        let events = futures::stream::iter(vec![
+            Ok(UpstreamEvent::Chunk {
+                content: "Echo: sk-live-123".to_string(),
+            }),
+            Err(GenAIError::rate_limit("slow down")),
+        ])
+        .boxed();
+        let recorded: Vec<_> = record_stream(events, recorder).collect().await;
+        assert_eq!(recorded.len(), 2);
+
+        let text = std::fs::read_to_string(dir.join(format!("{}.json", name))).unwrap();
+        assert!(!text.contains("sk-live-123"));
+        let fixture: Fixture = serde_json::from_str(&text).unwrap();
+        let request = fixture.request.as_ref().unwrap();
+        assert_eq!(request["headers"]["Authorization"], REDACTED);
+        assert_eq!(request["messages"][0]["content"], "my key is [redacted], why 401?");
+
+        // The recording replays through the same classification
+        let replayed: Vec<_> = fixture.stream().await.unwrap().collect().await;
+        assert!(matches!(&replayed[0], Ok(UpstreamEvent::Chunk { content }) if content == "Echo: [redacted]"));
+        assert_eq!(replayed[1].as_ref().err().unwrap().category(), "rate_limit");
+
+        // A timeout is recorded as one, not as a gateway error
+        let mut recorder = target.start();
+        let timeout = GenAIError::timeout_elapsed("first_token", Duration::from_secs(30), Duration::from_millis(30_010));
+        recorder.step(Err(&timeout));
+        let replayed = recorder.fixture().unwrap().stream().await.err().unwrap();
+        assert!(matches!(
+            replayed,
+            GenAIError::Timeout { timeout_seconds: 30, elapsed_ms: 30_010, ref phase } if phase == "first_token"
+        ));
+        recorder.finish().await;
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
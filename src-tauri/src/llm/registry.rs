@@ -0,0 +1,330 @@
+//! Provider registry: bundled provider metadata, overridable by a user JSON file, and the
+//! user-defined providers saved beside it
+
+use super::error::{GenAIError, GenAIResult};
+use super::models::{CustomProvider, ModelCapabilities};
+use super::router::parse_provider;
+use genai::adapter::AdapterKind;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Provider metadata shipped with the app
+const BUNDLED_PROVIDERS: &str = include_str!("providers.json");
+
+/// Name of the user override file in the app config directory
+pub const USER_REGISTRY_FILE: &str = "providers.json";
+
+/// Name of the file in the app config directory holding user-defined providers
+pub const CUSTOM_PROVIDERS_FILE: &str = "custom_providers.json";
+
+/// Metadata for a built-in provider
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProviderEntry {
+    /// Provider name, matching a genai adapter (e.g. "openai")
+    pub name: String,
+    pub display_name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Where users get an API key
+    #[serde(default)]
+    pub website: String,
+    /// Human-readable key hint, e.g. "sk-..."
+    #[serde(default)]
+    pub key_format: String,
+    /// Regex a valid API key must match
+    #[serde(default)]
+    pub key_pattern: Option<String>,
+    /// Environment variable read when no key is stored
+    #[serde(default)]
+    pub env_var: Option<String>,
+    /// Whether the provider needs an API key at all
+    #[serde(default = "default_requires_key")]
+    pub requires_key: bool,
+    /// Cheap model used by connection tests
+    pub test_model: String,
+    /// Suggested models
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// OpenAI-compatible embeddings endpoint, if the provider offers one
+    #[serde(default)]
+    pub embeddings_url: Option<String>,
+    /// Provider-wide capabilities
+    #[serde(default)]
+    pub capabilities: ModelCapabilities,
+}
+
+fn default_requires_key() -> bool {
+    true
+}
+
+impl ProviderEntry {
+    /// Adapter used to talk to this provider
+    pub fn adapter_kind(&self) -> GenAIResult<AdapterKind> {
+        parse_provider(&self.name)
+    }
+}
+
+#[derive(Deserialize)]
+struct RegistryFile {
+    providers: Vec<ProviderEntry>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CustomProvidersFile {
+    providers: Vec<CustomProvider>,
+}
+
+/// User-defined providers saved in `path`, keyed by id; none if the file does not exist.
+///
+/// An unreadable or invalid file is logged and ignored.
+pub fn load_custom_providers(path: &Path) -> HashMap<String, CustomProvider> {
+    let Ok(contents) = std::fs::read_to_string(path) else {
+        return HashMap::new();
+    };
+    match serde_json::from_str::<CustomProvidersFile>(&contents) {
+        Ok(file) => file
+            .providers
+            .into_iter()
+            .map(|provider| (provider.id.clone(), provider))
+            .collect(),
+        Err(e) => {
+            tracing::warn!("Ignoring invalid custom providers in {}: {}", path.display(), e);
+            HashMap::new()
+        }
+    }
+}
+
+/// Save user-defined providers to `path`, sorted by id
+pub async fn save_custom_providers(path: &Path, providers: &HashMap<String, CustomProvider>) -> GenAIResult<()> {
+    let mut providers: Vec<CustomProvider> = providers.values().cloned().collect();
+    providers.sort_by(|a, b| a.id.cmp(&b.id));
+    let text = serde_json::to_string_pretty(&CustomProvidersFile { providers })?;
+
+    let write = async {
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tokio::fs::write(path, text).await
+    };
+    write.await.map_err(|e| {
+        GenAIError::configuration(format!("Failed to save custom providers to {}: {}", path.display(), e))
+    })
+}
+
+/// Ordered set of built-in providers
+#[derive(Debug, Clone)]
+pub struct ProviderRegistry {
+    providers: Vec<ProviderEntry>,
+    /// User override file the registry was loaded with
+    user_path: Option<PathBuf>,
+}
+
+impl Default for ProviderRegistry {
+    fn default() -> Self {
+        Self::bundled()
+    }
+}
+
+impl ProviderRegistry {
+    /// The registry shipped with the app
+    pub fn bundled() -> Self {
+        Self::parse(BUNDLED_PROVIDERS, None).expect("bundled providers.json is valid")
+    }
+
+    /// Bundled registry with a user override file applied, if it exists.
+    ///
+    /// An unreadable or invalid override is logged and ignored.
+    pub fn load(user_path: &Path) -> Self {
+        let overrides = match std::fs::read_to_string(user_path) {
+            Ok(contents) => contents,
+            Err(_) => {
+                let mut registry = Self::bundled();
+                registry.user_path = Some(user_path.to_path_buf());
+                return registry;
+            }
+        };
+
+        match Self::parse(BUNDLED_PROVIDERS, Some(&overrides)) {
+            Ok(mut registry) => {
+                tracing::info!("Loaded provider overrides from {}", user_path.display());
+                registry.user_path = Some(user_path.to_path_buf());
+                registry
+            }
+            Err(e) => {
+                tracing::warn!("Ignoring invalid provider overrides in {}: {}", user_path.display(), e);
+                let mut registry = Self::bundled();
+                registry.user_path = Some(user_path.to_path_buf());
+                registry
+            }
+        }
+    }
+
+    /// Re-read the user override file this registry was loaded with
+    pub fn reload(&self) -> Self {
+        match &self.user_path {
+            Some(path) => Self::load(path),
+            None => Self::bundled(),
+        }
+    }
+
+    /// Parse a registry, merging `overrides` into it by provider name.
+    ///
+    /// Override fields replace bundled ones (objects merge recursively); unknown names are
+    /// appended and must name a built-in adapter.
+    fn parse(bundled: &str, overrides: Option<&str>) -> GenAIResult<Self> {
+        let mut merged: Value = serde_json::from_str(bundled)?;
+        if let Some(overrides) = overrides {
+            let overrides: Value = serde_json::from_str(overrides)?;
+            let entries = merged["providers"]
+                .as_array_mut()
+                .ok_or_else(|| GenAIError::configuration("providers.json has no providers list"))?;
+            for entry in overrides["providers"].as_array().into_iter().flatten() {
+                let name = entry["name"]
+                    .as_str()
+                    .ok_or_else(|| GenAIError::configuration("Provider override is missing a name"))?;
+                match entries.iter_mut().find(|e| e["name"] == name) {
+                    Some(existing) => merge(existing, entry),
+                    None => entries.push(entry.clone()),
+                }
+            }
+        }
+
+        let file: RegistryFile = serde_json::from_value(merged)?;
+        for entry in &file.providers {
+            entry.adapter_kind()?;
+            if let Some(pattern) = &entry.key_pattern {
+                Regex::new(pattern).map_err(|e| {
+                    GenAIError::configuration(format!("Invalid key_pattern for {}: {}", entry.name, e))
+                })?;
+            }
+        }
+
+        Ok(Self {
+            providers: file.providers,
+            user_path: None,
+        })
+    }
+
+    pub fn get(&self, name: &str) -> Option<&ProviderEntry> {
+        self.providers.iter().find(|p| p.name == name)
+    }
+
+    pub fn entries(&self) -> &[ProviderEntry] {
+        &self.providers
+    }
+
+    /// Lookup by adapter kind
+    pub fn for_adapter(&self, adapter: AdapterKind) -> Option<&ProviderEntry> {
+        self.get(adapter.as_lower_str())
+    }
+
+    /// Check an API key against the provider's key pattern, if it has one
+    pub fn validate_key(&self, provider: &str, key: &str) -> GenAIResult<()> {
+        let Some(entry) = self.get(provider) else {
+            return Ok(());
+        };
+        let Some(pattern) = &entry.key_pattern else {
+            return Ok(());
+        };
+        let regex = Regex::new(pattern)
+            .map_err(|e| GenAIError::configuration(format!("Invalid key_pattern for {}: {}", provider, e)))?;
+        if regex.is_match(key.trim()) {
+            Ok(())
+        } else {
+            Err(GenAIError::invalid_request(format!(
+                "Invalid API key format for {}. Expected format: {}",
+                entry.display_name, entry.key_format
+            )))
+        }
+    }
+}
+
+/// Recursively merge `top` into `base`; non-object values replace
+fn merge(base: &mut Value, top: &Value) {
+    match (base, top) {
+        (Value::Object(base), Value::Object(top)) => {
+            for (key, value) in top {
+                match base.get_mut(key) {
+                    Some(existing) => merge(existing, value),
+                    None => {
+                        base.insert(key.clone(), value.clone());
+                    }
+                }
+            }
+        }
+        (base, top) => *base = top.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_registry() {
+        let registry = ProviderRegistry::bundled();
+        let openai = registry.get("openai").unwrap();
+        assert_eq!(openai.env_var.as_deref(), Some("OPENAI_API_KEY"));
+        assert_eq!(openai.test_model, "gpt-4o-mini");
+        assert!(!registry.get("ollama").unwrap().requires_key);
+        assert!(registry.entries().iter().all(|e| e.adapter_kind().is_ok()));
+    }
+
+    #[test]
+    fn test_overrides_merge_by_name() {
+        let overrides = r#"{"providers": [
+            {"name": "openai", "test_model": "gpt-4.1-nano", "capabilities": {"max_context_length": 1000000}}
+        ]}"#;
+        let registry = ProviderRegistry::parse(BUNDLED_PROVIDERS, Some(overrides)).unwrap();
+        let openai = registry.get("openai").unwrap();
+        assert_eq!(openai.test_model, "gpt-4.1-nano");
+        assert_eq!(openai.capabilities.max_context_length, Some(1_000_000));
+        // Untouched fields keep their bundled values
+        assert!(openai.capabilities.tools);
+        assert_eq!(openai.display_name, "OpenAI");
+    }
+
+    #[test]
+    fn test_overrides_reject_unknown_adapter() {
+        let overrides = r#"{"providers": [{"name": "acme", "display_name": "Acme", "test_model": "a"}]}"#;
+        assert!(ProviderRegistry::parse(BUNDLED_PROVIDERS, Some(overrides)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_custom_providers_round_trip() {
+        use crate::llm::models::{ApiDialect, AuthHeaderStyle};
+
+        let path = std::env::temp_dir()
+            .join(format!("aye-providers-{}", uuid::Uuid::new_v4()))
+            .join(CUSTOM_PROVIDERS_FILE);
+        assert!(load_custom_providers(&path).is_empty());
+
+        let vllm = CustomProvider {
+            id: "vllm".to_string(),
+            display_name: "vLLM".to_string(),
+            base_url: "http://localhost:8000/v1/".to_string(),
+            auth: AuthHeaderStyle::None,
+            dialect: ApiDialect::OpenAI,
+            models: vec!["qwen2.5".to_string()],
+        };
+        let providers = HashMap::from([(vllm.id.clone(), vllm)]);
+        save_custom_providers(&path, &providers).await.unwrap();
+
+        let loaded = load_custom_providers(&path);
+        assert_eq!(loaded["vllm"].base_url, "http://localhost:8000/v1/");
+        assert_eq!(loaded["vllm"].models, ["qwen2.5"]);
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[test]
+    fn test_validate_key() {
+        let registry = ProviderRegistry::bundled();
+        assert!(registry.validate_key("anthropic", "sk-ant-REDACTED").is_ok());
+        assert!(registry.validate_key("anthropic", "sk-abcdefghijklmnopqrstu").is_err());
+        // Providers without a pattern accept anything
+        assert!(registry.validate_key("ollama", "x").is_ok());
+    }
+}
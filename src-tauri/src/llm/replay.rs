@@ -0,0 +1,394 @@
+//! Replay provider: serves recorded or scripted responses, so the chat pipeline runs offline.
+//!
+//! A fixture is selected like any model, as `replay::<name>`. It is either registered in memory
+//! or read from `<name>.json` in the fixture directory.
+
+use super::error::{GenAIError, GenAIResult};
+use super::upstream::{Completion, ToolCallAssembler, UpstreamEvent, UpstreamStream};
+use futures::StreamExt;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Provider key of replayed models
+pub const PROVIDER: &str = "replay";
+
+/// Directory under the app config dir that fixtures are read from
+pub const FIXTURE_DIR: &str = "fixtures";
+
+/// A recorded or scripted response
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Fixture {
+    /// The request that produced the response, for reference; replay does not match on it
+    #[serde(default)]
+    pub request: Option<serde_json::Value>,
+    pub steps: Vec<FixtureStep>,
+}
+
+/// One event of a fixture, or the error that ended it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureStep {
+    /// Wait before this step, counted from the previous one
+    #[serde(default)]
+    pub delay_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub event: Option<UpstreamEvent>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<FixtureError>,
+}
+
+/// The failure that ended a fixture
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum FixtureError {
+    /// A timeout, replayed with the limit that expired
+    Timeout {
+        phase: String,
+        timeout_seconds: u64,
+        #[serde(default)]
+        elapsed_ms: u64,
+    },
+    /// A provider failure, replayed through the same classification as a live one
+    Api {
+        /// HTTP status, if the failure had one
+        #[serde(default)]
+        status: Option<u16>,
+        message: String,
+    },
+}
+
+impl FixtureError {
+    fn to_error(&self) -> GenAIError {
+        match self {
+            Self::Timeout { phase, timeout_seconds, elapsed_ms } => GenAIError::Timeout {
+                timeout_seconds: *timeout_seconds,
+                elapsed_ms: *elapsed_ms,
+                phase: phase.clone(),
+            },
+            Self::Api { status: Some(status), message } => GenAIError::from_http_status(*status, message, None),
+            Self::Api { status: None, message } => GenAIError::api(message.clone()),
+        }
+    }
+}
+
+/// A recorded error, kept in a form that is classified the same way again on replay
+impl From<&GenAIError> for FixtureError {
+    fn from(error: &GenAIError) -> Self {
+        if let GenAIError::Timeout { timeout_seconds, elapsed_ms, phase } = error {
+            return Self::Timeout {
+                phase: phase.clone(),
+                timeout_seconds: *timeout_seconds,
+                elapsed_ms: *elapsed_ms,
+            };
+        }
+        let status = match error {
+            GenAIError::Api { status, .. } => *status,
+            GenAIError::Authentication { .. } => Some(401),
+            GenAIError::RateLimit { .. } => Some(429),
+            GenAIError::InvalidRequest { .. } => Some(400),
+            _ => None,
+        };
+        Self::Api {
+            status,
+            message: error.to_string(),
+        }
+    }
+}
+
+impl FixtureStep {
+    fn into_result(self) -> Option<GenAIResult<UpstreamEvent>> {
+        match (self.error, self.event) {
+            (Some(error), _) => Some(Err(error.to_error())),
+            (None, event) => event.map(Ok),
+        }
+    }
+}
+
+impl Fixture {
+    /// Serve the fixture as a stream, honoring its delays.
+    ///
+    /// An error as the first step fails the stream setup, so retries and fallbacks apply.
+    pub async fn stream(&self) -> GenAIResult<UpstreamStream> {
+        if let Some(FixtureStep { delay_ms, error: Some(error), .. }) = self.steps.first() {
+            tokio::time::sleep(Duration::from_millis(*delay_ms)).await;
+            return Err(error.to_error());
+        }
+        Ok(futures::stream::iter(self.steps.clone())
+            .filter_map(|step| async move {
+                if step.delay_ms > 0 {
+                    tokio::time::sleep(Duration::from_millis(step.delay_ms)).await;
+                }
+                step.into_result()
+            })
+            .boxed())
+    }
+
+    /// Serve the fixture as a whole response, after its total delay.
+    ///
+    /// Tool calls are the ones of the `End` step, else those assembled from the call chunks.
+    pub async fn complete(&self) -> GenAIResult<Completion> {
+        let delay: u64 = self.steps.iter().map(|step| step.delay_ms).sum();
+        tokio::time::sleep(Duration::from_millis(delay)).await;
+
+        let mut completion = Completion::default();
+        let mut assembler = ToolCallAssembler::default();
+        let mut chunked_calls = Vec::new();
+        for step in self.steps.iter().cloned() {
+            match step.into_result() {
+                Some(Err(e)) => return Err(e),
+                Some(Ok(UpstreamEvent::Chunk { content })) => {
+                    completion.text.get_or_insert_with(String::new).push_str(&content)
+                }
+                Some(Ok(UpstreamEvent::Reasoning { content })) => {
+                    completion.reasoning.get_or_insert_with(String::new).push_str(&content)
+                }
+                Some(Ok(UpstreamEvent::ToolCallChunk { tool_call })) => chunked_calls.extend(assembler.push(tool_call)),
+                Some(Ok(UpstreamEvent::End { usage, tool_calls })) => {
+                    completion.usage = usage.unwrap_or_default();
+                    completion.tool_calls = tool_calls;
+                }
+                _ => {}
+            }
+        }
+        if completion.tool_calls.is_empty() {
+            completion.tool_calls = chunked_calls;
+        }
+        Ok(completion)
+    }
+}
+
+/// Fixtures registered in memory, backed by the fixture directory
+#[derive(Debug, Default)]
+pub struct ReplayStore {
+    dir: Option<PathBuf>,
+    fixtures: HashMap<String, Arc<Fixture>>,
+}
+
+impl ReplayStore {
+    pub fn new(dir: Option<PathBuf>) -> Self {
+        Self {
+            dir,
+            fixtures: HashMap::new(),
+        }
+    }
+
+    pub fn dir(&self) -> Option<&PathBuf> {
+        self.dir.as_ref()
+    }
+
+    /// Register a scripted fixture under `name`, replacing any fixture of that name
+    pub fn insert(&mut self, name: impl Into<String>, fixture: Fixture) {
+        self.fixtures.insert(name.into(), Arc::new(fixture));
+    }
+
+    /// Names of registered fixtures and of those in the fixture directory
+    pub fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.fixtures.keys().cloned().collect();
+        if let Some(entries) = self.dir.as_ref().and_then(|dir| std::fs::read_dir(dir).ok()) {
+            names.extend(entries.filter_map(|entry| {
+                let path = entry.ok()?.path();
+                if path.extension()? != "json" {
+                    return None;
+                }
+                path.file_stem()?.to_str().map(str::to_string)
+            }));
+        }
+        names.sort();
+        names.dedup();
+        names
+    }
+
+    /// Fixture for `name`: a registered one, else `<name>.json` from the fixture directory
+    pub fn get(&self, name: &str) -> GenAIResult<Arc<Fixture>> {
+        if let Some(fixture) = self.fixtures.get(name) {
+            return Ok(fixture.clone());
+        }
+        if name.contains(['/', '\\']) || name.starts_with('.') {
+            return Err(GenAIError::invalid_request(format!("Invalid fixture name '{}'", name)));
+        }
+        let Some(path) = self.dir.as_ref().map(|dir| dir.join(format!("{}.json", name))) else {
+            return Err(GenAIError::model_not_available(format!("{}::{}", PROVIDER, name)));
+        };
+        let text = std::fs::read_to_string(&path)
+            .map_err(|_| GenAIError::model_not_available(format!("{}::{}", PROVIDER, name)))?;
+        let fixture: Fixture = serde_json::from_str(&text).map_err(|e| GenAIError::Serialization {
+            message: format!("Invalid fixture {}: {}", path.display(), e),
+        })?;
+        Ok(Arc::new(fixture))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::models::{StreamState, StreamingEventPayload, StreamingEventType};
+    use crate::llm::{send_message, stop_streaming_message, stream, stream_message, GenAIState};
+    use serde_json::json;
+    use tauri::Manager;
+
+    fn fixture(steps: serde_json::Value) -> Fixture {
+        serde_json::from_value(json!({ "steps": steps })).unwrap()
+    }
+
+    fn app_with(name: &str, fixture: Fixture) -> tauri::App<tauri::test::MockRuntime> {
+        let app = tauri::test::mock_app();
+        let state = GenAIState::new();
+        state.replays.try_write().unwrap().insert(name, fixture);
+        app.manage(state);
+        app
+    }
+
+    fn request(model: &str) -> serde_json::Value {
+        json!({
+            "model": model,
+            "messages": [{ "role": "user", "content": "What's the weather in Paris?" }],
+        })
+    }
+
+    #[tokio::test]
+    async fn test_leading_error_fails_setup() {
+        let rate_limited = fixture(json!([
+            { "error": { "status": 429, "message": "slow down" } },
+        ]));
+        let err = rate_limited.stream().await.err().unwrap();
+        assert_eq!(err.category(), "rate_limit");
+        assert!(err.is_fallback_trigger());
+        assert!(rate_limited.complete().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_stream_message_replays_tool_call_chunks() {
+        // The first chunk names the call, later ones carry its arguments under "call_0"
+        let app = app_with(
+            "weather",
+            fixture(json!([
+                { "event": { "type": "start" } },
+                { "event": { "type": "reasoning", "content": "Look it up." } },
+                { "delay_ms": 5, "event": { "type": "chunk", "content": "Checking." } },
+                { "event": { "type": "tool_call_chunk",
+                             "tool_call": { "call_id": "call_abc", "fn_name": "get_weather", "fn_arguments": "" } } },
+                { "event": { "type": "tool_call_chunk",
+                             "tool_call": { "call_id": "call_0", "fn_name": "", "fn_arguments": "{\"city\":" } } },
+                { "event": { "type": "tool_call_chunk",
+                             "tool_call": { "call_id": "call_0", "fn_name": "", "fn_arguments": "\"Paris\"}" } } },
+                { "event": { "type": "end",
+                             "usage": { "prompt_tokens": 12, "completion_tokens": 8, "total_tokens": 20 } } },
+            ])),
+        );
+        let request = serde_json::from_value(request("replay::weather")).unwrap();
+        let stream_id = stream_message(request, app.handle().clone(), app.state()).await.unwrap();
+
+        let journal = app.state::<GenAIState>().streams.read().await[&stream_id].journal.clone();
+        let replay = journal.replay(0);
+        let mut events: Vec<StreamingEventPayload> = Vec::new();
+        stream::follow(journal, replay, |event| {
+            events.push(event);
+            true
+        })
+        .await;
+
+        let types: Vec<String> = events.iter().map(|event| format!("{:?}", event.event_type)).collect();
+        assert_eq!(types, ["Start", "Reasoning", "Chunk", "ToolCall", "End"]);
+        let tool_call = &events[3].data["tool_call"];
+        assert_eq!(tool_call["call_id"], "call_abc");
+        assert_eq!(tool_call["fn_arguments"], json!({ "city": "Paris" }));
+
+        let end = &events[4].data;
+        assert!(matches!(events[4].event_type, StreamingEventType::End));
+        assert_eq!(end["model"], "replay::weather");
+        assert_eq!(end["final_response"], "Checking.");
+        assert_eq!(end["usage"]["total_tokens"], 20);
+        assert_eq!(end["thinking"]["steps"][0]["content"], "Look it up.");
+    }
+
+    #[tokio::test]
+    async fn test_stream_without_end_emits_buffered_content() {
+        let app = app_with("cut", fixture(json!([{ "event": { "type": "chunk", "content": "Sunny" } }])));
+        let request = serde_json::from_value(request("replay::cut")).unwrap();
+        let stream_id = stream_message(request, app.handle().clone(), app.state()).await.unwrap();
+
+        let journal = app.state::<GenAIState>().streams.read().await[&stream_id].journal.clone();
+        let replay = journal.replay(0);
+        let mut content = String::new();
+        stream::follow(journal, replay, |event| {
+            if matches!(event.event_type, StreamingEventType::Chunk) {
+                content.push_str(event.data["content"].as_str().unwrap_or_default());
+            }
+            true
+        })
+        .await;
+        assert_eq!(content, "Sunny");
+    }
+
+    #[tokio::test]
+    async fn test_stop_before_end_cancels_the_stream() {
+        let app = app_with(
+            "slow",
+            fixture(json!([
+                { "event": { "type": "chunk", "content": "Sunny" } },
+                { "delay_ms": 200, "event": { "type": "end" } },
+            ])),
+        );
+        let request = serde_json::from_value(request("replay::slow")).unwrap();
+        let stream_id = stream_message(request, app.handle().clone(), app.state()).await.unwrap();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        stop_streaming_message(stream_id, app.handle().clone(), app.state()).await.unwrap();
+
+        let journal = app.state::<GenAIState>().streams.read().await[&stream_id].journal.clone();
+        let replay = journal.replay(0);
+        let mut types = Vec::new();
+        stream::follow(journal, replay, |event| {
+            types.push(format!("{:?}", event.event_type));
+            true
+        })
+        .await;
+        assert!(!types.contains(&"End".to_string()), "a stopped stream does not end: {:?}", types);
+
+        // The task never claims the end once stopped, so the session stays cancelled
+        tokio::time::sleep(Duration::from_millis(250)).await;
+        let streams = app.state::<GenAIState>().streams.read().await;
+        assert_eq!(streams[&stream_id].state, StreamState::Cancelled);
+        assert!(!streams[&stream_id].cancel.finish());
+    }
+
+    #[tokio::test]
+    async fn test_send_message_replays_completion() {
+        let app = app_with(
+            "answer",
+            fixture(json!([
+                { "event": { "type": "reasoning", "content": "Mild season." } },
+                { "event": { "type": "chunk", "content": "Sunny, " } },
+                { "event": { "type": "chunk", "content": "22°C." } },
+                { "event": { "type": "end", "usage": { "prompt_tokens": 12, "completion_tokens": 6 } } },
+            ])),
+        );
+        let request = serde_json::from_value(request("replay::answer")).unwrap();
+        let response = send_message(request, app.handle().clone(), app.state()).await.unwrap();
+
+        assert_eq!(response.message, "Sunny, 22°C.");
+        assert_eq!(response.model, "replay::answer");
+        assert!(response.metadata.fallbacks.is_empty());
+        assert_eq!(response.thinking.unwrap()["steps"][0]["content"], "Mild season.");
+    }
+
+    #[tokio::test]
+    async fn test_complete_assembles_tool_call_arguments() {
+        let calling = fixture(json!([
+            { "event": { "type": "tool_call_chunk",
+                         "tool_call": { "call_id": "call_abc", "fn_name": "get_weather", "fn_arguments": "" } } },
+            { "event": { "type": "tool_call_chunk",
+                         "tool_call": { "call_id": "call_0", "fn_name": "", "fn_arguments": "{\"city\":" } } },
+            { "event": { "type": "tool_call_chunk",
+                         "tool_call": { "call_id": "call_0", "fn_name": "", "fn_arguments": "\"Paris\"}" } } },
+            { "event": { "type": "end" } },
+        ]));
+        let completion = calling.complete().await.unwrap();
+
+        assert_eq!(completion.tool_calls.len(), 1);
+        assert_eq!(completion.tool_calls[0].call_id, "call_abc");
+        assert_eq!(completion.tool_calls[0].fn_name, "get_weather");
+        assert_eq!(completion.tool_calls[0].fn_arguments, json!({ "city": "Paris" }));
+    }
+}
@@ -0,0 +1,168 @@
+//! Retry with jittered exponential backoff for provider calls
+
+use super::error::{GenAIError, GenAIResult};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::Duration;
+
+/// Retry policy for LLM calls
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    /// Maximum number of retries after the first attempt (0 disables retrying)
+    pub max_retries: u32,
+    /// Base delay for the exponential backoff in milliseconds
+    pub base_delay_ms: u64,
+    /// Upper bound for a single backoff delay in milliseconds
+    pub max_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            base_delay_ms: 500,
+            max_delay_ms: 30_000,
+        }
+    }
+}
+
+/// Information about a retry that is about to happen
+#[derive(Debug, Clone, Serialize)]
+pub struct RetryAttempt {
+    /// Retry number, starting at 1
+    pub attempt: u32,
+    /// Configured maximum number of retries
+    pub max_retries: u32,
+    /// Delay before the next attempt in milliseconds
+    pub delay_ms: u64,
+    /// Error that triggered the retry
+    pub error: String,
+    /// Error category
+    pub category: String,
+}
+
+impl RetryPolicy {
+    /// Backoff delay before retry `attempt` (1-based), honoring a provider `Retry-After`
+    pub fn delay_for(&self, attempt: u32, error: &GenAIError) -> Duration {
+        let max = Duration::from_millis(self.max_delay_ms);
+        if let Some(retry_after) = error.retry_after() {
+            return retry_after.min(max);
+        }
+
+        let exp = self
+            .base_delay_ms
+            .saturating_mul(1u64 << (attempt.saturating_sub(1)).min(16));
+        let ceiling = exp.min(self.max_delay_ms);
+        // Equal jitter: half fixed, half random
+        let jittered = ceiling / 2 + rand::thread_rng().gen_range(0..=ceiling / 2);
+        Duration::from_millis(jittered)
+    }
+}
+
+/// Run `op`, retrying retryable errors according to `policy`.
+///
+/// `on_retry` is called before each backoff sleep so callers can surface the attempt.
+pub async fn with_retry<T, F, Fut>(
+    policy: &RetryPolicy,
+    mut op: F,
+    mut on_retry: impl FnMut(&RetryAttempt),
+) -> GenAIResult<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = GenAIResult<T>>,
+{
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Ok(value) => return Ok(value),
+            Err(err) if err.is_retryable() && attempt < policy.max_retries => {
+                attempt += 1;
+                let delay = policy.delay_for(attempt, &err);
+                tracing::warn!(
+                    "Retrying after {:?} (attempt {}/{}): {}",
+                    delay,
+                    attempt,
+                    policy.max_retries,
+                    err
+                );
+                on_retry(&RetryAttempt {
+                    attempt,
+                    max_retries: policy.max_retries,
+                    delay_ms: delay.as_millis() as u64,
+                    error: err.to_string(),
+                    category: err.category().to_string(),
+                });
+                tokio::time::sleep(delay).await;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn fast_policy(max_retries: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_retries,
+            base_delay_ms: 1,
+            max_delay_ms: 2,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retries_retryable_errors() {
+        let calls = &AtomicU32::new(0);
+        let mut attempts = Vec::new();
+
+        let result = with_retry(
+            &fast_policy(3),
+            || async move {
+                if calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                    Err(GenAIError::api_status(503, "unavailable"))
+                } else {
+                    Ok("ok")
+                }
+            },
+            |attempt| attempts.push(attempt.attempt),
+        )
+        .await;
+
+        assert_eq!(result.unwrap(), "ok");
+        assert_eq!(attempts, vec![1, 2]);
+    }
+
+    #[tokio::test]
+    async fn test_does_not_retry_client_errors() {
+        let calls = &AtomicU32::new(0);
+        let result: GenAIResult<()> = with_retry(
+            &fast_policy(3),
+            || async move {
+                calls.fetch_add(1, Ordering::SeqCst);
+                Err(GenAIError::authentication("bad key"))
+            },
+            |_| {},
+        )
+        .await;
+
+        assert!(result.is_err());
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_delay_honors_retry_after_and_cap() {
+        let policy = RetryPolicy::default();
+        let err = GenAIError::rate_limit_after("429", Some(Duration::from_secs(5)));
+        assert_eq!(policy.delay_for(1, &err), Duration::from_secs(5));
+
+        let err = GenAIError::rate_limit_after("429", Some(Duration::from_secs(600)));
+        assert_eq!(policy.delay_for(1, &err), Duration::from_millis(policy.max_delay_ms));
+
+        let err = GenAIError::api("boom");
+        assert!(policy.delay_for(10, &err) <= Duration::from_millis(policy.max_delay_ms));
+    }
+}
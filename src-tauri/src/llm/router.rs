@@ -0,0 +1,141 @@
+//! Provider-qualified model identifiers (`provider::model`)
+
+use super::error::{GenAIError, GenAIResult};
+use super::models::CustomProvider;
+use genai::adapter::AdapterKind;
+use std::collections::HashMap;
+
+/// Separator between provider and model name in a qualified model id
+pub const PROVIDER_SEPARATOR: &str = "::";
+
+/// A model identifier resolved to the provider that serves it
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelRef {
+    /// Provider key (a built-in adapter name like "anthropic", or a custom provider id)
+    pub provider: String,
+    /// Adapter (API dialect) used to talk to the provider
+    pub adapter: AdapterKind,
+    /// Model name as understood by the provider
+    pub model: String,
+}
+
+impl ModelRef {
+    /// Parse `provider::model`, or infer the provider from a bare model name
+    pub fn parse(id: &str) -> GenAIResult<Self> {
+        Self::resolve(id, &HashMap::new())
+    }
+
+    /// Like [`ModelRef::parse`], but also resolves user-defined providers.
+    ///
+    /// A bare model name listed by a custom provider routes to that provider.
+    pub fn resolve(id: &str, custom: &HashMap<String, CustomProvider>) -> GenAIResult<Self> {
+        match id.split_once(PROVIDER_SEPARATOR) {
+            Some((provider, model)) => {
+                if model.is_empty() {
+                    return Err(GenAIError::invalid_request(format!("Missing model name in '{}'", id)));
+                }
+                let adapter = match custom.get(provider) {
+                    Some(custom_provider) => custom_provider.dialect.adapter_kind(),
+                    // Replay fixtures speak no API; options are mapped as for an OpenAI-compatible one
+                    None if provider.eq_ignore_ascii_case(super::replay::PROVIDER) => AdapterKind::OpenAI,
+                    None => parse_provider(provider)?,
+                };
+                Ok(Self {
+                    provider: provider.to_lowercase(),
+                    adapter,
+                    model: model.to_string(),
+                })
+            }
+            None => {
+                if let Some(custom_provider) = custom.values().find(|p| p.models.iter().any(|m| m == id)) {
+                    return Ok(Self {
+                        provider: custom_provider.id.clone(),
+                        adapter: custom_provider.dialect.adapter_kind(),
+                        model: id.to_string(),
+                    });
+                }
+                let adapter = AdapterKind::from_model(id)
+                    .map_err(|_| GenAIError::model_not_available(id))?;
+                Ok(Self::builtin(adapter, id))
+            }
+        }
+    }
+
+    /// Model served by a built-in provider
+    pub fn builtin(adapter: AdapterKind, model: impl Into<String>) -> Self {
+        Self {
+            provider: adapter.as_lower_str().to_string(),
+            adapter,
+            model: model.into(),
+        }
+    }
+
+    /// Fully qualified identifier, e.g. `anthropic::claude-3-haiku-20240307`
+    pub fn qualified(&self) -> String {
+        format!("{}{}{}", self.provider, PROVIDER_SEPARATOR, self.model)
+    }
+}
+
+/// Parse a built-in provider name into its adapter kind
+pub fn parse_provider(provider: &str) -> GenAIResult<AdapterKind> {
+    AdapterKind::from_lower_str(&provider.to_lowercase())
+        .ok_or_else(|| GenAIError::invalid_request(format!("Unknown provider: {}", provider)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::llm::models::{ApiDialect, AuthHeaderStyle};
+
+    fn vllm() -> HashMap<String, CustomProvider> {
+        let provider = CustomProvider {
+            id: "vllm".to_string(),
+            display_name: "vLLM".to_string(),
+            base_url: "http://localhost:8000/v1/".to_string(),
+            auth: AuthHeaderStyle::None,
+            dialect: ApiDialect::OpenAI,
+            models: vec!["qwen2.5-coder".to_string()],
+        };
+        HashMap::from([(provider.id.clone(), provider)])
+    }
+
+    #[test]
+    fn test_parse_qualified_model() {
+        let model = ModelRef::parse("anthropic::claude-3-haiku-20240307").unwrap();
+        assert_eq!(model.adapter, AdapterKind::Anthropic);
+        assert_eq!(model.model, "claude-3-haiku-20240307");
+        assert_eq!(model.qualified(), "anthropic::claude-3-haiku-20240307");
+    }
+
+    #[test]
+    fn test_qualifier_overrides_inference() {
+        // Looks like an OpenAI model name but is served by a local Ollama
+        let model = ModelRef::parse("ollama::gpt-oss").unwrap();
+        assert_eq!(model.adapter, AdapterKind::Ollama);
+        assert_eq!(model.model, "gpt-oss");
+    }
+
+    #[test]
+    fn test_bare_model_is_inferred() {
+        let model = ModelRef::parse("gpt-4o-mini").unwrap();
+        assert_eq!(model.adapter, AdapterKind::OpenAI);
+        assert_eq!(model.provider, "openai");
+    }
+
+    #[test]
+    fn test_custom_provider_resolution() {
+        let custom = vllm();
+        let model = ModelRef::resolve("vllm::llama-3.1-8b", &custom).unwrap();
+        assert_eq!(model.provider, "vllm");
+        assert_eq!(model.adapter, AdapterKind::OpenAI);
+
+        let model = ModelRef::resolve("qwen2.5-coder", &custom).unwrap();
+        assert_eq!(model.qualified(), "vllm::qwen2.5-coder");
+    }
+
+    #[test]
+    fn test_invalid_ids() {
+        assert!(ModelRef::parse("nope::model").is_err());
+        assert!(ModelRef::parse("openai::").is_err());
+    }
+}
@@ -0,0 +1,246 @@
+//! Streaming session registry: lifecycle, reaping and limits
+
+use super::error::{GenAIError, GenAIResult};
+use super::models::{StreamState, StreamingSession};
+use chrono::Utc;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+/// Live and recently finished streams, by stream id
+pub type StreamRegistry = Arc<RwLock<HashMap<Uuid, StreamingSession>>>;
+
+/// How long a finished stream stays registered so late subscribers can replay it
+pub const FINISHED_RETENTION: Duration = Duration::from_secs(60);
+
+/// How often the reaper sweeps the registry
+pub const REAP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Decides whether a stream ends by finishing or by being stopped, so exactly one of the
+/// streaming task and `stop_streaming_message` writes the message's final state
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken(Arc<AtomicBool>);
+
+impl CancelToken {
+    /// Claim the end of the stream for a stop; false if it is already finishing
+    pub fn cancel(&self) -> bool {
+        !self.0.swap(true, Ordering::AcqRel)
+    }
+
+    /// Claim the end of the stream for its task; false if it was stopped
+    pub fn finish(&self) -> bool {
+        !self.0.swap(true, Ordering::AcqRel)
+    }
+}
+
+/// A registered stream, as listed by `list_stream_sessions`
+#[derive(Debug, Clone, Serialize)]
+pub struct StreamSessionInfo {
+    pub stream_id: Uuid,
+    pub model: String,
+    pub message_id: Option<String>,
+    pub state: StreamState,
+    pub started_at: chrono::DateTime<Utc>,
+    pub finished_at: Option<chrono::DateTime<Utc>>,
+    /// Time from start until now, or until the stream finished
+    pub elapsed_ms: u64,
+}
+
+impl From<&StreamingSession> for StreamSessionInfo {
+    fn from(session: &StreamingSession) -> Self {
+        let until = session.finished_at.unwrap_or_else(Utc::now);
+        Self {
+            stream_id: session.id,
+            model: session.model.clone(),
+            message_id: session.message_id.clone(),
+            state: session.state,
+            started_at: session.started_at,
+            finished_at: session.finished_at,
+            elapsed_ms: (until - session.started_at).num_milliseconds().max(0) as u64,
+        }
+    }
+}
+
+/// Record the final state of a stream
+pub fn finish(session: &mut StreamingSession, state: StreamState) {
+    if session.state == StreamState::Running {
+        session.state = state;
+        session.finished_at = Some(Utc::now());
+    }
+    session.active = false;
+    session.journal.close();
+}
+
+/// Remove finished streams older than `retention` and streams whose task is gone.
+///
+/// A task that ended without finishing its session (panicked or was aborted) counts as failed.
+/// Returns the number of sessions removed.
+pub fn reap(sessions: &mut HashMap<Uuid, StreamingSession>, retention: Duration) -> usize {
+    let now = Utc::now();
+    let before = sessions.len();
+    sessions.retain(|_, session| {
+        if session.state == StreamState::Running
+            && session.handle.as_ref().is_some_and(|handle| handle.is_finished())
+        {
+            finish(session, StreamState::Failed);
+        }
+        match session.finished_at {
+            Some(finished_at) => (now - finished_at).to_std().unwrap_or_default() < retention,
+            None => true,
+        }
+    });
+    before - sessions.len()
+}
+
+/// Make room for a new stream under `max_sessions` by dropping the oldest finished streams.
+///
+/// Fails when every slot is taken by a running stream.
+pub fn reserve(sessions: &mut HashMap<Uuid, StreamingSession>, max_sessions: usize) -> GenAIResult<()> {
+    if max_sessions == 0 {
+        return Ok(());
+    }
+    while sessions.len() >= max_sessions {
+        let oldest_finished = sessions
+            .values()
+            .filter_map(|session| session.finished_at.map(|at| (at, session.id)))
+            .min()
+            .map(|(_, id)| id);
+        match oldest_finished {
+            Some(id) => {
+                sessions.remove(&id);
+            }
+            None => {
+                return Err(GenAIError::invalid_request(format!(
+                    "Too many concurrent streams (max_sessions is {})",
+                    max_sessions
+                )))
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Note the arrival of a stream's first output, once
+pub async fn first_token(registry: &StreamRegistry, stream_id: Uuid) {
+    if let Some(session) = registry.write().await.get_mut(&stream_id) {
+        session.first_token_at.get_or_insert_with(Utc::now);
+    }
+}
+
+/// Mark a stream finished from its own task, then deregister it after the retention period
+pub async fn complete(registry: StreamRegistry, stream_id: Uuid, state: StreamState) {
+    {
+        let mut sessions = registry.write().await;
+        let Some(session) = sessions.get_mut(&stream_id) else {
+            return;
+        };
+        finish(session, state);
+    }
+    tokio::time::sleep(FINISHED_RETENTION).await;
+
+    let mut sessions = registry.write().await;
+    if sessions.get(&stream_id).is_some_and(|session| session.state != StreamState::Running) {
+        sessions.remove(&stream_id);
+    }
+}
+
+/// Sweep the registry every `REAP_INTERVAL` for as long as the app runs
+pub fn spawn_reaper(registry: StreamRegistry) {
+    tauri::async_runtime::spawn(async move {
+        let mut tick = tokio::time::interval(REAP_INTERVAL);
+        tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tick.tick().await;
+            let removed = reap(&mut *registry.write().await, FINISHED_RETENTION);
+            if removed > 0 {
+                tracing::debug!("Reaped {} finished stream sessions", removed);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry_with(states: &[StreamState]) -> HashMap<Uuid, StreamingSession> {
+        states
+            .iter()
+            .map(|state| {
+                let mut session = StreamingSession::new("openai::gpt-4o");
+                if *state != StreamState::Running {
+                    finish(&mut session, *state);
+                }
+                (session.id, session)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_reap_keeps_running_and_recent() {
+        let mut sessions = registry_with(&[StreamState::Running, StreamState::Completed, StreamState::Failed]);
+        assert_eq!(reap(&mut sessions, Duration::from_secs(60)), 0);
+        assert_eq!(reap(&mut sessions, Duration::ZERO), 2);
+        assert_eq!(sessions.values().next().unwrap().state, StreamState::Running);
+    }
+
+    #[tokio::test]
+    async fn test_reap_fails_sessions_whose_task_is_gone() {
+        let mut sessions = registry_with(&[StreamState::Running]);
+        let handle = tokio::spawn(async {});
+        while !handle.is_finished() {
+            tokio::task::yield_now().await;
+        }
+        sessions.values_mut().next().unwrap().handle = Some(handle);
+
+        reap(&mut sessions, Duration::from_secs(60));
+        let session = sessions.values().next().unwrap();
+        assert_eq!(session.state, StreamState::Failed);
+        assert!(session.journal.is_finished());
+    }
+
+    #[test]
+    fn test_reserve_evicts_finished_before_rejecting() {
+        let mut sessions = registry_with(&[StreamState::Running, StreamState::Completed]);
+        assert!(reserve(&mut sessions, 2).is_ok());
+        assert_eq!(sessions.len(), 1);
+        assert!(reserve(&mut sessions, 1).is_err());
+        assert!(reserve(&mut sessions, 0).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_first_token_is_kept() {
+        let registry: StreamRegistry = Arc::new(RwLock::new(registry_with(&[StreamState::Running])));
+        let stream_id = *registry.read().await.keys().next().unwrap();
+        first_token(&registry, stream_id).await;
+        let first = registry.read().await[&stream_id].first_token_at;
+        assert!(first.is_some());
+
+        first_token(&registry, stream_id).await;
+        assert_eq!(registry.read().await[&stream_id].first_token_at, first);
+    }
+
+    #[test]
+    fn test_cancel_token_has_one_winner() {
+        let stopped = CancelToken::default();
+        assert!(stopped.clone().cancel());
+        assert!(!stopped.finish(), "a stopped stream does not finish");
+
+        let finished = CancelToken::default();
+        assert!(finished.finish());
+        assert!(!finished.cancel(), "a finishing stream is not cancelled");
+    }
+
+    #[test]
+    fn test_session_info_elapsed() {
+        let sessions = registry_with(&[StreamState::Cancelled]);
+        let info = StreamSessionInfo::from(sessions.values().next().unwrap());
+        assert_eq!(info.state, StreamState::Cancelled);
+        assert!(info.finished_at.is_some());
+        assert!(info.elapsed_ms < 1000);
+    }
+}
@@ -0,0 +1,377 @@
+//! Stream emission helpers: event sequencing, journaling and chunk coalescing
+
+use super::models::{StreamConfig, StreamingEventPayload, StreamingEventType};
+use serde_json::json;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Runtime};
+use tokio::sync::broadcast;
+use uuid::Uuid;
+
+/// Event name used for all streaming payloads
+pub const STREAM_EVENT: &str = "genai-stream-event";
+
+/// Default number of events a stream journal keeps for late subscribers
+pub const DEFAULT_JOURNAL_SIZE: usize = 512;
+
+/// Live events buffered per subscriber before it lags and catches up from the journal
+const LIVE_CHANNEL_SIZE: usize = 256;
+
+/// Emits stream events with monotonically increasing sequence numbers
+pub struct StreamEmitter<R: Runtime> {
+    app: AppHandle<R>,
+    journal: StreamJournal,
+}
+
+impl<R: Runtime> StreamEmitter<R> {
+    pub fn new(app: AppHandle<R>, journal: StreamJournal) -> Self {
+        Self { app, journal }
+    }
+
+    /// Emit an event, stamping it with the next sequence number and journaling it
+    pub fn emit(&mut self, event_type: StreamingEventType, data: serde_json::Value) {
+        let payload = self.journal.record(event_type, data);
+        if let Err(e) = self.app.emit(STREAM_EVENT, payload) {
+            tracing::warn!("Failed to emit stream event for {}: {}", self.journal.stream_id(), e);
+        }
+    }
+}
+
+impl<R: Runtime> Drop for StreamEmitter<R> {
+    // Also runs when the streaming task is aborted, releasing subscribers
+    fn drop(&mut self) {
+        self.journal.close();
+    }
+}
+
+/// Bounded record of a stream's events, shared by its emitter and subscribers.
+///
+/// The `Start` event is always kept so a late subscriber learns the model and message id.
+#[derive(Debug, Clone)]
+pub struct StreamJournal {
+    stream_id: String,
+    inner: Arc<Mutex<JournalInner>>,
+}
+
+#[derive(Debug)]
+struct JournalInner {
+    capacity: usize,
+    start: Option<StreamingEventPayload>,
+    events: VecDeque<StreamingEventPayload>,
+    next_seq: u64,
+    /// Highest sequence number evicted, other than `Start`
+    evicted_through: Option<u64>,
+    /// Dropped once the stream is over, which ends live subscriptions
+    live: Option<broadcast::Sender<StreamingEventPayload>>,
+}
+
+/// Journaled events from a sequence number on, plus a receiver for what follows
+#[derive(Debug)]
+pub struct Replay {
+    pub events: Vec<StreamingEventPayload>,
+    /// Whether events at or after the requested sequence number were evicted
+    pub truncated: bool,
+    /// Live events after `events`; `None` once the stream is over
+    pub live: Option<broadcast::Receiver<StreamingEventPayload>>,
+}
+
+impl StreamJournal {
+    pub fn new(stream_id: Uuid, capacity: usize) -> Self {
+        let (live, _) = broadcast::channel(LIVE_CHANNEL_SIZE);
+        Self {
+            stream_id: stream_id.to_string(),
+            inner: Arc::new(Mutex::new(JournalInner {
+                capacity: capacity.max(1),
+                start: None,
+                events: VecDeque::new(),
+                next_seq: 0,
+                evicted_through: None,
+                live: Some(live),
+            })),
+        }
+    }
+
+    pub fn stream_id(&self) -> &str {
+        &self.stream_id
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, JournalInner> {
+        self.inner.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Stamp an event with the next sequence number, store it and send it to live subscribers.
+    ///
+    /// `End` and `Error` finish the stream.
+    pub fn record(&self, event_type: StreamingEventType, data: serde_json::Value) -> StreamingEventPayload {
+        let mut inner = self.lock();
+        let payload = StreamingEventPayload {
+            event_type,
+            stream_id: self.stream_id.clone(),
+            seq: inner.next_seq,
+            data,
+            timestamp: chrono::Utc::now(),
+        };
+        inner.next_seq += 1;
+
+        if inner.events.len() >= inner.capacity {
+            if let Some(evicted) = inner.events.pop_front() {
+                if matches!(evicted.event_type, StreamingEventType::Start) {
+                    inner.start = Some(evicted);
+                } else {
+                    inner.evicted_through = Some(evicted.seq);
+                }
+            }
+        }
+        inner.events.push_back(payload.clone());
+
+        if let Some(live) = &inner.live {
+            // No receivers is fine: nobody is following
+            let _ = live.send(payload.clone());
+        }
+        if payload.is_terminal() {
+            inner.live = None;
+        }
+        payload
+    }
+
+    /// Events with `seq >= from_seq`, taken atomically with a receiver for later events
+    pub fn replay(&self, from_seq: u64) -> Replay {
+        let inner = self.lock();
+        let events = inner
+            .start
+            .iter()
+            .chain(inner.events.iter())
+            .filter(|event| event.seq >= from_seq)
+            .cloned()
+            .collect();
+        Replay {
+            events,
+            truncated: inner.evicted_through.is_some_and(|seq| from_seq <= seq),
+            live: inner.live.as_ref().map(|live| live.subscribe()),
+        }
+    }
+
+    /// Sequence number of the last event, if any was recorded
+    pub fn last_seq(&self) -> Option<u64> {
+        self.lock().next_seq.checked_sub(1)
+    }
+
+    /// Whether the stream ended, failed or was stopped
+    pub fn is_finished(&self) -> bool {
+        self.lock().live.is_none()
+    }
+
+    /// End live subscriptions without recording an event
+    pub fn close(&self) {
+        self.lock().live = None;
+    }
+}
+
+/// Send a replay and then live events to `send` until the stream is over or `send` fails.
+///
+/// A subscriber that falls behind the live channel catches up from the journal.
+pub async fn follow(
+    journal: StreamJournal,
+    mut replay: Replay,
+    mut send: impl FnMut(StreamingEventPayload) -> bool,
+) {
+    let mut next_seq = 0;
+    loop {
+        for event in replay.events.drain(..) {
+            if event.seq < next_seq {
+                continue;
+            }
+            next_seq = event.seq + 1;
+            if !send(event) {
+                return;
+            }
+        }
+        let Some(mut live) = replay.live.take() else {
+            return;
+        };
+
+        loop {
+            match live.recv().await {
+                Ok(event) => {
+                    if event.seq < next_seq {
+                        continue;
+                    }
+                    next_seq = event.seq + 1;
+                    let terminal = event.is_terminal();
+                    if !send(event) || terminal {
+                        return;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    replay = journal.replay(next_seq);
+                    break;
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            }
+        }
+    }
+}
+
+/// Coalesces content deltas by size and time window before they are emitted
+#[derive(Debug)]
+pub struct ChunkBuffer {
+    pending: String,
+    accumulated: String,
+    include_accumulated: bool,
+    max_bytes: usize,
+    window: Duration,
+    pending_since: Option<Instant>,
+}
+
+impl ChunkBuffer {
+    pub fn new(config: &StreamConfig) -> Self {
+        Self {
+            pending: String::new(),
+            accumulated: String::new(),
+            include_accumulated: config.include_accumulated,
+            max_bytes: config.buffer_size,
+            window: Duration::from_millis(config.flush_interval_ms),
+            pending_since: None,
+        }
+    }
+
+    /// Buffer a content delta. Returns true when the buffer should be flushed right away.
+    pub fn push(&mut self, content: &str) -> bool {
+        if content.is_empty() {
+            return false;
+        }
+        if self.pending.is_empty() {
+            self.pending_since = Some(Instant::now());
+        }
+        self.pending.push_str(content);
+        self.accumulated.push_str(content);
+
+        self.max_bytes == 0 || self.pending.len() >= self.max_bytes || self.is_due()
+    }
+
+    /// Whether the oldest pending delta has waited longer than the coalescing window
+    pub fn is_due(&self) -> bool {
+        self.pending_since
+            .map(|since| since.elapsed() >= self.window)
+            .unwrap_or(false)
+    }
+
+    /// Take the pending delta as a chunk event payload, if any
+    pub fn take(&mut self) -> Option<serde_json::Value> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        self.pending_since = None;
+        let content = std::mem::take(&mut self.pending);
+
+        Some(if self.include_accumulated {
+            json!({
+                "content": content,
+                "accumulated": self.accumulated,
+            })
+        } else {
+            json!({ "content": content })
+        })
+    }
+
+    /// Full response text received so far
+    pub fn accumulated(&self) -> &str {
+        &self.accumulated
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_journal_keeps_start_and_reports_truncation() {
+        let journal = StreamJournal::new(Uuid::new_v4(), 3);
+        journal.record(StreamingEventType::Start, json!({}));
+        for i in 0..4 {
+            journal.record(StreamingEventType::Chunk, json!({ "content": i }));
+        }
+
+        let replay = journal.replay(0);
+        let seqs: Vec<u64> = replay.events.iter().map(|e| e.seq).collect();
+        assert_eq!(seqs, vec![0, 2, 3, 4]);
+        assert!(replay.truncated);
+        assert!(!journal.replay(2).truncated);
+        assert_eq!(journal.last_seq(), Some(4));
+    }
+
+    #[tokio::test]
+    async fn test_follow_replays_then_streams_live() {
+        let journal = StreamJournal::new(Uuid::new_v4(), 16);
+        journal.record(StreamingEventType::Start, json!({}));
+        journal.record(StreamingEventType::Chunk, json!({ "content": "a" }));
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let follower = tokio::spawn(follow(journal.clone(), journal.replay(1), move |event| {
+            tx.send(event.seq).is_ok()
+        }));
+        journal.record(StreamingEventType::Chunk, json!({ "content": "b" }));
+        journal.record(StreamingEventType::End, json!({}));
+        follower.await.unwrap();
+
+        let mut seqs = Vec::new();
+        while let Ok(seq) = rx.try_recv() {
+            seqs.push(seq);
+        }
+        assert_eq!(seqs, vec![1, 2, 3]);
+        assert!(journal.is_finished());
+        assert!(journal.replay(0).live.is_none());
+    }
+
+    fn config(buffer_size: usize, flush_interval_ms: u64, include_accumulated: bool) -> StreamConfig {
+        StreamConfig {
+            include_accumulated,
+            buffer_size,
+            flush_interval_ms,
+            ..StreamConfig::default()
+        }
+    }
+
+    #[test]
+    fn test_coalesces_until_size_window() {
+        let mut buffer = ChunkBuffer::new(&config(8, 60_000, false));
+        assert!(!buffer.push("Hel"));
+        assert!(!buffer.push("lo "));
+        assert!(buffer.push("world"));
+
+        let chunk = buffer.take().unwrap();
+        assert_eq!(chunk["content"], "Hello world");
+        assert!(chunk.get("accumulated").is_none());
+        assert!(buffer.take().is_none());
+    }
+
+    #[test]
+    fn test_zero_buffer_flushes_every_delta() {
+        let mut buffer = ChunkBuffer::new(&config(0, 60_000, false));
+        assert!(buffer.push("a"));
+        assert!(!buffer.push(""));
+    }
+
+    #[test]
+    fn test_accumulated_is_opt_in() {
+        let mut buffer = ChunkBuffer::new(&config(1, 60_000, true));
+        buffer.push("foo");
+        buffer.take();
+        buffer.push("bar");
+
+        let chunk = buffer.take().unwrap();
+        assert_eq!(chunk["content"], "bar");
+        assert_eq!(chunk["accumulated"], "foobar");
+        assert_eq!(buffer.accumulated(), "foobar");
+    }
+
+    #[test]
+    fn test_time_window_marks_buffer_due() {
+        let mut buffer = ChunkBuffer::new(&config(1024, 0, false));
+        assert!(buffer.push("x"));
+        assert!(buffer.is_due());
+        buffer.take();
+        assert!(!buffer.is_due());
+    }
+}
@@ -0,0 +1,306 @@
+//! Structured output: JSON Schema requests, validation and self-repair
+
+use super::error::{GenAIError, GenAIResult};
+use super::router::ModelRef;
+use super::timeout::Timeouts;
+use genai::adapter::AdapterKind;
+use genai::chat::{ChatMessage, ChatOptions, ChatRequest, ChatResponseFormat, JsonSpec, Usage};
+use jsonschema::Validator;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Validation errors quoted back to the model in a repair prompt
+const MAX_REPORTED_ERRORS: usize = 5;
+
+/// JSON Schema the response must conform to
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ResponseSchema {
+    /// Schema name, sent to providers that label structured outputs
+    #[serde(default = "default_schema_name")]
+    pub name: String,
+    pub schema: Value,
+    /// Follow-up requests allowed to fix an invalid response
+    #[serde(default = "default_max_repairs")]
+    pub max_repairs: u32,
+    /// Use the provider's native structured output; disable for schemas the provider rejects
+    /// (OpenAI only accepts its strict subset of JSON Schema)
+    #[serde(default = "default_native")]
+    pub native: bool,
+}
+
+fn default_schema_name() -> String {
+    "response".to_string()
+}
+
+fn default_max_repairs() -> u32 {
+    2
+}
+
+fn default_native() -> bool {
+    true
+}
+
+/// How a provider is asked for JSON
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NativeFormat {
+    /// The schema is enforced by the provider
+    Schema,
+    /// The provider guarantees JSON; the schema is given in the prompt
+    JsonMode,
+    /// Prompt only
+    None,
+}
+
+/// Validated response
+#[derive(Debug, Clone, Serialize)]
+pub struct StructuredOutput {
+    pub value: Value,
+    /// Text of the accepted response (differs from the original after a repair)
+    pub text: String,
+    /// Repair requests that were needed
+    pub repairs: u32,
+    pub format: NativeFormat,
+}
+
+/// Native format for a model; `json_mode` is the catalog's knowledge, when it has any
+pub fn native_format(model: &ModelRef, json_mode: Option<bool>, schema: &ResponseSchema) -> NativeFormat {
+    if json_mode == Some(false) {
+        return NativeFormat::None;
+    }
+    // Custom providers only promise OpenAI compatibility, which JSON mode covers more widely
+    let builtin = model.provider == model.adapter.as_lower_str();
+    let format = match model.adapter {
+        AdapterKind::OpenAI | AdapterKind::Gemini | AdapterKind::Xai if builtin => NativeFormat::Schema,
+        AdapterKind::OpenAI | AdapterKind::Groq | AdapterKind::DeepSeek | AdapterKind::Ollama => NativeFormat::JsonMode,
+        _ if json_mode == Some(true) => NativeFormat::JsonMode,
+        _ => NativeFormat::None,
+    };
+    if format == NativeFormat::Schema && !schema.native {
+        NativeFormat::JsonMode
+    } else {
+        format
+    }
+}
+
+/// Compile the schema, rejecting invalid ones up front
+pub fn compile(schema: &ResponseSchema) -> GenAIResult<Validator> {
+    jsonschema::validator_for(&schema.schema)
+        .map_err(|e| GenAIError::invalid_request(format!("Invalid response schema: {}", e)))
+}
+
+/// Ask for JSON in `format` on top of `options`
+pub fn apply_format(options: ChatOptions, format: NativeFormat, schema: &ResponseSchema) -> ChatOptions {
+    match format {
+        NativeFormat::Schema => options.with_response_format(JsonSpec::new(
+            sanitize_name(&schema.name),
+            schema.schema.clone(),
+        )),
+        NativeFormat::JsonMode => options.with_response_format(ChatResponseFormat::JsonMode),
+        NativeFormat::None => options,
+    }
+}
+
+/// Output instructions for a schema, sent as a system message ahead of the conversation
+pub fn instructions(schema: &ResponseSchema) -> String {
+    format!(
+        "Respond with a single JSON value that conforms to this JSON Schema, and nothing else \
+         (no prose, no code fences):\n{}",
+        schema.schema
+    )
+}
+
+/// Put the output instructions ahead of the conversation
+pub fn add_instructions(chat_req: &mut ChatRequest, schema: &ResponseSchema) {
+    chat_req.messages.insert(0, ChatMessage::system(instructions(schema)));
+}
+
+/// Provider schema names allow only `[a-zA-Z0-9_-]`
+fn sanitize_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .take(64)
+        .collect();
+    if name.is_empty() {
+        default_schema_name()
+    } else {
+        name
+    }
+}
+
+/// Parse the JSON in a response, tolerating code fences and surrounding prose
+pub fn extract_json(text: &str) -> Option<Value> {
+    let trimmed = text.trim();
+    if let Ok(value) = serde_json::from_str(trimmed) {
+        return Some(value);
+    }
+
+    let unfenced = trimmed
+        .strip_prefix("```json")
+        .or_else(|| trimmed.strip_prefix("```"))
+        .and_then(|rest| rest.trim_end().strip_suffix("```"))
+        .map(str::trim);
+    if let Some(value) = unfenced.and_then(|inner| serde_json::from_str(inner).ok()) {
+        return Some(value);
+    }
+
+    let start = trimmed.find(['{', '['])?;
+    let end = trimmed.rfind(['}', ']'])?;
+    (start < end)
+        .then(|| serde_json::from_str(&trimmed[start..=end]).ok())
+        .flatten()
+}
+
+/// Parse and validate a response; on failure, a description of what is wrong
+pub fn check(validator: &Validator, text: &str) -> Result<Value, String> {
+    let value = extract_json(text).ok_or_else(|| "The response is not valid JSON.".to_string())?;
+    let errors: Vec<String> = validator
+        .iter_errors(&value)
+        .take(MAX_REPORTED_ERRORS)
+        .map(|e| {
+            let path = e.instance_path.to_string();
+            if path.is_empty() {
+                e.to_string()
+            } else {
+                format!("{}: {}", path, e)
+            }
+        })
+        .collect();
+    if errors.is_empty() {
+        Ok(value)
+    } else {
+        Err(format!("The response does not match the schema:\n- {}", errors.join("\n- ")))
+    }
+}
+
+/// Validate `text`, asking the model to fix it up to `schema.max_repairs` times.
+///
+/// Usage of each repair request is pushed to `usages` so callers can account for it.
+#[allow(clippy::too_many_arguments)]
+pub async fn validate_with_repair(
+    client: &genai::Client,
+    model: &str,
+    chat_req: &ChatRequest,
+    options: &ChatOptions,
+    timeouts: &Timeouts,
+    schema: &ResponseSchema,
+    validator: &Validator,
+    format: NativeFormat,
+    text: String,
+    usages: &mut Vec<Usage>,
+) -> GenAIResult<StructuredOutput> {
+    let mut conversation = chat_req.clone();
+    let mut text = text;
+    let mut repairs = 0;
+    loop {
+        let problem = match check(validator, &text) {
+            Ok(value) => {
+                return Ok(StructuredOutput {
+                    value,
+                    text,
+                    repairs,
+                    format,
+                })
+            }
+            Err(problem) => problem,
+        };
+        if repairs >= schema.max_repairs {
+            return Err(GenAIError::Serialization {
+                message: format!("No valid response after {} repair attempts. {}", repairs, problem),
+            });
+        }
+
+        repairs += 1;
+        tracing::debug!("Structured output from {} failed validation, repair {}: {}", model, repairs, problem);
+        conversation = conversation
+            .append_message(ChatMessage::assistant(text))
+            .append_message(ChatMessage::user(format!(
+                "{}\nReply with the corrected JSON only.",
+                problem
+            )));
+        let res = timeouts
+            .run("total", timeouts.total, async {
+                client
+                    .exec_chat(model, conversation.clone(), Some(options))
+                    .await
+                    .map_err(GenAIError::from)
+            })
+            .await?;
+        usages.push(res.usage.clone());
+        text = res.first_text().unwrap_or_default().to_string();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn schema() -> ResponseSchema {
+        serde_json::from_value(json!({
+            "schema": {
+                "type": "object",
+                "properties": { "port": { "type": "integer" }, "host": { "type": "string" } },
+                "required": ["port", "host"]
+            }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_extract_json_variants() {
+        assert_eq!(extract_json(r#" {"a": 1} "#), Some(json!({"a": 1})));
+        assert_eq!(extract_json("```json\n[1, 2]\n```"), Some(json!([1, 2])));
+        assert_eq!(extract_json("Here you go: {\"a\": true}. Done."), Some(json!({"a": true})));
+        assert_eq!(extract_json("no json here"), None);
+    }
+
+    #[test]
+    fn test_check_reports_schema_errors() {
+        let schema = schema();
+        let validator = compile(&schema).unwrap();
+        assert_eq!(
+            check(&validator, r#"{"port": 8080, "host": "localhost"}"#).unwrap()["port"],
+            8080
+        );
+
+        let problem = check(&validator, r#"{"port": "8080"}"#).unwrap_err();
+        assert!(problem.contains("/port"));
+        assert!(problem.contains("host"));
+        assert!(check(&validator, "port = 8080").unwrap_err().contains("not valid JSON"));
+    }
+
+    #[test]
+    fn test_invalid_schema_is_rejected() {
+        let mut schema = schema();
+        schema.schema = json!({ "type": "no-such-type" });
+        assert!(compile(&schema).is_err());
+    }
+
+    #[test]
+    fn test_native_format_by_provider() {
+        let schema = schema();
+        let openai = ModelRef::builtin(AdapterKind::OpenAI, "gpt-4o");
+        assert_eq!(native_format(&openai, None, &schema), NativeFormat::Schema);
+        assert_eq!(
+            native_format(&ModelRef::builtin(AdapterKind::Anthropic, "claude-3-5-sonnet"), None, &schema),
+            NativeFormat::None
+        );
+        assert_eq!(
+            native_format(&ModelRef::builtin(AdapterKind::DeepSeek, "deepseek-reasoner"), Some(false), &schema),
+            NativeFormat::None
+        );
+
+        let custom = ModelRef {
+            provider: "vllm".to_string(),
+            adapter: AdapterKind::OpenAI,
+            model: "qwen".to_string(),
+        };
+        assert_eq!(native_format(&custom, None, &schema), NativeFormat::JsonMode);
+
+        let loose = ResponseSchema { native: false, ..schema };
+        assert_eq!(native_format(&openai, None, &loose), NativeFormat::JsonMode);
+        assert_eq!(sanitize_name("my config.v1"), "my_config_v1");
+    }
+}
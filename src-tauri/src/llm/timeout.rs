@@ -0,0 +1,197 @@
+//! Timeout resolution and enforcement for provider calls
+
+use super::error::{GenAIError, GenAIResult};
+use super::models::{GlobalSettings, ModelConfig};
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::time::{Duration, Instant};
+
+/// Timeout overrides in milliseconds; unset fields fall back to the next layer
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    /// Time allowed to establish the connection
+    pub connect_ms: Option<u64>,
+    /// Time allowed until the first response token (or stream setup) arrives
+    pub first_token_ms: Option<u64>,
+    /// Time allowed for the whole request or stream
+    pub total_ms: Option<u64>,
+}
+
+/// Effective timeouts for a request. `Duration::ZERO` disables a limit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Timeouts {
+    pub connect: Duration,
+    pub first_token: Duration,
+    pub total: Duration,
+}
+
+impl Timeouts {
+    /// Resolve timeouts from global settings, overridden per model.
+    ///
+    /// `GlobalSettings.request_timeout` (seconds) is the total limit unless overridden.
+    pub fn resolve(settings: &GlobalSettings, model_config: Option<&ModelConfig>) -> Self {
+        let global = &settings.timeouts;
+        let model = model_config.filter(|c| c.enabled).map(|c| &c.timeouts);
+        let pick = |field: fn(&TimeoutConfig) -> Option<u64>, fallback: u64| {
+            let ms = model
+                .and_then(field)
+                .or_else(|| field(global))
+                .unwrap_or(fallback);
+            Duration::from_millis(ms)
+        };
+
+        let total_ms = settings.request_timeout.saturating_mul(1000);
+        Self {
+            connect: pick(|c| c.connect_ms, DEFAULT_CONNECT_TIMEOUT_MS.min(total_ms)),
+            first_token: pick(|c| c.first_token_ms, total_ms),
+            total: pick(|c| c.total_ms, total_ms),
+        }
+    }
+
+    /// Run `fut` under `limit`, producing a `Timeout` error with the elapsed time on expiry.
+    ///
+    /// Timeouts reported by the HTTP client are stamped with the limit of their phase and the
+    /// elapsed time too.
+    pub async fn run<T>(
+        &self,
+        phase: &str,
+        limit: Duration,
+        fut: impl Future<Output = GenAIResult<T>>,
+    ) -> GenAIResult<T> {
+        self.run_since(phase, limit, Instant::now(), fut).await
+    }
+
+    /// Like `run`, with `limit` counted from `started` rather than from now, so the retries
+    /// of a request share one deadline
+    pub async fn run_since<T>(
+        &self,
+        phase: &str,
+        limit: Duration,
+        started: Instant,
+        fut: impl Future<Output = GenAIResult<T>>,
+    ) -> GenAIResult<T> {
+        let attempt = Instant::now();
+        let fut = async {
+            fut.await.map_err(|err| match err {
+                GenAIError::Timeout { phase, .. } => {
+                    let limit = match phase.as_str() {
+                        "connect" => self.connect,
+                        "first_token" => self.first_token,
+                        "total" => self.total,
+                        _ => limit,
+                    };
+                    GenAIError::timeout_elapsed(phase, limit, attempt.elapsed())
+                }
+                other => other,
+            })
+        };
+        if limit.is_zero() {
+            return fut.await;
+        }
+        match tokio::time::timeout(limit.saturating_sub(started.elapsed()), fut).await {
+            Ok(result) => result,
+            Err(_) => Err(GenAIError::timeout_elapsed(phase, limit, started.elapsed())),
+        }
+    }
+}
+
+/// Default connect timeout when none is configured
+const DEFAULT_CONNECT_TIMEOUT_MS: u64 = 10_000;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use genai::chat::ChatOptions;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_request_timeout_is_default_total() {
+        let settings = GlobalSettings::default();
+        let timeouts = Timeouts::resolve(&settings, None);
+        assert_eq!(timeouts.total, Duration::from_secs(settings.request_timeout));
+        assert_eq!(timeouts.first_token, timeouts.total);
+        assert_eq!(timeouts.connect, Duration::from_millis(DEFAULT_CONNECT_TIMEOUT_MS));
+    }
+
+    #[test]
+    fn test_model_overrides_global() {
+        let mut settings = GlobalSettings::default();
+        settings.timeouts.first_token_ms = Some(5_000);
+        let model_config = ModelConfig {
+            model: "llama3".to_string(),
+            options: ChatOptions::default(),
+            parameters: HashMap::new(),
+            enabled: true,
+            timeouts: TimeoutConfig {
+                total_ms: Some(300_000),
+                ..TimeoutConfig::default()
+            },
+            fallbacks: Vec::new(),
+        };
+
+        let timeouts = Timeouts::resolve(&settings, Some(&model_config));
+        assert_eq!(timeouts.first_token, Duration::from_secs(5));
+        assert_eq!(timeouts.total, Duration::from_secs(300));
+    }
+
+    #[tokio::test]
+    async fn test_run_reports_elapsed_on_expiry() {
+        let timeouts = Timeouts::resolve(&GlobalSettings::default(), None);
+        let result: GenAIResult<()> = timeouts
+            .run("total", Duration::from_millis(10), async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok(())
+            })
+            .await;
+
+        match result {
+            Err(GenAIError::Timeout { phase, elapsed_ms, .. }) => {
+                assert_eq!(phase, "total");
+                assert!(elapsed_ms >= 10);
+            }
+            other => panic!("expected timeout, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_since_shares_the_deadline() {
+        let timeouts = Timeouts::resolve(&GlobalSettings::default(), None);
+        let started = Instant::now() - Duration::from_millis(50);
+        let result: GenAIResult<()> = timeouts
+            .run_since("total", Duration::from_millis(60), started, async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                Ok(())
+            })
+            .await;
+
+        match result {
+            Err(GenAIError::Timeout { elapsed_ms, .. }) => assert!((60..1000).contains(&elapsed_ms)),
+            other => panic!("expected timeout, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_stamps_client_timeouts() {
+        let timeouts = Timeouts::resolve(&GlobalSettings::default(), None);
+        let result: GenAIResult<()> = timeouts
+            .run("first_token", timeouts.first_token, async {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+                Err(GenAIError::Timeout {
+                    timeout_seconds: 0,
+                    elapsed_ms: 0,
+                    phase: "connect".to_string(),
+                })
+            })
+            .await;
+
+        match result {
+            Err(GenAIError::Timeout { phase, timeout_seconds, elapsed_ms }) => {
+                assert_eq!(phase, "connect");
+                assert_eq!(timeout_seconds, timeouts.connect.as_secs());
+                assert!(elapsed_ms >= 5);
+            }
+            other => panic!("expected timeout, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,174 @@
+//! Provider responses in one shape, whether they come from a live provider or a replay fixture
+
+use super::error::{GenAIError, GenAIResult};
+use super::utils::repair_json;
+use futures::stream::BoxStream;
+use futures::StreamExt;
+use genai::chat::{ChatStreamEvent, ChatStreamResponse, ToolCall, Usage};
+use serde::{Deserialize, Serialize};
+
+/// One event of a streamed response
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum UpstreamEvent {
+    Start,
+    Chunk {
+        content: String,
+    },
+    Reasoning {
+        content: String,
+    },
+    /// Part of a tool call; providers split the arguments over several chunks
+    ToolCallChunk {
+        tool_call: ToolCall,
+    },
+    End {
+        #[serde(default)]
+        usage: Option<Usage>,
+        /// Tool calls the provider assembled itself
+        #[serde(default)]
+        tool_calls: Vec<ToolCall>,
+    },
+}
+
+/// Events of a streamed response; an error ends the stream
+pub type UpstreamStream = BoxStream<'static, GenAIResult<UpstreamEvent>>;
+
+impl UpstreamEvent {
+    /// Convert a genai event; events the pipeline has no use for are dropped
+    pub fn from_genai(event: ChatStreamEvent) -> Option<Self> {
+        match event {
+            ChatStreamEvent::Start => Some(Self::Start),
+            ChatStreamEvent::Chunk(chunk) => Some(Self::Chunk { content: chunk.content }),
+            ChatStreamEvent::ReasoningChunk(chunk) => Some(Self::Reasoning { content: chunk.content }),
+            ChatStreamEvent::ToolCallChunk(chunk) => Some(Self::ToolCallChunk {
+                tool_call: chunk.tool_call,
+            }),
+            ChatStreamEvent::End(end) => Some(Self::End {
+                usage: end.captured_usage.clone(),
+                tool_calls: end.captured_into_tool_calls().unwrap_or_default(),
+            }),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+
+    /// Whether this is model output (content, reasoning or a tool call)
+    pub fn is_output(&self) -> bool {
+        matches!(self, Self::Chunk { .. } | Self::Reasoning { .. } | Self::ToolCallChunk { .. })
+    }
+}
+
+/// Stream of a live provider response
+pub fn from_genai_stream(response: ChatStreamResponse) -> UpstreamStream {
+    response
+        .stream
+        .filter_map(|event| async move {
+            match event {
+                Ok(event) => UpstreamEvent::from_genai(event).map(Ok),
+                Err(e) => Some(Err(GenAIError::from(e))),
+            }
+        })
+        .boxed()
+}
+
+/// Tool calls put together from their chunks.
+///
+/// Some providers send the id and name in a first chunk and the arguments in later chunks
+/// under `call_0` or no id at all; those arguments go to the most recent call.
+#[derive(Debug, Default)]
+pub struct ToolCallAssembler {
+    pending: Vec<ToolCall>,
+}
+
+impl ToolCallAssembler {
+    /// Add a chunk; returns the calls whose arguments now form a complete JSON object
+    pub fn push(&mut self, chunk: ToolCall) -> Vec<ToolCall> {
+        let unnamed_arguments = chunk.call_id == "call_0" || chunk.call_id.is_empty();
+        if !chunk.fn_name.is_empty() {
+            self.pending.retain(|call| call.call_id != chunk.call_id);
+            self.pending.push(chunk);
+        } else {
+            let call = match unnamed_arguments {
+                true => self.pending.last_mut(),
+                false => self.pending.iter_mut().find(|call| call.call_id == chunk.call_id),
+            };
+            match call {
+                Some(call) => {
+                    let arguments = format!("{}{}", argument_text(&call.fn_arguments), argument_text(&chunk.fn_arguments));
+                    call.fn_arguments = serde_json::Value::String(arguments);
+                }
+                None if !unnamed_arguments => self.pending.push(chunk),
+                None => {}
+            }
+        }
+
+        let mut completed = Vec::new();
+        self.pending.retain(|call| {
+            let arguments = match &call.fn_arguments {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            let parsed = (!call.fn_name.is_empty() && arguments.starts_with('{') && arguments.ends_with('}'))
+                .then(|| repair_json(&arguments))
+                .flatten();
+            match parsed {
+                Some(parsed) => {
+                    completed.push(ToolCall {
+                        fn_arguments: parsed,
+                        ..call.clone()
+                    });
+                    false
+                }
+                None => true,
+            }
+        });
+        completed
+    }
+}
+
+/// Arguments of a chunk as the text to append
+fn argument_text(arguments: &serde_json::Value) -> String {
+    match arguments {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string().trim_matches('"').to_string(),
+    }
+}
+
+/// A whole (non-streamed) response
+#[derive(Debug, Clone, Default)]
+pub struct Completion {
+    pub text: Option<String>,
+    pub reasoning: Option<String>,
+    pub usage: Usage,
+    pub tool_calls: Vec<ToolCall>,
+}
+
+impl Completion {
+    /// The response as the events a stream of it would have carried
+    pub fn events(&self) -> Vec<UpstreamEvent> {
+        let mut events = Vec::new();
+        if let Some(content) = &self.reasoning {
+            events.push(UpstreamEvent::Reasoning { content: content.clone() });
+        }
+        if let Some(content) = &self.text {
+            events.push(UpstreamEvent::Chunk { content: content.clone() });
+        }
+        events.push(UpstreamEvent::End {
+            usage: Some(self.usage.clone()),
+            tool_calls: self.tool_calls.clone(),
+        });
+        events
+    }
+}
+
+impl From<genai::chat::ChatResponse> for Completion {
+    fn from(response: genai::chat::ChatResponse) -> Self {
+        Self {
+            text: response.first_text().map(str::to_string),
+            reasoning: response.reasoning_content.clone(),
+            usage: response.usage.clone(),
+            tool_calls: response.into_tool_calls(),
+        }
+    }
+}
@@ -1,5 +1,6 @@
-import type { GenaiChatRequest, GenaiChatResponse, GenaiStreamEventPayload, GenaiToolCall } from "@/ipc/genai/types";
+import type { GenaiActiveStream, GenaiChatRequest, GenaiChatResponse, GenaiCompareRequest, GenaiComparisonResult, GenaiEmbedResponse, GenaiStreamEventPayload, GenaiStreamSession, GenaiStreamSubscription, GenaiToolCall, ExportFormat, ImportSummary, MessageBranches, MessageFilter, MessageUpdate, NewMessage, NewProject, NewThread, Page, ProjectFilter, ProjectUpdate, SearchQuery, SearchResults, SemanticHit, SpendFilter, SpendRow, StoredMessage, StoredProject, StoredThread, ThreadFilter, ThreadUpdate } from "@/ipc/genai/types";
 import {safeInvoke} from "@/utils";
+import { Channel } from "@tauri-apps/api/core";
 import { listen, type UnlistenFn } from "@tauri-apps/api/event";
 
 export async function streamMessage(args: GenaiChatRequest): Promise<void> {
@@ -14,6 +15,150 @@ export async function sendMessage(args: GenaiChatRequest): Promise<GenaiChatResp
     });
 }
 
+// Resolves once every model's stream has ended; stream events arrive as usual meanwhile
+export async function compareModels(args: GenaiCompareRequest): Promise<GenaiComparisonResult|null> {
+    return await safeInvoke<GenaiComparisonResult>("compare_models", {
+        request: args
+    });
+}
+
+export async function listActiveStreams(): Promise<GenaiActiveStream[]|null> {
+    return await safeInvoke<GenaiActiveStream[]>("list_active_streams", {});
+}
+
+// Running and recently finished streams, newest first
+export async function listStreamSessions(): Promise<GenaiStreamSession[]|null> {
+    return await safeInvoke<GenaiStreamSession[]>("list_stream_sessions", {});
+}
+
+// Replays the stream's journaled events from `fromSeq`, then follows it live until it ends
+export async function subscribeStream(
+    streamId: string,
+    onEvent: (event: GenaiStreamEventPayload) => void,
+    fromSeq = 0,
+): Promise<GenaiStreamSubscription|null> {
+    const channel = new Channel<GenaiStreamEventPayload>();
+    channel.onmessage = onEvent;
+    return await safeInvoke<GenaiStreamSubscription>("subscribe_stream", {
+        streamId,
+        fromSeq,
+        onEvent: channel,
+    });
+}
+
+export async function getSpend(groupBy: 'day' | 'model' | 'project', filter?: SpendFilter): Promise<SpendRow[]|null> {
+    return await safeInvoke<SpendRow[]>(`get_spend_by_${groupBy}`, { filter });
+}
+
+export async function embed(inputs: string[], model?: string): Promise<GenaiEmbedResponse|null> {
+    return await safeInvoke<GenaiEmbedResponse>("embed", { request: { inputs, model } });
+}
+
+// Searches messages the background indexer has embedded
+export async function semanticSearch(query: string, projectId?: string, limit?: number): Promise<SemanticHit[]|null> {
+    return await safeInvoke<SemanticHit[]>("semantic_search", { query, projectId, limit });
+}
+
+export async function searchChatHistory(query: SearchQuery): Promise<SearchResults|null> {
+    return await safeInvoke<SearchResults>("search_chat_history", { query });
+}
+
+// Thread or project as Markdown, lossless JSON or a self-contained HTML page
+export async function exportThread(threadId: string, format: ExportFormat): Promise<string|null> {
+    return await safeInvoke<string>("export_thread", { threadId, format });
+}
+
+export async function exportProject(projectId: string, format: ExportFormat): Promise<string|null> {
+    return await safeInvoke<string>("export_project", { projectId, format });
+}
+
+// Our JSON export, or a ChatGPT/Claude export archive (.zip or conversations.json)
+export async function importConversations(path: string, projectId?: string): Promise<ImportSummary|null> {
+    return await safeInvoke<ImportSummary>("import_conversations", { path, projectId });
+}
+
+// The thread's active branch, root first
+export async function getActivePath(threadId: string): Promise<StoredMessage[]|null> {
+    return await safeInvoke<StoredMessage[]>("get_active_path", { threadId });
+}
+
+export async function listBranches(messageId: string): Promise<MessageBranches|null> {
+    return await safeInvoke<MessageBranches>("list_branches", { messageId });
+}
+
+export async function switchBranch(messageId: string): Promise<StoredMessage[]|null> {
+    return await safeInvoke<StoredMessage[]>("switch_branch", { messageId });
+}
+
+// Adds the edited text as a sibling branch; stream a reply with it as parent_message_id
+export async function editMessage(messageId: string, content: string): Promise<StoredMessage|null> {
+    return await safeInvoke<StoredMessage>("edit_message", { messageId, content });
+}
+
+export async function forkThread(messageId: string, title?: string): Promise<string|null> {
+    return await safeInvoke<string>("fork_thread", { messageId, title });
+}
+
+export async function listProjects(filter?: ProjectFilter): Promise<Page<StoredProject>|null> {
+    return await safeInvoke<Page<StoredProject>>("list_projects", { filter });
+}
+
+export async function getProject(id: string): Promise<StoredProject|null> {
+    return await safeInvoke<StoredProject>("get_project", { id });
+}
+
+export async function createProject(project: NewProject): Promise<StoredProject|null> {
+    return await safeInvoke<StoredProject>("create_project", { project });
+}
+
+export async function updateProject(id: string, update: ProjectUpdate): Promise<StoredProject|null> {
+    return await safeInvoke<StoredProject>("update_project", { id, update });
+}
+
+export async function deleteProject(id: string): Promise<void> {
+    await safeInvoke<void>("delete_project", { id });
+}
+
+export async function listThreads(filter?: ThreadFilter): Promise<Page<StoredThread>|null> {
+    return await safeInvoke<Page<StoredThread>>("list_threads", { filter });
+}
+
+export async function getThread(id: string): Promise<StoredThread|null> {
+    return await safeInvoke<StoredThread>("get_thread", { id });
+}
+
+export async function createThread(thread: NewThread): Promise<StoredThread|null> {
+    return await safeInvoke<StoredThread>("create_thread", { thread });
+}
+
+export async function updateThread(id: string, update: ThreadUpdate): Promise<StoredThread|null> {
+    return await safeInvoke<StoredThread>("update_thread", { id, update });
+}
+
+export async function deleteThread(id: string): Promise<void> {
+    await safeInvoke<void>("delete_thread", { id });
+}
+
+export async function listMessages(filter?: MessageFilter): Promise<Page<StoredMessage>|null> {
+    return await safeInvoke<Page<StoredMessage>>("list_messages", { filter });
+}
+
+export async function getMessage(id: string): Promise<StoredMessage|null> {
+    return await safeInvoke<StoredMessage>("get_message", { id });
+}
+
+export async function createMessage(message: NewMessage): Promise<StoredMessage|null> {
+    return await safeInvoke<StoredMessage>("create_message", { message });
+}
+
+export async function updateMessage(id: string, update: MessageUpdate): Promise<StoredMessage|null> {
+    return await safeInvoke<StoredMessage>("update_message", { id, update });
+}
+
+export async function deleteMessage(id: string): Promise<void> {
+    await safeInvoke<void>("delete_message", { id });
+}
+
 interface EventCallback {
     onChunk({chunk, accumulated}: {chunk: string, accumulated: string}): void;
     onReasoning(reasoning: string): void;
@@ -23,16 +168,30 @@ interface EventCallback {
 }
 
 export async function listenToStream({ onChunk, onReasoning, onToolCall, onEnd, onError }: EventCallback): Promise<UnlistenFn> {
+    // Chunks are delta-only by default, so the accumulated text is rebuilt per stream here
+    const accumulated = new Map<string, string>();
+    const lastSeq = new Map<string, number>();
+
     return await listen<GenaiStreamEventPayload>('genai-stream-event', async (event) => {
         const streamEvent = event.payload;
+
+        const previousSeq = lastSeq.get(streamEvent.stream_id);
+        if (previousSeq !== undefined && streamEvent.seq !== previousSeq + 1) {
+            console.warn(`Stream ${streamEvent.stream_id}: expected seq ${previousSeq + 1}, got ${streamEvent.seq}`);
+        }
+        lastSeq.set(streamEvent.stream_id, streamEvent.seq);
         
         switch (streamEvent.event_type) {
-          case 'Chunk':
+          case 'Chunk': {
+            const text = streamEvent.data.accumulated
+                ?? (accumulated.get(streamEvent.stream_id) ?? '') + streamEvent.data.content;
+            accumulated.set(streamEvent.stream_id, text);
             onChunk({
                 chunk: streamEvent.data.content, 
-                accumulated: streamEvent.data.accumulated
+                accumulated: text
             });
             break;
+          }
 
           case 'Reasoning':
             onReasoning(streamEvent.data.content);
@@ -45,6 +204,8 @@ export async function listenToStream({ onChunk, onReasoning, onToolCall, onEnd,
             break;
             
           case 'End':
+            accumulated.delete(streamEvent.stream_id);
+            lastSeq.delete(streamEvent.stream_id);
             await onEnd({
                 tool_calls: streamEvent.data.tool_calls || [], 
                 final_response: streamEvent.data.final_response || ''
@@ -52,7 +213,9 @@ export async function listenToStream({ onChunk, onReasoning, onToolCall, onEnd,
             break;
             
           case 'Error':
-            await onError(streamEvent.data.content);
+            accumulated.delete(streamEvent.stream_id);
+            lastSeq.delete(streamEvent.stream_id);
+            await onError(streamEvent.data.error ?? streamEvent.data.content);
             break;
         }
     });
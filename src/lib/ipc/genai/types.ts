@@ -1,21 +1,37 @@
-import type { ChatMessage } from "../../types";
+import type { ChatMessage, ThinkingProcess } from "../../types";
 import type { FunctionDefProperty } from "../../types/func";
 
-export interface SimpleChatMessage extends Pick<ChatMessage, 'content' | 'role'> {}
+export interface SimpleChatMessage extends Pick<ChatMessage, 'content' | 'role'> {
+    pinned?: boolean;
+}
 
 export interface GenaiChatRequest {
     model: string;
     messages: SimpleChatMessage[];
     options?: GenaiChatOptions;
+    thread_options?: GenaiChatOptions;
+    context_strategy?: GenaiContextStrategy;
+    // With a thread id, the backend stores the streamed assistant message in that thread
+    // (message_id is generated when omitted and reported in the start event)
+    thread_id?: string;
+    message_id?: string;
+    // The stored reply becomes a child of this message; with no messages, the stored branch
+    // ending here is sent as the history (answering an edit, regenerating)
+    parent_message_id?: string;
     tools?: GenaiToolDef[];
+    response_schema?: GenaiResponseSchema;
+    reasoning?: GenaiReasoningConfig;
+    // Overrides the project's and the model's fallback chain; [] disables falling back
+    fallbacks?: string[];
     context_id?: string;
     stream_config?: GenaiStreamConfig;
 }
 
-interface GenaiChatOptions {
+export interface GenaiChatOptions {
     temperature?: number;
     max_tokens?: number;
     top_p?: number;
+    stop_sequences?: string[];
     frequency_penalty?: number;
     presence_penalty?: number;
 }
@@ -29,6 +45,32 @@ interface GenaiResponseMetadata {
     };
     response_time_ms: number;
     streamed: boolean;
+    options: GenaiChatOptions;
+    context?: GenaiContextReport;
+    cost?: number;
+    fallbacks: GenaiFallbackAttempt[];
+}
+
+// A model that failed before the one in `model` answered
+export interface GenaiFallbackAttempt {
+    model: string;
+    error: string;
+    category: string;
+}
+
+export type GenaiContextStrategy = 'none' | 'drop_oldest' | 'keep_system_and_pinned' | 'summarize_middle';
+
+// What was trimmed to fit the model's context window
+export interface GenaiContextReport {
+    strategy: GenaiContextStrategy;
+    context_window: number;
+    budget_tokens: number;
+    estimated_tokens_before: number;
+    estimated_tokens_after: number;
+    dropped: number[];
+    summarized: boolean;
+    /** `o200k_base` for OpenAI models; `heuristic` where the count is an estimate */
+    token_counter: 'o200k_base' | 'heuristic';
 }
 
 export interface GenaiChatResponse {
@@ -36,6 +78,30 @@ export interface GenaiChatResponse {
     model: string;
     metadata: GenaiResponseMetadata;
     tool_calls?: GenaiToolCall[];
+    structured?: GenaiStructuredOutput;
+    thinking?: ThinkingProcess;
+}
+
+// Level for OpenAI/xAI/Groq, token budget for Anthropic/Gemini; the other is derived
+export interface GenaiReasoningConfig {
+    effort?: "low" | "medium" | "high";
+    budget_tokens?: number;
+}
+
+export interface GenaiResponseSchema {
+    name?: string;
+    schema: Record<string, unknown>;
+    // Follow-up requests allowed to fix an invalid response (default 2)
+    max_repairs?: number;
+    // Use the provider's native structured output (default true)
+    native?: boolean;
+}
+
+export interface GenaiStructuredOutput {
+    value: unknown;
+    text: string;
+    repairs: number;
+    format: "schema" | "json_mode" | "none";
 }
 
 export interface GenaiToolCall {
@@ -58,26 +124,391 @@ export interface GenaiToolDef {
 export interface GenaiStreamConfig {
     include_accumulated?: boolean;
     buffer_size?: number;
+    flush_interval_ms?: number;
     timeout_ms?: number;
     capture_tool_calls?: boolean;
+    journal_size?: number;
 }
 
-export type GenaiStreamEventType = "Start" | "Chunk" | "ToolCall" | "Reasoning" | "End" | "Error";
+export type GenaiStreamEventType = "Start" | "Chunk" | "ToolCall" | "Reasoning" | "Retry" | "Fallback" | "End" | "Error";
 
 export interface GenaiStreamEventPayload {
     event_type: GenaiStreamEventType;
     stream_id: string;
-    data: { content: string, tool_calls?: GenaiToolCall[], tool_call?: GenaiToolCall, final_response?: string, accumulated: string };
+    seq: number;
+    data: { content: string, tool_calls?: GenaiToolCall[], tool_call?: GenaiToolCall, final_response?: string, accumulated?: string, error?: string };
     timestamp: string;
 }
 
+export interface GenaiActiveStream {
+    stream_id: string;
+    model: string;
+    message_id?: string;
+    started_at: string;
+    last_seq?: number;
+}
+
+export type GenaiStreamState = "running" | "completed" | "failed" | "cancelled";
+
+export interface GenaiStreamSession {
+    stream_id: string;
+    model: string;
+    message_id?: string;
+    state: GenaiStreamState;
+    started_at: string;
+    finished_at?: string;
+    elapsed_ms: number;
+}
+
+// One conversation streamed to several models; each stream's start event carries comparison_id
+export interface GenaiCompareRequest extends Omit<GenaiChatRequest, 'model' | 'message_id' | 'fallbacks' | 'context_id'> {
+    models: string[];
+    comparison_id?: string;
+}
+
+export interface GenaiModelComparison {
+    model: string;
+    stream_id?: string;
+    message_id?: string;
+    state: GenaiStreamState;
+    first_token_ms?: number;
+    total_ms?: number;
+    usage?: {
+        prompt_tokens: number;
+        completion_tokens: number;
+        reasoning_tokens: number;
+        cached_tokens: number;
+        total_tokens: number;
+    };
+    cost?: number;
+    error?: string;
+}
+
+export interface GenaiComparisonResult {
+    comparison_id: string;
+    models: GenaiModelComparison[];
+    total_ms: number;
+    cost?: number;
+}
+
+export interface GenaiStreamSubscription {
+    stream_id: string;
+    replayed: number;
+    truncated: boolean;
+    finished: boolean;
+}
+
 // Provider config
 export interface ProviderConfig {
     name: string;
     display_name: string;
     description: string;
     key_format: string;
+    key_pattern?: string;
     website: string;
     models: string[];
     is_configured: boolean;
+    is_custom: boolean;
+    capabilities: ModelCapabilities;
+}
+
+export interface ModelCapabilities {
+    streaming: boolean;
+    tools: boolean;
+    vision: boolean;
+    max_context_length?: number;
+    max_output_tokens?: number;
+    reasoning: boolean;
+    json_mode: boolean;
+    modalities: string[];
+}
+
+export interface ModelPricing {
+    input_per_mtok: number;
+    output_per_mtok: number;
+}
+
+export interface ModelInfo {
+    name: string;
+    provider: string;
+    available: boolean;
+    capabilities: ModelCapabilities;
+    pricing?: ModelPricing;
+    metadata: Record<string, unknown>;
+}
+
+// User-defined OpenAI/Anthropic-compatible endpoint
+export type CustomProviderAuth =
+    | { type: 'none' }
+    | { type: 'bearer' }
+    | { type: 'header'; name: string };
+
+export interface CustomProvider {
+    id: string;
+    display_name: string;
+    base_url: string;
+    auth: CustomProviderAuth;
+    dialect: 'openai' | 'anthropic';
+    models: string[];
+}
+
+
+// Ollama model management
+export interface OllamaModel {
+    name: string;
+    size: number;
+    quantization?: string;
+    parameter_size?: string;
+    family?: string;
+    modified_at?: string;
+}
+
+export interface OllamaModelSettings {
+    keep_alive?: string;
+    num_ctx?: number;
+}
+
+export interface OllamaPullProgress {
+    model: string;
+    status: string;
+    digest?: string;
+    total?: number;
+    completed?: number;
+}
+
+// Usage and spend
+export interface TokenUsage {
+    prompt_tokens: number;
+    completion_tokens: number;
+    reasoning_tokens: number;
+    cached_tokens: number;
+    total_tokens: number;
+}
+
+export interface SpendFilter {
+    from?: string;
+    to?: string;
+    project_id?: string;
+    provider?: string;
+    model?: string;
+}
+
+export interface SpendRow extends TokenUsage {
+    key: string;
+    label?: string;
+    requests: number;
+    cost: number;
+}
+
+export interface GenaiEmbedResponse {
+    model: string;
+    dimensions: number;
+    embeddings: number[][];
+}
+
+export interface SearchMessage {
+    id: string;
+    role: string;
+    content: string;
+    created_at: string;
+}
+
+export interface SemanticHit {
+    score: number;
+    message: SearchMessage;
+    thread_id: string;
+    thread_title: string;
+    project_id?: string;
+    before: SearchMessage[];
+    after: SearchMessage[];
+}
+
+// `"quoted words"` match as a phrase, `word*` as a prefix; all terms must match
+export interface SearchQuery {
+    query: string;
+    project_id?: string;
+    roles?: string[];
+    from?: string;
+    to?: string;
+    model?: string;
+    highlight_start?: string;
+    highlight_end?: string;
+    limit?: number;
+    offset?: number;
+}
+
+export interface MessageMatch {
+    message_id: string;
+    thread_id: string;
+    thread_title: string;
+    project_id?: string;
+    role: string;
+    model?: string;
+    created_at: string;
+    // HTML-escaped, with matched terms between the highlight markers
+    snippet: string;
+    rank: number;
+}
+
+export interface ThreadMatch {
+    thread_id: string;
+    project_id?: string;
+    title: string;
+    description?: string;
+    rank: number;
+}
+
+export interface SearchResults {
+    messages: MessageMatch[];
+    threads: ThreadMatch[];
+}
+
+export type ExportFormat = "markdown" | "json" | "html";
+
+export interface ImportSummary {
+    source: "aye" | "chatgpt" | "claude";
+    project_id?: string;
+    thread_ids: string[];
+    messages: number;
+}
+
+// A stored message with its JSON columns parsed
+export interface StoredMessage {
+    id: string;
+    thread_id: string;
+    parent_message_id?: string;
+    role: "user" | "assistant" | "system" | "tool";
+    content: string;
+    attachments: unknown[];
+    thinking: Partial<ThinkingProcess>;
+    tool_calls: unknown[];
+    metadata: Record<string, unknown>;
+    is_edited: boolean;
+    // Versions this message replaced, oldest first
+    edit_history: { message_id: string; content: string; edited_at: string }[];
+    response_time?: number;
+    model_used?: string;
+    provider_used?: string;
+    prompt_tokens: number;
+    completion_tokens: number;
+    reasoning_tokens: number;
+    cached_tokens: number;
+    cost: number;
+    created_at: string;
+    updated_at: string;
+}
+
+export interface MessageBranches {
+    siblings: StoredMessage[];
+    active_index?: number;
+}
+
+export type SortOrder = "asc" | "desc";
+
+export interface Page<T> {
+    items: T[];
+    // Matching rows across all pages
+    total: number;
+    limit: number;
+    offset: number;
+}
+
+export interface StoredProject {
+    id: string;
+    name: string;
+    description?: string;
+    system_prompt?: string;
+    default_model_provider: string;
+    default_model_name: string;
+    tool_presets: string[];
+    settings: Record<string, unknown>;
+    is_archived: boolean;
+    color?: string;
+    icon?: string;
+    priority?: number;
+    tags: string[];
+    folder_path?: string;
+    created_at: string;
+    updated_at: string;
+    last_accessed_at?: string;
+}
+
+export type NewProject = Partial<Omit<StoredProject, 'is_archived' | 'created_at' | 'updated_at' | 'last_accessed_at'>> & { name: string };
+
+/** An update whose fields `K` can also be `null`, which clears them */
+type Clearable<T, K extends keyof T> = Omit<T, K> & { [P in K]?: T[P] | null };
+
+export type ProjectUpdate = Clearable<Partial<Omit<StoredProject, 'id' | 'created_at' | 'updated_at' | 'last_accessed_at'>>, 'description' | 'system_prompt' | 'folder_path'>;
+
+export interface ProjectFilter {
+    archived?: boolean;
+    search?: string;
+    tag?: string;
+    limit?: number;
+    offset?: number;
+}
+
+export interface StoredThread {
+    id: string;
+    project_id?: string;
+    title: string;
+    description?: string;
+    is_archived: boolean;
+    is_pinned: boolean;
+    model_provider: string;
+    model_name: string;
+    system_prompt?: string;
+    tool_presets: string[];
+    settings: Record<string, unknown>;
+    tags: string[];
+    status?: string;
+    thread_type?: string;
+    priority?: number;
+    parent_thread_id?: string;
+    active_message_id?: string;
+    message_count: number;
+    last_message_at?: string;
+    avg_response_time: number;
+    error_count: number;
+    total_tokens: number;
+    total_cost: number;
+    created_at: string;
+    updated_at: string;
+}
+
+export type NewThread = Partial<Pick<StoredThread, 'id' | 'project_id' | 'title' | 'description' | 'model_provider' | 'model_name' | 'system_prompt' | 'tool_presets' | 'settings' | 'tags' | 'thread_type' | 'priority' | 'parent_thread_id'>>;
+
+export type ThreadUpdate = Clearable<Partial<Pick<StoredThread, 'project_id' | 'title' | 'description' | 'is_archived' | 'is_pinned' | 'model_provider' | 'model_name' | 'system_prompt' | 'tool_presets' | 'settings' | 'tags' | 'status' | 'thread_type' | 'priority'>>, 'project_id' | 'description' | 'system_prompt'>;
+
+export interface ThreadFilter {
+    project_id?: string;
+    archived?: boolean;
+    pinned?: boolean;
+    status?: string;
+    thread_type?: string;
+    tag?: string;
+    search?: string;
+    sort_by?: 'created_at' | 'updated_at' | 'last_message_at' | 'title';
+    sort_order?: SortOrder;
+    limit?: number;
+    offset?: number;
+}
+
+// Without parent_message_id the message continues the thread's active branch
+export type NewMessage = Pick<StoredMessage, 'thread_id' | 'role' | 'content'> &
+    Partial<Pick<StoredMessage, 'id' | 'parent_message_id' | 'attachments' | 'thinking' | 'tool_calls' | 'metadata' | 'response_time' | 'model_used' | 'provider_used'>>;
+
+export type MessageUpdate = Clearable<Partial<Pick<StoredMessage, 'content' | 'attachments' | 'thinking' | 'tool_calls' | 'metadata' | 'response_time' | 'model_used' | 'provider_used'>>, 'response_time' | 'model_used' | 'provider_used'>;
+
+export interface MessageFilter {
+    thread_id?: string;
+    role?: StoredMessage['role'];
+    search?: string;
+    // created_at bounds, `YYYY-MM-DD HH:MM:SS`
+    since?: string;
+    until?: string;
+    // Oldest first by default
+    sort_order?: SortOrder;
+    limit?: number;
+    offset?: number;
 }
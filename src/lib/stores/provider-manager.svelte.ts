@@ -28,6 +28,12 @@ export class ProviderManager {
     });
   }
 
+  /** Provider-qualified model id (`provider::model`) routed by the backend */
+  get qualifiedModel(): string | undefined {
+    if(!this.selectedProvider || !this.selectedModel) return undefined;
+    return `${this.selectedProvider}::${this.selectedModel}`;
+  }
+
   loadProviders = async () => {
       try {
         this.loadingProviders = true;
@@ -62,12 +62,15 @@ export interface ThinkingProcess {
   status: 'thinking' | 'complete' | 'error';
   startTime: Date;
   endTime?: Date;
+  tokens?: number;
 }
 
 export interface ThinkingStep {
   id: string;
   content: string;
   timestamp: Date;
+  endTime?: Date;
+  durationMs?: number;
   type: 'reasoning' | 'analysis' | 'decision' | 'conclusion';
 }
 